@@ -0,0 +1,148 @@
+//! 以 `BTreeMap` 为基准, 测量不同 order 下的顺序插入、随机插入、点查、范围遍历与混合负载
+//!
+//! 运行 `cargo bench`, 只运行其中一组时可以加上名字, 例如 `cargo bench -- get`
+
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::ops::Bound;
+
+use btree_test::BPTree;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+const ORDERS: [usize; 4] = [4, 16, 64, 256];
+const N: usize = 10_000;
+
+// 固定种子的 xorshift, 每次运行生成相同的 key, 结果可以互相比较
+fn random_keys(n: usize) -> Vec<String> {
+    let mut x = 0x2545_f491_4f6c_dd1d_u64;
+    (0..n)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            format!("{:016x}", x)
+        })
+        .collect()
+}
+
+fn sequential_keys(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("{:016x}", i)).collect()
+}
+
+fn entries(keys: &[String]) -> Vec<(String, String)> {
+    keys.iter().map(|key| (key.clone(), key.clone())).collect()
+}
+
+fn insert(c: &mut Criterion, name: &str, keys: &[String]) {
+    let mut group = c.benchmark_group(name);
+    for order in ORDERS {
+        group.bench_with_input(BenchmarkId::new("BPTree", order), &order, |b, &order| {
+            b.iter_batched(
+                || entries(keys),
+                |entries| {
+                    let mut tree = BPTree::new(order);
+                    for (key, value) in entries {
+                        tree.put(key, value).unwrap();
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.bench_function("BTreeMap", |b| {
+        b.iter_batched(|| entries(keys), |entries| entries.into_iter().collect::<BTreeMap<_, _>>(), BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn sequential_insert(c: &mut Criterion) {
+    insert(c, "sequential_insert", &sequential_keys(N));
+}
+
+fn random_insert(c: &mut Criterion) {
+    insert(c, "random_insert", &random_keys(N));
+}
+
+fn get(c: &mut Criterion) {
+    let keys = random_keys(N);
+    let mut group = c.benchmark_group("get");
+    for order in ORDERS {
+        let tree = BPTree::bulk_load(order, entries(&keys));
+        group.bench_with_input(BenchmarkId::new("BPTree", order), &tree, |b, tree| {
+            b.iter(|| keys.iter().filter(|key| tree.get(key.as_str()).is_some()).count())
+        });
+    }
+    let map: BTreeMap<String, String> = entries(&keys).into_iter().collect();
+    group.bench_function("BTreeMap", |b| b.iter(|| keys.iter().filter(|key| map.contains_key(key.as_str())).count()));
+    group.finish();
+}
+
+fn range_scan(c: &mut Criterion) {
+    // 每次从随机位置开始取 100 个键值对
+    let keys = random_keys(N);
+    let starts = &keys[..100];
+    let mut group = c.benchmark_group("range_scan");
+    for order in ORDERS {
+        let tree = BPTree::bulk_load(order, entries(&keys));
+        group.bench_with_input(BenchmarkId::new("BPTree", order), &tree, |b, tree| {
+            b.iter(|| {
+                starts
+                    .iter()
+                    .map(|start| tree.range::<str>(Bound::Included(start), Bound::Unbounded).take(100).count())
+                    .sum::<usize>()
+            })
+        });
+    }
+    let map: BTreeMap<String, String> = entries(&keys).into_iter().collect();
+    group.bench_function("BTreeMap", |b| {
+        b.iter(|| {
+            starts
+                .iter()
+                .map(|start| map.range::<str, _>((Bound::Included(start.as_str()), Bound::Unbounded)).take(100).count())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+fn mixed(c: &mut Criterion) {
+    // 在已有 N 个 key 的树上交替执行查找、插入与删除, 树的大小基本不变
+    let keys = random_keys(2 * N);
+    let (loaded, rest) = keys.split_at(N);
+    let mut group = c.benchmark_group("mixed");
+    for order in ORDERS {
+        group.bench_with_input(BenchmarkId::new("BPTree", order), &order, |b, &order| {
+            b.iter_batched(
+                || BPTree::bulk_load(order, entries(loaded)),
+                |mut tree| {
+                    for (old, new) in loaded.iter().zip(rest) {
+                        black_box(tree.get(old.as_str()));
+                        tree.put(new.clone(), new.clone()).unwrap();
+                        tree.remove(old.as_str()).unwrap();
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.bench_function("BTreeMap", |b| {
+        b.iter_batched(
+            || entries(loaded).into_iter().collect::<BTreeMap<_, _>>(),
+            |mut map| {
+                for (old, new) in loaded.iter().zip(rest) {
+                    black_box(map.get(old.as_str()));
+                    map.insert(new.clone(), new.clone());
+                    map.remove(old.as_str());
+                }
+                map
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, sequential_insert, random_insert, get, range_scan, mixed);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+//! 把任意字节序列解码为 put/get/remove 操作, 以 `BTreeMap` 为参照检查结果, 每一步之后都检查树的结构
+//!
+//! 运行 `cargo fuzz run ops` (需要 nightly 与 cargo-fuzz)
+
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use btree_test::BPTree;
+use libfuzzer_sys::fuzz_target;
+
+// 第一个字节决定 order, 之后每个操作占 3 个字节: 操作类型、key、值
+//
+// key 取自很小的字符集, 长度由 key 字节的高位决定, 让插入与删除频繁地命中同一批 key,
+// 小的 order 下很快就会出现多层分裂与合并
+fn key(byte: u8) -> String {
+    let len = usize::from(byte >> 6) + 1;
+    (0..len).map(|_i| char::from(b'a' + ((byte >> _i) & 0b111))).collect()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&order, ops)) = data.split_first() else { return; };
+    let mut tree = BPTree::new(usize::from(order % 16) + 3);
+    let mut model = BTreeMap::new();
+    for op in ops.chunks_exact(3) {
+        let key = key(op[1]);
+        match op[0] % 4 {
+            0 | 1 => {
+                let value = op[2].to_string();
+                assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key, value));
+            }
+            2 => assert_eq!(tree.remove(&key).unwrap(), model.remove(&key)),
+            _ => assert_eq!(tree.get(&key).map(|_kv| _kv.value()), model.get(&key).map(String::as_str)),
+        }
+        if let Err(error) = tree.check_invariants() {
+            panic!("{}\n{}", error, tree);
+        }
+        assert_eq!(tree.len(), model.len());
+    }
+    assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    assert!(tree.iter().rev().eq(model.iter().rev().map(|(key, value)| (key.as_str(), value.as_str()))));
+});
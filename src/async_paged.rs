@@ -0,0 +1,124 @@
+use std::io;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::BPTreeError;
+use crate::paged::PagedBPTree;
+
+/// [`PagedBPTree`] 的异步接口, 需要开启 `tokio` feature
+///
+/// 查找与修改的逻辑仍然是同步的, 每次调用连同其中的页读写一起交给 tokio 的阻塞线程池执行
+/// (`tokio::fs` 也是这样实现的), 等待页从文件中加载时不会阻塞调用方所在的异步任务.
+/// 可以在多个任务之间共享 (`Clone` 只复制引用), 同一时刻只有一个调用在访问树, 其余的排队等待
+///
+/// ```
+/// use std::ops::Bound;
+/// use btree_test::AsyncBPTree;
+///
+/// let path = std::env::temp_dir().join(format!("btree-test-doc-async-{}", std::process::id()));
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// runtime.block_on(async {
+///     let tree = AsyncBPTree::create(&path, 4, 256, 1 << 16).await.unwrap();
+///     for i in 0..100 {
+///         tree.put(format!("{:03}", i), i.to_string()).await.unwrap();
+///     }
+///     assert_eq!(tree.get("042").await.unwrap().as_deref(), Some("42"));
+///     assert_eq!(tree.range(Bound::Included("097"), Bound::Unbounded).await.unwrap().len(), 3);
+///     tree.flush().await.unwrap();
+/// });
+/// # std::fs::remove_file(&path).unwrap();
+/// # std::fs::remove_file(path.with_extension("ovf")).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct AsyncBPTree {
+    inner: Arc<Mutex<PagedBPTree>>,
+}
+
+impl AsyncBPTree {
+    /// 在 `path` 创建一棵空树, 参数与 [`PagedBPTree::create`] 相同
+    pub async fn create<P: Into<PathBuf>>(path: P, order: usize, page_size: usize, memory_budget: usize) -> io::Result<Self> {
+        let path = path.into();
+        let tree = blocking(move || PagedBPTree::create(path, order, page_size, memory_budget)).await?;
+        Ok(Self::from(tree))
+    }
+
+    /// 打开已有的文件, 参数与 [`PagedBPTree::open`] 相同
+    pub async fn open<P: Into<PathBuf>>(path: P, memory_budget: usize) -> io::Result<Self> {
+        let path = path.into();
+        let tree = blocking(move || PagedBPTree::open(path, memory_budget)).await?;
+        Ok(Self::from(tree))
+    }
+
+    /// 按 key 查找值
+    pub async fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        let key = key.as_ref().to_vec();
+        self.with_tree(move |_tree| _tree.get(&key)).await
+    }
+
+    /// 插入键值对, key 已存在时更新其值并返回旧值
+    pub async fn put(&self, key: String, value: String) -> Result<Option<String>, BPTreeError> {
+        self.with_tree(move |_tree| _tree.put(key, value)).await
+    }
+
+    /// 批量插入键值对, 返回新插入的 key 的数量
+    pub async fn put_batch(&self, entries: Vec<(String, String)>) -> Result<usize, BPTreeError> {
+        self.with_tree(move |_tree| _tree.put_batch(entries)).await
+    }
+
+    /// 删除 key, 返回被删除的值
+    pub async fn remove<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        let key = key.as_ref().to_vec();
+        self.with_tree(move |_tree| _tree.remove(&key)).await
+    }
+
+    /// 按 key 的顺序取出 `start` 到 `end` 之间的键值对
+    pub async fn range<Q: AsRef<[u8]> + ?Sized>(
+        &self,
+        start: Bound<&Q>,
+        end: Bound<&Q>,
+    ) -> Result<Vec<(String, String)>, BPTreeError> {
+        let start = start.map(|_key| _key.as_ref().to_vec());
+        let end = end.map(|_key| _key.as_ref().to_vec());
+        self.with_tree(move |_tree| _tree.range(start.as_ref(), end.as_ref())).await
+    }
+
+    /// 将修改过的节点与元数据写回文件
+    ///
+    /// 最后一个引用被 drop 时也会写回, 但那次写回发生在 drop 所在的线程中并且会忽略错误
+    pub async fn flush(&self) -> io::Result<()> {
+        self.with_tree(PagedBPTree::flush).await
+    }
+
+    async fn with_tree<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut PagedBPTree) -> T + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        blocking(move || f(&mut lock(&inner))).await
+    }
+}
+
+impl From<PagedBPTree> for AsyncBPTree {
+    fn from(tree: PagedBPTree) -> Self {
+        Self { inner: Arc::new(Mutex::new(tree)) }
+    }
+}
+
+fn lock(inner: &Mutex<PagedBPTree>) -> MutexGuard<'_, PagedBPTree> {
+    // 修改到一半 panic 时树可能已经不完整, 与 ConcurrentBPTree 一样不再继续使用
+    inner.lock().expect("tree poisoned")
+}
+
+async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    // 阻塞线程中的 panic 传回调用方; 运行时关闭时任务被取消, 调用方也不会再被 poll
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(_error) => std::panic::resume_unwind(_error.into_panic()),
+    }
+}
@@ -0,0 +1,167 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::bptree::{BPTree, Fanout};
+use crate::pager::invalid_data;
+
+// 备份文件的魔数与格式版本
+const MAGIC: &[u8; 8] = b"BPTBACK\0";
+const VERSION: u32 = 1;
+
+/// 写入时同时计算已写入内容的哈希
+struct HashWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 读取时同时计算已读取内容的哈希
+struct HashReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashReader<R> {
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        // 文件提前结束说明备份不完整, 与内容损坏一样返回 InvalidData
+        self.read_exact(buf).map_err(|_error| match _error.kind() {
+            io::ErrorKind::UnexpectedEof => invalid_data("backup file is truncated"),
+            _ => _error,
+        })
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0u8; 4];
+        self.fill(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0u8; 8];
+        self.fill(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        let mut buf = vec![];
+        // 长度字段损坏时不按它一次分配, 读到文件末尾为止
+        self.by_ref().take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(invalid_data("backup file is truncated"));
+        }
+        String::from_utf8(buf).map_err(|_| invalid_data("backup contains invalid UTF-8"))
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+impl BPTree {
+    /// 将所有键值对按 key 的顺序写入备份文件 `path`, 之后可以用 [`restore`](Self::restore) 重建
+    ///
+    /// 备份中记录了叶子节点与内部节点的 order, 末尾是之前所有内容的 SHA-256, 恢复时检查.
+    /// 先写入旁边 `.tmp` 后缀的临时文件, 写完并落盘后再重命名, 中途失败时不会留下不完整的备份, 已有的备份也不会被破坏
+    ///
+    /// 需要在服务继续读写的同时备份时, 先用 [`snapshot`](Self::snapshot) 创建快照, 在另一个线程中调用快照的 `backup`,
+    /// 备份的内容是创建快照时的状态
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let path = std::env::temp_dir().join(format!("btree-test-doc-backup-{}", std::process::id()));
+    /// let mut tree = BPTree::bulk_load(4, (0..100).map(|i| (format!("{:03}", i), i.to_string())));
+    /// let snapshot = tree.snapshot();
+    /// let backup = std::thread::spawn({
+    ///     let path = path.clone();
+    ///     move || snapshot.backup(&path)
+    /// });
+    /// tree.remove("042").unwrap();
+    /// backup.join().unwrap().unwrap();
+    ///
+    /// let restored = BPTree::restore(&path).unwrap();
+    /// assert_eq!(restored.len(), 100);
+    /// assert_eq!(restored.get("042").map(|kv| kv.value()), Some("42"));
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn backup<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temp = temp_path(path);
+        let file = File::create(&temp)?;
+        let mut out = HashWriter { inner: BufWriter::new(file), hasher: Sha256::new() };
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        for order in [self.fanout.internal, self.fanout.leaf] {
+            out.write_all(&(order as u32).to_le_bytes())?;
+        }
+        out.write_all(&(self.len as u64).to_le_bytes())?;
+        for (key, value) in self {
+            for field in [key, value] {
+                out.write_all(&(field.len() as u32).to_le_bytes())?;
+                out.write_all(field.as_bytes())?;
+            }
+        }
+        let HashWriter { inner: mut writer, hasher } = out;
+        writer.write_all(&hasher.finalize())?;
+        let file = writer.into_inner().map_err(|_error| _error.into_error())?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    }
+
+    /// 从 [`backup`](Self::backup) 写入的文件用 [`bulk_load`](Self::bulk_load) 重建一棵内存中的树
+    ///
+    /// 使用备份时的 order; 比较器与合并函数不会保存在备份中, 重建的树按字节比较 key.
+    /// 文件被截断或内容与末尾的哈希不符时返回 [`io::ErrorKind::InvalidData`]
+    pub fn restore<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut input = HashReader { inner: BufReader::new(File::open(path)?), hasher: Sha256::new() };
+        let mut magic = [0u8; 8];
+        input.fill(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a backup file"));
+        }
+        let version = input.u32()?;
+        if version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("unsupported backup version {}", version)));
+        }
+        let (internal_order, leaf_order) = (input.u32()? as usize, input.u32()? as usize);
+        let len = input.u64()?;
+        let mut entries = vec![];
+        for _ in 0..len {
+            entries.push((input.string()?, input.string()?));
+        }
+        // 末尾的哈希不计入哈希本身
+        let expected = std::mem::take(&mut input.hasher).finalize();
+        let mut hash = [0u8; 32];
+        input.fill(&mut hash)?;
+        if hash[..] != expected[..] || input.inner.read(&mut [0u8])? != 0 {
+            return Err(invalid_data("backup checksum mismatch"));
+        }
+        Ok(Self::bulk_load_with(Fanout::new(leaf_order, internal_order), entries))
+    }
+}
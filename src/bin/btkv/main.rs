@@ -0,0 +1,140 @@
+mod repl;
+
+use std::io::{self, BufReader};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use btree_test::{BPTree, MaintenanceConfig, MaintenanceScheduler, DEFAULT_PAGE_SIZE};
+
+const USAGE: &str = "\
+用法:
+  btkv [order]                          在内存中的树上运行交互式命令行
+  btkv open <file> [选项]               打开数据库文件 (不存在时创建) 并运行交互式命令行
+  btkv serve <file> <addr> [选项]       打开数据库文件, 在 addr 上接受 TCP 连接, 每行一条命令, 格式与命令行相同
+  btkv backup <file> <backup>           把数据库文件中的树备份到 backup
+  btkv restore <backup> <file> [选项]   从备份创建新的数据库文件
+  btkv fsck <file>                      检查数据库文件中的树结构
+  btkv stats <file>                     打印数据库文件的统计信息
+
+选项:
+  --order <n>        创建数据库时节点的 order, 默认为 32
+  --page-size <n>    创建数据库时的页大小, 默认为 4096";
+
+// 创建数据库时的默认 order, 与默认的页大小搭配时可以存放较短的键值对
+const DEFAULT_ORDER: usize = 32;
+
+/// 创建数据库时使用的参数
+struct CreateOptions {
+    order: usize,
+    page_size: usize,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let Some((positional, options)) = parse_options(&args) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+    let result = match positional.as_slice() {
+        [] => interactive(BPTree::new(5)),
+        // 兼容原来的 `btree-test [order]`
+        [order] if order.parse::<usize>().is_ok() => interactive(BPTree::new(order.parse().unwrap())),
+        ["open", path] => open_or_create(path, &options).and_then(interactive),
+        ["serve", path, addr] => open_or_create(path, &options).and_then(|_tree| serve(_tree, addr)),
+        ["backup", path, backup] => BPTree::open(path).and_then(|_tree| _tree.backup(backup)).map(|()| println!("已备份到 {}", backup)),
+        ["restore", backup, path] => restore(backup, path, &options),
+        ["fsck", path] => fsck(path),
+        ["stats", path] => BPTree::open(path).and_then(|mut _tree| repl::execute(&mut _tree, "stats", &mut io::stdout())).map(|_| ()),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("错误: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_options<'a>(args: &[&'a str]) -> Option<(Vec<&'a str>, CreateOptions)> {
+    let mut positional = vec![];
+    let mut options = CreateOptions { order: DEFAULT_ORDER, page_size: DEFAULT_PAGE_SIZE };
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--order" => options.order = args.next()?.parse().ok()?,
+            "--page-size" => options.page_size = args.next()?.parse().ok()?,
+            _ if arg.starts_with("--") => return None,
+            _ => positional.push(arg),
+        }
+    }
+    Some((positional, options))
+}
+
+fn open_or_create(path: &str, options: &CreateOptions) -> io::Result<BPTree> {
+    if Path::new(path).exists() {
+        BPTree::open(path)
+    } else {
+        println!("创建 {} (order = {}, 页大小 = {})", path, options.order, options.page_size);
+        BPTree::create(path, options.order, options.page_size)
+    }
+}
+
+/// 在后台维护 (检查点、回收预写日志、整理节点) 的同时运行交互式命令行, 退出前做一次检查点
+fn interactive(tree: BPTree) -> io::Result<()> {
+    println!("B+Tree (order = {}), 输入 help 查看命令", tree.order());
+    let scheduler = MaintenanceScheduler::new(tree, MaintenanceConfig::default());
+    repl::run(&scheduler, io::stdin().lock(), io::stdout(), true)?;
+    scheduler.into_inner().checkpoint()
+}
+
+/// 每个连接一个线程, 所有连接共用一棵树, 每条命令执行期间持有树的锁
+///
+/// 修改都先写入预写日志, 直接结束进程也不会丢失, 下一次打开时重放
+fn serve(tree: BPTree, addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("在 {} 上等待连接 (order = {})", listener.local_addr()?, tree.order());
+    let scheduler = Arc::new(MaintenanceScheduler::new(tree, MaintenanceConfig::default()));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let scheduler = scheduler.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().map(|_addr| _addr.to_string()).unwrap_or_default();
+            let result = stream.try_clone().and_then(|_input| repl::run(&scheduler, BufReader::new(_input), &stream, false));
+            if let Err(error) = result {
+                eprintln!("{}: {}", peer, error);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn restore(backup: &str, path: &str, options: &CreateOptions) -> io::Result<()> {
+    if Path::new(path).exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", path)));
+    }
+    let mut restored = BPTree::restore(backup)?;
+    // 使用备份时的 order, 只有页大小取自选项
+    let mut tree = BPTree::builder()
+        .leaf_order(restored.leaf_order())
+        .internal_order(restored.order())
+        .create(path, options.page_size)?;
+    tree.append(&mut restored)?;
+    tree.checkpoint()?;
+    println!("从 {} 恢复 {} 条到 {}", backup, tree.len(), path);
+    Ok(())
+}
+
+fn fsck(path: &str) -> io::Result<()> {
+    // open 在重放预写日志之前已经检查过文件中的树结构, 这里检查重放之后的结果
+    let tree = BPTree::open(path)?;
+    tree.check_invariants().map_err(|_error| io::Error::new(io::ErrorKind::InvalidData, _error))?;
+    println!("{}: 正常, {} 条", path, tree.len());
+    Ok(())
+}
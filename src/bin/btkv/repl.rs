@@ -0,0 +1,152 @@
+use std::io::{self, BufRead, Write};
+use std::ops::Bound;
+
+use btree_test::{BPTree, Format, MaintenanceScheduler};
+
+const HELP: &str = "\
+命令:
+  put <key> <value>    插入或更新, value 可以包含空格
+  get <key>            查找
+  del <key>            删除
+  scan [a]..[z]        按顺序列出 [a, z) 之间的键值对, a..=z 包含 z, 省略表示不限
+  count [a]..[z]       [a, z) 之间的键值对数量, 格式与 scan 相同
+  prefix <p>           按顺序列出以 p 开头的键值对
+  rank <key>           小于 key 的键值对数量
+  select <n>           按顺序排在第 n 位 (从 0 开始) 的键值对
+  import <fmt> <file>  从文件导入键值对, fmt 为 csv 或 jsonl
+  export <fmt> <file>  按顺序导出所有键值对到文件, 格式与 import 相同
+  dump                 按层打印树的结构
+  dot                  输出 Graphviz DOT 格式的树结构
+  stats                打印统计信息
+  help                 显示本帮助
+  quit                 退出";
+
+/// 执行一行命令, 输出写入 `out`; 遇到 `quit` 时返回 `false`
+pub fn execute(tree: &mut BPTree, line: &str, out: &mut dyn Write) -> io::Result<bool> {
+    let line = line.trim();
+    let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+    let args = args.trim();
+    match command {
+        "" => Ok(()),
+        "put" => match args.split_once(' ') {
+            Some((key, value)) => match tree.put(key.to_string(), value.trim_start().to_string()) {
+                Ok(Some(old_value)) => writeln!(out, "更新 {} (旧值: {})", key, old_value),
+                Ok(None) => writeln!(out, "插入 {}", key),
+                Err(error) => writeln!(out, "错误: {}", error),
+            },
+            None => writeln!(out, "用法: put <key> <value>"),
+        },
+        "get" if !args.is_empty() => match tree.get(args) {
+            Some(kv) => writeln!(out, "{}", kv.value()),
+            None => writeln!(out, "(不存在)"),
+        },
+        "del" if !args.is_empty() => match tree.remove(args) {
+            Ok(Some(value)) => writeln!(out, "删除 {} (值: {})", args, value),
+            Ok(None) => writeln!(out, "(不存在)"),
+            Err(error) => writeln!(out, "错误: {}", error),
+        },
+        "get" | "del" => writeln!(out, "用法: {} <key>", command),
+        "scan" => match parse_range(args) {
+            Some((start, end)) => {
+                let mut count = 0;
+                for (key, value) in tree.range(start, end) {
+                    writeln!(out, "{}: {}", key, value)?;
+                    count += 1;
+                }
+                writeln!(out, "({} 条)", count)
+            }
+            None => writeln!(out, "用法: scan [a]..[z] 或 scan [a]..=[z]"),
+        },
+        "count" => match parse_range(args) {
+            Some((start, end)) => writeln!(out, "{}", tree.range_count(start, end)),
+            None => writeln!(out, "用法: count [a]..[z] 或 count [a]..=[z]"),
+        },
+        "prefix" if !args.is_empty() => {
+            let mut count = 0;
+            for (key, value) in tree.prefix(args) {
+                writeln!(out, "{}: {}", key, value)?;
+                count += 1;
+            }
+            writeln!(out, "({} 条)", count)
+        }
+        "prefix" => writeln!(out, "用法: prefix <p>"),
+        "rank" if !args.is_empty() => writeln!(out, "{}", tree.rank(args)),
+        "rank" => writeln!(out, "用法: rank <key>"),
+        "select" => match args.parse() {
+            Ok(n) => match tree.select(n) {
+                Some((key, value)) => writeln!(out, "{}: {}", key, value),
+                None => writeln!(out, "(不存在)"),
+            },
+            Err(_) => writeln!(out, "用法: select <n>"),
+        },
+        "import" | "export" => match args.split_once(' ').and_then(|(format, path)| Some((parse_format(format)?, path.trim()))) {
+            Some((format, path)) if command == "import" => {
+                match std::fs::File::open(path).map_err(Into::into).and_then(|_file| tree.import(_file, format)) {
+                    Ok(inserted) => writeln!(out, "导入 {} 条新 key", inserted),
+                    Err(error) => writeln!(out, "错误: {}", error),
+                }
+            }
+            Some((format, path)) => match std::fs::File::create(path).and_then(|_file| tree.export(_file, format)) {
+                Ok(()) => writeln!(out, "导出 {} 条", tree.len()),
+                Err(error) => writeln!(out, "错误: {}", error),
+            },
+            None => writeln!(out, "用法: {} <csv|jsonl> <file>", command),
+        },
+        "dump" => write!(out, "{}", tree),
+        "dot" => write!(out, "{}", tree.to_dot()),
+        "stats" => {
+            let stats = tree.stats();
+            writeln!(out, "键值对: {}", stats.len)?;
+            writeln!(out, "高度: {}", stats.height)?;
+            writeln!(out, "叶子节点: {}", stats.leaf_count)?;
+            writeln!(out, "内部节点: {}", stats.internal_count)?;
+            writeln!(out, "叶子填充率: {:.1}%", stats.fill_factor * 100.0)?;
+            writeln!(out, "插入模式: {:?}", stats.insert_pattern)?;
+            writeln!(out, "key 与值: {} 字节", stats.byte_size)?;
+            writeln!(out, "估计占用内存: {} 字节", tree.memory_usage().total())
+        }
+        "help" => writeln!(out, "{}", HELP),
+        "quit" | "exit" => return Ok(false),
+        _ => writeln!(out, "无法识别的命令: {}, 输入 help 查看命令", line),
+    }?;
+    Ok(true)
+}
+
+/// 逐行读取命令并执行, 每条命令执行期间持有树的锁; `prompt` 为 true 时在每行之前输出提示符
+pub fn run<R: BufRead, W: Write>(tree: &MaintenanceScheduler<BPTree>, input: R, mut out: W, prompt: bool) -> io::Result<()> {
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            write!(out, "> ")?;
+        }
+        out.flush()?;
+        let Some(line) = lines.next() else { return Ok(()); };
+        if !execute(&mut tree.lock(), &line?, &mut out)? {
+            return Ok(());
+        }
+    }
+}
+
+fn parse_range(args: &str) -> Option<(Bound<&str>, Bound<&str>)> {
+    // a..z, a..=z, ..z, a.., 不带参数时为全部
+    if args.is_empty() {
+        return Some((Bound::Unbounded, Bound::Unbounded));
+    }
+    let (start, end) = args.split_once("..")?;
+    let start = if start.is_empty() { Bound::Unbounded } else { Bound::Included(start) };
+    let end = match end.strip_prefix('=') {
+        Some("") => return None,
+        Some(end) => Bound::Included(end),
+        None if end.is_empty() => Bound::Unbounded,
+        None => Bound::Excluded(end),
+    };
+    Some((start, end))
+}
+
+fn parse_format(name: &str) -> Option<Format> {
+    match name {
+        "csv" => Some(Format::Csv),
+        "jsonl" => Some(Format::JsonLines),
+        _ => None,
+    }
+}
@@ -0,0 +1,2800 @@
+use std::io;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::change::ChangeFeed;
+use crate::cipher::PageCipher;
+use crate::error::{BPTreeError, CasError};
+use crate::branches::Branches;
+use crate::builder::{BPTreeBuilder, DEFAULT_ORDER};
+use crate::comparator::KeyOrder;
+use crate::cursor::{Cursor, CursorMut};
+use crate::entry::{Entry, OccupiedEntry, VacantEntry, ValueMut};
+use crate::instrument;
+use crate::merge::{MergeOperator, Merger};
+use crate::iter::{normalize, Drain, Iter, IterMut, Keys, Range, Values};
+use crate::pager::{Meta, Pager};
+use crate::slab::{self, NodeId, NodeSlab};
+use crate::snapshot::BPTreeSnapshot;
+use crate::split::{InsertPattern, PatternDetector, SplitPolicy};
+use crate::transaction::Transaction;
+use crate::store::{NodeRead, NodeStore};
+use crate::wal::{Record, Wal};
+
+/// 叶子节点中存放的键值对
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BPTreeKeyValue {
+    pub(crate) key: String,
+    pub(crate) value: String,
+}
+
+impl BPTreeKeyValue {
+    /// 键
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// 值
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// 树中的节点, 所有节点都存放在 [`BPTree`] 内部的 [`NodeSlab`] 中, 相互之间通过 [`NodeId`] 引用
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BPTreeNode {
+    /// 内部节点, 只存放用于查找的 key 以及子节点的下标
+    ///
+    /// 子节点、分隔 key 与子树计数放在同一块按 order 分配的内存中, 见 [`Branches`]
+    Internal {
+        #[cfg_attr(feature = "serde", serde(flatten))]
+        branches: Branches,
+        /// `keys` 的公共前缀, 不为空时 `keys` 中只存放去掉它之后的部分, 见 [`PrefixBPTree`](crate::PrefixBPTree)
+        #[cfg_attr(feature = "serde", serde(default))]
+        prefix: String,
+    },
+    /// 叶子节点, 存放实际的键值对, 并通过 `prev` 和 `next` 串成一条有序的双向链表
+    Leaf {
+        prev: Option<NodeId>,
+        next: Option<NodeId>,
+        kvs: Vec<BPTreeKeyValue>,
+        /// 所有 key 的公共前缀, 不为空时 `kvs` 中的 key 只存放去掉它之后的部分
+        #[cfg_attr(feature = "serde", serde(default))]
+        prefix: String,
+    },
+}
+
+
+impl BPTreeNode {
+    /// 内部节点的 [`Branches`] 与叶子节点的 `kvs` 每段预留的容量
+    ///
+    /// 节点在分裂前最多有 order 个 key 与 order + 1 个子节点, 按这个容量一次分配之后,
+    /// 插入与分裂都不需要重新分配; 只有批量插入时积累到两倍上限的叶子节点与合并后再分裂的内部节点会再扩容一次
+    pub(crate) fn capacity(order: usize) -> usize {
+        order + 1
+    }
+
+    /// 去掉节点中 `Vec` 与字符串未使用的容量
+    pub(crate) fn shrink_to_fit(&mut self) {
+        match self {
+            BPTreeNode::Internal { branches, prefix } => {
+                branches.shrink_to_fit();
+                branches.parts_mut().1.iter_mut().for_each(String::shrink_to_fit);
+                prefix.shrink_to_fit();
+            }
+            BPTreeNode::Leaf { kvs, prefix, .. } => {
+                prefix.shrink_to_fit();
+                kvs.shrink_to_fit();
+                for kv in kvs {
+                    kv.key.shrink_to_fit();
+                    kv.value.shrink_to_fit();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn split(&mut self, at: usize, order: usize) -> (String, BPTreeNode) {
+        // 该分裂仅将节点内部数据分成两份, 左节点保留前 at 个元素, 并不涉及父节点的连锁反应
+        // 返回需要插入父节点的 key 以及分裂出来的右节点, 右节点与左节点一样预留 order 对应的容量
+        let capacity = Self::capacity(order);
+        match self {
+            BPTreeNode::Internal { branches, .. } => {
+                // 分裂 Internal 节点, 第 at 个 key 上移到父节点, 不再保留在子节点中
+                // 超出上限时节点中有 order 个 key, 从中间分裂时去掉上移的 key 后剩下 order - 1 个,
+                // order 为偶数时无法平分, 左节点多分一个, 右节点也至少有 order / 2 - 1 个, 满足下限
+                let center = at;
+                let right = branches.split_off(center + 1, capacity);
+                let center_key = branches.parts_mut().1.pop().unwrap_or_default();
+                (center_key, BPTreeNode::Internal { branches: right, prefix: String::new() })
+            }
+            BPTreeNode::Leaf { kvs, .. } => {
+                // 分裂 Leaf 节点, 右节点的第一个 key 复制一份到父节点
+                // 分裂点由 SplitPolicy 决定, 从中间分裂时 order 为奇数则右节点多分一个, 为偶数时两边一样多
+                let right_kvs = split_vec(kvs, at, capacity);
+                (right_kvs[0].key.clone(), BPTreeNode::Leaf {
+                    prev: None,
+                    next: None,
+                    kvs: right_kvs,
+                    prefix: String::new(),
+                })
+            }
+        }
+    }
+
+    /// 节点中 key 的数量
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            BPTreeNode::Internal { branches, .. } => branches.keys().len(),
+            BPTreeNode::Leaf { kvs, .. } => kvs.len(),
+        }
+    }
+
+    /// 节点中 key 与值的字节数, 不包括 `Vec` 与 `String` 本身的开销
+    pub fn byte_size(&self) -> usize {
+        match self {
+            BPTreeNode::Internal { branches, prefix } => prefix.len() + branches.keys().iter().map(String::len).sum::<usize>(),
+            BPTreeNode::Leaf { kvs, prefix, .. } => prefix.len() + kvs.iter().map(|_kv| _kv.key.len() + _kv.value.len()).sum::<usize>(),
+        }
+    }
+
+    /// 节点中所有 key 的公共前缀, 节点按前缀压缩存放时才可能不为空, 节点中的 key 都要拼接在它之后
+    pub(crate) fn prefix(&self) -> &str {
+        match self {
+            BPTreeNode::Internal { prefix, .. } | BPTreeNode::Leaf { prefix, .. } => prefix,
+        }
+    }
+
+    /// 把前缀拼接回每个 key 的开头, 之后节点中存放完整的 key, 前缀为空
+    ///
+    /// 前缀本来就为空时返回 false
+    pub(crate) fn expand(&mut self) -> bool {
+        let (prefix, keys) = self.keys_mut();
+        if prefix.is_empty() {
+            return false;
+        }
+        for key in keys {
+            key.insert_str(0, prefix);
+        }
+        *prefix = String::new();
+        true
+    }
+
+    /// 把所有 key 的公共前缀移到节点的前缀中, key 中只留下之后的部分, 没有可以移动的部分时返回 false
+    ///
+    /// 去掉前缀后的 key 重新分配, 不保留原来的容量
+    pub(crate) fn compress(&mut self) -> bool {
+        let (prefix, keys) = self.keys_mut();
+        let mut keys: Vec<&mut String> = keys.collect();
+        let len = common_prefix(keys.iter().map(|_k| _k.as_str())).len();
+        if len == 0 {
+            return false;
+        }
+        prefix.push_str(&keys[0][..len]);
+        for key in &mut keys {
+            **key = key[len..].to_string();
+        }
+        true
+    }
+
+    /// 所有 key 是否有不为空的公共前缀, 即 [`compress`](Self::compress) 是否会修改节点
+    pub(crate) fn is_compressible(&self) -> bool {
+        match self {
+            BPTreeNode::Internal { branches, .. } => !common_prefix(branches.keys().iter().map(String::as_str)).is_empty(),
+            BPTreeNode::Leaf { kvs, .. } => !common_prefix(kvs.iter().map(|_kv| _kv.key.as_str())).is_empty(),
+        }
+    }
+
+    fn keys_mut(&mut self) -> (&mut String, Box<dyn Iterator<Item = &mut String> + '_>) {
+        match self {
+            BPTreeNode::Internal { branches, prefix } => {
+                let (_, keys, _) = branches.parts_mut();
+                (prefix, Box::new(keys.into_slice().iter_mut()))
+            }
+            BPTreeNode::Leaf { kvs, prefix, .. } => (prefix, Box::new(kvs.iter_mut().map(|_kv| &mut _kv.key))),
+        }
+    }
+
+    /// 子树中键值对的数量
+    pub(crate) fn count(&self) -> usize {
+        match self {
+            BPTreeNode::Internal { branches, .. } => branches.counts().iter().sum(),
+            BPTreeNode::Leaf { kvs, .. } => kvs.len(),
+        }
+    }
+
+    /// 第 `idx` 个子节点分裂后, 在它右侧插入分隔 key 与新的子节点, 并更新两者的子树计数
+    ///
+    /// 节点不是内部节点或 `idx` 越界时返回 false
+    pub(crate) fn push_data(&mut self, idx: usize, new_child: NodeId, key: String, new_count: usize) -> bool {
+        let BPTreeNode::Internal { branches, .. } = self else { return false; };
+        if idx > branches.keys().len() || branches.counts()[idx] < new_count {
+            return false;
+        }
+        branches.reserve(1);
+        let (mut child, mut keys, mut counts) = branches.parts_mut();
+        keys.insert(idx, key);
+        child.insert(idx + 1, new_child);
+        counts[idx] -= new_count;
+        counts.insert(idx + 1, new_count);
+        true
+    }
+}
+
+/// 所有 key 的最长公共前缀, 在字符边界处截断, 去掉它之后的部分仍然是合法的 UTF-8
+pub(crate) fn common_prefix<'a, I: Iterator<Item = &'a str>>(mut keys: I) -> &'a str {
+    let Some(first) = keys.next() else { return ""; };
+    let mut len = first.len();
+    for key in keys {
+        len = first.bytes().zip(key.bytes()).take(len).take_while(|(_a, _b)| _a == _b).count();
+    }
+    while !first.is_char_boundary(len) {
+        len -= 1;
+    }
+    &first[..len]
+}
+
+/// 把 `vec` 从 `at` 处分成两半, 右半部分放在预留了 `capacity` 容量的新 `Vec` 中
+fn split_vec<T>(vec: &mut Vec<T>, at: usize, capacity: usize) -> Vec<T> {
+    let mut right = Vec::with_capacity(capacity.max(vec.len() - at));
+    right.extend(vec.drain(at..));
+    right
+}
+
+/// 从根节点到叶子节点经过的内部节点, 以及在每个内部节点中走向的子节点下标
+///
+/// 节点中不保存父节点, 分裂与合并需要的父节点都从路径中取得
+pub(crate) type DescentPath = Vec<(NodeId, usize)>;
+
+/// 树的统计信息, 由 [`BPTree::stats`] 创建
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BPTreeStats {
+    /// 键值对的数量
+    pub len: usize,
+    /// 树的高度, 只有一个叶子节点时为 1
+    pub height: usize,
+    /// 叶子节点的数量
+    pub leaf_count: usize,
+    /// 内部节点的数量
+    pub internal_count: usize,
+    /// 叶子节点的平均填充率, 即键值对数量与叶子节点容量之比
+    pub fill_factor: f64,
+    /// 最近插入的 key 的模式, [`SplitPolicy::Adaptive`] 按它选择分配方式
+    pub insert_pattern: InsertPattern,
+    /// 所有节点中 key 与值的字节数, 见 [`BPTreeNode::byte_size`]
+    pub byte_size: usize,
+}
+
+/// 树占用内存的估计, 由 [`BPTree::memory_usage`] 创建
+///
+/// 与快照共享的节点也按独占计算, 不包括内存分配器自身的开销
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// 节点本身的大小, 包括 `Arc` 的引用计数、slab 中指向它的指针,
+    /// 以及 `Vec` 中每个元素的固定部分 (`String` 头、子节点编号与子树计数)
+    pub headers: usize,
+    /// key 的字节数, 包括内部节点中的分隔 key
+    pub keys: usize,
+    /// 值的字节数
+    pub values: usize,
+    /// `Vec` 与 `String` 已分配但没有使用的容量, 以及 slab 中空闲的槽
+    pub slack: usize,
+    /// 以上各项中属于内部节点的部分
+    pub internal: usize,
+    /// 以上各项中属于叶子节点的部分, slab 中空闲的槽不属于任何节点
+    pub leaf: usize,
+}
+
+impl MemoryStats {
+    /// 总字节数
+    pub fn total(&self) -> usize {
+        self.headers + self.keys + self.values + self.slack
+    }
+}
+
+/// 叶子节点与内部节点各自的最大路数
+///
+/// 叶子节点中是完整的键值对, 内部节点中只有分隔 key 与子节点, 值较大时两者的 order 不同才能让节点大小接近,
+/// 见 [`BPTreeBuilder::leaf_order`]. 两者都不小于 3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fanout {
+    pub(crate) leaf: usize,
+    pub(crate) internal: usize,
+}
+
+impl Fanout {
+    pub(crate) fn new(leaf: usize, internal: usize) -> Self {
+        Self { leaf: leaf.max(3), internal: internal.max(3) }
+    }
+
+    /// 叶子节点与内部节点使用相同的 order
+    pub(crate) fn uniform(order: usize) -> Self {
+        Self::new(order, order)
+    }
+
+    /// 节点所在层的 order
+    pub(crate) fn of(self, node: &BPTreeNode) -> usize {
+        match node {
+            BPTreeNode::Internal { .. } => self.internal,
+            BPTreeNode::Leaf { .. } => self.leaf,
+        }
+    }
+}
+
+/// 基于 [`NodeSlab`] 存放节点的 B+Tree
+///
+/// 修改树的方法在写预写日志失败或发现结构损坏时返回 [`BPTreeError`], 只读的方法遇到损坏的结构时 panic
+#[derive(Debug)]
+pub struct BPTree {
+    // BTree 是一种多路搜索树, order 对应着形状, 也就是对应的路数, 或者说是节点中指针的数量
+    // 因为节点中是指针和数据间隔排列, 因此节点中可存放的数据有以下规则
+    // 最多可存放元素 order - 1, 最少可存放 (order / 2) 向上取整后 -1 个
+    // 叶子节点与内部节点的 order 可以不同
+    pub(crate) fanout: Fanout,
+    pub(crate) nodes: NodeSlab,
+    pub(crate) split_policy: SplitPolicy,
+    // 最近插入的 key 的模式, 决定 SplitPolicy::Adaptive 的分配方式
+    pub(crate) insert_pattern: PatternDetector,
+    // key 的顺序, 默认按字节比较
+    pub(crate) key_order: KeyOrder,
+    pub(crate) root: NodeId,
+    pub(crate) first_leaf: NodeId,
+    pub(crate) last_leaf: NodeId,
+    // 键值对的数量, 随插入和删除增减
+    pub(crate) len: usize,
+    // 关联的文件与预写日志, 内存中的树为 None
+    pub(crate) pager: Option<Pager>,
+    pub(crate) wal: Option<Wal>,
+    // merge 使用的合并函数
+    pub(crate) merge_operator: Option<Merger>,
+    // subscribe 创建的订阅, 每次修改后发出事件
+    pub(crate) changes: ChangeFeed,
+}
+
+impl BPTree {
+    /// 创建一棵空树, `order` 为节点的最大路数
+    ///
+    /// 奇数与偶数都可以, 小于 3 时按 3 处理
+    pub fn new(order: usize) -> Self {
+        // order 小于 3 的时候, 与正常二叉树一致, 所以无意义
+        Self::with_fanout(Fanout::uniform(order))
+    }
+
+    /// 创建一棵空树, 并按预计存放 `expected_entries` 个键值对为节点的 slab 预留空间
+    ///
+    /// 按每个非根节点都只有下限个元素估计节点数量, 槽表中需要的块都预先创建, 只插入时 slab 不会再分配;
+    /// 每个槽只是一个指针, 多预留的空间很小. 每个节点中的 `Vec` 在创建节点时已经按 order 一次分配
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::with_capacity(16, 10_000);
+    /// // 预留的槽计入未使用的容量
+    /// assert!(tree.memory_usage().slack > BPTree::new(16).memory_usage().slack);
+    /// for i in 0..10_000 {
+    ///     tree.put(format!("{:05}", i), i.to_string()).unwrap();
+    /// }
+    /// assert_eq!(tree.len(), 10_000);
+    /// ```
+    pub fn with_capacity(order: usize, expected_entries: usize) -> Self {
+        let mut tree = Self::new(order);
+        tree.nodes.reserve(Self::estimate_nodes(tree.fanout, expected_entries));
+        tree
+    }
+
+    // 存放 entries 个键值对最多需要的节点数量, 每个非根节点至少有 min_len 个元素 (内部节点有 min_len + 1 个子节点)
+    pub(crate) fn estimate_nodes(fanout: Fanout, entries: usize) -> usize {
+        let mut level = entries.div_ceil(Self::min_len(fanout.leaf).max(1)).max(1);
+        let mut total = level;
+        while level > 1 {
+            level = level.div_ceil(Self::min_len(fanout.internal) + 1);
+            total += level;
+        }
+        total
+    }
+
+    pub(crate) fn with_fanout(fanout: Fanout) -> Self {
+        let mut nodes = NodeSlab::new();
+        let root = nodes.alloc_node(BPTreeNode::Leaf {
+            prev: None,
+            next: None,
+            kvs: Vec::with_capacity(BPTreeNode::capacity(fanout.leaf)),
+            prefix: String::new(),
+        });
+        Self {
+            fanout,
+            nodes,
+            split_policy: SplitPolicy::default(),
+            insert_pattern: PatternDetector::default(),
+            key_order: KeyOrder::default(),
+            root,
+            first_leaf: root,
+            last_leaf: root,
+            len: 0,
+            pager: None,
+            wal: None,
+            merge_operator: None,
+            changes: ChangeFeed::default(),
+        }
+    }
+
+    /// 通过 [`BPTreeBuilder`] 配置 order、分裂方式等选项后创建一棵空树
+    pub fn builder() -> BPTreeBuilder {
+        BPTreeBuilder::new()
+    }
+
+    /// 从按 key 升序排列的键值对自底向上构建一棵树
+    ///
+    /// 叶子节点依次填满, 再逐层构建内部节点, 比逐个 [`put`](Self::put) 快得多, 叶子节点也更满;
+    /// 每一层的最后几个节点会平均分配元素, 保证都不少于下限
+    ///
+    /// 相同的 key 保留最后一个值, 输入没有排好序时会先排序
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let tree = BPTree::bulk_load(4, (0..100).map(|i| (format!("{:03}", i), i.to_string())));
+    /// assert_eq!(tree.len(), 100);
+    /// assert_eq!(tree.get("042").map(|kv| kv.value()), Some("42"));
+    /// ```
+    pub fn bulk_load<I: IntoIterator<Item = (String, String)>>(order: usize, iter: I) -> Self {
+        Self::bulk_load_with(Fanout::uniform(order), iter)
+    }
+
+    pub(crate) fn bulk_load_with<I: IntoIterator<Item = (String, String)>>(fanout: Fanout, iter: I) -> Self {
+        let mut tree = Self::with_fanout(fanout);
+        let mut kvs: Vec<BPTreeKeyValue> = iter.into_iter().map(|(key, value)| BPTreeKeyValue { key, value }).collect();
+        Self::sort_dedup(&mut kvs, &KeyOrder::BYTES);
+        if kvs.is_empty() {
+            return tree;
+        }
+        tree.len = kvs.len();
+        tree.nodes = NodeSlab::new();
+
+        // 叶子层, 每个节点最多 order - 1 个元素, 同时记录每个节点中最小的 key 与子树中键值对的数量
+        let mut level: Vec<(NodeId, String, usize)> = vec![];
+        let mut rest = kvs.into_iter();
+        for size in Self::chunk_sizes(tree.len, tree.fanout.leaf - 1) {
+            let mut kvs = Vec::with_capacity(BPTreeNode::capacity(tree.fanout.leaf));
+            kvs.extend(rest.by_ref().take(size));
+            let prev = level.last().map(|(_offset, _, _)| *_offset);
+            let first_key = kvs[0].key.clone();
+            let offset = tree.nodes.alloc_node(BPTreeNode::Leaf { prev, next: None, kvs, prefix: String::new() });
+            if let Some(BPTreeNode::Leaf { next, .. }) = prev.map(|_prev| &mut tree.nodes[_prev]) {
+                *next = Some(offset);
+            }
+            level.push((offset, first_key, size));
+        }
+        tree.first_leaf = level[0].0;
+        tree.last_leaf = level[level.len() - 1].0;
+        tree.root = Self::build_levels(&mut tree.nodes, level, tree.fanout.internal);
+        tree
+    }
+
+    fn build_levels(nodes: &mut NodeSlab, mut level: Vec<(NodeId, String, usize)>, order: usize) -> NodeId {
+        // 内部节点层, 每个节点最多 order 个子节点, 第一个子节点以外的子节点中最小的 key 作为分隔 key
+        // level 中是最下面一层的节点以及其中最小的 key 与子树中键值对的数量, 返回根节点
+        while level.len() > 1 {
+            let mut rest = level.into_iter();
+            let mut upper = vec![];
+            for size in Self::chunk_sizes(rest.len(), order) {
+                let children: Vec<(NodeId, String, usize)> = rest.by_ref().take(size).collect();
+                let mut branches = Branches::with_capacity(BPTreeNode::capacity(order));
+                let (mut child, mut keys, mut counts) = branches.parts_mut();
+                let mut min_key = String::new();
+                for (idx, (child_offset, key, count)) in children.into_iter().enumerate() {
+                    child.push(child_offset);
+                    counts.push(count);
+                    if idx == 0 {
+                        min_key = key;
+                    } else {
+                        keys.push(key);
+                    }
+                }
+                let count = counts.iter().sum();
+                let offset = nodes.alloc_node(BPTreeNode::Internal { branches, prefix: String::new() });
+                upper.push((offset, min_key, count));
+            }
+            level = upper;
+        }
+        level[0].0
+    }
+
+    fn chunk_sizes(count: usize, capacity: usize) -> impl Iterator<Item = usize> {
+        // 将 count 个元素平均分到最少的节点中, 每个节点不超过 capacity 个
+        // 节点数多于一个时, 每个节点至少有 capacity / 2 个, 满足下限
+        let chunks = count.div_ceil(capacity).max(1);
+        (0..chunks).map(move |_idx| count / chunks + usize::from(_idx < count % chunks))
+    }
+
+    /// 在 `path` 创建一棵以页的形式存放在文件中的空树, 已存在的文件会被清空
+    ///
+    /// 每个节点占用一页, `page_size` 需要足够存放 `order - 1` 个键值对, 否则 [`sync`](Self::sync) 时会返回错误;
+    /// 页大小不在 64 字节 (加密时为 128 字节) 到 16 MiB 之间时返回 [`io::ErrorKind::InvalidInput`]. 页中每个节点的 key 的公共前缀只存放一次. 值较大时可以用 [`BPTreeBuilder::create`] 分别指定叶子节点与内部节点的 order
+    ///
+    /// 同时会在旁边创建一个 `.wal` 后缀的预写日志, 之后的每次 [`put`](Self::put) 和 [`remove`](Self::remove)
+    /// 都会先追加到日志中, 所以即使没有调用 [`checkpoint`](Self::checkpoint) 就崩溃了, 修改也不会丢失
+    pub fn create<P: AsRef<Path>>(path: P, order: usize, page_size: usize) -> io::Result<Self> {
+        Self::create_with(path.as_ref(), order, page_size, None)
+    }
+
+    pub(crate) fn create_with(path: &Path, order: usize, page_size: usize, cipher: Option<PageCipher>) -> io::Result<Self> {
+        Self::new(order).attach(path, page_size, cipher)
+    }
+
+    pub(crate) fn attach(mut self, path: &Path, page_size: usize, cipher: Option<PageCipher>) -> io::Result<Self> {
+        // 为内存中的树创建文件与预写日志, 并写入当前的所有节点
+        self.pager = Some(Pager::create_with(path, page_size, cipher.clone())?);
+        self.wal = Some(Wal::create(path, cipher)?);
+        self.sync()?;
+        Ok(self)
+    }
+
+    /// 从 `path` 重新加载一棵由 [`create`](Self::create) 创建的树
+    ///
+    /// 加载完文件中的节点后, 会重放预写日志中上一次 [`checkpoint`](Self::checkpoint) 之后的修改
+    ///
+    /// 文件中的树结构不满足 [`check_invariants`](Self::check_invariants) 时返回 [`io::ErrorKind::InvalidData`];
+    /// 文件格式比 [`FORMAT_VERSION`](crate::FORMAT_VERSION) 新时返回 [`io::ErrorKind::Unsupported`],
+    /// 旧版本的文件会先原地升级到当前版本
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_with(path.as_ref(), None)
+    }
+
+    pub(crate) fn open_with(path: &Path, cipher: Option<PageCipher>) -> io::Result<Self> {
+        let (mut pager, meta) = Pager::open(path, cipher.clone())?;
+        let nodes = (0..meta.node_count)
+            .map(|_offset| pager.read_node(NodeId::new(_offset)))
+            .collect::<io::Result<Vec<_>>>()?;
+        if [meta.root, meta.first_leaf, meta.last_leaf].iter().any(|_offset| _offset.index() >= nodes.len()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupted meta page"));
+        }
+        // 文件中没有记录键值对的数量, 加载时统计一次, 被释放的节点是空的叶子节点, 不影响结果
+        let len = nodes.iter().map(|_node| match _node {
+            BPTreeNode::Leaf { kvs, .. } => kvs.len(),
+            BPTreeNode::Internal { .. } => 0,
+        }).sum();
+        let mut tree = Self {
+            fanout: meta.fanout,
+            nodes: NodeSlab::from_nodes(nodes, meta.root),
+            split_policy: SplitPolicy::default(),
+            insert_pattern: PatternDetector::default(),
+            key_order: KeyOrder::default(),
+            root: meta.root,
+            first_leaf: meta.first_leaf,
+            last_leaf: meta.last_leaf,
+            len,
+            pager: Some(pager),
+            wal: None,
+            merge_operator: None,
+            changes: ChangeFeed::default(),
+        };
+        // 先检查文件中的树结构, 之后的操作都可以直接按偏移量访问节点
+        tree.check_invariants().map_err(|_error| io::Error::new(io::ErrorKind::InvalidData, _error))?;
+
+        // 重放日志, 日志中的修改可能已经有一部分写入了文件, 但按顺序重放的结果是一样的
+        let (wal, records) = Wal::open(path, cipher)?;
+        for record in records {
+            tree.replay(record)?;
+        }
+        tree.wal = Some(wal);
+        Ok(tree)
+    }
+
+    fn replay(&mut self, record: Record) -> Result<(), BPTreeError> {
+        match record {
+            Record::Put { key, value } => {
+                self.put_entry(key, value)?;
+            }
+            Record::Remove { key } => {
+                self.remove_entry(key.as_bytes())?;
+            }
+            Record::Batch(records) => {
+                for record in records {
+                    self.replay(record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 将所有节点写回关联的文件, 内存中的树什么也不做
+    ///
+    /// 节点写入旁边的新文件, 刷新到磁盘后再替换原来的文件与溢出文件, 写到一半时崩溃原来的文件仍然完整
+    pub fn sync(&mut self) -> io::Result<()> {
+        let Some(pager) = &mut self.pager else { return Ok(()); };
+        let meta = Meta {
+            page_size: pager.page_size(),
+            fanout: self.fanout,
+            root: self.root,
+            first_leaf: self.first_leaf,
+            last_leaf: self.last_leaf,
+            node_count: self.nodes.len(),
+        };
+        pager.rewrite(self.nodes.len(), |_shadow| {
+            for (offset, node) in self.nodes.iter() {
+                _shadow.write_node(offset, node)?;
+            }
+            _shadow.write_meta(&meta)
+        })
+    }
+
+    /// 检查点: 将所有节点写回文件后清空预写日志, 内存中的树什么也不做
+    ///
+    /// 日志在新的文件替换旧文件之后才清空, 两者之间崩溃时, 打开时在新的文件上重放日志, 按顺序重放的结果是一样的
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.sync()?;
+        match &mut self.wal {
+            Some(wal) => wal.truncate(),
+            None => Ok(()),
+        }
+    }
+
+    /// 键值对的数量
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 树中没有键值对
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 从根节点向下遍历整棵树, 统计高度、节点数量与叶子节点的填充率
+    pub fn stats(&self) -> BPTreeStats {
+        let mut stats = BPTreeStats { len: self.len, insert_pattern: self.insert_pattern.pattern(), ..BPTreeStats::default() };
+        let mut level = vec![self.root];
+        while !level.is_empty() {
+            stats.height += 1;
+            let mut next_level = vec![];
+            for offset in level {
+                stats.byte_size += self.nodes[offset].byte_size();
+                match &self.nodes[offset] {
+                    BPTreeNode::Internal { branches, .. } => {
+                        stats.internal_count += 1;
+                        next_level.extend_from_slice(branches.child());
+                    }
+                    BPTreeNode::Leaf { .. } => stats.leaf_count += 1,
+                }
+            }
+            level = next_level;
+        }
+        // 每个叶子节点最多存放 leaf_order - 1 个键值对
+        stats.fill_factor = self.len as f64 / (stats.leaf_count * (self.fanout.leaf - 1)) as f64;
+        stats
+    }
+
+    /// 估计树占用的内存, 按节点结构、key、值与未使用的容量分别统计, 见 [`MemoryStats`]
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let tree: BPTree = (0..100).map(|i| (format!("{:03}", i), "x".repeat(10))).collect();
+    /// let usage = tree.memory_usage();
+    /// // 叶子节点中的 key 与内部节点中的分隔 key
+    /// assert!(usage.keys > 300);
+    /// assert_eq!(usage.values, 1000);
+    /// assert!(usage.total() >= usage.internal + usage.leaf);
+    /// ```
+    pub fn memory_usage(&self) -> MemoryStats {
+        // 每个节点单独放在一个 Arc 中, 引用计数与节点放在一起, slab 中保存指向它的指针
+        let node_size = size_of::<Arc<BPTreeNode>>() + 2 * size_of::<usize>() + size_of::<BPTreeNode>();
+        let slack = |_len: usize, _capacity: usize, _size: usize| (_capacity - _len) * _size;
+        let mut usage = MemoryStats::default();
+        let mut reachable = 0;
+        let mut stack = vec![self.root];
+        while let Some(offset) = stack.pop() {
+            reachable += 1;
+            let mut node = MemoryStats { headers: node_size, ..MemoryStats::default() };
+            match &self.nodes[offset] {
+                BPTreeNode::Internal { branches, prefix } => {
+                    let (child, keys, counts) = branches.parts();
+                    node.keys += prefix.len();
+                    node.slack += slack(prefix.len(), prefix.capacity(), 1);
+                    node.headers += size_of_val(child) + size_of_val(keys) + size_of_val(counts);
+                    node.slack += slack(child.len(), branches.capacity(), size_of::<NodeId>())
+                        + slack(keys.len(), branches.capacity(), size_of::<String>())
+                        + slack(counts.len(), branches.capacity(), size_of::<usize>());
+                    for key in keys {
+                        node.keys += key.len();
+                        node.slack += slack(key.len(), key.capacity(), 1);
+                    }
+                    stack.extend_from_slice(child);
+                }
+                BPTreeNode::Leaf { kvs, prefix, .. } => {
+                    node.keys += prefix.len();
+                    node.slack += slack(prefix.len(), prefix.capacity(), 1);
+                    node.headers += kvs.len() * size_of::<BPTreeKeyValue>();
+                    node.slack += slack(kvs.len(), kvs.capacity(), size_of::<BPTreeKeyValue>());
+                    for kv in kvs {
+                        node.keys += kv.key.len();
+                        node.values += kv.value.len();
+                        node.slack += slack(kv.key.len(), kv.key.capacity(), 1) + slack(kv.value.len(), kv.value.capacity(), 1);
+                    }
+                }
+            }
+            usage.headers += node.headers;
+            usage.keys += node.keys;
+            usage.values += node.values;
+            usage.slack += node.slack;
+            match &self.nodes[offset] {
+                BPTreeNode::Internal { .. } => usage.internal += node.total(),
+                BPTreeNode::Leaf { .. } => usage.leaf += node.total(),
+            }
+        }
+        // 空闲的槽中是不被引用的空叶子节点, 以及 slab 中还没有使用的指针
+        usage.slack += (self.nodes.len() - reachable) * node_size
+            + slack(self.nodes.len(), self.nodes.capacity(), size_of::<Arc<BPTreeNode>>());
+        usage
+    }
+
+    /// 整理节点, 去掉所有被释放的槽, 可以从根节点到达的节点按深度优先的顺序重新编号
+    ///
+    /// 删除后释放的槽会被之后的插入复用, 大量删除之后不再插入时可以用它回收内存;
+    /// 整理后所有节点的编号都可能改变. 关联了文件的树在下一次 [`sync`](Self::sync)
+    /// 或 [`checkpoint`](Self::checkpoint) 时文件才会随之缩小
+    pub fn compact(&mut self) {
+        if self.nodes.free_len() == 0 {
+            return;
+        }
+        // 旧编号 -> 新编号
+        let mut ids: Vec<Option<NodeId>> = vec![None; self.nodes.len()];
+        let mut order = vec![];
+        let mut stack = vec![self.root];
+        while let Some(offset) = stack.pop() {
+            ids[offset.index()] = Some(NodeId::new(order.len()));
+            order.push(offset);
+            if let BPTreeNode::Internal { branches, .. } = &self.nodes[offset] {
+                stack.extend(branches.child().iter().rev());
+            }
+        }
+
+        let map = |_offset: NodeId| ids[_offset.index()].expect("reachable nodes only refer to reachable nodes");
+        let mut slots = self.nodes.slots_mut();
+        let mut nodes = Vec::with_capacity(order.len());
+        for offset in order {
+            let mut node = Arc::unwrap_or_clone(std::mem::replace(slots[offset.index()], Arc::new(slab::empty_leaf())));
+            match &mut node {
+                BPTreeNode::Internal { branches, .. } => {
+                    branches.parts_mut().0.iter_mut().for_each(|_child| *_child = map(*_child));
+                }
+                BPTreeNode::Leaf { prev, next, .. } => {
+                    *prev = prev.map(map);
+                    *next = next.map(map);
+                }
+            }
+            nodes.push(node);
+        }
+        self.root = map(self.root);
+        self.first_leaf = map(self.first_leaf);
+        self.last_leaf = map(self.last_leaf);
+        let compressed = self.nodes.is_compressed();
+        self.nodes = NodeSlab::from_nodes(nodes, self.root);
+        self.nodes.set_compressed(compressed);
+    }
+
+    /// 去掉节点的 slab 与每个节点中未使用的容量, 适合在批量插入或删除之后、不再大量写入时调用
+    ///
+    /// 与 [`snapshot`](Self::snapshot) 或克隆共享的节点不会被修改, 避免为了缩小而复制一份.
+    /// 之后插入到被缩小的节点时需要重新分配; 被释放的槽不会被去掉, 需要时先调用 [`compact`](Self::compact)
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::with_capacity(8, 10_000);
+    /// for i in 0..1000 {
+    ///     tree.put(format!("{:04}", i), i.to_string()).unwrap();
+    /// }
+    /// let before = tree.memory_usage();
+    /// tree.shrink_to_fit();
+    /// let after = tree.memory_usage();
+    /// assert!(after.slack < before.slack);
+    /// assert_eq!(after.keys, before.keys);
+    /// assert_eq!(tree.get("0042").map(|_kv| _kv.value()), Some("42"));
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+
+    /// 创建一个只读快照, 与树共享所有节点, 见 [`BPTreeSnapshot`]
+    ///
+    /// 只复制槽表的根指针, 耗时与节点数量无关; 快照不关联文件与预写日志.
+    /// 节点的哈希缓存不被共享, 在快照上第一次调用 [`root_hash`](Self::root_hash) 时重新计算所有节点
+    pub fn snapshot(&self) -> BPTreeSnapshot {
+        BPTreeSnapshot::new(self.clone())
+    }
+
+    /// 内部节点的最大路数, 与叶子节点不同时见 [`leaf_order`](Self::leaf_order)
+    pub fn order(&self) -> usize {
+        self.fanout.internal
+    }
+
+    /// 叶子节点的最大路数, 每个叶子节点最多存放 `leaf_order - 1` 个键值对
+    ///
+    /// 没有通过 [`BPTreeBuilder::leaf_order`] 单独指定时与 [`order`](Self::order) 相同
+    pub fn leaf_order(&self) -> usize {
+        self.fanout.leaf
+    }
+
+    /// 叶子节点分裂时的分配方式
+    pub fn split_policy(&self) -> SplitPolicy {
+        self.split_policy
+    }
+
+    /// 设置叶子节点分裂时的分配方式, 只影响之后的分裂
+    ///
+    /// 分裂方式不会保存在文件中, 打开文件或反序列化得到的树使用默认的 [`SplitPolicy::Adaptive`]
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.split_policy = policy;
+    }
+
+    /// 注册 [`merge`](Self::merge) 使用的合并函数, 见 [`MergeOperator`]
+    ///
+    /// 与比较器一样不会保存在文件中, 打开文件之后需要重新注册
+    pub fn set_merge_operator<M: MergeOperator + 'static>(&mut self, merge_operator: M) {
+        self.merge_operator = Some(Merger::new(merge_operator));
+    }
+
+    /// 存放所有节点的 slab, 可以用 [`NodeId`] 索引
+    pub fn nodes(&self) -> &NodeSlab {
+        &self.nodes
+    }
+
+    /// 根节点的偏移量
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// 第一个 (最小的) 叶子节点的偏移量
+    pub fn first_leaf(&self) -> NodeId {
+        self.first_leaf
+    }
+
+    /// 最后一个 (最大的) 叶子节点的偏移量
+    pub fn last_leaf(&self) -> NodeId {
+        self.last_leaf
+    }
+
+    /// 插入键值对, key 已存在时更新其值并返回旧值
+    ///
+    /// key 大于树中所有的 key 时直接追加到最后一个叶子节点, 不需要从根节点查找,
+    /// 按递增顺序写入 (例如以时间戳为 key) 时都会走这条路径
+    ///
+    /// 关联了文件的树写预写日志失败时返回 [`BPTreeError::Io`], 树不会被修改
+    pub fn put(&mut self, key: String, value: String) -> Result<Option<String>, BPTreeError> {
+        self.log_put(&key, &value)?;
+        self.put_entry(key, value)
+    }
+
+    /// 开始一个事务, 修改先缓存在事务中, 提交时一次应用到树上, 见 [`Transaction`]
+    pub fn begin(&mut self) -> Transaction<'_> {
+        Transaction::new(self)
+    }
+
+    pub(crate) fn log_put(&mut self, key: &str, value: &str) -> Result<(), BPTreeError> {
+        if let Some(wal) = &mut self.wal {
+            wal.append_put(key, value)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn put_entry(&mut self, key: String, value: String) -> Result<Option<String>, BPTreeError> {
+        // 没有订阅时不复制键值对
+        let change = self.changes.is_active().then(|| (key.clone(), value.clone()));
+        let (edges, policy) = (self.key_edges(&key), self.effective_split_policy());
+        let kv = BPTreeKeyValue { key, value };
+        let old_value = Self::insert(&mut self.nodes, &mut self.root, &mut self.last_leaf, self.fanout, policy, &self.key_order, kv)?;
+        if old_value.is_none() {
+            self.len += 1;
+            self.insert_pattern.record(edges.0, edges.1);
+        }
+        if let Some((key, value)) = change {
+            self.changes.send(key, old_value.clone(), Some(value));
+        }
+        Ok(old_value)
+    }
+
+    /// `key` 是否大于最后一个叶子节点中所有的 key, 以及是否小于第一个叶子节点中所有的 key
+    ///
+    /// 前者就是 [`insert`](Self::insert) 能否走追加的快速路径, 两者的命中率用来识别插入模式
+    fn key_edges(&self, key: &str) -> (bool, bool) {
+        let BPTreeNode::Leaf { kvs: last, prefix: last_prefix, .. } = &self.nodes[self.last_leaf] else { return (false, false) };
+        let BPTreeNode::Leaf { kvs: first, prefix: first_prefix, .. } = &self.nodes[self.first_leaf] else { return (false, false) };
+        let append = last.last().is_some_and(|_kv| self.key_order.cmp_in(last_prefix, _kv.key.as_bytes(), key.as_bytes()).is_lt());
+        let prepend = first.first().is_some_and(|_kv| self.key_order.cmp_in(first_prefix, _kv.key.as_bytes(), key.as_bytes()).is_gt());
+        (append, prepend)
+    }
+
+    /// 分裂叶子节点时实际使用的分配方式, [`SplitPolicy::Adaptive`] 按识别出的插入模式确定
+    pub(crate) fn effective_split_policy(&self) -> SplitPolicy {
+        self.split_policy.resolve(self.insert_pattern.pattern())
+    }
+
+    pub(crate) fn insert<S: NodeStore>(
+        nodes: &mut S,
+        root: &mut NodeId,
+        last_leaf: &mut NodeId,
+        fanout: Fanout,
+        policy: SplitPolicy,
+        key_order: &KeyOrder,
+        kv: BPTreeKeyValue,
+    ) -> Result<Option<String>, BPTreeError> {
+        // 比最大的 key 还大时直接追加到最后一个叶子节点的末尾, 时间序列这样单调递增的 key 都走这条路径
+        if let Some(path) = Self::append_path(nodes, *root, *last_leaf, key_order, &kv.key)? {
+            let leaf_offset = *last_leaf;
+            let BPTreeNode::Leaf { kvs, .. } = nodes.node_mut(leaf_offset)? else {
+                return Err(BPTreeError::corrupted(leaf_offset, "last leaf is an internal node"));
+            };
+            kvs.push(kv);
+            Self::finish_insert(nodes, root, last_leaf, fanout, policy, leaf_offset, path)?;
+            return Ok(None);
+        }
+        // 查找, 同时记录从根节点到叶子节点的路径
+        let (leaf_offset, path) = Self::search_path(nodes, *root, key_order, kv.key.as_bytes())?;
+        let BPTreeNode::Leaf { kvs, .. } = nodes.node_mut(leaf_offset)? else {
+            return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+        };
+        // 先插入, 节点中的元素超出上限后再分裂, 更新已有的 key 时节点大小不变
+        if let Some(old_value) = Self::insert_non_full(kvs, key_order, kv) {
+            return Ok(Some(old_value));
+        }
+        Self::finish_insert(nodes, root, last_leaf, fanout, policy, leaf_offset, path)?;
+        Ok(None)
+    }
+
+    fn append_path<S: NodeStore>(
+        nodes: &mut S,
+        root: NodeId,
+        last_leaf: NodeId,
+        key_order: &KeyOrder,
+        key: &str,
+    ) -> Result<Option<DescentPath>, BPTreeError> {
+        // key 大于最后一个叶子节点中所有的 key 时, 返回到最后一个叶子节点的路径, 否则返回 None
+        // 这条路径总是走向每个内部节点的最后一个子节点, 不需要比较 key
+        let BPTreeNode::Leaf { kvs, prefix, .. } = nodes.node(last_leaf)? else {
+            return Err(BPTreeError::corrupted(last_leaf, "last leaf is an internal node"));
+        };
+        if kvs.last().is_some_and(|_kv| key_order.cmp_in(prefix, _kv.key.as_bytes(), key.as_bytes()).is_ge()) {
+            return Ok(None);
+        }
+        let mut offset = root;
+        let mut path = DescentPath::new();
+        while let BPTreeNode::Internal { branches, .. } = nodes.node(offset)? {
+            let child = branches.child();
+            path.push((offset, child.len() - 1));
+            offset = child[child.len() - 1];
+        }
+        if offset != last_leaf {
+            return Err(BPTreeError::corrupted(offset, "rightmost leaf is not the last leaf"));
+        }
+        Ok(Some(path))
+    }
+
+    /// 叶子节点中新增了一个键值对之后调用: 路径上的子树计数加一, 叶子节点超出上限时分裂
+    pub(crate) fn finish_insert<S: NodeStore>(
+        nodes: &mut S,
+        root: &mut NodeId,
+        last_leaf: &mut NodeId,
+        fanout: Fanout,
+        policy: SplitPolicy,
+        leaf_offset: NodeId,
+        path: DescentPath,
+    ) -> Result<(), BPTreeError> {
+        Self::adjust_counts(nodes, &path, 1)?;
+        Self::split_if_full(nodes, root, last_leaf, fanout, policy, leaf_offset, path)
+    }
+
+    fn split_if_full<S: NodeStore>(
+        nodes: &mut S,
+        root: &mut NodeId,
+        last_leaf: &mut NodeId,
+        fanout: Fanout,
+        policy: SplitPolicy,
+        leaf_offset: NodeId,
+        path: DescentPath,
+    ) -> Result<(), BPTreeError> {
+        // 叶子节点中最多有 2 * (leaf_order - 1) 个元素, 分裂一次即可
+        if nodes.node(leaf_offset)?.len() > fanout.leaf - 1 {
+            if let Some(new_root) = Self::insert_full(nodes, leaf_offset, fanout, policy, path)? {
+                instrument::root_change(*root, new_root);
+                *root = new_root;
+            }
+        }
+        // 最后一个叶子节点分裂时, 分裂出来的右节点成为新的最后一个
+        if let BPTreeNode::Leaf { next: Some(next), .. } = nodes.node(*last_leaf)? {
+            *last_leaf = *next;
+        }
+        Ok(())
+    }
+
+    fn insert_full<S: NodeStore>(
+        nodes: &mut S,
+        old_leaf_offset: NodeId,
+        fanout: Fanout,
+        policy: SplitPolicy,
+        path: DescentPath,
+    ) -> Result<Option<NodeId>, BPTreeError> {
+        // 分裂叶子节点
+        let old_leaf = nodes.node_mut(old_leaf_offset)?;
+        let BPTreeNode::Leaf { prev, next, kvs, .. } = old_leaf else {
+            return Err(BPTreeError::corrupted(old_leaf_offset, "expected a leaf"));
+        };
+        let at = policy.split_point(kvs.len(), fanout.leaf, prev.is_none(), next.is_none());
+        let (key, new_leaf) = old_leaf.split(at, fanout.leaf);
+        let new_leaf_offset = nodes.alloc_node(new_leaf)?;
+        Self::link_leaf(nodes, old_leaf_offset, new_leaf_offset)?;
+
+        // 循环处理父节点
+        Self::split_nodes(nodes, path, old_leaf_offset, new_leaf_offset, key, fanout.internal)
+    }
+
+    fn link_leaf<S: NodeStore>(nodes: &mut S, leaf_offset: NodeId, new_leaf_offset: NodeId) -> Result<(), BPTreeError> {
+        // 分裂出来的叶子节点插入到链表中原叶子节点的后面
+        let BPTreeNode::Leaf { next, .. } = nodes.header_mut(leaf_offset)? else {
+            return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+        };
+        let old_next = next.replace(new_leaf_offset);
+        if let BPTreeNode::Leaf { prev, next, .. } = nodes.header_mut(new_leaf_offset)? {
+            *prev = Some(leaf_offset);
+            *next = old_next;
+        }
+        if let Some(old_next) = old_next {
+            let BPTreeNode::Leaf { prev, .. } = nodes.header_mut(old_next)? else {
+                return Err(BPTreeError::corrupted(old_next, "leaf chain points to an internal node"));
+            };
+            *prev = Some(new_leaf_offset);
+        }
+        Ok(())
+    }
+
+    fn split_nodes<S: NodeStore>(
+        nodes: &mut S,
+        mut path: DescentPath,
+        left_offset: NodeId,
+        right_offset: NodeId,
+        right_key: String,
+        order: usize,
+    ) -> Result<Option<NodeId>, BPTreeError> {
+        // 子节点分裂后会传上来右节点的 key 和 索引, 沿着查找时记录的路径将其插入父节点
+        // 如果父节点也超出上限, 则继续分裂父节点, 直到不再需要分裂为止
+        // 返回值为新的根节点 (如果根节点发生了变化)
+        let mut left_offset = left_offset;
+        let mut right_offset = right_offset;
+        let mut right_key = right_key;
+        loop {
+            instrument::split(left_offset, right_offset, path.len());
+            let right_count = nodes.node(right_offset)?.count();
+            let Some((parent_offset, idx)) = path.pop() else {
+                // 路径已经走完, 说明分裂的是根节点, 新建一个根节点
+                let left_count = nodes.node(left_offset)?.count();
+                let mut branches = Branches::with_capacity(BPTreeNode::capacity(order));
+                let (mut child, mut keys, mut counts) = branches.parts_mut();
+                child.extend([left_offset, right_offset]);
+                keys.push(right_key);
+                counts.extend([left_count, right_count]);
+                let new_root_offset = nodes.alloc_node(BPTreeNode::Internal { branches, prefix: String::new() })?;
+                return Ok(Some(new_root_offset));
+            };
+
+            let parent_node = nodes.node_mut(parent_offset)?;
+            if !parent_node.push_data(idx, right_offset, right_key, right_count) {
+                return Err(BPTreeError::corrupted(parent_offset, "cannot insert separator key into parent"));
+            }
+
+            // 节点元素未超出上限, 分裂完毕
+            if parent_node.len() < order {
+                return Ok(None);
+            }
+
+            // 分裂父节点, 中间的 key 继续扔给上一层
+            let (center_key, new_node) = parent_node.split(parent_node.len() / 2, order);
+            let new_node_offset = nodes.alloc_node(new_node)?;
+
+            left_offset = parent_offset;
+            right_offset = new_node_offset;
+            right_key = center_key;
+        }
+    }
+
+    fn insert_non_full(kvs: &mut Vec<BPTreeKeyValue>, key_order: &KeyOrder, kv: BPTreeKeyValue) -> Option<String> {
+        match key_order.search(kvs, |_kv| _kv.key.as_bytes(), kv.key.as_bytes()) {
+            Ok(idx) => {
+                // 已存在则更新, 返回旧值
+                Some(std::mem::replace(&mut kvs[idx].value, kv.value))
+            }
+            Err(idx) => {
+                // 不存在则插入
+                kvs.insert(idx, kv);
+                None
+            }
+        }
+    }
+
+    /// 批量插入键值对, 返回新插入的 key 的数量
+    ///
+    /// 先将这一批键值对排序, 每次从根节点查找一个叶子节点后, 把所有落在该叶子节点范围内的键值对一起插入,
+    /// 叶子节点最多积累到两倍上限再分裂一次, 顺序写入大量 key 时比逐个 [`put`](Self::put) 少很多次查找与分裂
+    ///
+    /// 相同的 key 保留最后一个值, 关联了文件的树会先将所有键值对写入预写日志
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(4);
+    /// tree.put("050".to_string(), "old".to_string()).unwrap();
+    /// let inserted = tree.put_batch((0..100).map(|i| (format!("{:03}", i), i.to_string()))).unwrap();
+    /// assert_eq!(inserted, 99);
+    /// assert_eq!(tree.get("050").map(|kv| kv.value()), Some("50"));
+    /// ```
+    pub fn put_batch<I: IntoIterator<Item = (String, String)>>(&mut self, entries: I) -> Result<usize, BPTreeError> {
+        let mut kvs: Vec<BPTreeKeyValue> = entries.into_iter().map(|(key, value)| BPTreeKeyValue { key, value }).collect();
+        Self::sort_dedup(&mut kvs, &self.key_order);
+        if self.wal.is_some() {
+            for kv in &kvs {
+                self.log_put(&kv.key, &kv.value)?;
+            }
+        }
+        if self.changes.is_active() {
+            // 有订阅时需要每个 key 原来的值, 逐个插入
+            let mut inserted = 0;
+            for kv in kvs {
+                inserted += usize::from(self.put_entry(kv.key, kv.value)?.is_none());
+            }
+            return Ok(inserted);
+        }
+        let policy = self.effective_split_policy();
+        let inserted = Self::insert_batch(&mut self.nodes, &mut self.root, &mut self.last_leaf, self.fanout, policy, &self.key_order, kvs)?;
+        self.len += inserted;
+        Ok(inserted)
+    }
+
+    pub(crate) fn insert_batch<S: NodeStore>(
+        nodes: &mut S,
+        root: &mut NodeId,
+        last_leaf: &mut NodeId,
+        fanout: Fanout,
+        policy: SplitPolicy,
+        key_order: &KeyOrder,
+        kvs: Vec<BPTreeKeyValue>,
+    ) -> Result<usize, BPTreeError> {
+        // kvs 已经按 key 排好序且没有重复
+        let mut kvs = kvs.into_iter().peekable();
+        let mut inserted = 0;
+        while let Some(kv) = kvs.peek() {
+            let (leaf_offset, path) = Self::search_path(nodes, *root, key_order, kv.key.as_bytes())?;
+            // 路径上最靠下的右侧分隔 key 是该叶子节点范围的上界, 不小于它的 key 属于后面的叶子节点
+            let mut upper = None;
+            for &(offset, idx) in path.iter().rev() {
+                let BPTreeNode::Internal { branches, .. } = nodes.node(offset)? else {
+                    return Err(BPTreeError::corrupted(offset, "expected an internal node"));
+                };
+                if let Some(key) = branches.keys().get(idx) {
+                    upper = Some(key.clone());
+                    break;
+                }
+            }
+            let BPTreeNode::Leaf { kvs: leaf_kvs, .. } = nodes.node_mut(leaf_offset)? else {
+                return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+            };
+            // 最多积累 2 * (leaf_order - 1) 个元素, 分裂一次后两边都不超出上限
+            let mut leaf_inserted = 0;
+            while leaf_kvs.len() < 2 * (fanout.leaf - 1) {
+                let below_upper = |_kv: &BPTreeKeyValue| {
+                    upper.as_ref().is_none_or(|_upper: &String| key_order.cmp(_kv.key.as_bytes(), _upper.as_bytes()).is_lt())
+                };
+                let Some(kv) = kvs.next_if(below_upper) else { break; };
+                if Self::insert_non_full(leaf_kvs, key_order, kv).is_none() {
+                    leaf_inserted += 1;
+                }
+            }
+            Self::adjust_counts(nodes, &path, leaf_inserted as isize)?;
+            Self::split_if_full(nodes, root, last_leaf, fanout, policy, leaf_offset, path)?;
+            inserted += leaf_inserted;
+        }
+        Ok(inserted)
+    }
+
+    pub(crate) fn sort_dedup(kvs: &mut Vec<BPTreeKeyValue>, key_order: &KeyOrder) {
+        let cmp = |_a: &BPTreeKeyValue, _b: &BPTreeKeyValue| key_order.cmp(_a.key.as_bytes(), _b.key.as_bytes());
+        if !kvs.windows(2).all(|_w| cmp(&_w[0], &_w[1]).is_le()) {
+            // 稳定排序, 相同 key 的先后顺序不变
+            kvs.sort_by(cmp);
+        }
+        // 相同的 key 保留最后一个
+        kvs.reverse();
+        kvs.dedup_by(|_a, _b| cmp(_a, _b).is_eq());
+        kvs.reverse();
+    }
+
+    /// 删除 key, 返回被删除的值
+    ///
+    /// 删除后节点中的元素少于下限时, 会先尝试向相邻的兄弟节点借元素, 借不到则与兄弟节点合并,
+    /// 合并可能一直传递到根节点, 根节点只剩一个子节点时树的高度减一
+    ///
+    /// 关联了文件的树写预写日志失败时返回 [`BPTreeError::Io`], 树不会被修改
+    pub fn remove<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        let key = key.as_ref();
+        if self.wal.is_some() && self.get(key).is_some() {
+            // 能找到说明与树中的某个 key 相同, 一定是合法的 UTF-8
+            if let (Some(wal), Ok(key)) = (&mut self.wal, std::str::from_utf8(key)) {
+                wal.append_remove(key)?;
+            }
+        }
+        self.remove_entry(key)
+    }
+
+    pub(crate) fn remove_entry(&mut self, key: &[u8]) -> Result<Option<String>, BPTreeError> {
+        let value = Self::delete(&mut self.nodes, &mut self.root, &mut self.last_leaf, self.fanout, &self.key_order, key)?;
+        if let Some(value) = &value {
+            self.len -= 1;
+            // 删除成功说明 key 与树中的某个 key 相同, 一定是合法的 UTF-8
+            if let (true, Ok(key)) = (self.changes.is_active(), std::str::from_utf8(key)) {
+                self.changes.send(key.to_string(), Some(value.clone()), None);
+            }
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn delete<S: NodeStore>(
+        nodes: &mut S,
+        root: &mut NodeId,
+        last_leaf: &mut NodeId,
+        fanout: Fanout,
+        key_order: &KeyOrder,
+        key: &[u8],
+    ) -> Result<Option<String>, BPTreeError> {
+        let (leaf_offset, path) = Self::search_path(nodes, *root, key_order, key)?;
+        let BPTreeNode::Leaf { kvs, .. } = nodes.node_mut(leaf_offset)? else {
+            return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+        };
+        let Ok(idx) = key_order.search(kvs, |_kv| _kv.key.as_bytes(), key) else { return Ok(None); };
+        let kv = kvs.remove(idx);
+        Self::adjust_counts(nodes, &path, -1)?;
+
+        let BPTreeNode::Leaf { prev: last_prev, .. } = *nodes.node(*last_leaf)? else {
+            return Err(BPTreeError::corrupted(*last_leaf, "last leaf is an internal node"));
+        };
+        if let Some(new_root) = Self::rebalance(nodes, path, leaf_offset, fanout)? {
+            instrument::root_change(*root, new_root);
+            *root = new_root;
+        }
+        // 最后一个叶子节点被合并进前一个叶子节点时会被释放 (prev 被清空), 前一个叶子节点成为新的最后一个
+        if let (Some(last_prev), BPTreeNode::Leaf { prev: None, .. }) = (last_prev, nodes.node(*last_leaf)?) {
+            *last_leaf = last_prev;
+        }
+        Ok(Some(kv.value))
+    }
+
+    fn adjust_counts<S: NodeStore>(nodes: &mut S, path: &DescentPath, delta: isize) -> Result<(), BPTreeError> {
+        // 插入或删除键值对后, 路径上每个内部节点中对应子树的计数随之增减
+        for &(offset, idx) in path {
+            let BPTreeNode::Internal { branches, .. } = nodes.header_mut(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "expected an internal node"));
+            };
+            let (_, _, mut counts) = branches.parts_mut();
+            counts[idx] = counts[idx]
+                .checked_add_signed(delta)
+                .ok_or(BPTreeError::corrupted(offset, "subtree count out of range"))?;
+        }
+        Ok(())
+    }
+
+    fn min_len(order: usize) -> usize {
+        // 非根节点最少存放 (order / 2) 向上取整后 -1 个元素
+        order.div_ceil(2) - 1
+    }
+
+    fn rebalance<S: NodeStore>(
+        nodes: &mut S,
+        mut path: DescentPath,
+        offset: NodeId,
+        fanout: Fanout,
+    ) -> Result<Option<NodeId>, BPTreeError> {
+        // 从删除了元素的节点开始沿着路径向上处理, 返回值为新的根节点 (如果根节点发生了变化)
+        // 叶子节点与内部节点的下限可能不同, 每一层按当前节点计算
+        let mut offset = offset;
+        loop {
+            let Some((parent_offset, idx)) = path.pop() else {
+                // 根节点没有下限, 但内部节点只剩一个子节点时, 将这个子节点作为新的根节点
+                let BPTreeNode::Internal { branches, .. } = nodes.node(offset)? else { return Ok(None); };
+                if !branches.keys().is_empty() {
+                    return Ok(None);
+                }
+                let new_root_offset = branches.child()[0];
+                nodes.free_node(offset)?;
+                return Ok(Some(new_root_offset));
+            };
+
+            let min_len = Self::min_len(fanout.of(nodes.node(offset)?));
+            if nodes.node(offset)?.len() >= min_len {
+                return Ok(None);
+            }
+
+            // 找到左右兄弟节点
+            let BPTreeNode::Internal { branches, .. } = nodes.node(parent_offset)? else {
+                return Err(BPTreeError::corrupted(parent_offset, "parent is a leaf"));
+            };
+            let child = branches.child();
+            if child.get(idx) != Some(&offset) {
+                return Err(BPTreeError::corrupted(parent_offset, "child is missing from its parent"));
+            }
+            let left_offset = idx.checked_sub(1).map(|_i| child[_i]);
+            let right_offset = child.get(idx + 1).copied();
+
+            // 兄弟节点有多余的元素则借过来, 借到满足下限即可结束
+            // 删除单个 key 时只差一个元素, 摘除一段范围后可能差很多个
+            if let Some(left_offset) = left_offset {
+                while nodes.node(offset)?.len() < min_len && nodes.node(left_offset)?.len() > min_len {
+                    Self::borrow_from_left(nodes, parent_offset, idx, left_offset, offset)?;
+                }
+            }
+            if let Some(right_offset) = right_offset {
+                while nodes.node(offset)?.len() < min_len && nodes.node(right_offset)?.len() > min_len {
+                    Self::borrow_from_right(nodes, parent_offset, idx, offset, right_offset)?;
+                }
+            }
+            if nodes.node(offset)?.len() >= min_len {
+                return Ok(None);
+            }
+
+            // 否则与兄弟节点合并, 父节点少了一个元素, 继续处理父节点
+            let depth = path.len() + 1;
+            if let Some(left_offset) = left_offset {
+                instrument::merge(left_offset, offset, depth);
+                Self::merge_nodes(nodes, parent_offset, idx - 1, left_offset, offset)?;
+            } else if let Some(right_offset) = right_offset {
+                instrument::merge(offset, right_offset, depth);
+                Self::merge_nodes(nodes, parent_offset, idx, offset, right_offset)?;
+            } else {
+                return Err(BPTreeError::corrupted(parent_offset, "non-root internal node has a single child"));
+            }
+            offset = parent_offset;
+        }
+    }
+
+    fn borrow_from_left<S: NodeStore>(
+        nodes: &mut S,
+        parent_offset: NodeId,
+        idx: usize,
+        left_offset: NodeId,
+        offset: NodeId,
+    ) -> Result<(), BPTreeError> {
+        // 左兄弟的最后一个元素移动到当前节点的开头, 父节点中两者之间的 key 与两者的子树计数随之更新
+        let separator = match nodes.node_mut(left_offset)? {
+            BPTreeNode::Leaf { kvs, .. } => {
+                let Some(kv) = kvs.pop() else {
+                    return Err(BPTreeError::corrupted(left_offset, "cannot borrow from an empty leaf"));
+                };
+                let separator = kv.key.clone();
+                let BPTreeNode::Leaf { kvs, .. } = nodes.node_mut(offset)? else {
+                    return Err(BPTreeError::corrupted(offset, "sibling of a leaf is an internal node"));
+                };
+                kvs.insert(0, kv);
+                separator
+            }
+            BPTreeNode::Internal { branches, .. } => {
+                // 内部节点需要经过父节点轮换 key, 移动的子节点带走它的整个子树
+                let (mut child, mut keys, mut counts) = branches.parts_mut();
+                let (Some(key), Some(moved_child), Some(moved_count)) = (keys.pop(), child.pop(), counts.pop()) else {
+                    return Err(BPTreeError::corrupted(left_offset, "cannot borrow from an empty internal node"));
+                };
+                let BPTreeNode::Internal { branches, .. } = nodes.node_mut(parent_offset)? else {
+                    return Err(BPTreeError::corrupted(parent_offset, "parent is a leaf"));
+                };
+                let (_, mut parent_keys, mut parent_counts) = branches.parts_mut();
+                let separator = std::mem::replace(&mut parent_keys[idx - 1], key);
+                parent_counts[idx - 1] -= moved_count;
+                parent_counts[idx] += moved_count;
+                let BPTreeNode::Internal { branches, .. } = nodes.node_mut(offset)? else {
+                    return Err(BPTreeError::corrupted(offset, "sibling of an internal node is a leaf"));
+                };
+                branches.reserve(1);
+                let (mut child, mut keys, mut counts) = branches.parts_mut();
+                keys.insert(0, separator);
+                child.insert(0, moved_child);
+                counts.insert(0, moved_count);
+                return Ok(());
+            }
+        };
+        let BPTreeNode::Internal { branches, .. } = nodes.node_mut(parent_offset)? else {
+            return Err(BPTreeError::corrupted(parent_offset, "parent is a leaf"));
+        };
+        let (_, mut keys, mut counts) = branches.parts_mut();
+        keys[idx - 1] = separator;
+        counts[idx - 1] -= 1;
+        counts[idx] += 1;
+        Ok(())
+    }
+
+    fn borrow_from_right<S: NodeStore>(
+        nodes: &mut S,
+        parent_offset: NodeId,
+        idx: usize,
+        offset: NodeId,
+        right_offset: NodeId,
+    ) -> Result<(), BPTreeError> {
+        // 右兄弟的第一个元素移动到当前节点的末尾, 父节点中两者之间的 key 与两者的子树计数随之更新
+        let separator = match nodes.node_mut(right_offset)? {
+            BPTreeNode::Leaf { kvs, .. } => {
+                // 借出后右兄弟至少还剩一个元素, 它的第一个 key 成为新的分隔 key
+                if kvs.len() < 2 {
+                    return Err(BPTreeError::corrupted(right_offset, "cannot borrow from a leaf with one entry"));
+                }
+                let kv = kvs.remove(0);
+                let separator = kvs[0].key.clone();
+                let BPTreeNode::Leaf { kvs, .. } = nodes.node_mut(offset)? else {
+                    return Err(BPTreeError::corrupted(offset, "sibling of a leaf is an internal node"));
+                };
+                kvs.push(kv);
+                separator
+            }
+            BPTreeNode::Internal { branches, .. } => {
+                // 内部节点需要经过父节点轮换 key, 移动的子节点带走它的整个子树
+                let (mut child, mut keys, mut counts) = branches.parts_mut();
+                if keys.is_empty() {
+                    return Err(BPTreeError::corrupted(right_offset, "cannot borrow from an empty internal node"));
+                }
+                let key = keys.remove(0);
+                let moved_child = child.remove(0);
+                let moved_count = counts.remove(0);
+                let BPTreeNode::Internal { branches, .. } = nodes.node_mut(parent_offset)? else {
+                    return Err(BPTreeError::corrupted(parent_offset, "parent is a leaf"));
+                };
+                let (_, mut parent_keys, mut parent_counts) = branches.parts_mut();
+                let separator = std::mem::replace(&mut parent_keys[idx], key);
+                parent_counts[idx + 1] -= moved_count;
+                parent_counts[idx] += moved_count;
+                let BPTreeNode::Internal { branches, .. } = nodes.node_mut(offset)? else {
+                    return Err(BPTreeError::corrupted(offset, "sibling of an internal node is a leaf"));
+                };
+                branches.reserve(1);
+                let (mut child, mut keys, mut counts) = branches.parts_mut();
+                keys.push(separator);
+                child.push(moved_child);
+                counts.push(moved_count);
+                return Ok(());
+            }
+        };
+        let BPTreeNode::Internal { branches, .. } = nodes.node_mut(parent_offset)? else {
+            return Err(BPTreeError::corrupted(parent_offset, "parent is a leaf"));
+        };
+        let (_, mut keys, mut counts) = branches.parts_mut();
+        keys[idx] = separator;
+        counts[idx + 1] -= 1;
+        counts[idx] += 1;
+        Ok(())
+    }
+
+    fn merge_nodes<S: NodeStore>(
+        nodes: &mut S,
+        parent_offset: NodeId,
+        separator_idx: usize,
+        left_offset: NodeId,
+        right_offset: NodeId,
+    ) -> Result<(), BPTreeError> {
+        // 将右节点合并到左节点中, 并从父节点中删除两者之间的 key 以及右节点
+        let BPTreeNode::Internal { branches, .. } = nodes.node_mut(parent_offset)? else {
+            return Err(BPTreeError::corrupted(parent_offset, "parent is a leaf"));
+        };
+        let (mut child, mut keys, mut counts) = branches.parts_mut();
+        let separator = keys.remove(separator_idx);
+        child.remove(separator_idx + 1);
+        let right_count = counts.remove(separator_idx + 1);
+        counts[separator_idx] += right_count;
+
+        let right_node = nodes.free_node(right_offset)?;
+        match (nodes.node_mut(left_offset)?, right_node) {
+            (
+                BPTreeNode::Leaf { next, kvs, .. },
+                BPTreeNode::Leaf { next: right_next, kvs: mut right_kvs, .. },
+            ) => {
+                kvs.append(&mut right_kvs);
+                *next = right_next;
+                if let Some(right_next) = right_next {
+                    let BPTreeNode::Leaf { prev, .. } = nodes.header_mut(right_next)? else {
+                        return Err(BPTreeError::corrupted(right_next, "leaf chain points to an internal node"));
+                    };
+                    *prev = Some(left_offset);
+                }
+            }
+            (BPTreeNode::Internal { branches, .. }, BPTreeNode::Internal { branches: mut right, .. }) => {
+                // 内部节点合并时, 父节点中的 key 需要下移到合并后的节点中
+                branches.reserve(right.child().len());
+                let (mut child, mut keys, mut counts) = branches.parts_mut();
+                let (mut right_child, mut right_keys, mut right_counts) = right.parts_mut();
+                keys.push(separator);
+                keys.append(&mut right_keys);
+                child.append(&mut right_child);
+                counts.append(&mut right_counts);
+            }
+            _ => return Err(BPTreeError::corrupted(left_offset, "cannot merge a leaf with an internal node")),
+        }
+        Ok(())
+    }
+
+    fn bound_path<S: NodeRead>(
+        nodes: &mut S,
+        root_offset: NodeId,
+        key_order: &KeyOrder,
+        bound: Bound<&[u8]>,
+        is_end: bool,
+    ) -> Result<(NodeId, DescentPath, usize), BPTreeError> {
+        // 与 seek 相同, 找到 bound 在叶子节点中对应的位置, 同时记录路径
+        // 没有边界时起点走向最左边的叶子节点, 终点走向最右边的叶子节点
+        let (key, inclusive) = match bound {
+            Bound::Included(key) => (key, true),
+            Bound::Excluded(key) => (key, false),
+            Bound::Unbounded => {
+                let mut offset = root_offset;
+                let mut path = DescentPath::new();
+                while let BPTreeNode::Internal { branches, .. } = nodes.node(offset)? {
+                    let child = branches.child();
+                    let idx = if is_end { child.len() - 1 } else { 0 };
+                    path.push((offset, idx));
+                    offset = child[idx];
+                }
+                let idx = if is_end { nodes.node(offset)?.len() } else { 0 };
+                return Ok((offset, path, idx));
+            }
+        };
+        let (leaf_offset, path) = Self::search_path(nodes, root_offset, key_order, key)?;
+        let BPTreeNode::Leaf { kvs, prefix, .. } = nodes.node(leaf_offset)? else {
+            return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+        };
+        // 起点包含 key 或终点不包含 key 时停在 key 之前, 否则停在 key 之后
+        let idx = key_order.partition_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key, inclusive == is_end);
+        Ok((leaf_offset, path, idx))
+    }
+
+    fn detach_range<S: NodeStore>(
+        nodes: &mut S,
+        (left_leaf, left_path, from): (NodeId, DescentPath, usize),
+        (right_leaf, right_path, to): (NodeId, DescentPath, usize),
+        fanout: Fanout,
+    ) -> Result<Vec<BPTreeKeyValue>, BPTreeError> {
+        // 删除左端位置到右端位置之间的键值对并按 key 的顺序返回, 之后只有两端的路径上的节点可能少于下限
+        let Some(fork) = left_path.iter().zip(&right_path).position(|(_l, _r)| _l != _r) else {
+            // 两端在同一个叶子节点中
+            let BPTreeNode::Leaf { kvs, .. } = nodes.node_mut(left_leaf)? else {
+                return Err(BPTreeError::corrupted(left_leaf, "expected a leaf"));
+            };
+            let detached: Vec<BPTreeKeyValue> = kvs.drain(from..to).collect();
+            Self::adjust_counts(nodes, &left_path, -(detached.len() as isize))?;
+            return Ok(detached);
+        };
+
+        // 先沿着叶子节点的链表取出两端之间的键值对, 这些叶子节点之后随所在的子树一起释放
+        let BPTreeNode::Leaf { next, kvs, .. } = nodes.node_mut(left_leaf)? else {
+            return Err(BPTreeError::corrupted(left_leaf, "expected a leaf"));
+        };
+        let mut detached = kvs.split_off(from);
+        let mut leaf = *next;
+        while let Some(offset) = leaf.filter(|_offset| *_offset != right_leaf) {
+            let BPTreeNode::Leaf { next, kvs, .. } = nodes.node_mut(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "expected a leaf"));
+            };
+            detached.append(kvs);
+            leaf = *next;
+        }
+
+        // 两条路径在 fork 这一层分叉, 分叉的节点删除两条路径之间的子节点, 保留两者之间的分隔 key
+        let mut garbage = vec![];
+        let (fork_offset, left_idx) = left_path[fork];
+        let right_idx = right_path[fork].1;
+        let BPTreeNode::Internal { branches, .. } = nodes.node_mut(fork_offset)? else {
+            return Err(BPTreeError::corrupted(fork_offset, "expected an internal node"));
+        };
+        let (mut child, mut keys, mut counts) = branches.parts_mut();
+        garbage.extend(child.drain(left_idx + 1..right_idx));
+        keys.drain(left_idx..right_idx - 1);
+        counts.drain(left_idx + 1..right_idx);
+        // 再往下, 左侧路径上的节点删除右边的子节点, 右侧路径上的节点删除左边的子节点
+        for &(offset, idx) in &left_path[fork + 1..] {
+            let BPTreeNode::Internal { branches, .. } = nodes.node_mut(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "expected an internal node"));
+            };
+            let (mut child, mut keys, mut counts) = branches.parts_mut();
+            garbage.extend(child.drain(idx + 1..));
+            keys.truncate(idx);
+            counts.truncate(idx + 1);
+        }
+        for &(offset, idx) in &right_path[fork + 1..] {
+            let BPTreeNode::Internal { branches, .. } = nodes.node_mut(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "expected an internal node"));
+            };
+            let (mut child, mut keys, mut counts) = branches.parts_mut();
+            garbage.extend(child.drain(..idx));
+            keys.drain(..idx);
+            counts.drain(..idx);
+        }
+
+        // 右端的叶子节点删除范围内的部分, 两端直接连接起来
+        let BPTreeNode::Leaf { next, .. } = nodes.node_mut(left_leaf)? else {
+            return Err(BPTreeError::corrupted(left_leaf, "expected a leaf"));
+        };
+        *next = Some(right_leaf);
+        let BPTreeNode::Leaf { prev, kvs, .. } = nodes.node_mut(right_leaf)? else {
+            return Err(BPTreeError::corrupted(right_leaf, "expected a leaf"));
+        };
+        detached.extend(kvs.drain(..to));
+        *prev = Some(left_leaf);
+        while let Some(offset) = garbage.pop() {
+            if let BPTreeNode::Internal { branches, .. } = nodes.free_node(offset)? {
+                garbage.extend_from_slice(branches.child());
+            }
+        }
+
+        // 自下而上重新计算两条路径上的子树计数, 右侧路径上剩下的子节点都是第一个
+        let right_spine: DescentPath = right_path[fork + 1..].iter().map(|&(_offset, _)| (_offset, 0)).collect();
+        Self::refresh_counts(nodes, &right_spine, right_leaf)?;
+        let right_count = nodes.node(right_spine.first().map_or(right_leaf, |_p| _p.0))?.count();
+        if let BPTreeNode::Internal { branches, .. } = nodes.header_mut(fork_offset)? {
+            branches.parts_mut().2[left_idx + 1] = right_count;
+        }
+        Self::refresh_counts(nodes, &left_path, left_leaf)?;
+
+        // 分叉的节点中两条路径相邻, 沿着两条路径逐层合并
+        Self::join(nodes, fork_offset, left_idx, fork, fanout)?;
+        Ok(detached)
+    }
+
+    fn refresh_counts<S: NodeStore>(nodes: &mut S, path: &[(NodeId, usize)], leaf_offset: NodeId) -> Result<(), BPTreeError> {
+        // 按路径上每个子节点实际的大小重新计算计数
+        let mut child_offset = leaf_offset;
+        for &(offset, idx) in path.iter().rev() {
+            let count = nodes.node(child_offset)?.count();
+            let BPTreeNode::Internal { branches, .. } = nodes.header_mut(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "expected an internal node"));
+            };
+            branches.parts_mut().2[idx] = count;
+            child_offset = offset;
+        }
+        Ok(())
+    }
+
+    fn join<S: NodeStore>(nodes: &mut S, parent_offset: NodeId, idx: usize, depth: usize, fanout: Fanout) -> Result<(), BPTreeError> {
+        // 合并深度为 depth 的父节点的第 idx 与 idx + 1 个子节点, 两者相接处的子节点同样需要合并, 递归处理到叶子节点
+        // 合并后超出上限时从中间分裂一次, 少于下限的节点留给 fix_boundary 处理
+        let BPTreeNode::Internal { branches, .. } = nodes.node(parent_offset)? else {
+            return Err(BPTreeError::corrupted(parent_offset, "expected an internal node"));
+        };
+        let (left_offset, right_offset) = (branches.child()[idx], branches.child()[idx + 1]);
+        let left_children = match nodes.node(left_offset)? {
+            BPTreeNode::Internal { branches, .. } => Some(branches.child().len()),
+            BPTreeNode::Leaf { .. } => None,
+        };
+        instrument::merge(left_offset, right_offset, depth + 1);
+        Self::merge_nodes(nodes, parent_offset, idx, left_offset, right_offset)?;
+        if let Some(left_children) = left_children {
+            Self::join(nodes, left_offset, left_children - 1, depth + 1, fanout)?;
+        }
+        let left = nodes.node(left_offset)?;
+        if left.len() > fanout.of(left) - 1 {
+            Self::split_child(nodes, parent_offset, idx, depth + 1, fanout)?;
+        }
+        Ok(())
+    }
+
+    fn split_child<S: NodeStore>(
+        nodes: &mut S,
+        parent_offset: NodeId,
+        idx: usize,
+        depth: usize,
+        fanout: Fanout,
+    ) -> Result<(), BPTreeError> {
+        // 从中间分裂第 idx 个 (深度为 depth 的) 子节点, 分裂出来的右节点插入父节点
+        let BPTreeNode::Internal { branches, .. } = nodes.node(parent_offset)? else {
+            return Err(BPTreeError::corrupted(parent_offset, "expected an internal node"));
+        };
+        let offset = branches.child()[idx];
+        let node = nodes.node_mut(offset)?;
+        let (key, new_node) = node.split(node.len() / 2, fanout.of(node));
+        let is_leaf = matches!(new_node, BPTreeNode::Leaf { .. });
+        let new_offset = nodes.alloc_node(new_node)?;
+        instrument::split(offset, new_offset, depth);
+        if is_leaf {
+            Self::link_leaf(nodes, offset, new_offset)?;
+        }
+        let new_count = nodes.node(new_offset)?.count();
+        if !nodes.node_mut(parent_offset)?.push_data(idx, new_offset, key, new_count) {
+            return Err(BPTreeError::corrupted(parent_offset, "cannot insert separator key into parent"));
+        }
+        Ok(())
+    }
+
+    fn fix_boundary<S: NodeStore>(
+        nodes: &mut S,
+        root: &mut NodeId,
+        key_order: &KeyOrder,
+        bound: Bound<&[u8]>,
+        fanout: Fanout,
+    ) -> Result<(), BPTreeError> {
+        // 摘除一段范围后, 少于下限的节点都在 bound 所在的路径上, 内部节点甚至可能只剩一个子节点
+        // 每次从根节点找到路径上第一个少于下限的节点处理, 它的父节点已经合法, 一定有兄弟节点
+        loop {
+            // 根内部节点只剩一个子节点时, 将这个子节点作为新的根节点
+            while let BPTreeNode::Internal { branches, .. } = nodes.node(*root)? {
+                if !branches.keys().is_empty() {
+                    break;
+                }
+                let new_root_offset = branches.child()[0];
+                nodes.free_node(*root)?;
+                instrument::root_change(*root, new_root_offset);
+                *root = new_root_offset;
+            }
+            let (leaf_offset, mut path, _) = Self::bound_path(nodes, *root, key_order, bound, false)?;
+            let offsets: Vec<NodeId> = path.iter().map(|_p| _p.0).chain([leaf_offset]).skip(1).collect();
+            let mut underflow = None;
+            for (depth, offset) in offsets.into_iter().enumerate() {
+                let node = nodes.node(offset)?;
+                if node.len() < Self::min_len(fanout.of(node)) {
+                    underflow = Some((depth + 1, offset));
+                    break;
+                }
+            }
+            let Some((depth, offset)) = underflow else { return Ok(()); };
+            path.truncate(depth);
+            if let Some(new_root) = Self::rebalance(nodes, path, offset, fanout)? {
+                instrument::root_change(*root, new_root);
+                *root = new_root;
+            }
+        }
+    }
+
+    fn split_path<S: NodeStore, T: NodeStore>(
+        nodes: &mut S,
+        other: &mut T,
+        (leaf_offset, path, idx): (NodeId, DescentPath, usize),
+    ) -> Result<NodeId, BPTreeError> {
+        // 自下而上把路径上的每个节点从分界位置一分为二, 右半部分与它右边的子树移到 other 中, 返回 other 中的根节点
+        // 两边切开的路径上的节点都可能少于下限, 内部节点甚至只剩一个子节点
+        let BPTreeNode::Leaf { next, kvs, .. } = nodes.node_mut(leaf_offset)? else {
+            return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+        };
+        let right_kvs = kvs.split_off(idx);
+        *next = None;
+        let mut spine = other.alloc_node(BPTreeNode::Leaf { prev: None, next: None, kvs: right_kvs, prefix: String::new() })?;
+        let mut leaves = vec![spine];
+        for &(offset, idx) in path.iter().rev() {
+            let BPTreeNode::Internal { branches, .. } = nodes.node_mut(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "expected an internal node"));
+            };
+            // 第 idx 个子节点被切开, 左半部分留在原节点中, 右半部分 spine 成为右侧节点的第一个子节点
+            let mut right = branches.split_off(idx, branches.capacity());
+            let (mut child, _, mut counts) = branches.parts_mut();
+            let (mut right_child, _, mut right_counts) = right.parts_mut();
+            child.push(std::mem::replace(&mut right_child[0], spine));
+            counts.push(std::mem::replace(&mut right_counts[0], other.node(spine)?.count()));
+            for moved in right_child[1..].iter_mut() {
+                *moved = Self::move_subtree(nodes, other, *moved, &mut leaves)?;
+            }
+            spine = other.alloc_node(BPTreeNode::Internal { branches: right, prefix: String::new() })?;
+        }
+        Self::refresh_counts(nodes, &path, leaf_offset)?;
+        Self::link_leaves(other, &leaves)?;
+        Ok(spine)
+    }
+
+    fn move_subtree<S: NodeStore, T: NodeStore>(
+        nodes: &mut S,
+        other: &mut T,
+        offset: NodeId,
+        leaves: &mut Vec<NodeId>,
+    ) -> Result<NodeId, BPTreeError> {
+        // 把以 offset 为根的子树从 nodes 移到 other 中, 返回新的编号, 叶子节点的新编号按顺序追加到 leaves
+        let node = match nodes.free_node(offset)? {
+            BPTreeNode::Internal { mut branches, prefix } => {
+                for moved in branches.parts_mut().0.iter_mut() {
+                    *moved = Self::move_subtree(nodes, other, *moved, leaves)?;
+                }
+                BPTreeNode::Internal { branches, prefix }
+            }
+            leaf => leaf,
+        };
+        let is_leaf = matches!(node, BPTreeNode::Leaf { .. });
+        let new_offset = other.alloc_node(node)?;
+        if is_leaf {
+            leaves.push(new_offset);
+        }
+        Ok(new_offset)
+    }
+
+    fn link_leaves<S: NodeStore>(nodes: &mut S, leaves: &[NodeId]) -> Result<(), BPTreeError> {
+        // 按顺序重新连接叶子节点链表, 两端不再指向其他叶子节点
+        for (idx, &offset) in leaves.iter().enumerate() {
+            let BPTreeNode::Leaf { prev, next, .. } = nodes.node_mut(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "expected a leaf"));
+            };
+            *prev = idx.checked_sub(1).map(|_i| leaves[_i]);
+            *next = leaves.get(idx + 1).copied();
+        }
+        Ok(())
+    }
+
+    /// 按 key 查找键值对
+    ///
+    /// key 可以是 `&str`、`&String` 或字节串等任何 `AsRef<[u8]>`, 按字节比较, 查找时不需要分配 `String`
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// tree.put("user:42".to_string(), "alice".to_string()).unwrap();
+    /// assert_eq!(tree.get(b"user:42").map(|kv| kv.value()), Some("alice"));
+    /// assert_eq!(tree.get("user:42").map(|kv| kv.value()), Some("alice"));
+    /// ```
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&BPTreeKeyValue> {
+        let key = key.as_ref();
+        let leaf_offset = self.find_leaf(key);
+        if let Some(BPTreeNode::Leaf { kvs, prefix, .. }) = self.nodes.get(leaf_offset) {
+            match self.key_order.search_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key) {
+                Ok(idx) => { kvs.get(idx) }
+                Err(_) => None
+            }
+        } else {
+            None
+        }
+    }
+
+    /// 一次查找多个 key, 按 `keys` 的顺序返回对应的值
+    ///
+    /// 先把 key 排序后从左到右依次查找, 下一个 key 仍然落在当前叶子节点中时直接复用, 不再从根节点向下查找;
+    /// key 比较集中时比逐个 [`get`](Self::get) 少很多次查找
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let tree: BPTree = (0..100).map(|i| (format!("{:03}", i), i.to_string())).collect();
+    /// assert_eq!(tree.get_many(&["042", "x", "007", "042"]), [Some("42"), None, Some("7"), Some("42")]);
+    /// ```
+    pub fn get_many<Q: AsRef<[u8]>>(&self, keys: &[Q]) -> Vec<Option<&str>> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by(|&_a, &_b| self.key_order.cmp(keys[_a].as_ref(), keys[_b].as_ref()));
+
+        let mut values = vec![None; keys.len()];
+        // 当前叶子节点, 以及下一个叶子节点的第一个 key (节点的前缀与其余部分)
+        let mut leaf: Option<&BPTreeNode> = None;
+        let mut upper: Option<(&str, &[u8])> = None;
+        for idx in order {
+            let key = keys[idx].as_ref();
+            // key 已经排好序, 不小于下一个叶子节点的第一个 key 时才需要重新查找
+            let node = match leaf {
+                Some(node) if upper.is_none_or(|(_prefix, _upper)| self.key_order.cmp_in(_prefix, _upper, key).is_gt()) => node,
+                _ => {
+                    let node = &self.nodes[self.find_leaf(key)];
+                    upper = match node {
+                        BPTreeNode::Leaf { next: Some(next), .. } => match &self.nodes[*next] {
+                            BPTreeNode::Leaf { kvs, prefix, .. } => kvs.first().map(|_kv| (prefix.as_str(), _kv.key.as_bytes())),
+                            BPTreeNode::Internal { .. } => panic!("leaf chain points to an internal node"),
+                        },
+                        _ => None,
+                    };
+                    *leaf.insert(node)
+                }
+            };
+            let BPTreeNode::Leaf { kvs, prefix, .. } = node else { unreachable!("search_leaf returns a leaf") };
+            if let Ok(pos) = self.key_order.search_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key) {
+                values[idx] = Some(kvs[pos].value.as_str());
+            }
+        }
+        values
+    }
+
+    /// 按 key 查找值的可变引用, 直接修改叶子节点中的值, 不需要重新插入
+    ///
+    /// 返回的 [`ValueMut`] 可以像 `&mut String` 一样使用; 关联了文件或有订阅的树中, 修改后的值在
+    /// [`commit`](ValueMut::commit) 时与 [`put`](Self::put) 一样写入预写日志并发出修改事件, 没有 commit 的修改被丢弃
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// tree.put("hits".to_string(), "1".to_string()).unwrap();
+    /// let changes = tree.subscribe(16);
+    /// if let Some(mut hits) = tree.get_mut("hits") {
+    ///     hits.push('0');
+    ///     hits.commit().unwrap();
+    /// }
+    /// assert_eq!(tree.get("hits").map(|kv| kv.value()), Some("10"));
+    /// let event = changes.try_recv().unwrap().unwrap();
+    /// assert_eq!((event.old_value.as_deref(), event.new_value.as_deref()), (Some("1"), Some("10")));
+    /// ```
+    pub fn get_mut<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Option<ValueMut<'_>> {
+        let (leaf_offset, idx) = self.position_of(key.as_ref())?;
+        Some(ValueMut::new(self, leaf_offset, idx))
+    }
+
+    fn position_of(&self, key: &[u8]) -> Option<(NodeId, usize)> {
+        let leaf_offset = self.find_leaf(key);
+        let BPTreeNode::Leaf { kvs, prefix, .. } = &self.nodes[leaf_offset] else { return None; };
+        let idx = self.key_order.search_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key).ok()?;
+        Some((leaf_offset, idx))
+    }
+
+    /// key 存在时用 `f` 原地修改它的值, 返回 key 是否存在
+    ///
+    /// 关联了文件或有订阅的树中, `f` 修改的是值的副本, 新的值与 [`put`](Self::put) 一样先写入预写日志并发出修改事件,
+    /// 之后才替换树中的值; 写入失败时返回错误, 树不会被修改
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// tree.put("hits".to_string(), "1".to_string()).unwrap();
+    /// assert!(tree.modify("hits", |value| value.push('0')).unwrap());
+    /// assert!(!tree.modify("misses", |value| value.push('0')).unwrap());
+    /// assert_eq!(tree.get("hits").map(|kv| kv.value()), Some("10"));
+    /// ```
+    pub fn modify<Q: AsRef<[u8]> + ?Sized, F: FnOnce(&mut String)>(&mut self, key: &Q, f: F) -> Result<bool, BPTreeError> {
+        let Some((leaf_offset, idx)) = self.position_of(key.as_ref()) else { return Ok(false); };
+        OccupiedEntry::new(self, leaf_offset, idx).update(f)?;
+        Ok(true)
+    }
+
+    /// 当前的值等于 `expected` 时才修改: `new` 为 `Some` 时插入或更新, 为 `None` 时删除
+    ///
+    /// `expected` 为 `None` 表示期望 key 不存在. 值不同时返回 [`CasError::Mismatch`] 并带上当前的值, 树不会被修改;
+    /// 修改与 [`put`](Self::put)/[`remove`](Self::remove) 相同, 写预写日志失败时返回 [`CasError::Tree`].
+    /// 树只能通过 `&mut self` 修改, 比较与修改之间不会有其他写入; 多个线程共享的树见
+    /// [`ConcurrentBPTree::compare_and_swap`](crate::ConcurrentBPTree::compare_and_swap)
+    ///
+    /// ```
+    /// use btree_test::{BPTree, CasError};
+    ///
+    /// let mut tree = BPTree::new(4);
+    /// tree.compare_and_swap("version".to_string(), None, Some("1".to_string())).unwrap();
+    /// tree.compare_and_swap("version".to_string(), Some("1"), Some("2".to_string())).unwrap();
+    /// let Err(CasError::Mismatch { current }) = tree.compare_and_swap("version".to_string(), Some("1"), None) else { panic!() };
+    /// assert_eq!(current.as_deref(), Some("2"));
+    /// tree.compare_and_swap("version".to_string(), Some("2"), None).unwrap();
+    /// assert!(tree.is_empty());
+    /// ```
+    pub fn compare_and_swap(&mut self, key: String, expected: Option<&str>, new: Option<String>) -> Result<(), CasError> {
+        let current = self.get(&key).map(|_kv| _kv.value());
+        if current != expected {
+            return Err(CasError::Mismatch { current: current.map(str::to_string) });
+        }
+        match new {
+            Some(value) => self.put(key, value)?,
+            None => self.remove(&key)?,
+        };
+        Ok(())
+    }
+
+    /// 用注册的合并函数把 `operand` 合并到 key 的值上, 见 [`MergeOperator`]
+    ///
+    /// 只从根节点向下查找一次, 比先 [`get`](Self::get) 再 [`put`](Self::put) 少一次查找;
+    /// key 不存在时以 `None` 作为原来的值, 合并的结果作为新值插入, 合并后的值与 put 一样写入预写日志
+    ///
+    /// # Panics
+    ///
+    /// 没有注册合并函数时 panic
+    pub fn merge(&mut self, key: String, operand: &str) -> Result<(), BPTreeError> {
+        let merge_operator = self.merge_operator.clone().expect("no merge operator registered");
+        let (leaf_offset, path) = Self::search_path(&mut self.nodes, self.root, &self.key_order, key.as_bytes())?;
+        let BPTreeNode::Leaf { kvs, prefix, .. } = &self.nodes[leaf_offset] else {
+            return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+        };
+        match self.key_order.search_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key.as_bytes()) {
+            Ok(idx) => {
+                let value = merge_operator.merge(&key, Some(&kvs[idx].value), operand);
+                self.log_put(&key, &value)?;
+                let change = self.changes.is_active().then(|| value.clone());
+                let BPTreeNode::Leaf { kvs, .. } = &mut self.nodes[leaf_offset] else { unreachable!("checked above") };
+                let old_value = std::mem::replace(&mut kvs[idx].value, value);
+                if let Some(value) = change {
+                    self.changes.send(key, Some(old_value), Some(value));
+                }
+            }
+            Err(idx) => {
+                let value = merge_operator.merge(&key, None, operand);
+                self.log_put(&key, &value)?;
+                if self.changes.is_active() {
+                    self.changes.send(key.clone(), None, Some(value.clone()));
+                }
+                let BPTreeNode::Leaf { kvs, .. } = &mut self.nodes[leaf_offset] else { unreachable!("checked above") };
+                kvs.insert(idx, BPTreeKeyValue { key, value });
+                self.len += 1;
+                let policy = self.effective_split_policy();
+                Self::finish_insert(&mut self.nodes, &mut self.root, &mut self.last_leaf, self.fanout, policy, leaf_offset, path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 小于 key 的最大键值对
+    pub fn get_lt<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&BPTreeKeyValue> {
+        self.kv_before(self.seek(Bound::Excluded(key.as_ref()), true))
+    }
+
+    /// 小于等于 key 的最大键值对
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// for version in ["v10", "v20", "v30"] {
+    ///     tree.put(version.to_string(), version.to_uppercase()).unwrap();
+    /// }
+    /// assert_eq!(tree.get_le("v25").map(|kv| kv.key()), Some("v20"));
+    /// assert_eq!(tree.get_le("v20").map(|kv| kv.key()), Some("v20"));
+    /// assert!(tree.get_le("v0").is_none());
+    /// ```
+    pub fn get_le<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&BPTreeKeyValue> {
+        self.kv_before(self.seek(Bound::Included(key.as_ref()), true))
+    }
+
+    /// 大于 key 的最小键值对
+    pub fn get_gt<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&BPTreeKeyValue> {
+        self.kv_at(self.seek(Bound::Excluded(key.as_ref()), false))
+    }
+
+    /// 大于等于 key 的最小键值对
+    pub fn get_ge<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&BPTreeKeyValue> {
+        self.kv_at(self.seek(Bound::Included(key.as_ref()), false))
+    }
+
+    /// 小于 key 的键值对数量, 即 key 按顺序排在第几位 (从 0 开始), key 不必存在
+    ///
+    /// 从根节点向下查找一次, 经过的内部节点中左侧子树的计数直接累加, 不需要遍历叶子节点
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let tree = BPTree::bulk_load(4, (0..100).map(|i| (format!("{:03}", i), i.to_string())));
+    /// assert_eq!(tree.rank("042"), 42);
+    /// assert_eq!(tree.rank("0425"), 43);
+    /// assert_eq!(tree.select(42), Some(("042", "42")));
+    /// ```
+    pub fn rank<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> usize {
+        self.count_before(key.as_ref(), false)
+    }
+
+    /// `start` 到 `end` 之间的键值对数量, 边界的含义与 [`range`](Self::range) 相同
+    ///
+    /// 两个边界各按子树计数查找一次, 不需要遍历范围内的叶子节点
+    ///
+    /// ```
+    /// use std::ops::Bound;
+    /// use btree_test::BPTree;
+    ///
+    /// let tree = BPTree::bulk_load(4, (0..100).map(|i| (format!("{:03}", i), i.to_string())));
+    /// assert_eq!(tree.range_count(Bound::Included("010"), Bound::Excluded("020")), 10);
+    /// assert_eq!(tree.range_count(Bound::Excluded("010"), Bound::Included("020")), 10);
+    /// assert_eq!(tree.range_count::<str>(Bound::Unbounded, Bound::Unbounded), 100);
+    /// ```
+    pub fn range_count<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> usize {
+        let front = match start.map(AsRef::as_ref) {
+            Bound::Included(key) => self.count_before(key, false),
+            Bound::Excluded(key) => self.count_before(key, true),
+            Bound::Unbounded => 0,
+        };
+        let back = match end.map(AsRef::as_ref) {
+            Bound::Included(key) => self.count_before(key, true),
+            Bound::Excluded(key) => self.count_before(key, false),
+            Bound::Unbounded => self.len,
+        };
+        // 起点在终点之后时范围为空
+        back.saturating_sub(front)
+    }
+
+    fn count_before(&self, key: &[u8], inclusive: bool) -> usize {
+        // 小于 key 的键值对数量, inclusive 时也包括等于 key 的键值对
+        let mut rank = 0;
+        let mut offset = self.root;
+        loop {
+            match &self.nodes[offset] {
+                BPTreeNode::Internal { branches, prefix } => {
+                    let (child, keys, counts) = branches.parts();
+                    // 与 search_leaf 一样, 等于分隔 key 时走向右侧的子节点
+                    let idx = self.key_order.child_index_in(prefix, keys, key);
+                    rank += counts[..idx].iter().sum::<usize>();
+                    offset = child[idx];
+                }
+                BPTreeNode::Leaf { kvs, prefix, .. } => {
+                    return rank + self.key_order.partition_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key, inclusive);
+                }
+            }
+        }
+    }
+
+    /// 按顺序排在第 `n` 位 (从 0 开始) 的键值对, `n` 超出范围时返回 `None`
+    ///
+    /// 与 [`rank`](Self::rank) 一样按子树计数向下查找, 可以用来求分位数
+    pub fn select(&self, n: usize) -> Option<(&str, &str)> {
+        if n >= self.len {
+            return None;
+        }
+        let mut n = n;
+        let mut offset = self.root;
+        loop {
+            match &self.nodes[offset] {
+                BPTreeNode::Internal { branches, .. } => {
+                    let (child, _, counts) = branches.parts();
+                    // 跳过整个子树都排在 n 之前的子节点
+                    let mut idx = 0;
+                    while idx + 1 < child.len() && n >= counts[idx] {
+                        n -= counts[idx];
+                        idx += 1;
+                    }
+                    offset = child[idx];
+                }
+                BPTreeNode::Leaf { kvs, .. } => return kvs.get(n).map(|_kv| (_kv.key(), _kv.value())),
+            }
+        }
+    }
+
+    /// 均匀随机地取出 `n` 个不同的键值对, 按 key 的顺序返回, 不足 `n` 个时返回全部; 需要开启 `rand` feature
+    ///
+    /// 先用 Floyd 算法选出 `n` 个不同的位置, 再对每个位置按子树计数 [`select`](Self::select),
+    /// 耗时与 `n` 和树高成正比, 与键值对的总数无关
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let tree = BPTree::bulk_load(16, (0..1_000_000).map(|i| (format!("{:07}", i), i.to_string())));
+    /// let sample = tree.sample(5, &mut rand::rng());
+    /// assert_eq!(sample.len(), 5);
+    /// assert!(sample.windows(2).all(|_w| _w[0].0 < _w[1].0));
+    /// assert!(sample.iter().all(|(key, value)| key.parse::<u32>().unwrap().to_string() == *value));
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn sample(&self, n: usize, rng: &mut impl rand::Rng) -> Vec<(&str, &str)> {
+        use rand::RngExt;
+
+        if n >= self.len {
+            return self.iter().collect();
+        }
+        let mut positions = std::collections::BTreeSet::new();
+        for bound in self.len - n..self.len {
+            let position = rng.random_range(0..=bound);
+            // 已经选过时改选 bound, bound 在之前的轮次中不可能被选到
+            if !positions.insert(position) {
+                positions.insert(bound);
+            }
+        }
+        positions.into_iter().map(|_position| self.select(_position).expect("position is within len")).collect()
+    }
+
+    /// 最小的键值对, 直接从第一个叶子节点中读取
+    pub fn first(&self) -> Option<&BPTreeKeyValue> {
+        let BPTreeNode::Leaf { kvs, .. } = &self.nodes[self.first_leaf] else { return None; };
+        kvs.first()
+    }
+
+    /// 最大的键值对, 直接从最后一个叶子节点中读取
+    pub fn last(&self) -> Option<&BPTreeKeyValue> {
+        let BPTreeNode::Leaf { kvs, .. } = &self.nodes[self.last_leaf] else { return None; };
+        kvs.last()
+    }
+
+    /// 删除并返回最小的键值对
+    ///
+    /// 与 [`remove`](Self::remove) 一样, 写预写日志失败时返回错误
+    pub fn pop_first(&mut self) -> Result<Option<(String, String)>, BPTreeError> {
+        let Some((key, _)) = self.range::<[u8]>(Bound::Unbounded, Bound::Unbounded).next_owned() else { return Ok(None); };
+        Ok(self.remove(&key)?.map(|_value| (key, _value)))
+    }
+
+    /// 删除并返回最大的键值对
+    ///
+    /// 与 [`remove`](Self::remove) 一样, 写预写日志失败时返回错误
+    pub fn pop_last(&mut self) -> Result<Option<(String, String)>, BPTreeError> {
+        let Some((key, _)) = self.range::<[u8]>(Bound::Unbounded, Bound::Unbounded).next_back_owned() else { return Ok(None); };
+        Ok(self.remove(&key)?.map(|_value| (key, _value)))
+    }
+
+    /// 只保留 `f` 返回 `true` 的键值对, 返回删除的数量
+    ///
+    /// 先遍历找出要删除的 key, 再逐个 [`remove`](Self::remove), 每次删除都会保持树的结构, 也会写入预写日志
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::bulk_load(4, (0..20).map(|i| (format!("session:{:02}", i), (i * 100).to_string())));
+    /// // 删除过期时间早于 1000 的会话
+    /// assert_eq!(tree.retain(|_, expires| expires.parse::<u32>().unwrap() >= 1000).unwrap(), 10);
+    /// assert_eq!(tree.first().map(|kv| kv.key()), Some("session:10"));
+    /// ```
+    pub fn retain<F: FnMut(&str, &str) -> bool>(&mut self, mut f: F) -> Result<usize, BPTreeError> {
+        let mut range = self.range::<[u8]>(Bound::Unbounded, Bound::Unbounded);
+        let keys: Vec<String> = std::iter::from_fn(|| range.next_owned()).filter(|(key, value)| !f(key, value)).map(|(key, _)| key).collect();
+        for key in &keys {
+            self.remove(key)?;
+        }
+        Ok(keys.len())
+    }
+
+    /// 删除 `start` 到 `end` 之间的所有键值对, 返回按 key 的顺序取出它们的迭代器
+    ///
+    /// 与 [`remove_range`](Self::remove_range) 一样整段摘下范围内的键值对, 调用返回时树中已经没有这些键值对;
+    /// 返回的 [`Drain`] 拥有摘下的键值对, 取出时不复制 key 与 value, 没有遍历完就被 drop 时剩下的键值对随之释放
+    ///
+    /// ```
+    /// use std::ops::Bound;
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::bulk_load(4, (0..10).map(|i| (i.to_string(), i.to_string())));
+    /// let mut drained = tree.drain(Bound::Included("3"), Bound::Excluded("6")).unwrap();
+    /// assert_eq!(drained.len(), 3);
+    /// assert_eq!(drained.next(), Some(("3".to_string(), "3".to_string())));
+    /// drop(drained);
+    /// assert_eq!(tree.len(), 7);
+    /// assert!(tree.get("4").is_none());
+    /// ```
+    pub fn drain<Q: AsRef<[u8]> + ?Sized>(&mut self, start: Bound<&Q>, end: Bound<&Q>) -> Result<Drain, BPTreeError> {
+        Ok(Drain::new(self.detach(start.map(AsRef::as_ref), end.map(AsRef::as_ref))?))
+    }
+
+    /// 删除 `start` 到 `end` 之间的所有键值对, 返回删除的数量
+    ///
+    /// 不逐个删除 key: 先找到范围两端所在的叶子节点, 完全落在范围内的子树与叶子节点直接从树中摘下释放,
+    /// 两端的叶子节点删掉范围内的部分后连接起来, 再沿着两端的路径合并、分裂或借用元素恢复树的结构,
+    /// 除了释放节点之外只需要处理两条从根节点到叶子节点的路径
+    ///
+    /// 关联了文件的树会把删除的 key 作为一条记录写入预写日志, 写入失败时返回 [`BPTreeError::Io`], 树不会被修改
+    ///
+    /// ```
+    /// use std::ops::Bound;
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::bulk_load(4, (0..1000).map(|i| (format!("log:{:04}", i), i.to_string())));
+    /// assert_eq!(tree.remove_range(Bound::Included("log:0100"), Bound::Excluded("log:0900")).unwrap(), 800);
+    /// assert_eq!(tree.len(), 200);
+    /// assert_eq!(tree.get_lt("log:0900").map(|kv| kv.key()), Some("log:0099"));
+    /// ```
+    pub fn remove_range<Q: AsRef<[u8]> + ?Sized>(&mut self, start: Bound<&Q>, end: Bound<&Q>) -> Result<usize, BPTreeError> {
+        Ok(self.detach(start.map(AsRef::as_ref), end.map(AsRef::as_ref))?.len())
+    }
+
+    // remove_range 与 drain 共用: 写预写日志, 摘下范围内的键值对并恢复树的结构, 按 key 的顺序返回摘下的键值对
+    fn detach(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Vec<BPTreeKeyValue>, BPTreeError> {
+        let removed = self.range_count(start, end);
+        if removed == 0 {
+            return Ok(vec![]);
+        }
+        if self.wal.is_some() {
+            let mut range = self.range(start, end);
+            let keys: Vec<String> = std::iter::from_fn(|| range.next_owned()).map(|(key, _)| key).collect();
+            if let Some(wal) = &mut self.wal {
+                wal.append_batch(keys.iter().map(|_k| (_k.as_str(), None)))?;
+            }
+        }
+        let removed_entries = self.changed_entries(start, end);
+
+        let left = Self::bound_path(&mut self.nodes, self.root, &self.key_order, start, false)?;
+        let right = Self::bound_path(&mut self.nodes, self.root, &self.key_order, end, true)?;
+        let detached = Self::detach_range(&mut self.nodes, left, right, self.fanout)?;
+        Self::fix_boundary(&mut self.nodes, &mut self.root, &self.key_order, start, self.fanout)?;
+        // 两端的叶子节点可能被合并或释放, 重新找到第一个与最后一个叶子节点
+        self.first_leaf = Self::bound_path(&mut self.nodes, self.root, &self.key_order, Bound::Unbounded, false)?.0;
+        self.last_leaf = Self::bound_path(&mut self.nodes, self.root, &self.key_order, Bound::Unbounded, true)?.0;
+        self.len -= removed;
+        for (key, value) in removed_entries {
+            self.changes.send(key, Some(value), None);
+        }
+        Ok(detached)
+    }
+
+    /// 将不小于 `key` 的键值对分离出来作为一棵新树返回, 与 `BTreeMap::split_off` 相同
+    ///
+    /// 沿着 key 所在的路径把每个节点一分为二, 右边的子树整棵移到新树中, 不需要逐个插入键值对;
+    /// 之后两棵树只有切开的路径上的节点可能少于下限, 与 [`remove_range`](Self::remove_range) 一样沿着这条路径修整
+    ///
+    /// 新树使用相同的 order、分裂方式、比较器与合并函数, 但不关联文件;
+    /// 关联了文件的树会把移走的 key 作为一条记录写入预写日志, 写入失败时返回 [`BPTreeError::Io`], 树不会被修改
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut lower = BPTree::bulk_load(4, (0..100).map(|i| (format!("user:{:03}", i), i.to_string())));
+    /// let upper = lower.split_off("user:060").unwrap();
+    /// assert_eq!((lower.len(), upper.len()), (60, 40));
+    /// assert_eq!(lower.last().map(|kv| kv.key()), Some("user:059"));
+    /// assert_eq!(upper.first().map(|kv| kv.key()), Some("user:060"));
+    /// ```
+    pub fn split_off<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<BPTree, BPTreeError> {
+        let key = key.as_ref();
+        let mut other = self.empty_like();
+        let moved = self.range_count(Bound::Included(key), Bound::Unbounded);
+        if moved == 0 {
+            return Ok(other);
+        }
+        if self.wal.is_some() {
+            let keys: Vec<String> = self.range(Bound::Included(key), Bound::Unbounded).map(|(key, _)| key.to_string()).collect();
+            if let Some(wal) = &mut self.wal {
+                wal.append_batch(keys.iter().map(|_k| (_k.as_str(), None)))?;
+            }
+        }
+        let moved_entries = self.changed_entries(Bound::Included(key), Bound::Unbounded);
+
+        let position = Self::bound_path(&mut self.nodes, self.root, &self.key_order, Bound::Included(key), false)?;
+        other.nodes = NodeSlab::new();
+        other.root = Self::split_path(&mut self.nodes, &mut other.nodes, position)?;
+        other.len = moved;
+        self.len -= moved;
+        // 原来的树沿着最右边的路径修整, 新树沿着最左边的路径修整
+        Self::fix_boundary(&mut self.nodes, &mut self.root, &self.key_order, Bound::Included(key), self.fanout)?;
+        Self::fix_boundary(&mut other.nodes, &mut other.root, &other.key_order, Bound::Unbounded, other.fanout)?;
+        for tree in [&mut *self, &mut other] {
+            tree.first_leaf = Self::bound_path(&mut tree.nodes, tree.root, &tree.key_order, Bound::Unbounded, false)?.0;
+            tree.last_leaf = Self::bound_path(&mut tree.nodes, tree.root, &tree.key_order, Bound::Unbounded, true)?.0;
+        }
+        for (key, value) in moved_entries {
+            self.changes.send(key, Some(value), None);
+        }
+        Ok(other)
+    }
+
+    /// 把 `other` 中的所有键值对移入这棵树, `other` 变为空树, 与 `BTreeMap::append` 相同, key 相同时使用 `other` 中的值
+    ///
+    /// 两棵树的 key 范围不重叠时 (例如 [`split_off`](Self::split_off) 分离出来的两部分), 直接把 `other` 的叶子节点
+    /// 接到叶子链表的一端, 修整相接处的两个叶子节点后自底向上重建内部节点, 不需要逐个插入键值对;
+    /// 范围重叠, 或者两棵树叶子节点与内部节点的 order 不同时退化为 [`put_batch`](Self::put_batch)
+    ///
+    /// 两棵树需要使用相同的 key 顺序; 关联了文件的树会把移入的键值对作为一条记录写入预写日志,
+    /// `other` 关联了文件时也会把删除写入它的预写日志
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::bulk_load(4, (0..50).map(|i| (format!("{:03}", i), i.to_string())));
+    /// let mut other = BPTree::bulk_load(4, (50..100).map(|i| (format!("{:03}", i), i.to_string())));
+    /// tree.append(&mut other).unwrap();
+    /// assert_eq!(tree.len(), 100);
+    /// assert!(other.is_empty());
+    /// assert_eq!(tree.get("075").map(|kv| kv.value()), Some("75"));
+    /// ```
+    pub fn append(&mut self, other: &mut BPTree) -> Result<(), BPTreeError> {
+        let (Some(other_first), Some(other_last)) = (other.first(), other.last()) else { return Ok(()); };
+        let cmp = |_a: &BPTreeKeyValue, _b: &BPTreeKeyValue| self.key_order.cmp(_a.key.as_bytes(), _b.key.as_bytes());
+        // other 中的 key 全部在这棵树之后或之前时, 可以直接接上叶子节点
+        let (after, before) = match (self.first(), self.last()) {
+            (Some(first), Some(last)) => (cmp(last, other_first).is_lt(), cmp(other_last, first).is_lt()),
+            _ => (true, false),
+        };
+
+        if other.wal.is_some() {
+            let keys: Vec<String> = other.keys().map(str::to_string).collect();
+            if let Some(wal) = &mut other.wal {
+                wal.append_batch(keys.iter().map(|_k| (_k.as_str(), None)))?;
+            }
+        }
+        // other 的键值对从 other 中删除, 插入这棵树, 任何一边有订阅时先复制一份
+        let moved_entries: Vec<(String, String)> = if self.changes.is_active() || other.changes.is_active() {
+            other.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+        } else {
+            vec![]
+        };
+        // order 不同时 other 的叶子节点可能超出这棵树的上限或少于下限, 不能直接接上
+        if (after || before) && self.fanout == other.fanout {
+            if let Some(wal) = &mut self.wal {
+                wal.append_batch(other.iter().map(|(key, value)| (key, Some(value))))?;
+            }
+            self.graft(other, after)?;
+            // 两棵树的 key 不重叠, 对这棵树来说都是新插入的
+            if self.changes.is_active() {
+                for (key, value) in &moved_entries {
+                    self.changes.send(key.clone(), None, Some(value.clone()));
+                }
+            }
+        } else {
+            let entries: Vec<(String, String)> = other.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+            self.put_batch(entries)?;
+        }
+
+        // other 保留关联的文件、预写日志与订阅, 其余部分重置为空树
+        let (pager, wal, mut changes) = (other.pager.take(), other.wal.take(), std::mem::take(&mut other.changes));
+        for (key, value) in moved_entries {
+            changes.send(key, Some(value), None);
+        }
+        *other = BPTree { pager, wal, changes, ..other.empty_like() };
+        Ok(())
+    }
+
+    fn graft(&mut self, other: &mut BPTree, after: bool) -> Result<(), BPTreeError> {
+        // 按叶子链表的顺序取出两棵树的叶子节点, other 的叶子节点移到这棵树中, 两棵树的内部节点全部丢弃
+        let mut own = vec![];
+        if !self.is_empty() {
+            let mut offset = Some(self.first_leaf);
+            while let Some(leaf_offset) = offset {
+                let BPTreeNode::Leaf { next, .. } = &self.nodes[leaf_offset] else {
+                    return Err(BPTreeError::corrupted(leaf_offset, "leaf chain points to an internal node"));
+                };
+                own.push(leaf_offset);
+                offset = *next;
+            }
+        }
+        let mut garbage = vec![self.root];
+        while let Some(offset) = garbage.pop() {
+            if let BPTreeNode::Internal { branches, .. } = &self.nodes[offset] {
+                garbage.extend_from_slice(branches.child());
+                self.nodes.free_node(offset);
+            } else if self.is_empty() {
+                // 空树只有一个空的根叶子节点
+                self.nodes.free_node(offset);
+            }
+        }
+        let mut other_nodes = std::mem::take(&mut other.nodes);
+        let mut moved = vec![];
+        let mut offset = Some(other.first_leaf);
+        while let Some(leaf_offset) = offset {
+            let leaf = other_nodes.free_node(leaf_offset);
+            let BPTreeNode::Leaf { next, .. } = leaf else {
+                return Err(BPTreeError::corrupted(leaf_offset, "leaf chain points to an internal node"));
+            };
+            offset = next;
+            moved.push(self.nodes.alloc_node(leaf));
+        }
+        let mut junction = if after { own.len() } else { moved.len() };
+        let mut leaves = if after { [own, moved].concat() } else { [moved, own].concat() };
+
+        // 相接处的两个叶子节点原来是各自树的最后一个与第一个, 可能少于下限, 合并后超出上限则平分,
+        // 否则合并后的节点仍可能少于下限, 继续与前一个叶子节点合并, 直到满足下限或者成为第一个叶子节点
+        let min_len = Self::min_len(self.fanout.leaf);
+        while junction > 0 && junction < leaves.len() {
+            let (left_offset, right_offset) = (leaves[junction - 1], leaves[junction]);
+            if self.nodes[left_offset].len() >= min_len && self.nodes[right_offset].len() >= min_len {
+                break;
+            }
+            let BPTreeNode::Leaf { kvs: right_kvs, .. } = self.nodes.free_node(right_offset) else {
+                return Err(BPTreeError::corrupted(right_offset, "expected a leaf"));
+            };
+            let BPTreeNode::Leaf { kvs, .. } = &mut self.nodes[left_offset] else {
+                return Err(BPTreeError::corrupted(left_offset, "expected a leaf"));
+            };
+            kvs.extend(right_kvs);
+            if kvs.len() > self.fanout.leaf - 1 {
+                let right_kvs = split_vec(kvs, kvs.len() / 2, BPTreeNode::capacity(self.fanout.leaf));
+                leaves[junction] = self.nodes.alloc_node(BPTreeNode::Leaf { prev: None, next: None, kvs: right_kvs, prefix: String::new() });
+                break;
+            }
+            leaves.remove(junction);
+            junction -= 1;
+        }
+
+        // 重新连接叶子节点链表, 再自底向上重建内部节点
+        Self::link_leaves(&mut self.nodes, &leaves)?;
+        let mut level = vec![];
+        for &offset in &leaves {
+            let BPTreeNode::Leaf { kvs, .. } = &self.nodes[offset] else {
+                return Err(BPTreeError::corrupted(offset, "expected a leaf"));
+            };
+            level.push((offset, kvs[0].key.clone(), kvs.len()));
+        }
+        self.first_leaf = leaves[0];
+        self.last_leaf = leaves[leaves.len() - 1];
+        self.root = Self::build_levels(&mut self.nodes, level, self.fanout.internal);
+        self.len += other.len;
+        Ok(())
+    }
+
+    fn empty_like(&self) -> BPTree {
+        // 配置相同的空树, 不关联文件
+        let mut tree = Self::with_fanout(self.fanout);
+        tree.split_policy = self.split_policy;
+        tree.key_order = self.key_order.clone();
+        tree.merge_operator = self.merge_operator.clone();
+        tree
+    }
+
+    fn kv_at(&self, position: (NodeId, usize)) -> Option<&BPTreeKeyValue> {
+        // 位置可能处于叶子节点的末尾, 此时对应下一个叶子节点的第一个元素
+        let (leaf_offset, idx) = normalize(&self.nodes, position);
+        let BPTreeNode::Leaf { kvs, .. } = &self.nodes[leaf_offset] else { return None; };
+        kvs.get(idx)
+    }
+
+    fn kv_before(&self, position: (NodeId, usize)) -> Option<&BPTreeKeyValue> {
+        // 位置处于叶子节点的开头时, 前一个元素是前一个叶子节点的最后一个元素
+        let (mut leaf_offset, mut idx) = position;
+        loop {
+            let BPTreeNode::Leaf { prev, kvs, .. } = &self.nodes[leaf_offset] else { return None; };
+            if idx > 0 {
+                return kvs.get(idx - 1);
+            }
+            leaf_offset = (*prev)?;
+            idx = self.nodes[leaf_offset].len();
+        }
+    }
+
+    /// 取得 key 对应的 [`Entry`], 用于原地更新或在不存在时插入, 只需从根节点查找一次
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(5);
+    /// for word in ["a", "b", "a"] {
+    ///     tree.entry(word.to_string())
+    ///         .and_modify(|count| *count = (count.parse::<u32>().unwrap() + 1).to_string())
+    ///         .or_insert_with(|| "1".to_string());
+    /// }
+    /// assert_eq!(tree.get("a").map(|kv| kv.value()), Some("2"));
+    /// ```
+    pub fn entry(&mut self, key: String) -> Entry<'_> {
+        let (leaf_offset, path) = self.find_path(key.as_bytes());
+        let BPTreeNode::Leaf { kvs, prefix, .. } = &self.nodes[leaf_offset] else { unreachable!("search_path returns a leaf") };
+        match self.key_order.search_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key.as_bytes()) {
+            Ok(idx) => Entry::Occupied(OccupiedEntry::new(self, leaf_offset, idx)),
+            Err(idx) => Entry::Vacant(VacantEntry::new(self, key, leaf_offset, idx, path)),
+        }
+    }
+
+    /// key 不存在时插入 `default` 的返回值, 返回值的可变引用
+    ///
+    /// 与 [`entry`](Self::entry) 一样只从根节点查找一次, key 已存在时不会调用 `default`.
+    /// 插入与 [`put`](Self::put) 一样写入预写日志, 写入失败时返回错误; 之后通过返回的 [`ValueMut`] 修改的值在
+    /// [`commit`](ValueMut::commit) 时写入日志
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut cache = BPTree::new(4);
+    /// let mut loads = 0;
+    /// for key in ["a", "b", "a", "a"] {
+    ///     let value = cache.get_or_insert_with(key.to_string(), || {
+    ///         loads += 1;
+    ///         key.repeat(3)
+    ///     }).unwrap();
+    ///     assert_eq!(*value, key.repeat(3));
+    /// }
+    /// assert_eq!(loads, 2);
+    /// ```
+    pub fn get_or_insert_with<F: FnOnce() -> String>(&mut self, key: String, default: F) -> Result<ValueMut<'_>, BPTreeError> {
+        match self.entry(key) {
+            Entry::Vacant(entry) => entry.try_insert(default()),
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+        }
+    }
+
+    /// key 不存在时插入 `insert` 的返回值, 已存在时用 `update` 原地修改它的值, 返回 key 原来是否存在
+    ///
+    /// 只从根节点查找一次, 比先 [`get`](Self::get) 再 [`put`](Self::put) 少一次查找, 也不需要复制原来的值.
+    /// 插入或修改后的值与 put 一样先写入预写日志, 写入失败时返回错误, 树不会被修改
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut counts = BPTree::new(4);
+    /// for word in ["a", "b", "a"] {
+    ///     counts.upsert(word.to_string(), || "1".to_string(), |count| *count = (count.parse::<u32>().unwrap() + 1).to_string()).unwrap();
+    /// }
+    /// assert_eq!(counts.get("a").map(|kv| kv.value()), Some("2"));
+    /// assert_eq!(counts.get("b").map(|kv| kv.value()), Some("1"));
+    /// ```
+    pub fn upsert<I: FnOnce() -> String, U: FnOnce(&mut String)>(&mut self, key: String, insert: I, update: U) -> Result<bool, BPTreeError> {
+        match self.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.try_insert(insert())?;
+                Ok(false)
+            }
+            Entry::Occupied(mut entry) => {
+                entry.update(update)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// 指向最小的键值对的游标, 可以用 [`Cursor::seek`] 重新定位
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor::new(self)
+    }
+
+    /// 指向最小的键值对的游标, 可以修改值或删除键值对
+    pub fn cursor_mut(&mut self) -> CursorMut<'_> {
+        CursorMut::new(self)
+    }
+
+    /// 按 key 的顺序遍历 `start` 到 `end` 之间的键值对
+    ///
+    /// 与 [`get`](Self::get) 一样, 边界可以是任何 `AsRef<[u8]>`
+    ///
+    /// ```
+    /// use std::ops::Bound;
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// for key in ["a", "b", "c", "d"] {
+    ///     tree.put(key.to_string(), key.to_uppercase()).unwrap();
+    /// }
+    /// let keys: Vec<_> = tree.range(Bound::Excluded("a"), Bound::Included("c")).map(|(k, _)| k).collect();
+    /// assert_eq!(keys, ["b", "c"]);
+    /// ```
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> Range<'_> {
+        let start = start.map(AsRef::as_ref);
+        let end = end.map(AsRef::as_ref);
+        let back = self.seek(end, true);
+        // 起点在终点之后时范围为空, 直接从终点开始
+        let is_empty = match (start, end) {
+            (Bound::Included(s), Bound::Included(e)) => self.key_order.cmp(s, e).is_gt(),
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => self.key_order.cmp(s, e).is_ge(),
+            _ => false,
+        };
+        let front = if is_empty { back } else { self.seek(start, false) };
+        Range::new(&self.nodes, front, back)
+    }
+
+    /// 按 key 的顺序遍历以 `prefix` 开头的键值对
+    ///
+    /// 从第一个不小于 `prefix` 的 key 开始, 到第一个大于所有以 `prefix` 开头的 key 的位置结束,
+    /// 只需从根节点查找两次
+    ///
+    /// 使用自定义的 [`Comparator`](crate::Comparator) 时, 范围的两端按比较器的顺序查找,
+    /// 例如忽略大小写时 `prefix("/API/")` 也会返回 `"/api/users"`
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// for path in ["/api/users", "/api/users/42", "/apix", "/static/app.js"] {
+    ///     tree.put(path.to_string(), "handler".to_string()).unwrap();
+    /// }
+    /// let keys: Vec<_> = tree.prefix("/api/").map(|(k, _)| k).collect();
+    /// assert_eq!(keys, ["/api/users", "/api/users/42"]);
+    /// ```
+    pub fn prefix<Q: AsRef<[u8]> + ?Sized>(&self, prefix: &Q) -> Range<'_> {
+        let prefix = prefix.as_ref();
+        let end = Self::prefix_end(prefix);
+        let end = match &end {
+            Some(end) => Bound::Excluded(end.as_slice()),
+            None => Bound::Unbounded,
+        };
+        self.range(Bound::Included(prefix), end)
+    }
+
+    fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+        // 以 prefix 开头的 key 都小于 prefix 去掉末尾的 0xff 后将最后一个字节加一的结果,
+        // 全部是 0xff (或为空) 时没有上界
+        let mut end = prefix.to_vec();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Some(end);
+            }
+        }
+        None
+    }
+
+    /// 按 key 的顺序遍历所有键值对
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(self.range::<str>(Bound::Unbounded, Bound::Unbounded))
+    }
+
+    /// 按 key 的顺序遍历所有键值对, 值可以直接修改
+    ///
+    /// # Panics
+    ///
+    /// 通过迭代器修改的值无法写入预写日志, 也无法发出修改事件, 关联了文件或有订阅的树中调用时 panic,
+    /// 这时应使用 [`modify`](Self::modify) 或 [`cursor_mut`](Self::cursor_mut) 逐个修改
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        assert!(self.wal.is_none() && !self.changes.is_active(), "iter_mut cannot log changes; use modify or cursor_mut instead");
+        IterMut::new(self.nodes.slots_mut(), self.first_leaf, self.last_leaf)
+    }
+
+    /// 按顺序遍历所有 key
+    pub fn keys(&self) -> Keys<'_> {
+        Keys::new(self.iter())
+    }
+
+    /// 按 key 的顺序遍历所有值
+    pub fn values(&self) -> Values<'_> {
+        Values::new(self.iter())
+    }
+
+    pub(crate) fn seek(&self, bound: Bound<&[u8]>, is_end: bool) -> (NodeId, usize) {
+        // 找到 bound 在叶子节点中对应的位置
+        let (key, inclusive) = match bound {
+            Bound::Included(key) => (key, true),
+            Bound::Excluded(key) => (key, false),
+            Bound::Unbounded if is_end => return (self.last_leaf, self.nodes[self.last_leaf].len()),
+            Bound::Unbounded => return (self.first_leaf, 0),
+        };
+        let leaf_offset = self.find_leaf(key);
+        let BPTreeNode::Leaf { kvs, prefix, .. } = &self.nodes[leaf_offset] else { return (leaf_offset, 0); };
+        // 起点包含 key 或终点不包含 key 时停在 key 之前, 否则停在 key 之后
+        let idx = self.key_order.partition_in(prefix, kvs, |_kv| _kv.key.as_bytes(), key, inclusive == is_end);
+        (leaf_offset, idx)
+    }
+
+    fn find_leaf(&self, key: &[u8]) -> NodeId {
+        // 只读的方法不返回错误, 结构损坏时直接 panic
+        Self::search_leaf(&mut &self.nodes, self.root, &self.key_order, key).unwrap_or_else(|_error| panic!("{}", _error))
+    }
+
+    pub(crate) fn search_leaf<S: NodeRead>(
+        nodes: &mut S,
+        root_offset: NodeId,
+        key_order: &KeyOrder,
+        key: &[u8],
+    ) -> Result<NodeId, BPTreeError> {
+        // 按照 key 从 root 开始搜索叶子节点, 默认按字节比较, 与 String 的顺序一致
+        let mut offset = root_offset;
+        let mut depth = 0;
+        while let BPTreeNode::Internal { branches, prefix } = nodes.node(offset)? {
+            offset = branches.child()[key_order.child_index_in(prefix, branches.keys(), key)];
+            depth += 1;
+        }
+        instrument::leaf_search(offset, depth);
+        Ok(offset)
+    }
+
+    fn find_path(&self, key: &[u8]) -> (NodeId, DescentPath) {
+        Self::search_path(&mut &self.nodes, self.root, &self.key_order, key).unwrap_or_else(|_error| panic!("{}", _error))
+    }
+
+    pub(crate) fn search_path<S: NodeRead>(
+        nodes: &mut S,
+        root_offset: NodeId,
+        key_order: &KeyOrder,
+        key: &[u8],
+    ) -> Result<(NodeId, DescentPath), BPTreeError> {
+        // 与 search_leaf 相同, 同时记录经过的每个内部节点以及走向的子节点下标, 分裂与合并时沿着路径向上处理
+        let mut offset = root_offset;
+        let mut path = DescentPath::new();
+        while let BPTreeNode::Internal { branches, prefix } = nodes.node(offset)? {
+            let idx = key_order.child_index_in(prefix, branches.keys(), key);
+            path.push((offset, idx));
+            offset = branches.child()[idx];
+        }
+        instrument::leaf_search(offset, path.len());
+        Ok((offset, path))
+    }
+}
+
+/// 与 [`snapshot`](BPTree::snapshot) 一样只复制槽表的根指针, 之后两棵树各自修改时才复制被修改的节点
+///
+/// 复制出的树不关联文件与预写日志
+impl Clone for BPTree {
+    fn clone(&self) -> Self {
+        BPTree {
+            fanout: self.fanout,
+            nodes: self.nodes.clone(),
+            split_policy: self.split_policy,
+            insert_pattern: self.insert_pattern,
+            key_order: self.key_order.clone(),
+            root: self.root,
+            first_leaf: self.first_leaf,
+            last_leaf: self.last_leaf,
+            len: self.len,
+            pager: None,
+            wal: None,
+            merge_operator: self.merge_operator.clone(),
+            changes: ChangeFeed::default(),
+        }
+    }
+}
+
+/// 按顺序比较所有键值对, 与 order 和节点的分布无关
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let a: BPTree = [("a", "1"), ("b", "2")].map(|(k, v)| (k.to_string(), v.to_string())).into_iter().collect();
+/// let mut b = BPTree::new(3);
+/// b.put("b".to_string(), "2".to_string()).unwrap();
+/// b.put("a".to_string(), "1".to_string()).unwrap();
+/// assert_eq!(a, b);
+/// ```
+impl PartialEq for BPTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for BPTree {}
+
+/// 用 [`bulk_load`](BPTree::bulk_load) 构建, order 与 [`BPTreeBuilder`] 的默认值相同
+impl FromIterator<(String, String)> for BPTree {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self::bulk_load(DEFAULT_ORDER, iter)
+    }
+}
+
+/// 与 [`put_batch`](BPTree::put_batch) 相同, 写预写日志失败时 panic, 需要处理错误时应直接调用 `put_batch`
+impl Extend<(String, String)> for BPTree {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        self.put_batch(iter).unwrap_or_else(|_error| panic!("{}", _error));
+    }
+}
@@ -0,0 +1,323 @@
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+use crate::slab::NodeId;
+
+/// 内部节点中的子节点、分隔 key 与子树计数, 三者依次存放在同一块内存中, 整个节点只分配一次
+///
+/// 容量在创建节点时按 order 确定 (见 [`BPTreeNode::capacity`](crate::BPTreeNode)), 插入与分裂都不需要重新分配;
+/// 只有合并两个节点或从文件中读出的节点放不下时才整块重新分配. 三段各自记录长度, `keys` 比另外两段少一个,
+/// 通过 [`parts_mut`](Self::parts_mut) 取得三段后像 `Vec` 一样分别修改
+pub struct Branches {
+    ptr: NonNull<u8>,
+    // 每一段的容量
+    capacity: usize,
+    // child, keys, counts 的长度
+    lens: [usize; 3],
+    _marker: PhantomData<(NodeId, String, usize)>,
+}
+
+// 与 (Vec<NodeId>, Vec<String>, Vec<usize>) 一样, 独占其中的元素
+unsafe impl Send for Branches {}
+unsafe impl Sync for Branches {}
+
+impl Branches {
+    /// 每段可以存放 `capacity` 个元素, 至少为 1
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (layout, _, _) = Self::layout(capacity);
+        // layout 的大小不为 0
+        let ptr = NonNull::new(unsafe { alloc::alloc(layout) }).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Self { ptr, capacity, lens: [0; 3], _marker: PhantomData }
+    }
+
+    /// 由三段的内容创建, 容量刚好放下最长的一段
+    pub(crate) fn from_parts(child: Vec<NodeId>, keys: Vec<String>, counts: Vec<usize>) -> Self {
+        let mut branches = Self::with_capacity(child.len().max(keys.len()).max(counts.len()));
+        let (mut child_part, mut keys_part, mut counts_part) = branches.parts_mut();
+        child_part.extend(child);
+        keys_part.extend(keys);
+        counts_part.extend(counts);
+        branches
+    }
+
+    // 整块内存的布局, 以及 keys 与 counts 的起始位置
+    fn layout(capacity: usize) -> (Layout, usize, usize) {
+        let array = |_layout: Result<Layout, _>| _layout.expect("node capacity overflow");
+        let (layout, keys_at) = array(Layout::array::<NodeId>(capacity)).extend(array(Layout::array::<String>(capacity))).expect("node capacity overflow");
+        let (layout, counts_at) = layout.extend(array(Layout::array::<usize>(capacity))).expect("node capacity overflow");
+        (layout, keys_at, counts_at)
+    }
+
+    fn ptrs(&self) -> (*mut NodeId, *mut String, *mut usize) {
+        let (_, keys_at, counts_at) = Self::layout(self.capacity);
+        let base = self.ptr.as_ptr();
+        unsafe { (base.cast(), base.add(keys_at).cast(), base.add(counts_at).cast()) }
+    }
+
+    /// 每段的容量
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 子节点
+    pub fn child(&self) -> &[NodeId] {
+        self.parts().0
+    }
+
+    /// 分隔 key, 第 i 个 key 是第 i 与第 i + 1 个子节点之间的分界
+    pub fn keys(&self) -> &[String] {
+        self.parts().1
+    }
+
+    /// 每个子树中键值对的数量, 与 `child` 一一对应
+    pub fn counts(&self) -> &[usize] {
+        self.parts().2
+    }
+
+    /// 同时取得三段
+    pub fn parts(&self) -> (&[NodeId], &[String], &[usize]) {
+        let (child, keys, counts) = self.ptrs();
+        let [child_len, keys_len, counts_len] = self.lens;
+        unsafe { (slice::from_raw_parts(child, child_len), slice::from_raw_parts(keys, keys_len), slice::from_raw_parts(counts, counts_len)) }
+    }
+
+    /// 同时取得三段用于修改, 添加元素前需要先 [`reserve`](Self::reserve) 保证容量
+    pub(crate) fn parts_mut(&mut self) -> (Part<'_, NodeId>, Part<'_, String>, Part<'_, usize>) {
+        let (child, keys, counts) = self.ptrs();
+        let capacity = self.capacity;
+        let [child_len, keys_len, counts_len] = &mut self.lens;
+        (Part::new(child, capacity, child_len), Part::new(keys, capacity, keys_len), Part::new(counts, capacity, counts_len))
+    }
+
+    /// 保证每段都还能再放下 `additional` 个元素
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let needed = self.lens.iter().max().copied().unwrap_or(0) + additional;
+        if needed > self.capacity {
+            self.reallocate(needed.max(self.capacity * 2));
+        }
+    }
+
+    /// 容量缩小到刚好放下最长的一段
+    pub(crate) fn shrink_to_fit(&mut self) {
+        let needed = self.lens.iter().max().copied().unwrap_or(0).max(1);
+        if needed < self.capacity {
+            self.reallocate(needed);
+        }
+    }
+
+    fn reallocate(&mut self, capacity: usize) {
+        let mut new = Self::with_capacity(capacity);
+        let (child, keys, counts) = self.ptrs();
+        let (new_child, new_keys, new_counts) = new.ptrs();
+        // 元素按位移动到新的内存中, 旧的内存直接释放, 不再析构其中的元素
+        unsafe {
+            ptr::copy_nonoverlapping(child, new_child, self.lens[0]);
+            ptr::copy_nonoverlapping(keys, new_keys, self.lens[1]);
+            ptr::copy_nonoverlapping(counts, new_counts, self.lens[2]);
+        }
+        new.lens = std::mem::take(&mut self.lens);
+        *self = new;
+    }
+
+    /// 从下标 `at` 处把三段都一分为二, 右半部分放在每段容量为 `capacity` 的新 `Branches` 中
+    pub(crate) fn split_off(&mut self, at: usize, capacity: usize) -> Branches {
+        let tail = self.lens.iter().max().map_or(0, |_len| _len.saturating_sub(at));
+        let mut right = Self::with_capacity(capacity.max(tail));
+        let (child, keys, counts) = self.parts_mut();
+        let (mut right_child, mut right_keys, mut right_counts) = right.parts_mut();
+        child.move_tail(at, &mut right_child);
+        keys.move_tail(at, &mut right_keys);
+        counts.move_tail(at, &mut right_counts);
+        right
+    }
+}
+
+impl Drop for Branches {
+    fn drop(&mut self) {
+        let (_, keys, _) = self.ptrs();
+        let (layout, _, _) = Self::layout(self.capacity);
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(keys, self.lens[1]));
+            alloc::dealloc(self.ptr.as_ptr(), layout);
+        }
+    }
+}
+
+impl Clone for Branches {
+    fn clone(&self) -> Self {
+        let mut branches = Self::with_capacity(self.capacity);
+        let (child, keys, counts) = self.parts();
+        let (mut child_part, mut keys_part, mut counts_part) = branches.parts_mut();
+        child_part.extend(child.iter().copied());
+        keys_part.extend(keys.iter().cloned());
+        counts_part.extend(counts.iter().copied());
+        branches
+    }
+}
+
+impl fmt::Debug for Branches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (child, keys, counts) = self.parts();
+        f.debug_struct("Branches").field("child", &child).field("keys", &keys).field("counts", &counts).finish()
+    }
+}
+
+// 与原来 BPTreeNode::Internal 中的 child、keys、counts 三个字段格式相同
+#[cfg(feature = "serde")]
+impl serde::Serialize for Branches {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let (child, keys, counts) = self.parts();
+        let mut state = serializer.serialize_struct("Branches", 3)?;
+        state.serialize_field("child", child)?;
+        state.serialize_field("keys", keys)?;
+        state.serialize_field("counts", counts)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Branches {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Parts {
+            child: Vec<NodeId>,
+            keys: Vec<String>,
+            counts: Vec<usize>,
+        }
+        let Parts { child, keys, counts } = Parts::deserialize(deserializer)?;
+        Ok(Branches::from_parts(child, keys, counts))
+    }
+}
+
+/// [`Branches`] 中的一段, 容量固定, 由 [`Branches::parts_mut`] 取得, 像 `Vec` 一样修改
+///
+/// 超出容量时 panic, 需要事先通过 [`Branches::reserve`] 保证容量
+pub(crate) struct Part<'a, T> {
+    ptr: *mut T,
+    capacity: usize,
+    len: &'a mut usize,
+}
+
+impl<'a, T> Part<'a, T> {
+    fn new(ptr: *mut T, capacity: usize, len: &'a mut usize) -> Self {
+        Self { ptr, capacity, len }
+    }
+
+    fn reserve_one(&self) {
+        assert!(*self.len < self.capacity, "internal node is full");
+    }
+
+    /// 转为借用整个 [`Branches`] 的切片
+    pub(crate) fn into_slice(self) -> &'a mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, *self.len) }
+    }
+
+    pub(crate) fn push(&mut self, value: T) {
+        self.reserve_one();
+        unsafe { self.ptr.add(*self.len).write(value) };
+        *self.len += 1;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if *self.len == 0 {
+            return None;
+        }
+        *self.len -= 1;
+        Some(unsafe { self.ptr.add(*self.len).read() })
+    }
+
+    pub(crate) fn insert(&mut self, idx: usize, value: T) {
+        assert!(idx <= *self.len, "insertion index {} is out of bounds ({})", idx, *self.len);
+        self.reserve_one();
+        unsafe {
+            let at = self.ptr.add(idx);
+            ptr::copy(at, at.add(1), *self.len - idx);
+            at.write(value);
+        }
+        *self.len += 1;
+    }
+
+    pub(crate) fn remove(&mut self, idx: usize) -> T {
+        assert!(idx < *self.len, "removal index {} is out of bounds ({})", idx, *self.len);
+        unsafe {
+            let at = self.ptr.add(idx);
+            let value = at.read();
+            ptr::copy(at.add(1), at, *self.len - idx - 1);
+            *self.len -= 1;
+            value
+        }
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len >= *self.len {
+            return;
+        }
+        // 先缩短长度, 析构时 panic 也不会再次析构
+        let tail = ptr::slice_from_raw_parts_mut(unsafe { self.ptr.add(len) }, *self.len - len);
+        *self.len = len;
+        unsafe { ptr::drop_in_place(tail) };
+    }
+
+    pub(crate) fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|_value| self.push(_value));
+    }
+
+    /// 把 `other` 中的元素全部移到末尾, `other` 变为空
+    pub(crate) fn append(&mut self, other: &mut Part<'_, T>) {
+        assert!(*self.len + *other.len <= self.capacity, "internal node is full");
+        unsafe { ptr::copy_nonoverlapping(other.ptr, self.ptr.add(*self.len), *other.len) };
+        *self.len += std::mem::take(other.len);
+    }
+
+    /// 删除 `range` 中的元素, 按顺序返回
+    pub(crate) fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> std::vec::IntoIter<T> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => *self.len,
+        };
+        assert!(start <= end && end <= *self.len, "drain range {}..{} is out of bounds ({})", start, end, *self.len);
+        let mut drained = Vec::with_capacity(end - start);
+        unsafe {
+            let at = self.ptr.add(start);
+            ptr::copy_nonoverlapping(at, drained.as_mut_ptr(), end - start);
+            drained.set_len(end - start);
+            ptr::copy(self.ptr.add(end), at, *self.len - end);
+        }
+        *self.len -= end - start;
+        drained.into_iter()
+    }
+
+    // 把 at 之后的元素移到空的 `right` 中
+    fn move_tail(self, at: usize, right: &mut Part<'_, T>) {
+        assert!(at <= *self.len, "split index {} is out of bounds ({})", at, *self.len);
+        let mut tail = Part { ptr: unsafe { self.ptr.add(at) }, capacity: self.capacity - at, len: &mut (*self.len - at) };
+        right.append(&mut tail);
+        *self.len = at;
+    }
+}
+
+impl<T> Deref for Part<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr, *self.len) }
+    }
+}
+
+impl<T> DerefMut for Part<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, *self.len) }
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use crate::bptree::BPTreeNode;
+use crate::error::BPTreeError;
+use crate::pager::Pager;
+use crate::slab::{self, NodeId};
+use crate::store::{NodeRead, NodeStore};
+
+// 一次分裂或合并会连续访问好几个节点, 缓存太小时会反复加载同一个节点
+const MIN_FRAMES: usize = 8;
+
+struct Frame {
+    node: BPTreeNode,
+    // 上一次统计时节点的字节数, 见 BPTreeNode::byte_size
+    bytes: usize,
+    dirty: bool,
+    // 最近一次被访问的时间, 同时也是 lru 中的 key
+    used: u64,
+}
+
+/// 页缓存, 按需从文件中加载节点, 缓存的节点超出内存预算时淘汰最久未使用 (LRU) 的节点
+///
+/// 除了按页大小换算的节点数, 还统计缓存中节点的 key 与值的字节数, 存放在溢出文件中的大值
+/// 加载后也计入内存预算, 一个很大的值会让更多的节点被淘汰, 而不是让缓存占用的内存超出预算
+///
+/// 被修改过的节点在淘汰或 [`flush`](Self::flush) 时写回文件
+///
+/// 被释放的页记录在内存中的空闲链表里, 之后分配节点时优先复用; 空闲链表不会写入文件,
+/// 重新打开后之前释放的页不会再被复用
+pub struct BufferPool {
+    pager: Pager,
+    capacity: usize,
+    memory_budget: usize,
+    // 缓存中所有节点的字节数
+    resident_bytes: usize,
+    // 最近一次返回的节点, 它可能被修改, 下一次访问缓存时重新统计字节数
+    last_used: Option<NodeId>,
+    frames: HashMap<NodeId, Frame>,
+    // 访问时间 -> 页号, 第一个元素即最久未使用的节点
+    lru: BTreeMap<u64, NodeId>,
+    clock: u64,
+    node_count: usize,
+    // 被释放的页, 分配节点时优先复用
+    free: Vec<NodeId>,
+    hits: u64,
+    misses: u64,
+}
+
+impl BufferPool {
+    /// `memory_budget` 为缓存可以使用的字节数, 按页大小换算为最多缓存的节点数, 同时限制缓存中 key 与值的总字节数
+    pub(crate) fn new(pager: Pager, memory_budget: usize, node_count: usize) -> Self {
+        let capacity = (memory_budget / pager.page_size()).max(MIN_FRAMES);
+        Self {
+            pager,
+            capacity,
+            memory_budget,
+            resident_bytes: 0,
+            last_used: None,
+            frames: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            node_count,
+            free: vec![],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// 最多缓存的节点数
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 当前缓存中的节点数
+    pub fn resident(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// 当前缓存中节点的 key 与值的字节数
+    pub fn resident_bytes(&self) -> usize {
+        self.resident_bytes
+    }
+
+    /// 文件中 (包括尚未写回的) 节点总数
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    /// 访问的节点已在缓存中的次数
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// 访问的节点需要从文件中加载的次数
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub(crate) fn pager_mut(&mut self) -> &mut Pager {
+        &mut self.pager
+    }
+
+    /// 将所有修改过的节点写回文件
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.recount();
+        for (offset, frame) in self.frames.iter_mut() {
+            if frame.dirty {
+                self.pager.write_node(*offset, &frame.node)?;
+                frame.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn frame(&mut self, offset: NodeId) -> Result<&mut Frame, BPTreeError> {
+        if offset.index() >= self.node_count {
+            return Err(BPTreeError::corrupted(offset, "node offset out of range"));
+        }
+        self.recount();
+        self.clock += 1;
+        if let Some(frame) = self.frames.get_mut(&offset) {
+            self.hits += 1;
+            self.lru.remove(&frame.used);
+        } else {
+            self.misses += 1;
+            let node = self.pager.read_node(offset)?;
+            let bytes = node.byte_size();
+            self.evict(bytes)?;
+            self.resident_bytes += bytes;
+            self.frames.insert(offset, Frame { node, bytes, dirty: false, used: 0 });
+        }
+        self.lru.insert(self.clock, offset);
+        self.last_used = Some(offset);
+        let frame = self.frames.get_mut(&offset).expect("frame was just inserted");
+        frame.used = self.clock;
+        Ok(frame)
+    }
+
+    fn recount(&mut self) {
+        // 返回的节点只能在下一次访问缓存之前修改, 此时统计它的字节数变化
+        let Some(offset) = self.last_used.take() else { return; };
+        if let Some(frame) = self.frames.get_mut(&offset) {
+            let bytes = frame.node.byte_size();
+            self.resident_bytes = self.resident_bytes - frame.bytes + bytes;
+            frame.bytes = bytes;
+        }
+    }
+
+    fn evict(&mut self, incoming: usize) -> io::Result<()> {
+        // 为即将加载的节点腾出位置, 节点数或字节数超出预算时都要淘汰, 但至少保留 MIN_FRAMES 个节点
+        while self.frames.len() >= self.capacity
+            || (self.frames.len() >= MIN_FRAMES && self.resident_bytes + incoming > self.memory_budget)
+        {
+            let Some((_, offset)) = self.lru.pop_first() else { break; };
+            if let Some(frame) = self.frames.remove(&offset) {
+                self.resident_bytes -= frame.bytes;
+                if frame.dirty {
+                    self.pager.write_node(offset, &frame.node)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NodeRead for BufferPool {
+    fn node(&mut self, offset: NodeId) -> Result<&BPTreeNode, BPTreeError> {
+        Ok(&self.frame(offset)?.node)
+    }
+}
+
+impl NodeStore for BufferPool {
+    fn node_mut(&mut self, offset: NodeId) -> Result<&mut BPTreeNode, BPTreeError> {
+        let frame = self.frame(offset)?;
+        frame.dirty = true;
+        Ok(&mut frame.node)
+    }
+
+    fn alloc_node(&mut self, node: BPTreeNode) -> Result<NodeId, BPTreeError> {
+        if let Some(offset) = self.free.pop() {
+            *self.node_mut(offset)? = node;
+            return Ok(offset);
+        }
+        self.recount();
+        let bytes = node.byte_size();
+        self.evict(bytes)?;
+        let offset = NodeId::new(self.node_count);
+        self.node_count += 1;
+        self.clock += 1;
+        self.lru.insert(self.clock, offset);
+        self.resident_bytes += bytes;
+        self.frames.insert(offset, Frame { node, bytes, dirty: true, used: self.clock });
+        Ok(offset)
+    }
+
+    fn free_node(&mut self, offset: NodeId) -> Result<BPTreeNode, BPTreeError> {
+        let node = std::mem::replace(self.node_mut(offset)?, slab::empty_leaf());
+        self.free.push(offset);
+        Ok(node)
+    }
+}
@@ -0,0 +1,179 @@
+use std::io;
+use std::path::Path;
+
+use crate::bptree::{BPTree, Fanout};
+use crate::comparator::{Comparator, KeyOrder};
+#[cfg(feature = "lz4")]
+use crate::compressed::CompressedBPTree;
+use crate::merge::{MergeOperator, Merger};
+use crate::multimap::BPTreeMultimap;
+use crate::prefix::PrefixBPTree;
+use crate::split::SplitPolicy;
+use crate::tombstone::TombstoneBPTree;
+use crate::versioned::VersionedBPTree;
+
+// 没有指定 order 时使用的默认值
+pub(crate) const DEFAULT_ORDER: usize = 5;
+
+/// 配置并创建 [`BPTree`], 由 [`BPTree::builder`](crate::BPTree::builder) 创建
+///
+/// 没有设置的选项使用与 [`BPTree::new`] 相同的默认值
+///
+/// ```
+/// use btree_test::{BPTree, SplitPolicy};
+///
+/// let tree = BPTree::builder()
+///     .order(64)
+///     .split_policy(SplitPolicy::RightBiased(1.0))
+///     .node_capacity(1024)
+///     .build();
+/// assert_eq!(tree.order(), 64);
+/// assert_eq!(tree.split_policy(), SplitPolicy::RightBiased(1.0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct BPTreeBuilder {
+    leaf_order: usize,
+    internal_order: usize,
+    split_policy: SplitPolicy,
+    key_order: KeyOrder,
+    node_capacity: usize,
+    merge_operator: Option<Merger>,
+}
+
+impl Default for BPTreeBuilder {
+    fn default() -> Self {
+        Self {
+            leaf_order: DEFAULT_ORDER,
+            internal_order: DEFAULT_ORDER,
+            split_policy: SplitPolicy::default(),
+            key_order: KeyOrder::default(),
+            node_capacity: 0,
+            merge_operator: None,
+        }
+    }
+}
+
+impl BPTreeBuilder {
+    /// 使用默认配置, order 为 5
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 节点的最大路数, 同时设置 [`leaf_order`](Self::leaf_order) 与 [`internal_order`](Self::internal_order), 小于 3 时按 3 处理
+    pub fn order(mut self, order: usize) -> Self {
+        self.leaf_order = order;
+        self.internal_order = order;
+        self
+    }
+
+    /// 叶子节点的最大路数, 每个叶子节点最多存放 `leaf_order - 1` 个键值对, 小于 3 时按 3 处理
+    ///
+    /// 叶子节点中是完整的键值对, 内部节点中只有分隔 key、子节点编号与子树计数, 值较大时同样的 order
+    /// 会让叶子节点比内部节点大得多. 存放在文件中时每个节点占用一页, 两种节点的 order 按页大小 `page_size` 分别估算:
+    ///
+    /// - 叶子节点约 25 字节的头部, 每个键值对另加 9 字节, `leaf_order - 1` 个键值对要放进一页:
+    ///   `leaf_order ≈ (page_size - 25) / (key_len + value_len + 9) + 1`. 超过页大小 1/4 的值放在溢出文件中,
+    ///   页中只占 13 字节
+    /// - 内部节点约 9 字节的头部, 每个分隔 key 另加 4 字节, 每个子节点 16 字节:
+    ///   `internal_order ≈ (page_size - 5 + key_len) / (key_len + 20)`
+    /// - 同一个节点中 key 的公共前缀只存放一次, 实际能放下的更多; 加密的文件中每页少 40 字节
+    ///
+    /// 例如 4096 字节的页, 16 字节的 key 与 500 字节的值, 叶子节点的 order 约为 8, 内部节点约为 114
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::builder().leaf_order(8).internal_order(114).build();
+    /// tree.put_batch((0..1000).map(|i| (format!("{:016}", i), "v".repeat(500)))).unwrap();
+    /// assert_eq!((tree.leaf_order(), tree.order()), (8, 114));
+    /// // 内部节点的路数大, 上百个叶子节点上面只有两层内部节点
+    /// assert!(tree.stats().height <= 3);
+    /// ```
+    pub fn leaf_order(mut self, order: usize) -> Self {
+        self.leaf_order = order;
+        self
+    }
+
+    /// 内部节点的最大路数, 即 [`BPTree::order`], 小于 3 时按 3 处理, 见 [`leaf_order`](Self::leaf_order)
+    pub fn internal_order(mut self, order: usize) -> Self {
+        self.internal_order = order;
+        self
+    }
+
+    /// 叶子节点分裂时的分配方式
+    pub fn split_policy(mut self, policy: SplitPolicy) -> Self {
+        self.split_policy = policy;
+        self
+    }
+
+    /// 使用自定义的 key 顺序代替按字节比较, 见 [`Comparator`]
+    ///
+    /// 比较器不会保存在文件或序列化的结果中, 只能用于内存中的树
+    pub fn comparator<C: Comparator + 'static>(mut self, comparator: C) -> Self {
+        self.key_order = KeyOrder::new(comparator);
+        self
+    }
+
+    /// 预先为 `capacity` 个节点分配空间, 预计会插入大量数据时可以减少扩容的次数
+    pub fn node_capacity(mut self, capacity: usize) -> Self {
+        self.node_capacity = capacity;
+        self
+    }
+
+    /// 注册 [`BPTree::merge`] 使用的合并函数, 见 [`MergeOperator`]
+    pub fn merge_operator<M: MergeOperator + 'static>(mut self, merge_operator: M) -> Self {
+        self.merge_operator = Some(Merger::new(merge_operator));
+        self
+    }
+
+    /// 创建一棵空树
+    pub fn build(self) -> BPTree {
+        let mut tree = BPTree::with_fanout(Fanout::new(self.leaf_order, self.internal_order));
+        tree.set_split_policy(self.split_policy);
+        tree.key_order = self.key_order;
+        tree.nodes.reserve(self.node_capacity);
+        tree.merge_operator = self.merge_operator;
+        tree
+    }
+
+    /// 在 `path` 创建一棵以页的形式存放在文件中的空树, 与 [`BPTree::create`] 相同, 但使用这里的配置
+    ///
+    /// 叶子节点与内部节点的 order 都会保存在文件中; 设置了比较器时返回 [`io::ErrorKind::InvalidInput`],
+    /// 合并函数与分裂方式不会保存, 重新打开后需要再设置
+    pub fn create<P: AsRef<Path>>(self, path: P, page_size: usize) -> io::Result<BPTree> {
+        if !self.key_order.is_bytes() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "files only support byte-ordered keys"));
+        }
+        self.build().attach(path.as_ref(), page_size, None)
+    }
+
+    /// 创建一棵允许重复 key 的空树, 见 [`BPTreeMultimap`]
+    pub fn build_multimap(self) -> BPTreeMultimap {
+        BPTreeMultimap::new(self.build())
+    }
+
+    /// 创建一棵每个 key 保存多个版本的空树, 见 [`VersionedBPTree`]
+    pub fn build_versioned(self) -> VersionedBPTree {
+        VersionedBPTree::new(self.build())
+    }
+
+    /// 创建一棵节点按 key 的公共前缀压缩存放的空树, 见 [`PrefixBPTree`]
+    ///
+    /// # Panics
+    ///
+    /// 设置了比较器时 panic, 前缀压缩只支持按字节比较的 key
+    pub fn build_prefix(self) -> PrefixBPTree {
+        PrefixBPTree::from_tree(self.build())
+    }
+
+    /// 创建一棵删除时只写入删除标记的空树, 见 [`TombstoneBPTree`]
+    pub fn build_tombstoned(self) -> TombstoneBPTree {
+        TombstoneBPTree::new(self.build())
+    }
+
+    /// 创建一棵压缩存放不短于 `threshold` 字节的值的空树, 见 [`CompressedBPTree`]
+    #[cfg(feature = "lz4")]
+    pub fn build_compressed(self, threshold: usize) -> CompressedBPTree {
+        CompressedBPTree::new(self.build(), threshold)
+    }
+}
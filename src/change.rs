@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::ops::Bound;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use crate::bptree::BPTree;
+
+/// 一次修改, 由 [`BPTree::subscribe`] 返回的 [`ChangeReceiver`] 接收
+///
+/// 插入时 `old_value` 为 `None`, 删除时 `new_value` 为 `None`, 更新时两者都有值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub key: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// [`ChangeReceiver`] 接收失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// 缓冲区满时最早的事件被丢弃, 自上一次接收以来丢弃了这么多个, 接收端需要重新同步树的内容
+    Lagged(u64),
+    /// 树已经被 drop, 缓冲区中的事件也已经全部接收
+    Closed,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Lagged(skipped) => write!(f, "receiver lagged behind, {} events skipped", skipped),
+            RecvError::Closed => write!(f, "tree has been dropped"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+#[derive(Debug)]
+struct State {
+    events: VecDeque<ChangeEvent>,
+    capacity: usize,
+    skipped: u64,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // 持有锁时不会 panic, 锁不会中毒
+        self.state.lock().unwrap_or_else(|_error| _error.into_inner())
+    }
+}
+
+/// 树一侧的所有订阅, 树被 drop 时通知所有接收端
+#[derive(Debug, Default)]
+pub(crate) struct ChangeFeed {
+    subscribers: Vec<Arc<Shared>>,
+}
+
+impl ChangeFeed {
+    /// 是否有订阅, 没有订阅时修改不需要复制键值对
+    pub(crate) fn is_active(&self) -> bool {
+        !self.subscribers.is_empty()
+    }
+
+    pub(crate) fn send(&mut self, key: String, old_value: Option<String>, new_value: Option<String>) {
+        // 接收端已经被 drop 的订阅直接移除
+        self.subscribers.retain(|_shared| Arc::strong_count(_shared) > 1);
+        let event = ChangeEvent { key, old_value, new_value };
+        for shared in &self.subscribers {
+            let mut state = shared.lock();
+            // 写入永远不阻塞, 缓冲区满时丢弃最早的事件
+            if state.events.len() == state.capacity {
+                state.events.pop_front();
+                state.skipped += 1;
+            }
+            state.events.push_back(event.clone());
+            shared.ready.notify_one();
+        }
+    }
+}
+
+impl Drop for ChangeFeed {
+    fn drop(&mut self) {
+        for shared in &self.subscribers {
+            shared.lock().closed = true;
+            shared.ready.notify_all();
+        }
+    }
+}
+
+/// 修改事件的接收端, 由 [`BPTree::subscribe`] 创建, 可以移到其他线程中接收
+///
+/// 事件按修改的顺序排列, 缓冲区最多保存创建时指定数量的事件.
+/// 树的修改不会等待接收端, 缓冲区满时丢弃最早的事件, 下一次接收返回 [`RecvError::Lagged`]
+pub struct ChangeReceiver {
+    shared: Arc<Shared>,
+}
+
+impl ChangeReceiver {
+    /// 等待下一个事件
+    pub fn recv(&self) -> Result<ChangeEvent, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(event) = Self::take(&mut state)? {
+                return Ok(event);
+            }
+            if state.closed {
+                return Err(RecvError::Closed);
+            }
+            state = self.shared.ready.wait(state).unwrap_or_else(|_error| _error.into_inner());
+        }
+    }
+
+    /// 不等待, 缓冲区为空时返回 `Ok(None)`
+    pub fn try_recv(&self) -> Result<Option<ChangeEvent>, RecvError> {
+        let mut state = self.shared.lock();
+        match Self::take(&mut state)? {
+            None if state.closed => Err(RecvError::Closed),
+            event => Ok(event),
+        }
+    }
+
+    fn take(state: &mut State) -> Result<Option<ChangeEvent>, RecvError> {
+        // 先报告丢弃的事件, 再继续接收缓冲区中剩下的事件
+        if state.skipped > 0 {
+            return Err(RecvError::Lagged(std::mem::take(&mut state.skipped)));
+        }
+        Ok(state.events.pop_front())
+    }
+}
+
+impl fmt::Debug for ChangeReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChangeReceiver").field("pending", &self.shared.lock().events.len()).finish()
+    }
+}
+
+impl BPTree {
+    /// 订阅之后的每一次插入、更新与删除, 缓冲区最多保存 `capacity` 个事件 (至少为 1)
+    ///
+    /// [`put_batch`](Self::put_batch)、[`remove_range`](Self::remove_range)、[`append`](Self::append)
+    /// 等批量修改按 key 的顺序为每个键值对发出一个事件; 通过 [`get_mut`](Self::get_mut) 等返回的 [`ValueMut`](crate::ValueMut)
+    /// 修改值时在 [`commit`](crate::ValueMut::commit) 时发出事件, 有订阅时不能使用 [`iter_mut`](Self::iter_mut).
+    /// 复制出的树 ([`Clone`]) 不带有订阅
+    ///
+    /// ```
+    /// use btree_test::{BPTree, ChangeEvent, RecvError};
+    ///
+    /// let mut tree = BPTree::new(4);
+    /// let changes = tree.subscribe(2);
+    /// tree.put("a".to_string(), "1".to_string()).unwrap();
+    /// tree.put("a".to_string(), "2".to_string()).unwrap();
+    /// assert_eq!(
+    ///     changes.recv(),
+    ///     Ok(ChangeEvent { key: "a".to_string(), old_value: None, new_value: Some("1".to_string()) })
+    /// );
+    ///
+    /// // 缓冲区满时丢弃最早的事件
+    /// tree.remove("a").unwrap();
+    /// tree.put("b".to_string(), "1".to_string()).unwrap();
+    /// assert_eq!(changes.recv(), Err(RecvError::Lagged(1)));
+    /// assert_eq!(changes.recv().unwrap().old_value.as_deref(), Some("2"));
+    /// assert_eq!(changes.recv().unwrap().key, "b");
+    ///
+    /// drop(tree);
+    /// assert_eq!(changes.recv(), Err(RecvError::Closed));
+    /// ```
+    pub fn subscribe(&mut self, capacity: usize) -> ChangeReceiver {
+        let capacity = capacity.max(1);
+        let state = State { events: VecDeque::with_capacity(capacity), capacity, skipped: 0, closed: false };
+        let shared = Arc::new(Shared { state: Mutex::new(state), ready: Condvar::new() });
+        self.changes.subscribers.push(shared.clone());
+        ChangeReceiver { shared }
+    }
+
+    pub(crate) fn changed_entries(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(String, String)> {
+        // 批量删除之前复制范围内的键值对, 之后逐个发出事件; 没有订阅时不复制
+        if !self.changes.is_active() {
+            return vec![];
+        }
+        let mut range = self.range(start, end);
+        std::iter::from_fn(|| range.next_owned()).map(|(key, value)| (key, value.to_string())).collect()
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::io;
+#[cfg(feature = "encryption")]
+use std::path::Path;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+#[cfg(feature = "encryption")]
+use crate::bptree::BPTree;
+#[cfg(feature = "encryption")]
+use crate::paged::PagedBPTree;
+use crate::pager::invalid_data;
+
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+/// 加密页、溢出值与日志记录的密钥, 需要开启 `encryption` feature
+///
+/// 使用 XChaCha20-Poly1305, 每次加密生成一个随机的 24 字节 nonce 放在密文前面, 加密后比原文多 [`OVERHEAD`](Self::OVERHEAD) 字节.
+/// 附加数据为内容在文件中的位置, 页被整个复制到别的位置时也无法通过校验.
+/// 没有开启 feature 时不能创建, 持有它的代码不需要区分是否开启
+#[derive(Clone)]
+pub(crate) struct PageCipher {
+    #[cfg(feature = "encryption")]
+    aead: XChaCha20Poly1305,
+    #[cfg(not(feature = "encryption"))]
+    never: std::convert::Infallible,
+}
+
+impl PageCipher {
+    /// 加密后增加的字节数: nonce 与认证标签
+    pub(crate) const OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+    #[cfg(feature = "encryption")]
+    pub(crate) fn new(key: &[u8; 32]) -> Self {
+        Self { aead: XChaCha20Poly1305::new(key.into()) }
+    }
+
+    /// 加密 `plaintext`, 返回 nonce 与密文
+    pub(crate) fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        #[cfg(feature = "encryption")]
+        {
+            let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let ciphertext = self.aead.encrypt(&nonce, Payload { msg: plaintext, aad }).expect("plaintext fits in memory");
+            let mut sealed = Vec::with_capacity(Self::OVERHEAD + plaintext.len());
+            sealed.extend_from_slice(&nonce);
+            sealed.extend_from_slice(&ciphertext);
+            sealed
+        }
+        #[cfg(not(feature = "encryption"))]
+        {
+            let _ = (aad, plaintext);
+            match self.never {}
+        }
+    }
+
+    /// 解密 [`seal`](Self::seal) 的结果, 密钥不对或内容被修改时返回 [`io::ErrorKind::InvalidData`]
+    pub(crate) fn open(&self, aad: &[u8], sealed: &[u8]) -> io::Result<Vec<u8>> {
+        if sealed.len() < Self::OVERHEAD {
+            return Err(invalid_data("encrypted data is truncated"));
+        }
+        #[cfg(feature = "encryption")]
+        {
+            let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+            self.aead
+                .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+                .map_err(|_| invalid_data("wrong key or corrupted encrypted data"))
+        }
+        #[cfg(not(feature = "encryption"))]
+        {
+            let _ = aad;
+            match self.never {}
+        }
+    }
+}
+
+impl fmt::Debug for PageCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 不输出密钥
+        f.write_str("PageCipher")
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl BPTree {
+    /// 与 [`create`](Self::create) 相同, 但写入文件的节点页、溢出文件中的值与预写日志都用 `key` 加密, 需要开启 `encryption` feature
+    ///
+    /// 加密的页中有 40 字节用于存放 nonce 与认证标签, `page_size` 至少为 128.
+    /// 文件头不加密, 但会被校验, 之后只能用同一个 `key` 通过 [`open_encrypted`](Self::open_encrypted) 打开
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let path = std::env::temp_dir().join(format!("btree-test-doc-encrypted-{}", std::process::id()));
+    /// let key = [7u8; 32];
+    /// let mut tree = BPTree::create_encrypted(&path, 4, 512, &key).unwrap();
+    /// tree.put("secret".to_string(), "value".to_string()).unwrap();
+    /// tree.checkpoint().unwrap();
+    /// drop(tree);
+    ///
+    /// assert!(!std::fs::read(&path).unwrap().windows(6).any(|_w| _w == b"secret"));
+    /// assert!(BPTree::open(&path).is_err());
+    /// assert!(BPTree::open_encrypted(&path, &[8u8; 32]).is_err());
+    /// let tree = BPTree::open_encrypted(&path, &key).unwrap();
+    /// assert_eq!(tree.get("secret").map(|_kv| _kv.value()), Some("value"));
+    /// # for extension in ["", ".wal", ".ovf"] {
+    /// #     std::fs::remove_file(format!("{}{}", path.display(), extension)).unwrap();
+    /// # }
+    /// ```
+    pub fn create_encrypted<P: AsRef<Path>>(path: P, order: usize, page_size: usize, key: &[u8; 32]) -> io::Result<Self> {
+        Self::create_with(path.as_ref(), order, page_size, Some(PageCipher::new(key)))
+    }
+
+    /// 用创建时的 `key` 打开 [`create_encrypted`](Self::create_encrypted) 创建的文件
+    ///
+    /// 密钥不对时返回 [`io::ErrorKind::InvalidData`], 文件没有加密时返回 [`io::ErrorKind::InvalidInput`]
+    pub fn open_encrypted<P: AsRef<Path>>(path: P, key: &[u8; 32]) -> io::Result<Self> {
+        Self::open_with(path.as_ref(), Some(PageCipher::new(key)))
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl PagedBPTree {
+    /// 与 [`create`](Self::create) 相同, 但写入文件的节点页与溢出文件中的值都用 `key` 加密, 见 [`BPTree::create_encrypted`]
+    pub fn create_encrypted<P: AsRef<Path>>(
+        path: P,
+        order: usize,
+        page_size: usize,
+        memory_budget: usize,
+        key: &[u8; 32],
+    ) -> io::Result<Self> {
+        Self::create_with(path.as_ref(), order, page_size, memory_budget, Some(PageCipher::new(key)))
+    }
+
+    /// 用创建时的 `key` 打开加密的文件, 见 [`BPTree::open_encrypted`]
+    pub fn open_encrypted<P: AsRef<Path>>(path: P, memory_budget: usize, key: &[u8; 32]) -> io::Result<Self> {
+        Self::open_with(path.as_ref(), memory_budget, Some(PageCipher::new(key)))
+    }
+}
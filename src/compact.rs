@@ -0,0 +1,70 @@
+//! 只序列化键值对的紧凑格式, 反序列化时用 [`BPTree::bulk_load`] 重新构建树结构
+//!
+//! 配合 `#[serde(with = "btree_test::compact")]` 使用:
+//!
+//! ```
+//! use btree_test::BPTree;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Snapshot {
+//!     #[serde(with = "btree_test::compact")]
+//!     tree: BPTree,
+//! }
+//!
+//! let mut tree = BPTree::new(5);
+//! tree.put("a".to_string(), "1".to_string()).unwrap();
+//! let json = serde_json::to_string(&Snapshot { tree }).unwrap();
+//! assert_eq!(json, r#"{"tree":{"order":5,"entries":[["a","1"]]}}"#);
+//!
+//! let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+//! assert_eq!(snapshot.tree.get("a").map(|kv| kv.value()), Some("1"));
+//! ```
+
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::bptree::{BPTree, Fanout};
+
+#[derive(Serialize)]
+struct CompactRef<'a> {
+    order: usize,
+    // 叶子节点的 order 与 order 相同时省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leaf_order: Option<usize>,
+    entries: Entries<'a>,
+}
+
+#[derive(Deserialize)]
+struct Compact {
+    order: usize,
+    #[serde(default)]
+    leaf_order: Option<usize>,
+    entries: Vec<(String, String)>,
+}
+
+// 按顺序逐个序列化键值对, 不需要先收集到 Vec 中
+struct Entries<'a>(&'a BPTree);
+
+impl Serialize for Entries<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for entry in self.0 {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+/// 序列化为 `order` 与按 key 排序的键值对列表, 叶子节点的 order 不同时还有 `leaf_order`
+pub fn serialize<S: Serializer>(tree: &BPTree, serializer: S) -> Result<S::Ok, S::Error> {
+    let leaf_order = Some(tree.leaf_order()).filter(|&_leaf| _leaf != tree.order());
+    CompactRef { order: tree.order(), leaf_order, entries: Entries(tree) }.serialize(serializer)
+}
+
+/// 从键值对列表重新构建树
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BPTree, D::Error> {
+    let compact = Compact::deserialize(deserializer)?;
+    let fanout = Fanout::new(compact.leaf_order.unwrap_or(compact.order), compact.order);
+    Ok(BPTree::bulk_load_with(fanout, compact.entries))
+}
@@ -0,0 +1,164 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+/// 自定义 key 的顺序, 通过 [`BPTreeBuilder::comparator`](crate::BPTreeBuilder::comparator) 设置
+///
+/// 比较的双方都是字节串: 树中的 key 总是合法的 UTF-8, 但查找时传入的 key 可以是任意的 `AsRef<[u8]>`
+///
+/// 比较器必须是一个全序, 比较结果为 [`Ordering::Equal`] 的两个 key 视为同一个 key,
+/// 例如忽略大小写时 `put("A")` 之后的 `put("a")` 会更新 `"A"` 的值
+///
+/// 所有 `Fn(&[u8], &[u8]) -> Ordering` 闭包都实现了这个 trait
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::builder()
+///     .comparator(|a: &[u8], b: &[u8]| {
+///         a.iter().map(u8::to_ascii_lowercase).cmp(b.iter().map(u8::to_ascii_lowercase))
+///     })
+///     .build();
+/// for name in ["banana", "Apple", "cherry"] {
+///     tree.put(name.to_string(), String::new()).unwrap();
+/// }
+/// assert_eq!(tree.keys().collect::<Vec<_>>(), ["Apple", "banana", "cherry"]);
+/// assert!(tree.get("APPLE").is_some());
+/// ```
+pub trait Comparator: Send + Sync {
+    /// 比较两个 key
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+}
+
+impl<F: Fn(&[u8], &[u8]) -> Ordering + Send + Sync> Comparator for F {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self(a, b)
+    }
+}
+
+/// 树使用的 key 顺序, 没有设置比较器时按字节比较
+#[derive(Clone, Default)]
+pub(crate) struct KeyOrder(Option<Arc<dyn Comparator>>);
+
+impl KeyOrder {
+    /// 按字节比较, 即 `String` 的默认顺序
+    pub(crate) const BYTES: KeyOrder = KeyOrder(None);
+
+    pub(crate) fn new<C: Comparator + 'static>(comparator: C) -> Self {
+        KeyOrder(Some(Arc::new(comparator)))
+    }
+
+    /// 是否按字节比较
+    pub(crate) fn is_bytes(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// 有序的 `keys` 中不大于 `key` 的 key 的数量, 即查找 `key` 时走向的子节点下标
+    ///
+    /// 按字节比较时使用无分支的二分查找: 循环次数只与 key 的数量有关, 每次比较先看前 8 个字节组成的整数,
+    /// 只有前缀相同时才比较完整的 key; 使用自定义比较器时退回普通的二分查找
+    pub(crate) fn child_index(&self, keys: &[String], key: &[u8]) -> usize {
+        match &self.0 {
+            Some(comparator) => keys.partition_point(|_k| comparator.compare(_k.as_bytes(), key).is_le()),
+            None => partition_bytes(keys, |_k| _k.as_bytes(), key, true),
+        }
+    }
+
+    /// 在按 `key_of` 有序的 `items` 中查找 `key`, 返回值与 [`slice::binary_search_by`] 相同, 与 [`child_index`](Self::child_index) 一样有按字节比较的快速路径
+    pub(crate) fn search<T>(&self, items: &[T], key_of: impl Fn(&T) -> &[u8], key: &[u8]) -> Result<usize, usize> {
+        let Some(comparator) = &self.0 else {
+            let idx = partition_bytes(items, &key_of, key, false);
+            return match items.get(idx) {
+                Some(item) if key_of(item) == key => Ok(idx),
+                _ => Err(idx),
+            };
+        };
+        items.binary_search_by(|_item| comparator.compare(key_of(_item), key))
+    }
+
+    pub(crate) fn cmp(&self, a: &[u8], b: &[u8]) -> Ordering {
+        match &self.0 {
+            Some(comparator) => comparator.compare(a, b),
+            None => a.cmp(b),
+        }
+    }
+
+    /// 与 [`child_index`](Self::child_index) 相同, 但 `keys` 中是去掉节点的公共前缀 `prefix` 之后的部分
+    pub(crate) fn child_index_in(&self, prefix: &str, keys: &[String], key: &[u8]) -> usize {
+        match strip(prefix, key) {
+            Ok(rest) => self.child_index(keys, rest),
+            Err(Ordering::Less) => 0,
+            Err(_) => keys.len(),
+        }
+    }
+
+    /// 与 [`search`](Self::search) 相同, 但 `items` 中的 key 是去掉节点的公共前缀 `prefix` 之后的部分
+    pub(crate) fn search_in<T>(&self, prefix: &str, items: &[T], key_of: impl Fn(&T) -> &[u8], key: &[u8]) -> Result<usize, usize> {
+        match strip(prefix, key) {
+            Ok(rest) => self.search(items, key_of, rest),
+            Err(Ordering::Less) => Err(0),
+            Err(_) => Err(items.len()),
+        }
+    }
+
+    /// 有序且互不相同的 `items` 中小于 `key` (`inclusive` 时为不大于 `key`) 的 key 的数量, key 同样是去掉 `prefix` 之后的部分
+    pub(crate) fn partition_in<T>(&self, prefix: &str, items: &[T], key_of: impl Fn(&T) -> &[u8], key: &[u8], inclusive: bool) -> usize {
+        match self.search_in(prefix, items, key_of, key) {
+            Ok(idx) => idx + usize::from(inclusive),
+            Err(idx) => idx,
+        }
+    }
+
+    /// `prefix` 与 `suffix` 拼接成的 key 与 `key` 比较, 不需要拼接
+    pub(crate) fn cmp_in(&self, prefix: &str, suffix: &[u8], key: &[u8]) -> Ordering {
+        match strip(prefix, key) {
+            Ok(rest) => self.cmp(suffix, rest),
+            Err(ordering) => ordering.reverse(),
+        }
+    }
+}
+
+// 去掉 key 开头的 prefix; key 不以 prefix 开头时它小于或大于所有以 prefix 开头的 key, 返回 key 与它们的大小关系
+//
+// 只有按字节比较时节点才会按前缀压缩, 见 BPTreeNode::compress
+fn strip<'a>(prefix: &str, key: &'a [u8]) -> Result<&'a [u8], Ordering> {
+    key.strip_prefix(prefix.as_bytes()).ok_or_else(|| key.cmp(prefix.as_bytes()))
+}
+
+// 小于 key (include_equal 时为不大于 key) 的元素数量
+fn partition_bytes<T>(items: &[T], key_of: impl Fn(&T) -> &[u8], key: &[u8], include_equal: bool) -> usize {
+    if items.is_empty() {
+        return 0;
+    }
+    let prefix = self::prefix(key);
+    let before = |_item: &T| {
+        let _key = key_of(_item);
+        let _prefix = self::prefix(_key);
+        _prefix < prefix || (_prefix == prefix && if include_equal { _key <= key } else { _key < key })
+    };
+    let (mut base, mut size) = (0, items.len());
+    while size > 1 {
+        let half = size / 2;
+        // 编译为条件传送而不是分支
+        base = if before(&items[base + half]) { base + half } else { base };
+        size -= half;
+    }
+    base + usize::from(before(&items[base]))
+}
+
+/// key 的前 8 个字节按大端序组成的整数, 不足 8 个字节时补 0; 整数的大小关系与字节串的前缀一致
+fn prefix(key: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    let len = key.len().min(8);
+    bytes[..len].copy_from_slice(&key[..len]);
+    u64::from_be_bytes(bytes)
+}
+
+impl fmt::Debug for KeyOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Custom"),
+            None => write!(f, "Bytes"),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+use std::ops::Bound;
+
+use crate::bptree::{BPTree, MemoryStats};
+use crate::error::BPTreeError;
+use crate::iter::Range;
+
+// 存放的值以一个字符标记编码方式: 原样存放, 或者 lz4 压缩后再编码为 base64
+const RAW: char = 'r';
+const LZ4: char = 'z';
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// 透明压缩值的 B+Tree, 由 [`BPTreeBuilder::build_compressed`](crate::BPTreeBuilder::build_compressed) 创建, 需要开启 `lz4` feature
+///
+/// 不短于 `threshold` 字节的值用 lz4 压缩后存放, [`get`](Self::get) 与 [`range`](Self::range) 读取时解压,
+/// 短的值原样存放, 读取时不需要复制. 节点中的值只能是 `String`, 压缩后的字节编码为 base64 存放, 多占用 1/3 的空间,
+/// 因此压缩后仍不比原来小的值也原样存放; JSON 这类重复较多的值一般可以缩小到原来的几分之一
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::builder().order(16).build_compressed(64);
+/// let blob = format!("{{\"tags\": [{}]}}", vec!["\"rust\""; 100].join(", "));
+/// tree.put("doc:1".to_string(), blob.clone()).unwrap();
+/// tree.put("doc:2".to_string(), "{}".to_string()).unwrap();
+/// assert_eq!(tree.get("doc:1").as_deref(), Some(blob.as_str()));
+/// assert_eq!(tree.get("doc:2").as_deref(), Some("{}"));
+/// assert!(tree.memory_usage().values < blob.len() / 4);
+/// ```
+#[derive(Debug)]
+pub struct CompressedBPTree {
+    tree: BPTree,
+    threshold: usize,
+}
+
+impl CompressedBPTree {
+    pub(crate) fn new(tree: BPTree, threshold: usize) -> Self {
+        Self { tree, threshold }
+    }
+
+    /// 键值对的数量
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// 没有任何键值对
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// 需要压缩的值的最小字节数
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// 插入键值对, key 已存在时更新其值并返回旧值
+    pub fn put(&mut self, key: String, value: String) -> Result<Option<String>, BPTreeError> {
+        let old_value = self.tree.put(key, encode(value, self.threshold))?;
+        Ok(old_value.map(|_old| decode(&_old).into_owned()))
+    }
+
+    /// 按 key 查找值, 压缩存放的值会被解压
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<Cow<'_, str>> {
+        self.tree.get(key).map(|_kv| decode(_kv.value()))
+    }
+
+    /// 删除 key, 返回被删除的值
+    pub fn remove<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        let old_value = self.tree.remove(key)?;
+        Ok(old_value.map(|_old| decode(&_old).into_owned()))
+    }
+
+    /// 按 key 的顺序遍历 `start` 到 `end` 之间的键值对, 遍历到的值才会被解压
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> CompressedRange<'_> {
+        CompressedRange { inner: self.tree.range(start, end) }
+    }
+
+    /// 按 key 的顺序遍历所有键值对
+    pub fn iter(&self) -> CompressedRange<'_> {
+        self.range::<str>(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// 内部的树占用的内存, 其中 `values` 为压缩后的大小, 见 [`BPTree::memory_usage`]
+    pub fn memory_usage(&self) -> MemoryStats {
+        self.tree.memory_usage()
+    }
+}
+
+/// 按 key 顺序遍历 [`CompressedBPTree`] 中键值对的迭代器, 返回解压后的值
+pub struct CompressedRange<'a> {
+    inner: Range<'a>,
+}
+
+impl<'a> Iterator for CompressedRange<'a> {
+    type Item = (&'a str, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, value)| (key, decode(value)))
+    }
+}
+
+impl DoubleEndedIterator for CompressedRange<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, value)| (key, decode(value)))
+    }
+}
+
+fn encode(value: String, threshold: usize) -> String {
+    if value.len() >= threshold {
+        let compressed = lz4_flex::compress_prepend_size(value.as_bytes());
+        // base64 每 3 个字节编码为 4 个字符
+        if compressed.len().div_ceil(3) * 4 < value.len() {
+            let mut stored = String::with_capacity(1 + compressed.len().div_ceil(3) * 4);
+            stored.push(LZ4);
+            base64_encode(&compressed, &mut stored);
+            return stored;
+        }
+    }
+    let mut stored = String::with_capacity(1 + value.len());
+    stored.push(RAW);
+    stored.push_str(&value);
+    stored
+}
+
+fn decode(stored: &str) -> Cow<'_, str> {
+    // 只读的方法不返回错误, 存放的值损坏时直接 panic
+    match stored.strip_prefix(LZ4) {
+        Some(encoded) => {
+            let compressed = base64_decode(encoded).expect("compressed value is not valid base64");
+            let bytes = lz4_flex::decompress_size_prepended(&compressed).expect("compressed value is corrupted");
+            Cow::Owned(String::from_utf8(bytes).expect("decompressed value is not valid UTF-8"))
+        }
+        None => Cow::Borrowed(stored.strip_prefix(RAW).expect("stored value has no encoding tag")),
+    }
+}
+
+fn base64_encode(bytes: &[u8], out: &mut String) {
+    // 不补齐 '=', 最后一组不足 3 个字节时只输出需要的字符
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |_acc, (_i, &_b)| _acc | u32::from(_b) << (16 - 8 * _i));
+        for i in 0..=chunk.len() {
+            out.push(char::from(BASE64[(group >> (18 - 6 * i)) as usize & 63]));
+        }
+    }
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3 + 2);
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut group = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            group |= sextet(c)? << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn sextet(c: u8) -> Option<u32> {
+    let value = match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return None,
+    };
+    Some(u32::from(value))
+}
@@ -0,0 +1,505 @@
+use std::fmt;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::bptree::BPTreeKeyValue;
+use crate::error::CasError;
+use crate::slab::NodeId;
+
+// 节点表按块分配, 第 n 块有 CHUNK_BASE << n 个槽, 块分配之后不再移动, 节点的锁可以一直借用
+const CHUNK_BASE: usize = 64;
+const CHUNK_COUNT: usize = 48;
+
+/// 可以被多个线程同时读写的 B+Tree, 所有方法都只需要 `&self`
+///
+/// 每个节点有一把自己的读写锁, 查找与修改从根节点向下逐层加锁 (latch crabbing):
+/// 取得子节点的锁之后才释放父节点的锁, 因此不同叶子节点上的读写可以同时进行, 而不是整棵树共用一个 `Mutex`
+///
+/// - 查找对经过的节点加读锁
+/// - 插入先按查找的方式加读锁, 只对叶子节点加写锁; 叶子节点已满需要分裂时,
+///   从根节点重新向下加写锁, 遇到插入后不会分裂的节点时释放它之上的所有锁
+/// - 删除只对叶子节点加写锁; 叶子节点被删空时从根节点重新向下加写锁, 在父节点的写锁下把它从父节点与叶子链表中摘掉
+///   (它是父节点的第一个子节点时, 改为把右边的兄弟节点并入它), 摘下的节点交给之后的分配复用.
+///   内部节点不合并, 只剩一个子节点的内部节点下被删空的叶子节点仍然留在树中, 之后的插入可以继续使用
+///
+/// 键值对按字节排序, 不支持自定义比较器; 返回的值是复制出来的 `String`,
+/// [`range`](Self::range) 不是快照, 遍历期间其他线程的修改可能部分可见
+///
+/// ```
+/// use std::ops::Bound;
+/// use btree_test::ConcurrentBPTree;
+///
+/// let tree = ConcurrentBPTree::new(8);
+/// std::thread::scope(|_s| {
+///     for t in 0..4 {
+///         let tree = &tree;
+///         _s.spawn(move || {
+///             for i in 0..100 {
+///                 tree.put(format!("{}-{:03}", t, i), i.to_string());
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(tree.len(), 400);
+/// assert_eq!(tree.get("2-042"), Some("42".to_string()));
+/// assert_eq!(tree.range(Bound::Included("3-"), Bound::Unbounded).len(), 100);
+/// ```
+pub struct ConcurrentBPTree {
+    order: usize,
+    nodes: NodeTable,
+    // 根节点的编号, 根节点分裂时需要持有它的写锁
+    root: RwLock<NodeId>,
+    // 叶子节点只会向右分裂, 第一个叶子节点始终不变
+    first_leaf: NodeId,
+    len: AtomicUsize,
+}
+
+enum Node {
+    Internal { keys: Vec<String>, child: Vec<NodeId> },
+    Leaf { kvs: Vec<BPTreeKeyValue>, next: Option<NodeId> },
+}
+
+impl Node {
+    fn len(&self) -> usize {
+        match self {
+            Node::Internal { keys, .. } => keys.len(),
+            Node::Leaf { kvs, .. } => kvs.len(),
+        }
+    }
+
+    /// 内部节点中 key 所在的子节点, 叶子节点返回 `None`
+    fn child_for(&self, key: &[u8]) -> Option<NodeId> {
+        match self {
+            Node::Internal { keys, child } => Some(child[child_index(keys, key)]),
+            Node::Leaf { .. } => None,
+        }
+    }
+}
+
+impl ConcurrentBPTree {
+    /// 创建一棵空树, `order` 为节点的最大路数, 小于 3 时按 3 处理
+    pub fn new(order: usize) -> Self {
+        let order = order.max(3);
+        let nodes = NodeTable::new();
+        let root = nodes.alloc(Node::Leaf { kvs: vec![], next: None });
+        Self {
+            order,
+            nodes,
+            root: RwLock::new(root),
+            first_leaf: root,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// 节点的最大路数
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// 键值对的数量
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// 树中正在使用的节点数量, 被删空后摘下的叶子节点不计算在内
+    ///
+    /// ```
+    /// use std::ops::Bound;
+    /// use btree_test::ConcurrentBPTree;
+    ///
+    /// let tree = ConcurrentBPTree::new(4);
+    /// for i in 0..1000 {
+    ///     tree.put(format!("{:04}", i), i.to_string());
+    /// }
+    /// let full = tree.node_count();
+    /// for i in 0..990 {
+    ///     tree.remove(&format!("{:04}", i));
+    /// }
+    /// // 被删空的叶子节点从树中摘下, 每个内部节点下只留一个叶子节点, 内部节点不回收
+    /// assert!(tree.node_count() < full * 2 / 3);
+    /// assert_eq!(tree.range::<str>(Bound::Unbounded, Bound::Unbounded).len(), 10);
+    /// ```
+    pub fn node_count(&self) -> usize {
+        self.nodes.next_offset.load(Ordering::Relaxed) - self.nodes.free.lock().expect("free list poisoned").len()
+    }
+
+    /// 树中没有键值对
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 按 key 查找值
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<String> {
+        let key = key.as_ref();
+        let leaf = self.read_leaf(key);
+        let Node::Leaf { kvs, .. } = &*leaf else { unreachable!("expected a leaf") };
+        kvs.binary_search_by(|_kv| _kv.key.as_bytes().cmp(key))
+            .ok()
+            .map(|idx| kvs[idx].value.clone())
+    }
+
+    /// 插入键值对, key 已存在时更新其值并返回旧值
+    pub fn put(&self, key: String, value: String) -> Option<String> {
+        {
+            // 乐观地假设叶子节点不需要分裂, 只锁住叶子节点
+            let mut leaf = self.write_leaf(key.as_bytes());
+            let Node::Leaf { kvs, .. } = &mut *leaf else { unreachable!("expected a leaf") };
+            match kvs.binary_search_by(|_kv| _kv.key.as_bytes().cmp(key.as_bytes())) {
+                Ok(idx) => return Some(std::mem::replace(&mut kvs[idx].value, value)),
+                Err(idx) if kvs.len() < self.order - 1 => {
+                    kvs.insert(idx, BPTreeKeyValue { key, value });
+                    self.len.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+                Err(_) => {}
+            }
+        }
+        self.put_split(key, value, true)
+    }
+
+    /// 当前的值等于 `expected` 时才修改, 与 [`BPTree::compare_and_swap`](crate::BPTree::compare_and_swap) 相同
+    ///
+    /// 比较与修改在同一把叶子节点的写锁下完成, 其他线程看不到中间状态, 可以在它之上实现乐观并发控制:
+    /// 读出当前的值, 计算新值, 比较并交换, 失败时重试
+    ///
+    /// ```
+    /// use btree_test::ConcurrentBPTree;
+    ///
+    /// let tree = ConcurrentBPTree::new(8);
+    /// std::thread::scope(|_s| {
+    ///     for _ in 0..4 {
+    ///         _s.spawn(|| {
+    ///             for _ in 0..100 {
+    ///                 loop {
+    ///                     let current = tree.get("counter");
+    ///                     let next = current.as_deref().map_or(1, |_v| _v.parse::<u32>().unwrap() + 1);
+    ///                     if tree.compare_and_swap("counter".to_string(), current.as_deref(), Some(next.to_string())).is_ok() {
+    ///                         break;
+    ///                     }
+    ///                 }
+    ///             }
+    ///         });
+    ///     }
+    /// });
+    /// assert_eq!(tree.get("counter"), Some("400".to_string()));
+    /// ```
+    pub fn compare_and_swap(&self, key: String, expected: Option<&str>, new: Option<String>) -> Result<(), CasError> {
+        let value = {
+            let mut leaf = self.write_leaf(key.as_bytes());
+            let Node::Leaf { kvs, .. } = &mut *leaf else { unreachable!("expected a leaf") };
+            let found = kvs.binary_search_by(|_kv| _kv.key.as_bytes().cmp(key.as_bytes()));
+            let current = found.ok().map(|_idx| kvs[_idx].value.as_str());
+            if current != expected {
+                return Err(CasError::Mismatch { current: current.map(str::to_string) });
+            }
+            match (found, new) {
+                (Ok(idx), Some(value)) => {
+                    kvs[idx].value = value;
+                    return Ok(());
+                }
+                (Ok(idx), None) => {
+                    kvs.remove(idx);
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    if kvs.is_empty() {
+                        drop(leaf);
+                        self.reclaim(key.as_bytes());
+                    }
+                    return Ok(());
+                }
+                (Err(_), None) => return Ok(()),
+                (Err(idx), Some(value)) if kvs.len() < self.order - 1 => {
+                    kvs.insert(idx, BPTreeKeyValue { key, value });
+                    self.len.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                (Err(_), Some(value)) => value,
+            }
+        };
+        // 需要分裂时重新加锁, 期间其他线程可能已经插入了这个 key
+        match self.put_split(key, value, false) {
+            Some(current) => Err(CasError::Mismatch { current: Some(current) }),
+            None => Ok(()),
+        }
+    }
+
+    /// 删除 key, 返回被删除的值
+    pub fn remove<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<String> {
+        let key = key.as_ref();
+        let mut leaf = self.write_leaf(key);
+        let Node::Leaf { kvs, .. } = &mut *leaf else { unreachable!("expected a leaf") };
+        let idx = kvs.binary_search_by(|_kv| _kv.key.as_bytes().cmp(key)).ok()?;
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        let value = kvs.remove(idx).value;
+        if kvs.is_empty() {
+            drop(leaf);
+            self.reclaim(key);
+        }
+        Some(value)
+    }
+
+    /// 按 key 的顺序取出 `start` 到 `end` 之间的键值对
+    ///
+    /// 沿叶子节点链表向后遍历时, 取得下一个叶子节点的读锁之后才释放当前叶子节点的锁
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> Vec<(String, String)> {
+        let start = start.map(AsRef::as_ref);
+        let end = end.map(AsRef::as_ref);
+        let mut leaf = match start {
+            Bound::Included(key) | Bound::Excluded(key) => self.read_leaf(key),
+            Bound::Unbounded => read(self.nodes.get(self.first_leaf)),
+        };
+
+        let mut result = vec![];
+        loop {
+            let Node::Leaf { kvs, next } = &*leaf else { unreachable!("leaf chain points to an internal node") };
+            for kv in kvs {
+                let key = kv.key.as_bytes();
+                let after_start = match start {
+                    Bound::Included(start) => key >= start,
+                    Bound::Excluded(start) => key > start,
+                    Bound::Unbounded => true,
+                };
+                let before_end = match end {
+                    Bound::Included(end) => key <= end,
+                    Bound::Excluded(end) => key < end,
+                    Bound::Unbounded => true,
+                };
+                if !before_end {
+                    return result;
+                }
+                if after_start {
+                    result.push((kv.key.clone(), kv.value.clone()));
+                }
+            }
+            let Some(next) = *next else { return result; };
+            leaf = read(self.nodes.get(next));
+        }
+    }
+
+    /// 从根节点向下加读锁, 返回 key 所在的叶子节点的读锁
+    fn read_leaf(&self, key: &[u8]) -> RwLockReadGuard<'_, Node> {
+        let root = read(&self.root);
+        let mut guard = read(self.nodes.get(*root));
+        drop(root);
+        while let Some(child) = guard.child_for(key) {
+            // 先取得子节点的锁, 赋值时才释放父节点的锁
+            guard = read(self.nodes.get(child));
+        }
+        guard
+    }
+
+    /// 与 `read_leaf` 相同, 但对叶子节点加写锁
+    fn write_leaf(&self, key: &[u8]) -> RwLockWriteGuard<'_, Node> {
+        // 分裂叶子节点需要持有父节点 (或根节点编号) 的写锁, 持有它们的读锁时可以放心地把叶子节点的读锁换成写锁
+        let root = read(&self.root);
+        let root_latch = self.nodes.get(*root);
+        let mut parent = read(root_latch);
+        if parent.child_for(key).is_none() {
+            drop(parent);
+            return write(root_latch);
+        }
+        drop(root);
+        loop {
+            let child = parent.child_for(key).expect("parent is an internal node");
+            let latch = self.nodes.get(child);
+            let guard = read(latch);
+            if guard.child_for(key).is_none() {
+                drop(guard);
+                return write(latch);
+            }
+            parent = guard;
+        }
+    }
+
+    /// 叶子节点已满时的插入, 从根节点重新向下加写锁
+    ///
+    /// key 已存在时, `replace` 为 `true` 则替换并返回旧值, 否则不做修改并返回当前的值
+    fn put_split(&self, key: String, value: String, replace: bool) -> Option<String> {
+        // 插入后不会分裂的节点之上的锁都可以释放, 剩下的锁就是分裂可能影响到的节点
+        let mut root = Some(write(&self.root));
+        let mut path: Vec<(NodeId, RwLockWriteGuard<'_, Node>)> = vec![];
+        let mut offset = **root.as_ref().expect("root latch is held");
+        loop {
+            let guard = write(self.nodes.get(offset));
+            if guard.len() < self.order - 1 {
+                root = None;
+                path.clear();
+            }
+            let child = guard.child_for(key.as_bytes());
+            path.push((offset, guard));
+            match child {
+                Some(child) => offset = child,
+                None => break,
+            }
+        }
+
+        // 等待写锁期间其他线程可能已经插入了同一个 key, 或者叶子节点已经被分裂
+        let (leaf_offset, mut leaf) = path.pop().expect("path ends at a leaf");
+        let Node::Leaf { kvs, next } = &mut *leaf else { unreachable!("expected a leaf") };
+        match kvs.binary_search_by(|_kv| _kv.key.as_bytes().cmp(key.as_bytes())) {
+            Ok(idx) if replace => return Some(std::mem::replace(&mut kvs[idx].value, value)),
+            Ok(idx) => return Some(kvs[idx].value.clone()),
+            Err(idx) => kvs.insert(idx, BPTreeKeyValue { key, value }),
+        }
+        self.len.fetch_add(1, Ordering::Relaxed);
+        if kvs.len() < self.order {
+            return None;
+        }
+
+        // 分裂叶子节点, 右半部分放入新节点, 新节点在挂到父节点之前其他线程都看不到
+        let right = kvs.split_off(kvs.len() / 2);
+        let mut separator = right[0].key.clone();
+        let mut right_offset = self.nodes.alloc(Node::Leaf { kvs: right, next: *next });
+        *next = Some(right_offset);
+        let mut left_offset = leaf_offset;
+        drop(leaf);
+
+        while let Some((offset, mut guard)) = path.pop() {
+            let Node::Internal { keys, child } = &mut *guard else { unreachable!("expected an internal node") };
+            // 分隔 key 与插入的 key 落在同一个子节点的范围内
+            let idx = child_index(keys, separator.as_bytes());
+            keys.insert(idx, separator);
+            child.insert(idx + 1, right_offset);
+            if keys.len() < self.order {
+                return None;
+            }
+            // 分裂内部节点, 中间的 key 移到父节点
+            let center = keys.len() / 2;
+            let right_keys = keys.split_off(center + 1);
+            let right_child = child.split_off(center + 1);
+            separator = keys.pop().expect("internal node has keys");
+            right_offset = self.nodes.alloc(Node::Internal { keys: right_keys, child: right_child });
+            left_offset = offset;
+        }
+
+        // 根节点分裂, 创建新的根节点
+        let mut root = root.expect("root latch is held when the root splits");
+        *root = self.nodes.alloc(Node::Internal {
+            keys: vec![separator],
+            child: vec![left_offset, right_offset],
+        });
+        None
+    }
+
+    /// key 所在的叶子节点被删空后, 把它从父节点与叶子链表中摘掉
+    ///
+    /// 从根节点向下加写锁直到叶子节点的父节点; 父节点的写锁挡住了其他线程从上方进入这两个叶子节点,
+    /// 两个相邻的叶子节点按从左到右的顺序加锁, 与遍历沿链表加锁的顺序一致, 不会死锁.
+    /// 等待锁的期间叶子节点可能又被插入了键值对, 这时什么也不做
+    fn reclaim(&self, key: &[u8]) {
+        let root = read(&self.root);
+        let mut parent = write(self.nodes.get(*root));
+        drop(root);
+        let mut idx = loop {
+            let Node::Internal { keys, child } = &*parent else { return; };
+            let idx = child_index(keys, key);
+            let latch = self.nodes.get(child[idx]);
+            // 节点的类型不会改变, 只在确认子节点是内部节点时才换成它的锁
+            if matches!(*read(latch), Node::Leaf { .. }) {
+                break idx;
+            }
+            parent = write(latch);
+        };
+        let Node::Internal { keys, child } = &mut *parent else { unreachable!("expected an internal node") };
+        if child.len() < 2 {
+            return;
+        }
+        // 被删空的叶子节点是第一个子节点时, 把它右边的兄弟节点并入它, 改为摘掉右边的节点
+        let merge_right = idx == 0;
+        if merge_right {
+            idx = 1;
+        }
+        let mut left = write(self.nodes.get(child[idx - 1]));
+        let mut right = write(self.nodes.get(child[idx]));
+        let (Node::Leaf { kvs: left_kvs, next }, Node::Leaf { kvs: right_kvs, next: right_next }) = (&mut *left, &mut *right) else {
+            unreachable!("siblings of a leaf are leaves")
+        };
+        let empty = if merge_right { left_kvs.is_empty() } else { right_kvs.is_empty() };
+        if !empty {
+            return;
+        }
+        left_kvs.append(right_kvs);
+        *next = right_next.take();
+        keys.remove(idx - 1);
+        let removed = child.remove(idx);
+        drop((left, right));
+        self.nodes.free(removed);
+    }
+}
+
+impl fmt::Debug for ConcurrentBPTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrentBPTree")
+            .field("order", &self.order)
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// 只增不减的节点表, 分配出去的槽一直有效, 节点的锁可以在整棵树的生命周期内借用
+///
+/// 从树中摘下的节点放回 `free`, 之后分配时优先复用; 摘下时已经没有其他线程能访问到它
+struct NodeTable {
+    chunks: [OnceLock<Box<[RwLock<Node>]>>; CHUNK_COUNT],
+    next_offset: AtomicUsize,
+    free: Mutex<Vec<NodeId>>,
+}
+
+impl NodeTable {
+    fn new() -> Self {
+        Self {
+            chunks: [const { OnceLock::new() }; CHUNK_COUNT],
+            next_offset: AtomicUsize::new(0),
+            free: Mutex::new(vec![]),
+        }
+    }
+
+    fn get(&self, offset: NodeId) -> &RwLock<Node> {
+        let (chunk, idx) = locate(offset);
+        &self.chunks[chunk].get().expect("node id out of range")[idx]
+    }
+
+    fn alloc(&self, node: Node) -> NodeId {
+        if let Some(offset) = self.free.lock().expect("free list poisoned").pop() {
+            *write(self.get(offset)) = node;
+            return offset;
+        }
+        let offset = NodeId::new(self.next_offset.fetch_add(1, Ordering::Relaxed));
+        let (chunk, idx) = locate(offset);
+        let slots = self.chunks[chunk].get_or_init(|| {
+            (0..CHUNK_BASE << chunk)
+                .map(|_| RwLock::new(Node::Leaf { kvs: vec![], next: None }))
+                .collect()
+        });
+        *write(&slots[idx]) = node;
+        offset
+    }
+
+    /// 回收从树中摘下的节点, 释放它占用的内存
+    fn free(&self, offset: NodeId) {
+        *write(self.get(offset)) = Node::Leaf { kvs: vec![], next: None };
+        self.free.lock().expect("free list poisoned").push(offset);
+    }
+}
+
+/// 节点所在的块与块中的下标
+fn locate(offset: NodeId) -> (usize, usize) {
+    // 第 n 块从 CHUNK_BASE * (2^n - 1) 开始
+    let n = offset.index() / CHUNK_BASE + 1;
+    let chunk = (usize::BITS - 1 - n.leading_zeros()) as usize;
+    (chunk, offset.index() - CHUNK_BASE * ((1 << chunk) - 1))
+}
+
+/// 内部节点中 key 所在的子节点的下标, 与分隔 key 相等时走右边
+fn child_index(keys: &[String], key: &[u8]) -> usize {
+    keys.partition_point(|_k| _k.as_bytes() <= key)
+}
+
+// 持有写锁的线程在修改节点的中途 panic 时树的结构可能已经损坏, 之后的操作都不再继续
+fn read<T>(latch: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    latch.read().expect("latch poisoned")
+}
+
+fn write<T>(latch: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    latch.write().expect("latch poisoned")
+}
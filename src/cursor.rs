@@ -0,0 +1,302 @@
+use std::ops::Bound;
+
+use crate::bptree::{BPTree, BPTreeKeyValue, BPTreeNode};
+use crate::entry::ValueMut;
+use crate::error::BPTreeError;
+use crate::iter::normalize;
+use crate::slab::NodeId;
+
+/// 指向树中某个键值对的游标, 由 [`BPTree::cursor`](crate::BPTree::cursor) 创建
+///
+/// 与迭代器不同, 游标可以随时用 [`seek`](Self::seek) 重新定位, 并在两个方向上移动
+///
+/// 除了指向某个键值对, 游标还可能处于一个不对应任何键值对的空位置, 它同时位于最后一个键值对之后与第一个键值对之前:
+/// 从最后一个键值对向后移动, 或从第一个键值对向前移动时到达空位置, 从空位置向后移动回到第一个键值对,
+/// 向前移动回到最后一个键值对
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let tree = BPTree::bulk_load(4, (0..10).map(|i| (i.to_string(), (i * i).to_string())));
+/// let mut cursor = tree.cursor();
+/// assert_eq!(cursor.seek("7"), Some(("7", "49")));
+/// assert_eq!(cursor.next(), Some(("8", "64")));
+/// assert_eq!(cursor.prev(), Some(("7", "49")));
+/// assert_eq!(cursor.seek("95"), None);
+/// assert_eq!(cursor.next(), Some(("0", "0")));
+/// ```
+pub struct Cursor<'a> {
+    tree: &'a BPTree,
+    // 当前位置, (叶子节点偏移量, 节点中的下标), 总是指向某个键值对, None 为空位置
+    position: Option<(NodeId, usize)>,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(tree: &'a BPTree) -> Self {
+        Self { tree, position: first(tree) }
+    }
+
+    /// 移动到第一个不小于 `key` 的键值对, 不存在时移动到空位置, 返回移动后指向的键值对
+    pub fn seek<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Option<(&'a str, &'a str)> {
+        self.position = seek(self.tree, key.as_ref());
+        self.current()
+    }
+
+    /// 向后移动一个键值对, 返回移动后指向的键值对
+    // 先移动再返回移动后的位置, 在空位置上还会回到开头, 与 Iterator::next 的含义不同
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.position = step_next(self.tree, self.position);
+        self.current()
+    }
+
+    /// 向前移动一个键值对, 返回移动后指向的键值对
+    pub fn prev(&mut self) -> Option<(&'a str, &'a str)> {
+        self.position = step_prev(self.tree, self.position);
+        self.current()
+    }
+
+    /// 当前指向的键值对, 处于空位置时返回 `None`
+    pub fn current(&self) -> Option<(&'a str, &'a str)> {
+        kv(self.tree, self.position).map(|_kv| (_kv.key(), _kv.value()))
+    }
+
+    /// 当前指向的键
+    pub fn key(&self) -> Option<&'a str> {
+        self.current().map(|(key, _)| key)
+    }
+
+    /// 当前指向的值
+    pub fn value(&self) -> Option<&'a str> {
+        self.current().map(|(_, value)| value)
+    }
+}
+
+/// 可以修改树的游标, 由 [`BPTree::cursor_mut`](crate::BPTree::cursor_mut) 创建
+///
+/// 移动方式与 [`Cursor`] 相同, 另外可以修改当前指向的值, 或者删除当前指向的键值对
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::bulk_load(4, (0..10).map(|i| (i.to_string(), i.to_string())));
+/// let mut cursor = tree.cursor_mut();
+/// cursor.seek("3");
+/// cursor.set_value("three".to_string()).unwrap();
+/// assert_eq!(cursor.remove_current().unwrap(), Some(("3".to_string(), "three".to_string())));
+/// assert_eq!(cursor.key(), Some("4"));
+/// assert_eq!(tree.len(), 9);
+/// ```
+pub struct CursorMut<'a> {
+    tree: &'a mut BPTree,
+    position: Option<(NodeId, usize)>,
+}
+
+impl<'a> CursorMut<'a> {
+    pub(crate) fn new(tree: &'a mut BPTree) -> Self {
+        let position = first(tree);
+        Self { tree, position }
+    }
+
+    /// 移动到第一个不小于 `key` 的键值对, 不存在时移动到空位置, 返回移动后指向的键值对
+    pub fn seek<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Option<(&str, &str)> {
+        self.position = seek(self.tree, key.as_ref());
+        self.current()
+    }
+
+    /// 向后移动一个键值对, 返回移动后指向的键值对
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&str, &str)> {
+        self.position = step_next(self.tree, self.position);
+        self.current()
+    }
+
+    /// 向前移动一个键值对, 返回移动后指向的键值对
+    pub fn prev(&mut self) -> Option<(&str, &str)> {
+        self.position = step_prev(self.tree, self.position);
+        self.current()
+    }
+
+    /// 当前指向的键值对, 处于空位置时返回 `None`
+    pub fn current(&self) -> Option<(&str, &str)> {
+        kv(self.tree, self.position).map(|_kv| (_kv.key(), _kv.value()))
+    }
+
+    /// 当前指向的键
+    pub fn key(&self) -> Option<&str> {
+        self.current().map(|(key, _)| key)
+    }
+
+    /// 当前指向的值
+    pub fn value(&self) -> Option<&str> {
+        self.current().map(|(_, value)| value)
+    }
+
+    /// 当前指向的值的可变引用, 见 [`ValueMut`]
+    ///
+    /// 关联了文件或有订阅的树中, 修改后的值在 [`commit`](ValueMut::commit) 时写入预写日志, 没有 commit 的修改被丢弃;
+    /// 也可以用 [`set_value`](Self::set_value) 直接替换
+    pub fn value_mut(&mut self) -> Option<ValueMut<'_>> {
+        let (leaf_offset, idx) = self.position?;
+        kv(self.tree, self.position)?;
+        Some(ValueMut::new(self.tree, leaf_offset, idx))
+    }
+
+    /// 替换当前指向的值, 返回旧值, 处于空位置时不做任何修改并返回 `None`
+    ///
+    /// 与 [`put`](crate::BPTree::put) 一样先写预写日志, 写入失败时返回错误
+    pub fn set_value(&mut self, value: String) -> Result<Option<String>, BPTreeError> {
+        let Some((leaf_offset, idx)) = self.position else { return Ok(None); };
+        let Some(key) = self.key().map(str::to_string) else { return Ok(None); };
+        self.tree.log_put(&key, &value)?;
+        let change = self.tree.changes.is_active().then(|| value.clone());
+        let BPTreeNode::Leaf { kvs, .. } = &mut self.tree.nodes[leaf_offset] else { unreachable!("cursor points to a leaf") };
+        let old_value = std::mem::replace(&mut kvs[idx].value, value);
+        if let Some(value) = change {
+            self.tree.changes.send(key, Some(old_value.clone()), Some(value));
+        }
+        Ok(Some(old_value))
+    }
+
+    /// 删除当前指向的键值对, 之后游标指向被删除的键值对的下一个键值对
+    ///
+    /// 删除后节点可能被合并, 因此会从根节点重新定位一次
+    pub fn remove_current(&mut self) -> Result<Option<(String, String)>, BPTreeError> {
+        let Some(key) = self.key().map(str::to_string) else { return Ok(None); };
+        let value = self.tree.remove(&key)?;
+        self.position = seek(self.tree, key.as_bytes());
+        Ok(value.map(|_value| (key, _value)))
+    }
+}
+
+/// 不借用树的游标, 两次移动之间可以任意插入、删除或分裂节点
+///
+/// [`Cursor`] 与 [`CursorMut`] 借用整棵树, 存在期间树不能被其他代码修改. 这个游标只记住当前的 key 与它上一次所在的位置,
+/// 每次移动时传入树: 上一次的位置仍然存放着同一个 key 时直接从那里移动, 否则 (节点分裂、合并或 key 被删除)
+/// 按 key 从根节点重新定位一次. 因此无论中间发生了什么修改, 移动的结果都只由 key 的顺序决定:
+///
+/// - [`next`](Self::next) 移动到大于当前 key 的最小键值对, [`prev`](Self::prev) 移动到小于当前 key 的最大键值对,
+///   当前 key 已经被删除时也是如此
+/// - 游标之后新插入的键值对会在向后移动时访问到, 之前新插入的会被跳过; 已经访问过的键值对不会被重复访问,
+///   被删除的键值对不会被返回, 不会读到被释放的节点
+/// - 与 [`Cursor`] 一样有一个空位置, 从空位置向后移动到第一个键值对, 向前移动到最后一个键值对
+///
+/// 位置只是一个提示, 传入另一棵树, 或者树被 [`compact`](BPTree::compact) 重新编号之后也能正确地重新定位
+///
+/// ```
+/// use btree_test::{BPTree, StableCursor};
+///
+/// let mut tree = BPTree::bulk_load(3, (0..10).map(|i| (i.to_string(), i.to_string())));
+/// let mut cursor = StableCursor::new();
+/// assert_eq!(cursor.seek(&tree, "4"), Some(("4", "4")));
+/// // 移动的间隙修改树, 插入的 key 使游标所在的叶子节点分裂
+/// tree.remove("5").unwrap();
+/// for key in ["4a", "4b", "4c", "0a"] {
+///     tree.put(key.to_string(), "new".to_string()).unwrap();
+/// }
+/// let rest: Vec<&str> = std::iter::from_fn(|| cursor.next(&tree).map(|(key, _)| key)).collect();
+/// assert_eq!(rest, ["4a", "4b", "4c", "6", "7", "8", "9"]);
+/// assert_eq!(cursor.key(), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StableCursor {
+    // 当前指向的 key 与它上一次所在的位置, None 为空位置
+    current: Option<(String, (NodeId, usize))>,
+}
+
+impl StableCursor {
+    /// 处于空位置的游标
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 当前指向的 key, 处于空位置时返回 `None`; 这个 key 可能已经从树中删除
+    pub fn key(&self) -> Option<&str> {
+        self.current.as_ref().map(|(key, _)| key.as_str())
+    }
+
+    /// 移动到第一个不小于 `key` 的键值对, 不存在时移动到空位置, 返回移动后指向的键值对
+    pub fn seek<'t, Q: AsRef<[u8]> + ?Sized>(&mut self, tree: &'t BPTree, key: &Q) -> Option<(&'t str, &'t str)> {
+        self.move_to(tree, seek(tree, key.as_ref()))
+    }
+
+    /// 向后移动到大于当前 key 的最小键值对, 返回移动后指向的键值对
+    #[allow(clippy::should_implement_trait)]
+    pub fn next<'t>(&mut self, tree: &'t BPTree) -> Option<(&'t str, &'t str)> {
+        let position = match &self.current {
+            None => first(tree),
+            Some((key, position)) => match self.revalidate(tree, key, *position) {
+                Some(position) => step_next(tree, Some(position)),
+                None => valid(tree, tree.seek(Bound::Excluded(key.as_bytes()), false)),
+            },
+        };
+        self.move_to(tree, position)
+    }
+
+    /// 向前移动到小于当前 key 的最大键值对, 返回移动后指向的键值对
+    pub fn prev<'t>(&mut self, tree: &'t BPTree) -> Option<(&'t str, &'t str)> {
+        let position = match &self.current {
+            None => step_prev(tree, None),
+            Some((key, position)) => match self.revalidate(tree, key, *position) {
+                Some(position) => step_prev(tree, Some(position)),
+                // 第一个不小于 key 的位置之前就是小于 key 的最大键值对, 不存在时从空位置向前移动到最后一个
+                None => step_prev(tree, seek(tree, key.as_bytes())),
+            },
+        };
+        self.move_to(tree, position)
+    }
+
+    fn revalidate(&self, tree: &BPTree, key: &str, position: (NodeId, usize)) -> Option<(NodeId, usize)> {
+        // 上一次的位置可能已经被释放或属于别的节点, 只有仍然是存放着这个 key 的叶子节点时才能继续使用
+        let (leaf_offset, idx) = position;
+        let Some(BPTreeNode::Leaf { kvs, .. }) = tree.nodes.get(leaf_offset) else { return None; };
+        kvs.get(idx).filter(|_kv| _kv.key == key).map(|_| position)
+    }
+
+    fn move_to<'t>(&mut self, tree: &'t BPTree, position: Option<(NodeId, usize)>) -> Option<(&'t str, &'t str)> {
+        let kv = kv(tree, position);
+        self.current = kv.zip(position).map(|(_kv, _position)| (_kv.key.clone(), _position));
+        kv.map(|_kv| (_kv.key(), _kv.value()))
+    }
+}
+
+fn kv(tree: &BPTree, position: Option<(NodeId, usize)>) -> Option<&BPTreeKeyValue> {
+    let (leaf_offset, idx) = position?;
+    let BPTreeNode::Leaf { kvs, .. } = &tree.nodes[leaf_offset] else { return None; };
+    kvs.get(idx)
+}
+
+fn valid(tree: &BPTree, position: (NodeId, usize)) -> Option<(NodeId, usize)> {
+    // 位置处于最后一个叶子节点的末尾时没有对应的键值对, 即空位置
+    let (leaf_offset, idx) = normalize(&tree.nodes, position);
+    (idx < tree.nodes[leaf_offset].len()).then_some((leaf_offset, idx))
+}
+
+fn first(tree: &BPTree) -> Option<(NodeId, usize)> {
+    valid(tree, (tree.first_leaf, 0))
+}
+
+fn seek(tree: &BPTree, key: &[u8]) -> Option<(NodeId, usize)> {
+    valid(tree, tree.seek(Bound::Included(key), false))
+}
+
+fn step_next(tree: &BPTree, position: Option<(NodeId, usize)>) -> Option<(NodeId, usize)> {
+    match position {
+        Some((leaf_offset, idx)) => valid(tree, (leaf_offset, idx + 1)),
+        None => first(tree),
+    }
+}
+
+fn step_prev(tree: &BPTree, position: Option<(NodeId, usize)>) -> Option<(NodeId, usize)> {
+    // 处于叶子节点开头时沿 prev 链表找到前一个非空的叶子节点
+    let (mut leaf_offset, mut idx) = match position {
+        Some(position) => position,
+        None => (tree.last_leaf, tree.nodes[tree.last_leaf].len()),
+    };
+    while idx == 0 {
+        let BPTreeNode::Leaf { prev, .. } = &tree.nodes[leaf_offset] else { return None; };
+        leaf_offset = (*prev)?;
+        idx = tree.nodes[leaf_offset].len();
+    }
+    Some((leaf_offset, idx - 1))
+}
@@ -0,0 +1,149 @@
+use std::cmp::Ordering;
+
+use crate::bptree::{BPTree, BPTreeKeyValue, BPTreeNode};
+use crate::comparator::KeyOrder;
+use crate::iter::normalize;
+use crate::slab::{NodeId, NodeSlab};
+
+/// [`BPTree::diff`] 返回的一处差异, 从 `self` 变为 `other` 需要的修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffEntry<'a> {
+    /// 只在 `other` 中
+    Added { key: &'a str, value: &'a str },
+    /// 只在 `self` 中
+    Removed { key: &'a str, value: &'a str },
+    /// 两边都有, 但值不同
+    Changed { key: &'a str, old_value: &'a str, new_value: &'a str },
+}
+
+impl<'a> DiffEntry<'a> {
+    /// 差异所在的 key
+    pub fn key(&self) -> &'a str {
+        match *self {
+            DiffEntry::Added { key, .. } | DiffEntry::Removed { key, .. } | DiffEntry::Changed { key, .. } => key,
+        }
+    }
+}
+
+// 沿着叶子链表逐个访问键值对的位置
+struct LeafCursor<'a> {
+    nodes: &'a NodeSlab,
+    position: (NodeId, usize),
+}
+
+impl<'a> LeafCursor<'a> {
+    fn new(tree: &'a BPTree) -> Self {
+        Self { nodes: &tree.nodes, position: normalize(&tree.nodes, (tree.first_leaf, 0)) }
+    }
+
+    fn peek(&self) -> Option<&'a BPTreeKeyValue> {
+        let (leaf_offset, idx) = self.position;
+        let BPTreeNode::Leaf { kvs, .. } = &self.nodes[leaf_offset] else { return None; };
+        kvs.get(idx)
+    }
+
+    fn advance(&mut self) {
+        let (leaf_offset, idx) = self.position;
+        self.position = normalize(self.nodes, (leaf_offset, idx + 1));
+    }
+
+    fn skip_leaf(&mut self) {
+        // 跳过当前叶子节点中剩下的所有键值对
+        let (leaf_offset, _) = self.position;
+        let len = self.nodes[leaf_offset].len();
+        self.position = normalize(self.nodes, (leaf_offset, len));
+    }
+}
+
+/// 按 key 顺序列出两棵树之间差异的迭代器, 由 [`BPTree::diff`] 创建
+pub struct Diff<'a> {
+    left: LeafCursor<'a>,
+    right: LeafCursor<'a>,
+    key_order: &'a KeyOrder,
+}
+
+impl<'a> Iterator for Diff<'a> {
+    type Item = DiffEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (left, right) = match (self.left.peek(), self.right.peek()) {
+                (None, None) => return None,
+                (Some(kv), None) => {
+                    self.left.advance();
+                    return Some(DiffEntry::Removed { key: kv.key(), value: kv.value() });
+                }
+                (None, Some(kv)) => {
+                    self.right.advance();
+                    return Some(DiffEntry::Added { key: kv.key(), value: kv.value() });
+                }
+                (Some(left), Some(right)) => (left, right),
+            };
+            // 两边都在同一个共享的叶子节点的开头时, 整个节点都相同, 直接跳过
+            let ((left_leaf, left_idx), (right_leaf, right_idx)) = (self.left.position, self.right.position);
+            if left_idx == 0 && right_idx == 0 && self.left.nodes.shares(left_leaf, self.right.nodes, right_leaf) {
+                self.left.skip_leaf();
+                self.right.skip_leaf();
+                continue;
+            }
+            match self.key_order.cmp(left.key.as_bytes(), right.key.as_bytes()) {
+                Ordering::Less => {
+                    self.left.advance();
+                    return Some(DiffEntry::Removed { key: left.key(), value: left.value() });
+                }
+                Ordering::Greater => {
+                    self.right.advance();
+                    return Some(DiffEntry::Added { key: right.key(), value: right.value() });
+                }
+                Ordering::Equal => {
+                    self.left.advance();
+                    self.right.advance();
+                    if left.value != right.value {
+                        return Some(DiffEntry::Changed { key: left.key(), old_value: left.value(), new_value: right.value() });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl BPTree {
+    /// 按 key 的顺序列出从 `self` 变为 `other` 需要的修改
+    ///
+    /// 同时沿着两棵树的叶子链表向后遍历, 耗时与两棵树的大小之和成正比, 不需要额外的内存.
+    /// `other` 是 `self` 的 [`Clone`] 或 [`snapshot`](Self::snapshot) (或者反过来) 时, 两边都没有修改过的叶子节点是共享的,
+    /// 会被整个跳过而不逐个比较. 两棵树需要使用相同的 key 顺序, 按 `self` 的比较器比较
+    ///
+    /// ```
+    /// use btree_test::{BPTree, DiffEntry};
+    ///
+    /// let mut primary = BPTree::bulk_load(4, (0..100).map(|i| (format!("{:03}", i), i.to_string())));
+    /// let mut replica = primary.clone();
+    /// primary.put("005".to_string(), "five".to_string()).unwrap();
+    /// primary.remove("050").unwrap();
+    /// primary.put("100".to_string(), "100".to_string()).unwrap();
+    ///
+    /// let diff: Vec<DiffEntry> = replica.diff(&primary).collect();
+    /// assert_eq!(diff, [
+    ///     DiffEntry::Changed { key: "005", old_value: "5", new_value: "five" },
+    ///     DiffEntry::Removed { key: "050", value: "50" },
+    ///     DiffEntry::Added { key: "100", value: "100" },
+    /// ]);
+    ///
+    /// // 把差异应用到副本上, 两边重新一致
+    /// let changes: Vec<(String, Option<String>)> = diff.iter().map(|_entry| match *_entry {
+    ///     DiffEntry::Added { key, value } | DiffEntry::Changed { key, new_value: value, .. } => (key.to_string(), Some(value.to_string())),
+    ///     DiffEntry::Removed { key, .. } => (key.to_string(), None),
+    /// }).collect();
+    /// for (key, value) in changes {
+    ///     match value {
+    ///         Some(value) => drop(replica.put(key, value).unwrap()),
+    ///         None => drop(replica.remove(&key).unwrap()),
+    ///     }
+    /// }
+    /// assert_eq!(replica.diff(&primary).count(), 0);
+    /// ```
+    pub fn diff<'a>(&'a self, other: &'a BPTree) -> Diff<'a> {
+        Diff { left: LeafCursor::new(self), right: LeafCursor::new(other), key_order: &self.key_order }
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+use crate::bptree::{BPTree, BPTreeNode};
+
+/// 按层打印树的结构, 每层一行, 根节点在最上面
+///
+/// 内部节点显示分隔 key, 最下面一行是叶子节点中的键值对, 用箭头表示 `next` 链表;
+/// 观察插入与删除时节点如何分裂与合并很方便
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::new(3);
+/// for key in ["a", "b", "c", "d"] {
+///     tree.put(key.to_string(), "1".to_string()).unwrap();
+/// }
+/// assert_eq!(tree.to_string(), "[b | c]\n[a=1] -> [b=1] -> [c=1, d=1]\n");
+/// ```
+impl fmt::Display for BPTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut level = vec![self.root];
+        while !level.is_empty() {
+            let mut lower = vec![];
+            for (idx, &offset) in level.iter().enumerate() {
+                match &self.nodes[offset] {
+                    BPTreeNode::Internal { branches, .. } => {
+                        let (child, keys, _) = branches.parts();
+                        if idx > 0 {
+                            write!(f, "  ")?;
+                        }
+                        write!(f, "[{}]", keys.join(" | "))?;
+                        lower.extend_from_slice(child);
+                    }
+                    BPTreeNode::Leaf { kvs, .. } => {
+                        if idx > 0 {
+                            write!(f, " -> ")?;
+                        }
+                        let kvs: Vec<String> = kvs.iter().map(|_kv| format!("{}={}", _kv.key, _kv.value)).collect();
+                        write!(f, "[{}]", kvs.join(", "))?;
+                    }
+                }
+            }
+            writeln!(f)?;
+            level = lower;
+        }
+        Ok(())
+    }
+}
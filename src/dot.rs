@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use crate::bptree::{BPTree, BPTreeNode};
+use crate::format::json_string;
+
+impl BPTree {
+    /// 导出 Graphviz DOT 格式的树结构, 可以用 `dot -Tsvg` 渲染
+    ///
+    /// 内部节点显示分隔 key, 叶子节点显示键值对, 实线为父节点指向子节点的边,
+    /// 虚线为叶子节点的 `next` 链表; 被释放的节点不会出现在图中
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// for key in ["a", "b", "c"] {
+    ///     tree.put(key.to_string(), "1".to_string()).unwrap();
+    /// }
+    /// assert!(tree.to_dot().starts_with("digraph BPTree {"));
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph BPTree {\n    node [shape=record, fontname=\"monospace\"];\n");
+        let mut leaves = vec![];
+        let mut stack = vec![self.root];
+        while let Some(offset) = stack.pop() {
+            match &self.nodes[offset] {
+                BPTreeNode::Internal { branches, .. } => {
+                    let (child, keys, _) = branches.parts();
+                    // 子节点指针与 key 间隔排列, 每个指针是一个端口, 边从端口连到子节点
+                    let mut fields = vec![String::from("<c0> ")];
+                    for (idx, key) in keys.iter().enumerate() {
+                        fields.push(escape(key));
+                        fields.push(format!("<c{}> ", idx + 1));
+                    }
+                    let _ = writeln!(dot, "    n{} [label=\"{}\"];", offset, fields.join("|"));
+                    for (idx, child_offset) in child.iter().enumerate() {
+                        let _ = writeln!(dot, "    n{}:c{} -> n{};", offset, idx, child_offset);
+                    }
+                    stack.extend(child.iter().rev());
+                }
+                BPTreeNode::Leaf { kvs, .. } => {
+                    let fields: Vec<String> = kvs
+                        .iter()
+                        .map(|_kv| format!("{}: {}", escape(&_kv.key), escape(&_kv.value)))
+                        .collect();
+                    let label = if fields.is_empty() { String::from(" ") } else { fields.join("|") };
+                    let _ = writeln!(dot, "    n{} [label=\"{}\", style=filled, fillcolor=\"#eeeeee\"];", offset, label);
+                    leaves.push(offset);
+                }
+            }
+        }
+
+        // 叶子节点放在同一行, next 链表不参与布局
+        if leaves.len() > 1 {
+            let ranks: Vec<String> = leaves.iter().map(|_offset| format!("n{}", _offset)).collect();
+            let _ = writeln!(dot, "    {{ rank=same; {}; }}", ranks.join("; "));
+        }
+        for &offset in &leaves {
+            if let BPTreeNode::Leaf { next: Some(next), .. } = &self.nodes[offset] {
+                let _ = writeln!(dot, "    n{} -> n{} [style=dashed, constraint=false];", offset, next);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// 导出 JSON 格式的树结构, 供可视化工具按层绘制树并比较每次修改前后的变化
+    ///
+    /// `nodes` 按层从根节点开始排列, 每个节点带有编号、深度与类型; 内部节点有 `keys` 与 `children`,
+    /// 叶子节点有 `keys`、`values` 以及链表中的 `prev` 与 `next`, 没有时为 `null`
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::new(3);
+    /// for key in ["a", "b", "c"] {
+    ///     tree.put(key.to_string(), "1".to_string()).unwrap();
+    /// }
+    /// let json = tree.to_json();
+    /// assert!(json.starts_with("{\"order\":3,\"len\":3,"));
+    /// assert!(json.contains("\"type\":\"internal\",\"keys\":[\"b\"]"));
+    /// ```
+    pub fn to_json(&self) -> String {
+        let list = |_items: Vec<String>| format!("[{}]", _items.join(","));
+        let id = |_offset: Option<_>| _offset.map_or(String::from("null"), |_offset| format!("{}", _offset));
+        let mut nodes = vec![];
+        let mut level = vec![self.root];
+        let mut depth = 0;
+        while !level.is_empty() {
+            let mut lower = vec![];
+            for offset in level {
+                match &self.nodes[offset] {
+                    BPTreeNode::Internal { branches, .. } => {
+                        let (child, keys, _) = branches.parts();
+                        nodes.push(format!(
+                            "{{\"id\":{},\"depth\":{},\"type\":\"internal\",\"keys\":{},\"children\":{}}}",
+                            offset,
+                            depth,
+                            list(keys.iter().map(|_k| json_string(_k)).collect()),
+                            list(child.iter().map(|_c| _c.to_string()).collect()),
+                        ));
+                        lower.extend_from_slice(child);
+                    }
+                    BPTreeNode::Leaf { prev, next, kvs, .. } => nodes.push(format!(
+                        "{{\"id\":{},\"depth\":{},\"type\":\"leaf\",\"keys\":{},\"values\":{},\"prev\":{},\"next\":{}}}",
+                        offset,
+                        depth,
+                        list(kvs.iter().map(|_kv| json_string(&_kv.key)).collect()),
+                        list(kvs.iter().map(|_kv| json_string(&_kv.value)).collect()),
+                        id(*prev),
+                        id(*next),
+                    )),
+                }
+            }
+            level = lower;
+            depth += 1;
+        }
+        format!(
+            "{{\"order\":{},\"len\":{},\"root\":{},\"first_leaf\":{},\"leaf_order\":{},\"nodes\":{}}}",
+            self.fanout.internal,
+            self.len,
+            self.root,
+            self.first_leaf,
+            self.fanout.leaf,
+            list(nodes)
+        )
+    }
+}
+
+// record 标签中的 {}|<> 和空格有特殊含义, 引号与反斜杠需要转义
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' | '{' | '}' | '|' | '<' | '>' | ' ' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use crate::bptree::{BPTree, BPTreeKeyValue, BPTreeNode, DescentPath};
+use crate::error::BPTreeError;
+use crate::slab::NodeId;
+
+/// 树中某个 key 对应的位置, 由 [`BPTree::entry`](crate::BPTree::entry) 创建
+///
+/// [`or_insert`](Self::or_insert) 等方法返回 [`ValueMut`], 关联了文件或有订阅的树中, 通过它修改的值在
+/// [`commit`](ValueMut::commit) 时写入预写日志
+///
+/// # Panics
+///
+/// 与 `BTreeMap` 的 `Entry` 一样, 插入、修改与删除的方法不返回 `Result`,
+/// 写预写日志失败或树的结构损坏时 panic (先写日志再修改, panic 时树没有被修改), 需要处理这些错误时应使用
+/// [`put`](crate::BPTree::put)、[`modify`](crate::BPTree::modify) 与 [`remove`](crate::BPTree::remove)
+pub enum Entry<'a> {
+    /// key 不存在
+    Vacant(VacantEntry<'a>),
+    /// key 已存在
+    Occupied(OccupiedEntry<'a>),
+}
+
+/// 不存在的 key 对应的位置
+pub struct VacantEntry<'a> {
+    tree: &'a mut BPTree,
+    key: String,
+    // key 应该插入的位置, (叶子节点偏移量, 节点中的下标)
+    leaf_offset: NodeId,
+    idx: usize,
+    // 从根节点到叶子节点的路径, 插入后叶子节点分裂时使用
+    path: DescentPath,
+}
+
+/// 已存在的 key 对应的位置
+pub struct OccupiedEntry<'a> {
+    tree: &'a mut BPTree,
+    leaf_offset: NodeId,
+    idx: usize,
+}
+
+impl<'a> Entry<'a> {
+    /// 键
+    pub fn key(&self) -> &str {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// key 不存在时插入 `default`, 返回值的可变引用
+    pub fn or_insert(self, default: String) -> ValueMut<'a> {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// key 不存在时插入 `default` 的返回值, 返回值的可变引用
+    pub fn or_insert_with<F: FnOnce() -> String>(self, default: F) -> ValueMut<'a> {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    /// key 已存在时用 `f` 修改它的值
+    pub fn and_modify<F: FnOnce(&mut String)>(self, f: F) -> Self {
+        match self {
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+            Entry::Occupied(mut entry) => {
+                entry.update(f).unwrap_or_else(|_error| panic!("{}", _error));
+                Entry::Occupied(entry)
+            }
+        }
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    pub(crate) fn new(tree: &'a mut BPTree, key: String, leaf_offset: NodeId, idx: usize, path: DescentPath) -> Self {
+        Self { tree, key, leaf_offset, idx, path }
+    }
+
+    /// 键
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// 取回键的所有权
+    pub fn into_key(self) -> String {
+        self.key
+    }
+
+    /// 插入值, 返回值的可变引用
+    pub fn insert(self, value: String) -> ValueMut<'a> {
+        self.try_insert(value).unwrap_or_else(|_error| panic!("{}", _error))
+    }
+
+    /// 与 [`insert`](Self::insert) 相同, 但写预写日志失败或树的结构损坏时返回错误
+    pub(crate) fn try_insert(self, value: String) -> Result<ValueMut<'a>, BPTreeError> {
+        let tree = self.tree;
+        tree.log_put(&self.key, &value)?;
+        if tree.changes.is_active() {
+            tree.changes.send(self.key.clone(), None, Some(value.clone()));
+        }
+        let BPTreeNode::Leaf { kvs, .. } = &mut tree.nodes[self.leaf_offset] else { unreachable!("entry points to a leaf") };
+        kvs.insert(self.idx, BPTreeKeyValue { key: self.key, value });
+        tree.len += 1;
+        let policy = tree.effective_split_policy();
+        BPTree::finish_insert(&mut tree.nodes, &mut tree.root, &mut tree.last_leaf, tree.fanout, policy, self.leaf_offset, self.path)?;
+
+        // 叶子节点分裂时左节点保留前一半, 插入的键值对可能被移到了右节点
+        let left_len = tree.nodes[self.leaf_offset].len();
+        let (leaf_offset, idx) = match &tree.nodes[self.leaf_offset] {
+            BPTreeNode::Leaf { next: Some(next), .. } if self.idx >= left_len => (*next, self.idx - left_len),
+            _ => (self.leaf_offset, self.idx),
+        };
+        Ok(ValueMut::new(tree, leaf_offset, idx))
+    }
+}
+
+impl<'a> OccupiedEntry<'a> {
+    pub(crate) fn new(tree: &'a mut BPTree, leaf_offset: NodeId, idx: usize) -> Self {
+        // 按前缀压缩存放时展开叶子节点, key() 返回完整的 key
+        tree.nodes.expand(leaf_offset);
+        Self { tree, leaf_offset, idx }
+    }
+
+    fn kv(&self) -> &BPTreeKeyValue {
+        let BPTreeNode::Leaf { kvs, .. } = &self.tree.nodes[self.leaf_offset] else { unreachable!("entry points to a leaf") };
+        &kvs[self.idx]
+    }
+
+    fn value_mut(&mut self) -> &mut String {
+        let BPTreeNode::Leaf { kvs, .. } = &mut self.tree.nodes[self.leaf_offset] else { unreachable!("entry points to a leaf") };
+        &mut kvs[self.idx].value
+    }
+
+    /// 修改需要写入预写日志或发出事件
+    fn is_logged(&self) -> bool {
+        self.tree.wal.is_some() || self.tree.changes.is_active()
+    }
+
+    /// 用 `f` 修改值, 需要记录修改时先在副本上修改, 再通过 [`replace`](Self::replace) 写入树中
+    pub(crate) fn update<F: FnOnce(&mut String)>(&mut self, f: F) -> Result<(), BPTreeError> {
+        if !self.is_logged() {
+            f(self.value_mut());
+            return Ok(());
+        }
+        let mut value = self.get().to_string();
+        f(&mut value);
+        self.replace(value).map(drop)
+    }
+
+    /// 替换值并返回旧值, 与 [`put`](crate::BPTree::put) 一样先写入预写日志, 写入失败时树不会被修改
+    pub(crate) fn replace(&mut self, value: String) -> Result<String, BPTreeError> {
+        if self.is_logged() {
+            let key = self.key().to_string();
+            self.tree.log_put(&key, &value)?;
+            if self.tree.changes.is_active() {
+                let old_value = self.get().to_string();
+                self.tree.changes.send(key, Some(old_value), Some(value.clone()));
+            }
+        }
+        Ok(std::mem::replace(self.value_mut(), value))
+    }
+
+    /// 键
+    pub fn key(&self) -> &str {
+        &self.kv().key
+    }
+
+    /// 值
+    pub fn get(&self) -> &str {
+        &self.kv().value
+    }
+
+    /// 值的可变引用
+    pub fn get_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::new(self.tree, self.leaf_offset, self.idx)
+    }
+
+    /// 转换为生命周期与树相同的可变引用
+    pub fn into_mut(self) -> ValueMut<'a> {
+        ValueMut::new(self.tree, self.leaf_offset, self.idx)
+    }
+
+    /// 替换值, 返回旧值
+    pub fn insert(&mut self, value: String) -> String {
+        self.replace(value).unwrap_or_else(|_error| panic!("{}", _error))
+    }
+
+    /// 从树中删除该键值对, 返回它的值
+    pub fn remove(self) -> String {
+        let key = self.kv().key.clone();
+        match self.tree.remove(&key) {
+            Ok(value) => value.unwrap_or_default(),
+            Err(error) => panic!("{}", error),
+        }
+    }
+}
+
+/// 树中某个值的可变引用, 由 [`BPTree::get_mut`](crate::BPTree::get_mut) 与 [`Entry::or_insert`] 等方法创建
+///
+/// 可以像 `&mut String` 一样使用. 没有关联文件也没有订阅的树中直接修改树中的值, 与持有 `&mut String` 相同;
+/// 关联了文件或有订阅的树中, 修改作用在创建时复制的一份值上, [`commit`](Self::commit) 时与 [`put`](crate::BPTree::put)
+/// 一样先写入预写日志并发出修改事件, 再替换树中的值. 没有 commit 就被 drop 的修改会被丢弃
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::new(4);
+/// tree.put("hits".to_string(), "1".to_string()).unwrap();
+/// let changes = tree.subscribe(16);
+/// let mut hits = tree.get_mut("hits").unwrap();
+/// hits.push('0');
+/// hits.commit().unwrap();
+/// // 没有 commit 的修改不会写入树中
+/// tree.get_mut("hits").unwrap().push('0');
+/// assert_eq!(tree.get("hits").map(|kv| kv.value()), Some("10"));
+/// assert_eq!(changes.try_recv().unwrap().unwrap().new_value.as_deref(), Some("10"));
+/// ```
+pub struct ValueMut<'a> {
+    tree: &'a mut BPTree,
+    leaf_offset: NodeId,
+    idx: usize,
+    // 需要记录修改时为值的副本, 修改都作用在它上面, commit 时写回树中
+    pending: Option<String>,
+}
+
+impl<'a> ValueMut<'a> {
+    pub(crate) fn new(tree: &'a mut BPTree, leaf_offset: NodeId, idx: usize) -> Self {
+        tree.nodes.expand(leaf_offset);
+        let mut value = Self { tree, leaf_offset, idx, pending: None };
+        if value.tree.wal.is_some() || value.tree.changes.is_active() {
+            value.pending = Some(value.kv().value.clone());
+        }
+        value
+    }
+
+    fn kv(&self) -> &BPTreeKeyValue {
+        let BPTreeNode::Leaf { kvs, .. } = &self.tree.nodes[self.leaf_offset] else { unreachable!("value points to a leaf") };
+        &kvs[self.idx]
+    }
+
+    /// 值对应的键
+    pub fn key(&self) -> &str {
+        &self.kv().key
+    }
+
+    /// 提交修改: 值与树中的不同时写入预写日志、发出修改事件并替换树中的值; 直接修改树中的值时什么也不做
+    ///
+    /// 写预写日志失败时返回错误, 树中仍然是原来的值
+    pub fn commit(self) -> Result<(), BPTreeError> {
+        let ValueMut { tree, leaf_offset, idx, pending } = self;
+        let Some(value) = pending else { return Ok(()); };
+        let mut entry = OccupiedEntry::new(tree, leaf_offset, idx);
+        if entry.get() != value {
+            entry.replace(value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for ValueMut<'_> {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        self.pending.as_ref().unwrap_or(&self.kv().value)
+    }
+}
+
+impl DerefMut for ValueMut<'_> {
+    fn deref_mut(&mut self) -> &mut String {
+        if let Some(value) = &mut self.pending {
+            return value;
+        }
+        let BPTreeNode::Leaf { kvs, .. } = &mut self.tree.nodes[self.leaf_offset] else { unreachable!("value points to a leaf") };
+        &mut kvs[self.idx].value
+    }
+}
+
+impl fmt::Debug for ValueMut<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
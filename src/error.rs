@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::slab::NodeId;
+
+/// 修改树时可能发生的错误
+#[derive(Debug)]
+pub enum BPTreeError {
+    /// 读写文件或预写日志失败
+    Io(io::Error),
+    /// 树的结构不正确, 例如在应该是叶子节点的位置遇到了内部节点, 或者偏移量越界
+    ///
+    /// 出现这个错误说明树已经损坏, 之后的操作结果都不可信
+    Corrupted { offset: NodeId, message: &'static str },
+}
+
+impl BPTreeError {
+    pub(crate) fn corrupted(offset: NodeId, message: &'static str) -> Self {
+        BPTreeError::Corrupted { offset, message }
+    }
+}
+
+impl fmt::Display for BPTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BPTreeError::Io(error) => write!(f, "{}", error),
+            BPTreeError::Corrupted { offset, message } => write!(f, "tree is corrupted at node {}: {}", offset, message),
+        }
+    }
+}
+
+impl Error for BPTreeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BPTreeError::Io(error) => Some(error),
+            BPTreeError::Corrupted { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for BPTreeError {
+    fn from(error: io::Error) -> Self {
+        BPTreeError::Io(error)
+    }
+}
+
+impl From<BPTreeError> for io::Error {
+    fn from(error: BPTreeError) -> Self {
+        match error {
+            BPTreeError::Io(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// [`compare_and_swap`](crate::BPTree::compare_and_swap) 失败的原因
+#[derive(Debug)]
+pub enum CasError {
+    /// 当前的值与期望的值不同, 树没有被修改; `current` 为当前的值, key 不存在时为 `None`
+    Mismatch { current: Option<String> },
+    /// 值相同, 但修改树时出错
+    Tree(BPTreeError),
+}
+
+impl fmt::Display for CasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CasError::Mismatch { current: Some(current) } => write!(f, "current value {:?} does not match the expected value", current),
+            CasError::Mismatch { current: None } => write!(f, "key does not exist"),
+            CasError::Tree(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for CasError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CasError::Mismatch { .. } => None,
+            CasError::Tree(error) => Some(error),
+        }
+    }
+}
+
+impl From<BPTreeError> for CasError {
+    fn from(error: BPTreeError) -> Self {
+        CasError::Tree(error)
+    }
+}
@@ -0,0 +1,253 @@
+use std::io::{self, BufWriter, Read, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::bptree::BPTree;
+use crate::error::BPTreeError;
+
+/// [`BPTree::export`] 与 [`BPTree::import`] 使用的文本格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// 每行两列 `key,value`, 没有表头; 包含逗号、引号或换行的字段用双引号包围, 引号写成两个引号
+    Csv,
+    /// 每行一个 JSON 对象 `{"key": "...", "value": "..."}`
+    JsonLines,
+}
+
+impl BPTree {
+    /// 按 key 的顺序把所有键值对写到 `writer`
+    ///
+    /// ```
+    /// use btree_test::{BPTree, Format};
+    ///
+    /// let mut tree = BPTree::new(4);
+    /// tree.put("a".to_string(), "1, 2".to_string()).unwrap();
+    /// tree.put("b".to_string(), "say \"hi\"".to_string()).unwrap();
+    ///
+    /// let mut csv = vec![];
+    /// tree.export(&mut csv, Format::Csv).unwrap();
+    /// assert_eq!(String::from_utf8(csv).unwrap(), "a,\"1, 2\"\nb,\"say \"\"hi\"\"\"\n");
+    ///
+    /// let mut json = vec![];
+    /// tree.export(&mut json, Format::JsonLines).unwrap();
+    /// let mut copy = BPTree::new(4);
+    /// copy.import(json.as_slice(), Format::JsonLines).unwrap();
+    /// assert!(copy.iter().eq(tree.iter()));
+    /// ```
+    pub fn export<W: Write>(&self, writer: W, format: Format) -> io::Result<()> {
+        let mut out = BufWriter::new(writer);
+        for (key, value) in self {
+            match format {
+                Format::Csv => writeln!(out, "{},{}", csv_field(key), csv_field(value))?,
+                Format::JsonLines => writeln!(out, "{{\"key\":{},\"value\":{}}}", json_string(key), json_string(value))?,
+            }
+        }
+        out.flush()
+    }
+
+    /// 从 `reader` 读入 [`export`](Self::export) 格式的键值对, 返回新插入的 key 的数量
+    ///
+    /// 空树按字节比较 key 时用 [`bulk_load`](Self::bulk_load) 自底向上构建, 否则与 [`put_batch`](Self::put_batch) 相同;
+    /// 相同的 key 保留最后一个值. 输入格式不正确时返回 [`io::ErrorKind::InvalidData`], 树不会被修改
+    pub fn import<R: Read>(&mut self, mut reader: R, format: Format) -> Result<usize, BPTreeError> {
+        let mut input = String::new();
+        reader.read_to_string(&mut input)?;
+        let entries = match format {
+            Format::Csv => parse_csv(&input)?,
+            Format::JsonLines => parse_json_lines(&input)?,
+        };
+        if self.is_empty() && self.key_order.is_bytes() {
+            let mut loaded = Self::bulk_load_with(self.fanout, entries);
+            let inserted = loaded.len();
+            self.append(&mut loaded)?;
+            return Ok(inserted);
+        }
+        self.put_batch(entries)
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message))
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn parse_csv(input: &str) -> io::Result<Vec<(String, String)>> {
+    let mut entries = vec![];
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        // 引号中的换行属于字段本身, 报错时使用记录开始的行号
+        let start_line = line;
+        let mut fields = vec![];
+        loop {
+            let mut field = String::new();
+            if chars.next_if_eq(&'"').is_some() {
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                        Some('"') => break,
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            field.push(c);
+                        }
+                        None => return Err(parse_error(start_line, "unterminated quoted field")),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|_c| !matches!(_c, ',' | '\r' | '\n')) {
+                    if c == '"' {
+                        return Err(parse_error(start_line, "quote in unquoted field"));
+                    }
+                    field.push(c);
+                }
+            }
+            fields.push(field);
+            match chars.next() {
+                Some(',') => {}
+                Some('\n') | None => break,
+                Some('\r') if chars.next_if_eq(&'\n').is_some() => break,
+                Some(_) => return Err(parse_error(start_line, "unexpected character after field")),
+            }
+        }
+        line += 1;
+        // 跳过空行
+        if fields.len() == 1 && fields[0].is_empty() {
+            continue;
+        }
+        let Ok([key, value]) = <[String; 2]>::try_from(fields) else {
+            return Err(parse_error(start_line, "expected 2 fields"));
+        };
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn parse_json_lines(input: &str) -> io::Result<Vec<(String, String)>> {
+    let mut entries = vec![];
+    for (idx, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(parse_json_object(line).map_err(|_message| parse_error(idx + 1, _message))?);
+    }
+    Ok(entries)
+}
+
+fn parse_json_object(line: &str) -> Result<(String, String), &'static str> {
+    // 只接受 key 与 value 两个字符串字段, 顺序任意
+    let mut chars = line.chars().peekable();
+    let (mut key, mut value) = (None, None);
+    skip_whitespace(&mut chars);
+    expect(&mut chars, '{')?;
+    loop {
+        skip_whitespace(&mut chars);
+        let name = parse_json_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        expect(&mut chars, ':')?;
+        skip_whitespace(&mut chars);
+        let field = parse_json_string(&mut chars)?;
+        match name.as_str() {
+            "key" => key = Some(field),
+            "value" => value = Some(field),
+            _ => return Err("unknown field"),
+        }
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(',') => {}
+            Some('}') => break,
+            _ => return Err("expected ',' or '}'"),
+        }
+    }
+    skip_whitespace(&mut chars);
+    if chars.next().is_some() {
+        return Err("trailing characters after object");
+    }
+    Ok((key.ok_or("missing field \"key\"")?, value.ok_or("missing field \"value\"")?))
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|_c| matches!(_c, ' ' | '\t' | '\r' | '\n')).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), &'static str> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        _ => Err("unexpected character"),
+    }
+}
+
+fn parse_json_string(chars: &mut Peekable<Chars>) -> Result<String, &'static str> {
+    expect(chars, '"')?;
+    let mut out = String::new();
+    loop {
+        match chars.next().ok_or("unterminated string")? {
+            '"' => return Ok(out),
+            '\\' => {
+                let c = match chars.next().ok_or("unterminated string")? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let high = parse_hex4(chars)?;
+                        // UTF-16 代理对由两个 \u 转义组成
+                        let code = if (0xd800..0xdc00).contains(&high) {
+                            expect(chars, '\\')?;
+                            expect(chars, 'u')?;
+                            let low = parse_hex4(chars)?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err("invalid surrogate pair");
+                            }
+                            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                        } else {
+                            high
+                        };
+                        char::from_u32(code).ok_or("invalid unicode escape")?
+                    }
+                    _ => return Err("invalid escape"),
+                };
+                out.push(c);
+            }
+            c if c < ' ' => return Err("control character in string"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> Result<u32, &'static str> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = chars.next().and_then(|_c| _c.to_digit(16)).ok_or("invalid unicode escape")?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
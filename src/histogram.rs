@@ -0,0 +1,84 @@
+use crate::bptree::{BPTree, BPTreeNode};
+use crate::slab::NodeId;
+
+// 每个桶平均至少由这么多棵子树组成, 子树越多, 桶的边界越接近真实的分位点
+const SUBTREES_PER_BUCKET: usize = 8;
+
+/// [`BPTree::key_histogram`] 返回的一个桶, 包含 `start` 到 `end` 之间的 key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBucket {
+    /// 桶的下界 (包含), 第一个桶为 `None`, 表示没有下界
+    pub start: Option<String>,
+    /// 桶的上界 (不包含), 等于下一个桶的 `start`, 最后一个桶为 `None`
+    pub end: Option<String>,
+    /// 桶中键值对的数量
+    pub count: usize,
+}
+
+impl BPTree {
+    /// 把所有 key 按顺序分成大约 `buckets` 个数量接近的范围, 返回每个范围的边界与键值对的数量
+    ///
+    /// 从根节点向下逐层展开内部节点, 直到某一层的子树数量足够多 (最多展开到叶子节点那一层),
+    /// 以这一层的分隔 key 作为候选边界, 再按内部节点中记录的子树计数把相邻的子树合并成桶. 只读取内部节点,
+    /// 不访问叶子节点, 耗时与 `buckets` 成正比, 与树的大小无关.
+    ///
+    /// 桶的边界只能落在所选层的分隔 key 上, 因此各桶的数量只是近似相等; 每个桶的 `count` 是准确的.
+    /// 子树不够多时返回的桶少于 `buckets` 个, 树为空或 `buckets` 为 0 时返回空的 `Vec`.
+    /// 分隔 key 不一定是树中存在的 key, 删除之后仍然保留在内部节点中
+    ///
+    /// ```
+    /// use std::ops::Bound;
+    /// use btree_test::BPTree;
+    ///
+    /// let tree = BPTree::bulk_load(8, (0..10_000).map(|i| (format!("{:05}", i), i.to_string())));
+    /// let histogram = tree.key_histogram(4);
+    /// assert_eq!(histogram.len(), 4);
+    /// assert_eq!(histogram.iter().map(|_bucket| _bucket.count).sum::<usize>(), 10_000);
+    /// assert!(histogram.iter().all(|_bucket| _bucket.count.abs_diff(2500) < 500));
+    /// // 相邻的桶首尾相接, 可以直接作为分片的切分点
+    /// assert!(histogram.windows(2).all(|_w| _w[0].end == _w[1].start));
+    /// let second = &histogram[1];
+    /// let (start, end) = (second.start.as_deref().unwrap(), second.end.as_deref().unwrap());
+    /// assert_eq!(tree.range_count(Bound::Included(start), Bound::Excluded(end)), second.count);
+    /// ```
+    pub fn key_histogram(&self, buckets: usize) -> Vec<KeyBucket> {
+        if buckets == 0 || self.len == 0 {
+            return vec![];
+        }
+        // 每棵子树的下界、根节点与键值对数量, 从整棵树开始逐层展开
+        let mut level: Vec<(Option<&str>, NodeId, usize)> = vec![(None, self.root, self.len)];
+        while level.len() < buckets * SUBTREES_PER_BUCKET {
+            // 所有叶子节点都在同一层, 这一层已经是叶子节点时不再向下展开
+            if !matches!(self.nodes[level[0].1], BPTreeNode::Internal { .. }) {
+                break;
+            }
+            let mut next_level = vec![];
+            for (start, offset, _) in level {
+                let BPTreeNode::Internal { branches, .. } = &self.nodes[offset] else {
+                    unreachable!("leaves are all at the same depth")
+                };
+                let (child, keys, counts) = branches.parts();
+                // 第一棵子树的下界与父节点相同, 其余的下界为它前面的分隔 key
+                let starts = std::iter::once(start).chain(keys.iter().map(|_key| Some(_key.as_str())));
+                next_level.extend(starts.zip(child.iter().copied()).zip(counts.iter().copied()).map(|((_start, _child), _count)| (_start, _child, _count)));
+            }
+            level = next_level;
+        }
+
+        // 累计数量达到下一个分位点时结束当前的桶, 最后一棵子树总是留给最后一个桶
+        let mut histogram = vec![];
+        let mut start: Option<&str> = None;
+        let (mut count, mut total) = (0, 0);
+        for (idx, &(_, _, subtree)) in level.iter().enumerate() {
+            count += subtree;
+            total += subtree;
+            let Some(&(end, _, _)) = level.get(idx + 1) else { break; };
+            if histogram.len() + 1 < buckets && total * buckets >= (histogram.len() + 1) * self.len {
+                histogram.push(KeyBucket { start: start.map(str::to_string), end: end.map(str::to_string), count });
+                (start, count) = (end, 0);
+            }
+        }
+        histogram.push(KeyBucket { start: start.map(str::to_string), end: None, count });
+        histogram
+    }
+}
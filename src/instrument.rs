@@ -0,0 +1,42 @@
+//! 结构变化的埋点: 开启 `tracing` feature 时通过 [`tracing`](https://docs.rs/tracing) 发出事件, 否则都是空函数
+//!
+//! 深度从根节点开始计算, 根节点为 0, 叶子节点的深度等于树高减一.
+//! 分裂、合并与根节点变化为 `DEBUG` 级别, 每次查找叶子节点为 `TRACE` 级别, target 都是 `btree_test::instrument`
+
+use crate::slab::NodeId;
+
+/// 深度为 `depth` 的节点 `left` 分裂出了右节点 `right`
+#[inline]
+pub(crate) fn split(left: NodeId, right: NodeId, depth: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(left = left.index(), right = right.index(), depth, "split");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (left, right, depth);
+}
+
+/// 深度为 `depth` 的右节点 `right` 合并进了左兄弟 `left`, `right` 随后被释放
+#[inline]
+pub(crate) fn merge(left: NodeId, right: NodeId, depth: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(left = left.index(), right = right.index(), depth, "merge");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (left, right, depth);
+}
+
+/// 根节点由 `old` 变为 `new`, 树长高 (根节点分裂) 或变矮 (根节点只剩一个子节点)
+#[inline]
+pub(crate) fn root_change(old: NodeId, new: NodeId) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(old = old.index(), new = new.index(), "root change");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (old, new);
+}
+
+/// 从根节点向下找到了深度为 `depth` 的叶子节点 `leaf`
+#[inline]
+pub(crate) fn leaf_search(leaf: NodeId, depth: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(leaf = leaf.index(), depth, "leaf search");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (leaf, depth);
+}
@@ -0,0 +1,221 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+use crate::bptree::{BPTree, BPTreeNode};
+use crate::slab::{self, NodeId};
+
+/// [`BPTree::check_invariants`] 发现的第一个不满足的约束
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    /// 节点中的 key 不是严格递增的
+    UnsortedKeys { offset: NodeId },
+    /// key 超出了父节点中分隔 key 划定的范围
+    KeyOutOfRange { offset: NodeId, key: String },
+    /// 内部节点的子节点数量不等于 key 的数量加一, 或子树计数的数量与子节点数量不同
+    ChildCount { offset: NodeId, keys: usize, child: usize, counts: usize },
+    /// 节点中的元素超出 `order - 1` (叶子节点为 `leaf_order - 1`)
+    Overflow { offset: NodeId, len: usize },
+    /// 非根节点中的元素少于下限 (第一个与最后一个叶子节点为空), 或根内部节点没有 key
+    Underflow { offset: NodeId, len: usize },
+    /// 叶子节点不在同一层
+    UnevenDepth { offset: NodeId },
+    /// 节点被引用了不止一次 (或者引用超出了 `nodes` 的范围)
+    InvalidChild { offset: NodeId, child: NodeId },
+    /// 叶子节点的 `prev`/`next` 链表与树中叶子节点的顺序不一致
+    LeafChain { offset: NodeId },
+    /// 记录的第一个或最后一个叶子节点不正确
+    LeafBounds { first_leaf: NodeId, last_leaf: NodeId },
+    /// 节点无法从根节点到达, 也不是被释放的空节点
+    Unreachable { offset: NodeId },
+    /// 内部节点记录的子树计数与子树中键值对的实际数量不一致
+    SubtreeCount { offset: NodeId, child: NodeId, expected: usize, found: usize },
+    /// 记录的键值对数量与叶子节点中的实际数量不一致
+    LenMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantError::UnsortedKeys { offset } => write!(f, "keys of node {} are not sorted", offset),
+            InvariantError::KeyOutOfRange { offset, key } => {
+                write!(f, "key {:?} of node {} is outside the range of its parent", key, offset)
+            }
+            InvariantError::ChildCount { offset, keys, child, counts } => {
+                write!(f, "internal node {} has {} keys, {} children and {} subtree counts", offset, keys, child, counts)
+            }
+            InvariantError::Overflow { offset, len } => write!(f, "node {} holds {} entries, too many", offset, len),
+            InvariantError::Underflow { offset, len } => write!(f, "node {} holds {} entries, too few", offset, len),
+            InvariantError::UnevenDepth { offset } => write!(f, "leaf {} is not at the same depth as the others", offset),
+            InvariantError::InvalidChild { offset, child } => {
+                write!(f, "node {} refers to invalid or already visited child {}", offset, child)
+            }
+            InvariantError::LeafChain { offset } => write!(f, "leaf chain is broken at node {}", offset),
+            InvariantError::LeafBounds { first_leaf, last_leaf } => {
+                write!(f, "first leaf {} or last leaf {} is wrong", first_leaf, last_leaf)
+            }
+            InvariantError::Unreachable { offset } => write!(f, "node {} is not reachable from the root", offset),
+            InvariantError::SubtreeCount { offset, child, expected, found } => {
+                write!(f, "node {} records {} entries under child {}, found {}", offset, found, child, expected)
+            }
+            InvariantError::LenMismatch { expected, found } => {
+                write!(f, "tree records {} entries but leaves hold {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for InvariantError {}
+
+// 等待检查的节点
+struct Pending<'a> {
+    offset: NodeId,
+    // 父节点划定的 key 范围, [low, high)
+    low: Option<Cow<'a, str>>,
+    high: Option<Cow<'a, str>>,
+    depth: usize,
+}
+
+impl BPTree {
+    /// 检查树的结构是否满足所有约束, 返回发现的第一个问题
+    ///
+    /// 检查的内容包括: 节点内 key 严格递增且落在父节点划定的范围内,
+    /// 节点中的元素数量在 `order` 或 `leaf_order` 规定的上下限之间 (第一个与最后一个叶子节点只要求非空, 见 [`SplitPolicy`](crate::SplitPolicy)),
+    /// 所有叶子节点在同一层,
+    /// 叶子链表与树中叶子节点的顺序一致, 内部节点中的子树计数正确, 以及所有节点都可以从根节点到达 (被释放的空节点除外)
+    ///
+    /// 需要遍历整棵树, 主要用于调试与测试
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut leaves = vec![];
+        let mut leaf_depth = None;
+        let mut preorder = vec![];
+        let mut stack = vec![Pending { offset: self.root, low: None, high: None, depth: 0 }];
+        if self.nodes.get(self.root).is_none() {
+            return Err(InvariantError::Unreachable { offset: self.root });
+        }
+        visited[self.root.index()] = true;
+
+        while let Some(Pending { offset, low, high, depth }) = stack.pop() {
+            preorder.push(offset);
+            let node = &self.nodes[offset];
+            let len = node.len();
+            let order = self.fanout.of(node);
+            if len > order - 1 {
+                return Err(InvariantError::Overflow { offset, len });
+            }
+            // 根节点不受下限约束, 但根内部节点至少要有一个 key
+            // 分裂偏向一侧时第一个与最后一个叶子节点可能不满, 只要求非空
+            let min_len = match node {
+                _ if offset == self.root => matches!(node, BPTreeNode::Internal { .. }) as usize,
+                BPTreeNode::Leaf { .. } if offset == self.first_leaf || offset == self.last_leaf => 1,
+                _ => order.div_ceil(2) - 1,
+            };
+            if len < min_len {
+                return Err(InvariantError::Underflow { offset, len });
+            }
+
+            // 按前缀压缩存放的节点拼接出完整的 key 再比较
+            let keys: Vec<Cow<str>> = match node {
+                BPTreeNode::Internal { branches, prefix } => branches.keys().iter().map(|_k| full_key(prefix, _k)).collect(),
+                BPTreeNode::Leaf { kvs, prefix, .. } => kvs.iter().map(|_kv| full_key(prefix, &_kv.key)).collect(),
+            };
+            let cmp = |_a: &str, _b: &str| self.key_order.cmp(_a.as_bytes(), _b.as_bytes());
+            if keys.windows(2).any(|_w| cmp(&_w[0], &_w[1]).is_ge()) {
+                return Err(InvariantError::UnsortedKeys { offset });
+            }
+            let out_of_range = keys
+                .iter()
+                .find(|_k| low.as_ref().is_some_and(|_low| cmp(_k, _low).is_lt()) || high.as_ref().is_some_and(|_high| cmp(_k, _high).is_ge()));
+            if let Some(key) = out_of_range {
+                return Err(InvariantError::KeyOutOfRange { offset, key: key.to_string() });
+            }
+
+            match node {
+                BPTreeNode::Internal { branches, .. } => {
+                    let (child, _, counts) = branches.parts();
+                    if child.len() != keys.len() + 1 || counts.len() != child.len() {
+                        return Err(InvariantError::ChildCount {
+                            offset,
+                            keys: keys.len(),
+                            child: child.len(),
+                            counts: counts.len(),
+                        });
+                    }
+                    // 逆序入栈, 保证按 key 的顺序访问叶子节点
+                    for (idx, &child_offset) in child.iter().enumerate().rev() {
+                        if self.nodes.get(child_offset).is_none() || visited[child_offset.index()] {
+                            return Err(InvariantError::InvalidChild { offset, child: child_offset });
+                        }
+                        visited[child_offset.index()] = true;
+                        let child_low = if idx == 0 { low.clone() } else { Some(keys[idx - 1].clone()) };
+                        let child_high = if idx == keys.len() { high.clone() } else { Some(keys[idx].clone()) };
+                        stack.push(Pending {
+                            offset: child_offset,
+                            low: child_low,
+                            high: child_high,
+                            depth: depth + 1,
+                        });
+                    }
+                }
+                BPTreeNode::Leaf { .. } => {
+                    if *leaf_depth.get_or_insert(depth) != depth {
+                        return Err(InvariantError::UnevenDepth { offset });
+                    }
+                    leaves.push(offset);
+                }
+            }
+        }
+
+        if leaves.first() != Some(&self.first_leaf) || leaves.last() != Some(&self.last_leaf) {
+            return Err(InvariantError::LeafBounds { first_leaf: self.first_leaf, last_leaf: self.last_leaf });
+        }
+        let mut found = 0;
+        for (idx, &offset) in leaves.iter().enumerate() {
+            let BPTreeNode::Leaf { prev, next, kvs, .. } = &self.nodes[offset] else { continue; };
+            let expected_prev = idx.checked_sub(1).map(|_idx| leaves[_idx]);
+            let expected_next = leaves.get(idx + 1).copied();
+            if *prev != expected_prev || *next != expected_next {
+                return Err(InvariantError::LeafChain { offset });
+            }
+            found += kvs.len();
+        }
+        if found != self.len {
+            return Err(InvariantError::LenMismatch { expected: self.len, found });
+        }
+
+        // 先序遍历的逆序中子节点总在父节点之前, 可以自底向上统计每个子树中的键值对
+        let mut sizes = vec![0; self.nodes.len()];
+        for &offset in preorder.iter().rev() {
+            sizes[offset.index()] = match &self.nodes[offset] {
+                BPTreeNode::Internal { branches, .. } => {
+                    let (child, _, counts) = branches.parts();
+                    for (&child_offset, &count) in child.iter().zip(counts) {
+                        let expected = sizes[child_offset.index()];
+                        if count != expected {
+                            return Err(InvariantError::SubtreeCount { offset, child: child_offset, expected, found: count });
+                        }
+                    }
+                    counts.iter().sum()
+                }
+                BPTreeNode::Leaf { kvs, .. } => kvs.len(),
+            };
+        }
+
+        // 被释放的节点是脱离了树的空叶子节点
+        for (offset, node) in self.nodes.iter() {
+            if !visited[offset.index()] && !slab::is_free(node) {
+                return Err(InvariantError::Unreachable { offset });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn full_key<'a>(prefix: &str, suffix: &'a str) -> Cow<'a, str> {
+    if prefix.is_empty() {
+        Cow::Borrowed(suffix)
+    } else {
+        Cow::Owned(format!("{}{}", prefix, suffix))
+    }
+}
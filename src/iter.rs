@@ -0,0 +1,341 @@
+use std::sync::Arc;
+
+use crate::bptree::{BPTree, BPTreeKeyValue, BPTreeNode};
+use crate::slab::{NodeId, NodeSlab};
+
+/// 按 key 顺序遍历一段范围内键值对的迭代器, 由 [`BPTree::range`](crate::BPTree::range) 创建
+///
+/// 迭代器只在开始时从根节点向下查找一次起点和终点, 之后沿着叶子节点的 `next` 链表向后遍历,
+/// 或者沿着 `prev` 链表从终点向前遍历
+pub struct Range<'a> {
+    nodes: &'a NodeSlab,
+    // 当前位置, (叶子节点偏移量, 节点中的下标)
+    front: (NodeId, usize),
+    // 结束位置 (不包含)
+    back: (NodeId, usize),
+}
+
+impl<'a> Range<'a> {
+    pub(crate) fn new(nodes: &'a NodeSlab, front: (NodeId, usize), back: (NodeId, usize)) -> Self {
+        Self {
+            nodes,
+            front: normalize(nodes, front),
+            back: normalize(nodes, back),
+        }
+    }
+
+    /// 还没有遍历到的部分的起止位置, 之后可以用 [`new`](Self::new) 从这里继续
+    pub(crate) fn positions(&self) -> ((NodeId, usize), (NodeId, usize)) {
+        (self.front, self.back)
+    }
+
+    /// 与 [`next`](Iterator::next) 相同, 同时返回键值对所在叶子节点的前缀, 节点按前缀压缩存放时 key 拼接在它之后才完整
+    pub(crate) fn next_entry(&mut self) -> Option<(&'a str, &'a BPTreeKeyValue)> {
+        if self.front == self.back {
+            return None;
+        }
+        let (leaf_offset, idx) = self.front;
+        let BPTreeNode::Leaf { kvs, prefix, .. } = &self.nodes[leaf_offset] else { return None; };
+        // 已经到达叶子链表的末尾
+        let kv = kvs.get(idx)?;
+        self.front = normalize(self.nodes, (leaf_offset, idx + 1));
+        Some((prefix, kv))
+    }
+
+    /// 与 [`next_back`](DoubleEndedIterator::next_back) 相同, 同时返回键值对所在叶子节点的前缀
+    pub(crate) fn next_back_entry(&mut self) -> Option<(&'a str, &'a BPTreeKeyValue)> {
+        if self.front == self.back {
+            return None;
+        }
+        // 结束位置前移一个元素, 处于叶子节点开头时移动到前一个叶子节点的末尾
+        let (mut leaf_offset, mut idx) = self.back;
+        while idx == 0 {
+            let BPTreeNode::Leaf { prev, .. } = &self.nodes[leaf_offset] else { return None; };
+            leaf_offset = (*prev)?;
+            idx = self.nodes[leaf_offset].len();
+        }
+        let BPTreeNode::Leaf { kvs, prefix, .. } = &self.nodes[leaf_offset] else { return None; };
+        self.back = (leaf_offset, idx - 1);
+        Some((prefix, &kvs[idx - 1]))
+    }
+
+    /// 与 [`next`](Iterator::next) 相同, 但返回拼接了前缀的完整 key
+    pub(crate) fn next_owned(&mut self) -> Option<(String, &'a str)> {
+        self.next_entry().map(|(prefix, kv)| (format!("{}{}", prefix, kv.key), kv.value()))
+    }
+
+    /// 与 [`next_back`](DoubleEndedIterator::next_back) 相同, 但返回拼接了前缀的完整 key
+    pub(crate) fn next_back_owned(&mut self) -> Option<(String, &'a str)> {
+        self.next_back_entry().map(|(prefix, kv)| (format!("{}{}", prefix, kv.key), kv.value()))
+    }
+}
+
+impl<'a> Iterator for Range<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().map(|(_, kv)| (kv.key(), kv.value()))
+    }
+}
+
+impl DoubleEndedIterator for Range<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.next_back_entry().map(|(_, kv)| (kv.key(), kv.value()))
+    }
+}
+
+pub(crate) fn normalize(nodes: &NodeSlab, position: (NodeId, usize)) -> (NodeId, usize) {
+    // 位置处于叶子节点末尾时, 统一移动到下一个叶子节点的开头, 以便比较两个位置是否相同
+    let (mut leaf_offset, mut idx) = position;
+    while let BPTreeNode::Leaf { next: Some(next), kvs, .. } = &nodes[leaf_offset] {
+        if idx < kvs.len() {
+            break;
+        }
+        leaf_offset = *next;
+        idx = 0;
+    }
+    (leaf_offset, idx)
+}
+
+/// 按 key 顺序遍历所有键值对的迭代器, 由 [`BPTree::iter`](crate::BPTree::iter) 创建
+pub struct Iter<'a> {
+    inner: Range<'a>,
+}
+
+impl<'a> Iter<'a> {
+    pub(crate) fn new(inner: Range<'a>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+/// 按顺序遍历所有 key 的迭代器, 由 [`BPTree::keys`](crate::BPTree::keys) 创建
+pub struct Keys<'a> {
+    inner: Iter<'a>,
+}
+
+impl<'a> Keys<'a> {
+    pub(crate) fn new(inner: Iter<'a>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+impl DoubleEndedIterator for Keys<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+/// 按 key 的顺序遍历所有值的迭代器, 由 [`BPTree::values`](crate::BPTree::values) 创建
+pub struct Values<'a> {
+    inner: Iter<'a>,
+}
+
+impl<'a> Values<'a> {
+    pub(crate) fn new(inner: Iter<'a>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a> Iterator for Values<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+}
+
+impl DoubleEndedIterator for Values<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+/// 按 key 顺序遍历所有键值对并可修改值的迭代器, 由 [`BPTree::iter_mut`](crate::BPTree::iter_mut) 创建
+pub struct IterMut<'a> {
+    // 每个节点的可变引用, 遍历到某个叶子节点时从中取出, 仍被快照共享的叶子节点在取出时复制
+    slots: Vec<Option<&'a mut Arc<BPTreeNode>>>,
+    front: std::slice::IterMut<'a, BPTreeKeyValue>,
+    back: std::slice::IterMut<'a, BPTreeKeyValue>,
+    next: Option<NodeId>,
+    prev: Option<NodeId>,
+}
+
+impl<'a> IterMut<'a> {
+    pub(crate) fn new(slots: Vec<&'a mut Arc<BPTreeNode>>, first_leaf: NodeId, last_leaf: NodeId) -> Self {
+        Self {
+            slots: slots.into_iter().map(Some).collect(),
+            front: [].iter_mut(),
+            back: [].iter_mut(),
+            next: Some(first_leaf),
+            prev: Some(last_leaf),
+        }
+    }
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = (&'a str, &'a mut String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(kv) = self.front.next() {
+                return Some((kv.key.as_str(), &mut kv.value));
+            }
+            // 当前叶子节点遍历完毕, 沿链表取出下一个叶子节点
+            // 下一个叶子节点已经被反向遍历取走时, 剩下的元素都在反向遍历当前的叶子节点中
+            let Some(BPTreeNode::Leaf { next, kvs, .. }) = self.next.and_then(|_n| self.slots[_n.index()].take()).map(Arc::make_mut) else {
+                self.next = None;
+                return self.back.next().map(|kv| (kv.key.as_str(), &mut kv.value));
+            };
+            self.front = kvs.iter_mut();
+            self.next = *next;
+        }
+    }
+}
+
+impl DoubleEndedIterator for IterMut<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(kv) = self.back.next_back() {
+                return Some((kv.key.as_str(), &mut kv.value));
+            }
+            let Some(BPTreeNode::Leaf { prev, kvs, .. }) = self.prev.and_then(|_p| self.slots[_p.index()].take()).map(Arc::make_mut) else {
+                self.prev = None;
+                return self.front.next_back().map(|kv| (kv.key.as_str(), &mut kv.value));
+            };
+            self.back = kvs.iter_mut();
+            self.prev = *prev;
+        }
+    }
+}
+
+/// 按 key 顺序取出从树中删除的一段键值对的迭代器, 由 [`BPTree::drain`](crate::BPTree::drain) 创建
+///
+/// 创建时键值对已经从树中摘下, 迭代器不借用树
+pub struct Drain {
+    kvs: std::vec::IntoIter<BPTreeKeyValue>,
+}
+
+impl Drain {
+    pub(crate) fn new(kvs: Vec<BPTreeKeyValue>) -> Self {
+        Self { kvs: kvs.into_iter() }
+    }
+}
+
+impl Iterator for Drain {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.kvs.next().map(|kv| (kv.key, kv.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.kvs.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Drain {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.kvs.next_back().map(|kv| (kv.key, kv.value))
+    }
+}
+
+impl ExactSizeIterator for Drain {}
+
+/// 按 key 顺序取出所有键值对的迭代器, 由 [`BPTree`] 的 [`IntoIterator`] 实现创建
+pub struct IntoIter {
+    // 与 IterMut 相同, 每个叶子节点只会被正向或反向遍历取出一次
+    slots: Vec<Option<BPTreeNode>>,
+    front: std::vec::IntoIter<BPTreeKeyValue>,
+    back: std::vec::IntoIter<BPTreeKeyValue>,
+    next: Option<NodeId>,
+    prev: Option<NodeId>,
+}
+
+impl Iterator for IntoIter {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(kv) = self.front.next() {
+                return Some((kv.key, kv.value));
+            }
+            let Some(BPTreeNode::Leaf { next, kvs, .. }) = self.next.and_then(|_n| self.slots[_n.index()].take()) else {
+                self.next = None;
+                return self.back.next().map(|kv| (kv.key, kv.value));
+            };
+            self.front = kvs.into_iter();
+            self.next = next;
+        }
+    }
+}
+
+impl DoubleEndedIterator for IntoIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(kv) = self.back.next_back() {
+                return Some((kv.key, kv.value));
+            }
+            let Some(BPTreeNode::Leaf { prev, kvs, .. }) = self.prev.and_then(|_p| self.slots[_p.index()].take()) else {
+                self.prev = None;
+                return self.front.next_back().map(|kv| (kv.key, kv.value));
+            };
+            self.back = kvs.into_iter();
+            self.prev = prev;
+        }
+    }
+}
+
+impl IntoIterator for BPTree {
+    type Item = (String, String);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            slots: self.nodes.into_vec().into_iter().map(Some).collect(),
+            front: Vec::new().into_iter(),
+            back: Vec::new().into_iter(),
+            next: Some(self.first_leaf),
+            prev: Some(self.last_leaf),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a BPTree {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut BPTree {
+    type Item = (&'a str, &'a mut String);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
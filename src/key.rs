@@ -0,0 +1,147 @@
+use std::fmt::Write;
+
+// 整数按大端序编码为定长的小写十六进制, 字符的顺序与数值的顺序相同
+const HEX_LEN: usize = 16;
+// 字符串以 "\0\0" 结尾, 其中的 '\0' 转义为 "\0\x01", 比任何后续字符都小, 较短的字符串排在前面
+const TERMINATOR: &str = "\0\0";
+const ESCAPED_NUL: &str = "\0\x01";
+
+/// 可以编码为保持顺序的字符串 key 的类型
+///
+/// 树中的 key 按字节比较, 数字直接转换为十进制字符串时 `"10"` 排在 `"9"` 前面.
+/// 实现这个 trait 的类型编码后按字节比较的顺序与值本身的顺序相同, 可以直接作为 [`BPTree`](crate::BPTree) 的 key,
+/// 范围查询也按数值的顺序进行:
+///
+/// - `u64` 编码为大端序的 16 位小写十六进制
+/// - `i64` 翻转符号位后与 `u64` 相同, 负数排在正数前面
+/// - `f64` 的顺序与 [`f64::total_cmp`] 相同: 正数翻转符号位, 负数翻转所有位
+/// - `String` 以 `"\0\0"` 结尾, 其中的 `'\0'` 需要转义, 可以放在复合 key 的任意位置
+/// - 元组按字段依次编码, 先按第一个字段排序, 相同时再比较下一个字段
+///
+/// 复合 key 的编码是各字段编码的拼接, 因此前面几个字段的编码是完整 key 的前缀, 可以用 [`prefix`](crate::BPTree::prefix)
+/// 查找某个租户的所有 key
+///
+/// ```
+/// use std::ops::Bound;
+/// use btree_test::{BPTree, Key};
+///
+/// let mut tree = BPTree::new(4);
+/// for (tenant, ts) in [(2u64, 10i64), (1, 9), (1, 10), (1, -5), (10, 0)] {
+///     tree.put((tenant, ts).to_key(), format!("{}@{}", tenant, ts)).unwrap();
+/// }
+/// let tenant_1: Vec<(u64, i64)> = tree.prefix(&1u64.to_key()).map(|(key, _)| Key::from_key(key).unwrap()).collect();
+/// assert_eq!(tenant_1, [(1, -5), (1, 9), (1, 10)]);
+///
+/// let (start, end) = ((1u64, 0i64).to_key(), (2u64, i64::MAX).to_key());
+/// let values: Vec<&str> = tree.range(Bound::Included(start.as_str()), Bound::Included(end.as_str())).map(|(_, value)| value).collect();
+/// assert_eq!(values, ["1@9", "1@10", "2@10"]);
+/// ```
+pub trait Key: Sized {
+    /// 将编码追加到 `out` 后面
+    fn encode_key(&self, out: &mut String);
+
+    /// 从 `input` 的开头解码一个值, 并把 `input` 前移到编码之后; 格式不正确时返回 `None`
+    fn decode_key(input: &mut &str) -> Option<Self>;
+
+    /// 编码为一个 key
+    fn to_key(&self) -> String {
+        let mut key = String::new();
+        self.encode_key(&mut key);
+        key
+    }
+
+    /// 从完整的 key 解码, 编码之后还有多余的字符时返回 `None`
+    fn from_key(key: &str) -> Option<Self> {
+        let mut input = key;
+        let value = Self::decode_key(&mut input)?;
+        input.is_empty().then_some(value)
+    }
+}
+
+impl Key for u64 {
+    fn encode_key(&self, out: &mut String) {
+        write!(out, "{:016x}", self).expect("writing to a String cannot fail");
+    }
+
+    fn decode_key(input: &mut &str) -> Option<Self> {
+        let digits = input.get(..HEX_LEN)?;
+        // from_str_radix 也接受大写字母与正号, 只认同一种写法, 每个值只有一种编码
+        if !digits.bytes().all(|_b| matches!(_b, b'0'..=b'9' | b'a'..=b'f')) {
+            return None;
+        }
+        *input = &input[HEX_LEN..];
+        u64::from_str_radix(digits, 16).ok()
+    }
+}
+
+impl Key for i64 {
+    fn encode_key(&self, out: &mut String) {
+        (*self as u64 ^ 1 << 63).encode_key(out);
+    }
+
+    fn decode_key(input: &mut &str) -> Option<Self> {
+        Some((u64::decode_key(input)? ^ 1 << 63) as i64)
+    }
+}
+
+impl Key for f64 {
+    fn encode_key(&self, out: &mut String) {
+        let bits = self.to_bits();
+        let flipped = if bits >> 63 == 1 { !bits } else { bits | 1 << 63 };
+        flipped.encode_key(out);
+    }
+
+    fn decode_key(input: &mut &str) -> Option<Self> {
+        let flipped = u64::decode_key(input)?;
+        let bits = if flipped >> 63 == 1 { flipped ^ 1 << 63 } else { !flipped };
+        Some(f64::from_bits(bits))
+    }
+}
+
+impl Key for String {
+    fn encode_key(&self, out: &mut String) {
+        for (idx, part) in self.split('\0').enumerate() {
+            if idx > 0 {
+                out.push_str(ESCAPED_NUL);
+            }
+            out.push_str(part);
+        }
+        out.push_str(TERMINATOR);
+    }
+
+    fn decode_key(input: &mut &str) -> Option<Self> {
+        let mut value = String::new();
+        let mut rest = *input;
+        loop {
+            let nul = rest.find('\0')?;
+            value.push_str(&rest[..nul]);
+            rest = &rest[nul..];
+            if let Some(after) = rest.strip_prefix(TERMINATOR) {
+                *input = after;
+                return Some(value);
+            }
+            rest = rest.strip_prefix(ESCAPED_NUL)?;
+            value.push('\0');
+        }
+    }
+}
+
+macro_rules! tuple_key {
+    ($($name:ident),+) => {
+        impl<$($name: Key),+> Key for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, out: &mut String) {
+                let ($($name,)+) = self;
+                $($name.encode_key(out);)+
+            }
+
+            fn decode_key(input: &mut &str) -> Option<Self> {
+                Some(($($name::decode_key(input)?,)+))
+            }
+        }
+    };
+}
+
+tuple_key!(A, B);
+tuple_key!(A, B, C);
+tuple_key!(A, B, C, D);
@@ -0,0 +1,98 @@
+//! 一个基于 Rust `Vec<>` 的 B+Tree 实现
+//!
+//! ```
+//! use btree_test::BPTree;
+//!
+//! let mut tree = BPTree::new(5);
+//! tree.put("a".to_string(), "1".to_string()).unwrap();
+//! assert_eq!(tree.get("a").map(|kv| kv.value()), Some("1"));
+//!
+//! for (key, value) in &tree {
+//!     println!("{}: {}", key, value);
+//! }
+//! ```
+
+#[cfg(feature = "tokio")]
+mod async_paged;
+mod backup;
+mod bptree;
+mod branches;
+mod builder;
+mod change;
+mod cipher;
+mod comparator;
+#[cfg(feature = "lz4")]
+mod compressed;
+mod concurrent;
+mod buffer_pool;
+#[cfg(feature = "serde")]
+pub mod compact;
+mod cursor;
+mod diff;
+mod display;
+mod dot;
+mod entry;
+mod error;
+mod format;
+mod histogram;
+mod instrument;
+mod invariant;
+mod iter;
+mod key;
+mod maintenance;
+mod merge;
+mod merkle;
+mod mmap;
+mod multimap;
+mod paged;
+mod pager;
+mod prefix;
+#[cfg(feature = "serde")]
+mod serialize;
+mod slab;
+mod snapshot;
+mod split;
+mod store;
+mod tombstone;
+mod transaction;
+mod versioned;
+mod wal;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "tokio")]
+pub use async_paged::AsyncBPTree;
+pub use bptree::{BPTree, BPTreeKeyValue, BPTreeNode, BPTreeStats, MemoryStats};
+pub use branches::Branches;
+pub use builder::BPTreeBuilder;
+pub use change::{ChangeEvent, ChangeReceiver, RecvError};
+pub use comparator::Comparator;
+#[cfg(feature = "lz4")]
+pub use compressed::{CompressedBPTree, CompressedRange};
+pub use concurrent::ConcurrentBPTree;
+pub use buffer_pool::BufferPool;
+pub use cursor::{Cursor, CursorMut, StableCursor};
+pub use diff::{Diff, DiffEntry};
+pub use entry::{Entry, OccupiedEntry, VacantEntry, ValueMut};
+pub use error::{BPTreeError, CasError};
+pub use format::Format;
+pub use histogram::KeyBucket;
+pub use invariant::InvariantError;
+pub use iter::{Drain, IntoIter, Iter, IterMut, Keys, Range, Values};
+pub use key::Key;
+pub use maintenance::{Maintain, MaintenanceConfig, MaintenanceMetrics, MaintenanceScheduler, MaintenanceStats, MaintenanceTask};
+pub use merge::MergeOperator;
+pub use merkle::{verify, InclusionProof, ProofStep};
+pub use mmap::{MmapBPTree, MmapRange};
+pub use multimap::{BPTreeMultimap, MultiRange};
+pub use paged::PagedBPTree;
+pub use pager::{Pager, DEFAULT_PAGE_SIZE, FORMAT_VERSION};
+pub use prefix::{PrefixBPTree, PrefixRange};
+pub use slab::{NodeId, NodeSlab};
+pub use snapshot::{BPTreeSnapshot, SnapshotIter};
+pub use split::{InsertPattern, SplitPolicy};
+pub use tombstone::{TombstoneBPTree, TombstoneRange};
+pub use transaction::Transaction;
+pub use versioned::{VersionedBPTree, VersionedRange};
+#[cfg(feature = "wasm")]
+pub use wasm::WasmBPTree;
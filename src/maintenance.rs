@@ -0,0 +1,344 @@
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::bptree::BPTree;
+use crate::error::BPTreeError;
+use crate::tombstone::TombstoneBPTree;
+
+/// 后台维护任务, 由 [`MaintenanceScheduler`] 按 [`MaintenanceConfig`] 中的触发条件执行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    /// 清理删除标记, 见 [`TombstoneBPTree::compact`]; 没有删除标记的树什么也不做
+    Vacuum,
+    /// 整理节点, 去掉被释放的槽, 见 [`BPTree::compact`]
+    Compact,
+    /// 将节点写回文件并清空预写日志, 见 [`BPTree::checkpoint`]; 日志文件也因此被回收
+    Checkpoint,
+}
+
+/// 决定是否需要维护的指标, 由 [`Maintain::maintenance_metrics`] 返回
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenanceMetrics {
+    /// 节点 slab 中槽的数量
+    pub slots: usize,
+    /// 其中被释放、等待复用的槽的数量
+    pub free_slots: usize,
+    /// 没有被删除的键值对的数量
+    pub len: usize,
+    /// 还没有被清理的删除标记的数量
+    pub tombstones: usize,
+    /// 预写日志的字节数, 没有日志时为 0
+    pub wal_bytes: u64,
+}
+
+/// 可以由 [`MaintenanceScheduler`] 在后台维护的树
+pub trait Maintain {
+    /// 当前的指标, 调度器每次轮询时调用, 应当只读取计数而不遍历整棵树
+    fn maintenance_metrics(&self) -> MaintenanceMetrics;
+
+    /// 执行一项任务, 返回写入文件的字节数 (估计值), 用于 IO 限流
+    fn run_maintenance(&mut self, task: MaintenanceTask) -> Result<u64, BPTreeError>;
+}
+
+impl Maintain for BPTree {
+    fn maintenance_metrics(&self) -> MaintenanceMetrics {
+        MaintenanceMetrics {
+            slots: self.nodes.len(),
+            free_slots: self.nodes.free_len(),
+            len: self.len,
+            tombstones: 0,
+            wal_bytes: self.wal.as_ref().map_or(0, |_wal| _wal.len()),
+        }
+    }
+
+    fn run_maintenance(&mut self, task: MaintenanceTask) -> Result<u64, BPTreeError> {
+        match task {
+            MaintenanceTask::Vacuum => Ok(0),
+            MaintenanceTask::Compact => {
+                self.compact();
+                Ok(0)
+            }
+            MaintenanceTask::Checkpoint => {
+                self.checkpoint()?;
+                // 检查点重写所有节点, 每个节点占一页
+                Ok(self.pager.as_ref().map_or(0, |_pager| (self.nodes.len() * _pager.page_size()) as u64))
+            }
+        }
+    }
+}
+
+impl Maintain for TombstoneBPTree {
+    fn maintenance_metrics(&self) -> MaintenanceMetrics {
+        MaintenanceMetrics { len: self.len(), tombstones: self.tombstone_count(), ..self.tree.maintenance_metrics() }
+    }
+
+    fn run_maintenance(&mut self, task: MaintenanceTask) -> Result<u64, BPTreeError> {
+        match task {
+            MaintenanceTask::Vacuum => self.compact().map(|_| 0),
+            task => self.tree.run_maintenance(task),
+        }
+    }
+}
+
+/// [`MaintenanceScheduler`] 的触发条件与限流设置, 为 `None` 的条件不会触发
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaintenanceConfig {
+    /// 检查触发条件的间隔
+    pub poll_interval: Duration,
+    /// 删除标记占所有键值对的比例达到这个值时清理
+    pub vacuum_ratio: Option<f64>,
+    /// 被释放的槽占所有槽的比例达到这个值时整理节点
+    pub compact_ratio: Option<f64>,
+    /// 预写日志达到这么多字节时做检查点, 回收日志文件
+    pub wal_limit: Option<u64>,
+    /// 距离上一次检查点超过这么久时做检查点
+    pub checkpoint_interval: Option<Duration>,
+    /// 后台任务平均每秒最多写入这么多字节, 写入较多的任务之后推迟下一个任务
+    pub io_bytes_per_sec: Option<u64>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(100),
+            vacuum_ratio: Some(0.25),
+            compact_ratio: Some(0.25),
+            wal_limit: Some(16 << 20),
+            checkpoint_interval: None,
+            io_bytes_per_sec: None,
+        }
+    }
+}
+
+impl MaintenanceConfig {
+    /// 按清理、整理、检查点的顺序返回第一个满足触发条件的任务:
+    /// 清理删除标记会释放节点, 整理之后检查点写入的页更少
+    fn due(&self, metrics: &MaintenanceMetrics, since_checkpoint: Duration) -> Option<MaintenanceTask> {
+        let reached = |_ratio: Option<f64>, _part: usize, _total: usize| {
+            _part > 0 && _ratio.is_some_and(|_ratio| _part as f64 >= _ratio * _total as f64)
+        };
+        if reached(self.vacuum_ratio, metrics.tombstones, metrics.len + metrics.tombstones) {
+            Some(MaintenanceTask::Vacuum)
+        } else if reached(self.compact_ratio, metrics.free_slots, metrics.slots) {
+            Some(MaintenanceTask::Compact)
+        } else if self.wal_limit.is_some_and(|_limit| metrics.wal_bytes > 0 && metrics.wal_bytes >= _limit)
+            || self.checkpoint_interval.is_some_and(|_interval| since_checkpoint >= _interval)
+        {
+            Some(MaintenanceTask::Checkpoint)
+        } else {
+            None
+        }
+    }
+}
+
+/// 后台任务执行的次数, 由 [`MaintenanceScheduler::stats`] 返回
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MaintenanceStats {
+    pub vacuums: usize,
+    pub compactions: usize,
+    pub checkpoints: usize,
+    /// 所有任务写入文件的字节数 (估计值)
+    pub bytes_written: u64,
+    /// 失败的任务数
+    pub errors: usize,
+    /// 最近一次失败的原因
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    paused: bool,
+    // 后台线程正在执行任务, pause 需要等它结束
+    running: bool,
+    stopped: bool,
+    stats: MaintenanceStats,
+}
+
+struct Shared<T> {
+    tree: Mutex<T>,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn state(&self) -> MutexGuard<'_, State> {
+        // 持有锁时不会 panic, 锁不会中毒
+        self.state.lock().unwrap_or_else(|_error| _error.into_inner())
+    }
+
+    fn wait<'a>(&'a self, state: MutexGuard<'a, State>) -> MutexGuard<'a, State> {
+        self.changed.wait(state).unwrap_or_else(|_error| _error.into_inner())
+    }
+}
+
+/// 在后台线程中维护一棵树: 按触发条件清理删除标记、整理节点、做检查点并回收预写日志
+///
+/// 树放在调度器内部的 `Mutex` 中, 前台通过 [`lock`](Self::lock) 读写; 后台线程每隔
+/// [`poll_interval`](MaintenanceConfig::poll_interval) 取得锁检查一次指标, 满足条件时在同一次加锁中执行一个任务,
+/// 执行期间前台的读写需要等待. [`pause`](Self::pause) 之后不再开始新的任务, 返回时正在执行的任务已经结束,
+/// 可以在备份或批量导入之前调用; [`resume`](Self::resume) 恢复.
+///
+/// 设置了 [`io_bytes_per_sec`](MaintenanceConfig::io_bytes_per_sec) 时, 每个任务写入的字节数按这个速度折算成时间,
+/// 这段时间内不开始下一个任务; 单个检查点不会被拆开, 限流只控制平均速度. 树中没有布隆过滤器, 因此也没有重建它的任务
+///
+/// ```
+/// use std::ops::Bound;
+/// use std::time::Duration;
+/// use btree_test::{BPTree, MaintenanceConfig, MaintenanceScheduler};
+///
+/// let config = MaintenanceConfig { poll_interval: Duration::from_millis(1), compact_ratio: Some(0.5), ..MaintenanceConfig::default() };
+/// let scheduler = MaintenanceScheduler::new(BPTree::new(4), config);
+/// scheduler.pause();
+/// {
+///     let mut tree = scheduler.lock();
+///     for i in 0..1000 {
+///         tree.put(format!("{:04}", i), i.to_string()).unwrap();
+///     }
+///     tree.remove_range(Bound::Included("0000"), Bound::Excluded("0900")).unwrap();
+///     assert!(tree.nodes().free_len() > tree.nodes().len() / 2);
+/// }
+/// // 暂停期间不会整理
+/// std::thread::sleep(Duration::from_millis(20));
+/// assert_eq!(scheduler.stats().compactions, 0);
+///
+/// scheduler.resume();
+/// while scheduler.stats().compactions == 0 {
+///     std::thread::sleep(Duration::from_millis(1));
+/// }
+/// let tree = scheduler.into_inner();
+/// assert_eq!(tree.nodes().free_len(), 0);
+/// assert_eq!(tree.len(), 100);
+/// ```
+pub struct MaintenanceScheduler<T: Maintain + Send + 'static> {
+    shared: Arc<Shared<T>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<T: Maintain + Send + 'static> MaintenanceScheduler<T> {
+    /// 接管 `tree` 并启动后台线程
+    pub fn new(tree: T, config: MaintenanceConfig) -> Self {
+        let shared = Arc::new(Shared { tree: Mutex::new(tree), state: Mutex::new(State::default()), changed: Condvar::new() });
+        let worker = std::thread::Builder::new()
+            .name("btree-maintenance".to_string())
+            .spawn({
+                let shared = shared.clone();
+                move || run(&shared, config)
+            })
+            .expect("failed to spawn maintenance thread");
+        Self { shared, worker: Some(worker) }
+    }
+
+    /// 取得树的锁, 后台任务正在执行时等待它结束
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.shared.tree.lock().expect("tree poisoned")
+    }
+
+    /// 不再开始新的任务, 等待正在执行的任务结束后返回
+    pub fn pause(&self) {
+        let mut state = self.shared.state();
+        state.paused = true;
+        while state.running {
+            state = self.shared.wait(state);
+        }
+    }
+
+    /// 恢复 [`pause`](Self::pause) 之前的调度
+    pub fn resume(&self) {
+        self.shared.state().paused = false;
+        self.shared.changed.notify_all();
+    }
+
+    /// 是否已经暂停
+    pub fn is_paused(&self) -> bool {
+        self.shared.state().paused
+    }
+
+    /// 到目前为止执行任务的次数与失败的原因
+    pub fn stats(&self) -> MaintenanceStats {
+        self.shared.state().stats.clone()
+    }
+
+    /// 停止后台线程并取回树, 正在执行的任务会先结束
+    pub fn into_inner(mut self) -> T {
+        self.stop();
+        let shared = self.shared.clone();
+        drop(self);
+        let Ok(shared) = Arc::try_unwrap(shared) else { unreachable!("the worker has exited") };
+        shared.tree.into_inner().expect("tree poisoned")
+    }
+
+    fn stop(&mut self) {
+        self.shared.state().stopped = true;
+        self.shared.changed.notify_all();
+        if let Some(worker) = self.worker.take() {
+            // 后台线程只会因为树的锁中毒而 panic, 这时前台已经看到了 panic
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<T: Maintain + Send + 'static> Drop for MaintenanceScheduler<T> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl<T: Maintain + Send + 'static> fmt::Debug for MaintenanceScheduler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.shared.state();
+        f.debug_struct("MaintenanceScheduler").field("paused", &state.paused).field("stats", &state.stats).finish()
+    }
+}
+
+fn run<T: Maintain>(shared: &Shared<T>, config: MaintenanceConfig) {
+    let mut last_checkpoint = Instant::now();
+    // IO 限流: 这个时间之前不开始新的任务
+    let mut throttled_until = Instant::now();
+    let mut state = shared.state();
+    loop {
+        state = shared.changed.wait_timeout(state, config.poll_interval).unwrap_or_else(|_error| _error.into_inner()).0;
+        if state.stopped {
+            return;
+        }
+        if state.paused || Instant::now() < throttled_until {
+            continue;
+        }
+        state.running = true;
+        drop(state);
+
+        let result = {
+            let mut tree = shared.tree.lock().expect("tree poisoned");
+            let metrics = tree.maintenance_metrics();
+            config.due(&metrics, last_checkpoint.elapsed()).map(|_task| (_task, tree.run_maintenance(_task)))
+        };
+
+        state = shared.state();
+        state.running = false;
+        shared.changed.notify_all();
+        let Some((task, result)) = result else { continue; };
+        if task == MaintenanceTask::Checkpoint {
+            // 按时间触发的检查点失败时同样等待一个间隔再重试
+            last_checkpoint = Instant::now();
+        }
+        match result {
+            Ok(written) => {
+                let stats = &mut state.stats;
+                match task {
+                    MaintenanceTask::Vacuum => stats.vacuums += 1,
+                    MaintenanceTask::Compact => stats.compactions += 1,
+                    MaintenanceTask::Checkpoint => stats.checkpoints += 1,
+                }
+                stats.bytes_written += written;
+                if let Some(rate) = config.io_bytes_per_sec.filter(|_rate| *_rate > 0) {
+                    throttled_until = Instant::now() + Duration::from_secs_f64(written as f64 / rate as f64);
+                }
+            }
+            Err(error) => {
+                state.stats.errors += 1;
+                state.stats.last_error = Some(error.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// 合并函数, 通过 [`BPTreeBuilder::merge_operator`](crate::BPTreeBuilder::merge_operator) 注册,
+/// 由 [`BPTree::merge`](crate::BPTree::merge) 调用
+///
+/// `existing` 为 key 原来的值, key 不存在时为 `None`, 返回值作为 key 的新值
+///
+/// 所有 `Fn(&str, Option<&str>, &str) -> String` 闭包都实现了这个 trait
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// // 计数器: 操作数加到原来的值上
+/// let mut tree = BPTree::builder()
+///     .merge_operator(|_key: &str, existing: Option<&str>, operand: &str| {
+///         let existing: i64 = existing.map_or(0, |_v| _v.parse().unwrap());
+///         (existing + operand.parse::<i64>().unwrap()).to_string()
+///     })
+///     .build();
+/// for _ in 0..3 {
+///     tree.merge("hits".to_string(), "2").unwrap();
+/// }
+/// assert_eq!(tree.get("hits").map(|kv| kv.value()), Some("6"));
+/// ```
+pub trait MergeOperator: Send + Sync {
+    /// 把 `operand` 合并到原来的值上
+    fn merge(&self, key: &str, existing: Option<&str>, operand: &str) -> String;
+}
+
+impl<F: Fn(&str, Option<&str>, &str) -> String + Send + Sync> MergeOperator for F {
+    fn merge(&self, key: &str, existing: Option<&str>, operand: &str) -> String {
+        self(key, existing, operand)
+    }
+}
+
+/// 树中保存的合并函数
+#[derive(Clone)]
+pub(crate) struct Merger(Arc<dyn MergeOperator>);
+
+impl Merger {
+    pub(crate) fn new<M: MergeOperator + 'static>(merge_operator: M) -> Self {
+        Merger(Arc::new(merge_operator))
+    }
+
+    pub(crate) fn merge(&self, key: &str, existing: Option<&str>, operand: &str) -> String {
+        self.0.merge(key, existing, operand)
+    }
+}
+
+impl fmt::Debug for Merger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Merger")
+    }
+}
@@ -0,0 +1,162 @@
+use sha2::{Digest, Sha256};
+
+use crate::bptree::{BPTree, BPTreeKeyValue, BPTreeNode};
+use crate::slab::{NodeId, NodeSlab};
+
+// 叶子节点与内部节点的哈希使用不同的前缀, 叶子节点的内容不可能被当作内部节点的子节点哈希
+const LEAF: u8 = 0;
+const INTERNAL: u8 = 1;
+
+/// [`BPTree::prove`] 生成的证明, 说明 `key` 对应的值为 `value`, 用 [`verify`] 对照根哈希检查
+///
+/// 证明包含 key 所在叶子节点中的其他键值对, 以及从叶子节点到根节点每一层的兄弟节点哈希,
+/// 大小与 order 和树高成正比. 所有字段都是公开的, 可以按任何格式传给客户端
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InclusionProof {
+    pub key: String,
+    pub value: String,
+    /// 叶子节点中排在 key 前面的键值对
+    pub before: Vec<(String, String)>,
+    /// 叶子节点中排在 key 后面的键值对
+    pub after: Vec<(String, String)>,
+    /// 从叶子节点的父节点到根节点的每一层
+    pub path: Vec<ProofStep>,
+}
+
+/// [`InclusionProof`] 中的一层: 下一层节点在父节点中左右两侧的兄弟节点的哈希
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofStep {
+    pub left: Vec<[u8; 32]>,
+    pub right: Vec<[u8; 32]>,
+}
+
+/// 检查 `proof` 中的键值对是否属于根哈希为 `root_hash` 的树
+///
+/// 由证明中的叶子节点逐层向上计算哈希, 与 `root_hash` 相同时返回 `true`. 只需要根哈希, 不需要访问树
+pub fn verify(root_hash: &[u8; 32], proof: &InclusionProof) -> bool {
+    let entries = proof.before.iter().map(entry).chain([(proof.key.as_str(), proof.value.as_str())]);
+    let mut hash = leaf_hash(entries.chain(proof.after.iter().map(entry)));
+    for step in &proof.path {
+        hash = internal_hash(step.left.iter().chain([&hash]).chain(&step.right));
+    }
+    hash == *root_hash
+}
+
+fn leaf_hash<'a>(entries: impl Iterator<Item = (&'a str, &'a str)>) -> [u8; 32] {
+    // 每个字段前写入长度, 不同的切分方式不会得到相同的输入
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF]);
+    for (key, value) in entries {
+        for field in [key, value] {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+    }
+    hasher.finalize().into()
+}
+
+fn internal_hash<'a>(children: impl Iterator<Item = &'a [u8; 32]>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([INTERNAL]);
+    for hash in children {
+        hasher.update(hash);
+    }
+    hasher.finalize().into()
+}
+
+fn entry((key, value): &(String, String)) -> (&str, &str) {
+    (key, value)
+}
+
+fn to_entries(kvs: &[BPTreeKeyValue]) -> Vec<(String, String)> {
+    kvs.iter().map(|_kv| (_kv.key.clone(), _kv.value.clone())).collect()
+}
+
+fn refresh(nodes: &NodeSlab, hashes: &mut [Option<[u8; 32]>], offset: NodeId) -> ([u8; 32], bool) {
+    // 返回节点的哈希以及这次是否重新计算过
+    // 叶子节点的值可以在不修改父节点的情况下被修改, 因此总是访问所有节点, 但只重新计算被修改过的节点及其祖先
+    match &nodes[offset] {
+        BPTreeNode::Internal { branches, .. } => {
+            let child = branches.child();
+            let mut changed = false;
+            let mut child_hashes = Vec::with_capacity(child.len());
+            for &child_offset in child {
+                let (hash, child_changed) = refresh(nodes, hashes, child_offset);
+                changed |= child_changed;
+                child_hashes.push(hash);
+            }
+            if let (false, Some(hash)) = (changed, hashes[offset.index()]) {
+                return (hash, false);
+            }
+            let hash = internal_hash(child_hashes.iter());
+            hashes[offset.index()] = Some(hash);
+            (hash, true)
+        }
+        BPTreeNode::Leaf { kvs, .. } => {
+            if let Some(hash) = hashes[offset.index()] {
+                return (hash, false);
+            }
+            let hash = leaf_hash(kvs.iter().map(|_kv| (_kv.key.as_str(), _kv.value.as_str())));
+            hashes[offset.index()] = Some(hash);
+            (hash, true)
+        }
+    }
+}
+
+impl BPTree {
+    /// 整棵树的 SHA-256 Merkle 根哈希
+    ///
+    /// 叶子节点的哈希由其中所有的键值对计算, 内部节点的哈希由所有子节点的哈希计算.
+    /// 每个节点的哈希缓存在节点旁边, 节点被修改后才重新计算, 但每次调用仍会访问所有节点.
+    /// 哈希依赖于树的形状, 内容相同但 order 或插入顺序不同的树, 根哈希一般也不同
+    ///
+    /// ```
+    /// use btree_test::{verify, BPTree};
+    ///
+    /// let mut tree = BPTree::bulk_load(4, (0..100).map(|i| (format!("{:03}", i), i.to_string())));
+    /// let root_hash = tree.root_hash();
+    /// let proof = tree.prove("042").unwrap();
+    /// assert_eq!(proof.value, "42");
+    /// assert!(verify(&root_hash, &proof));
+    ///
+    /// // 修改之后旧的证明不再成立
+    /// tree.put("042".to_string(), "x".to_string()).unwrap();
+    /// assert!(!verify(&tree.root_hash(), &proof));
+    /// ```
+    pub fn root_hash(&self) -> [u8; 32] {
+        let mut hashes = self.nodes.hash_cache();
+        hashes.resize(self.nodes.len(), None);
+        refresh(&self.nodes, &mut hashes, self.root).0
+    }
+
+    /// 生成 key 的 [`InclusionProof`], key 不存在时返回 `None`
+    pub fn prove<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<InclusionProof> {
+        let key = key.as_ref();
+        let (leaf_offset, path) =
+            Self::search_path(&mut &self.nodes, self.root, &self.key_order, key).unwrap_or_else(|_error| panic!("{}", _error));
+        let BPTreeNode::Leaf { kvs, .. } = &self.nodes[leaf_offset] else { unreachable!("search ends at a leaf") };
+        let idx = self.key_order.search(kvs, |_kv| _kv.key.as_bytes(), key).ok()?;
+
+        self.root_hash();
+        let hashes = self.nodes.hash_cache();
+        let hash_of = |_offset: &NodeId| hashes[_offset.index()].expect("hashes were just refreshed");
+        let path = path
+            .iter()
+            .rev()
+            .map(|&(offset, idx)| {
+                let BPTreeNode::Internal { branches, .. } = &self.nodes[offset] else { unreachable!("path consists of internal nodes") };
+                let child = branches.child();
+                ProofStep { left: child[..idx].iter().map(hash_of).collect(), right: child[idx + 1..].iter().map(hash_of).collect() }
+            })
+            .collect();
+        Some(InclusionProof {
+            key: kvs[idx].key.clone(),
+            value: kvs[idx].value.clone(),
+            before: to_entries(&kvs[..idx]),
+            after: to_entries(&kvs[idx + 1..]),
+            path,
+        })
+    }
+}
@@ -0,0 +1,345 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::ops::{Bound, Range};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::bptree::BPTree;
+use crate::pager::{invalid_data, put_str, Reader};
+
+const MAGIC: &[u8; 8] = b"BPTMMAP1";
+// 文件头: 魔数, 根节点, 第一个叶子节点, 键值对数量
+const HEADER_LEN: usize = 32;
+
+const TAG_INTERNAL: u8 = 0;
+const TAG_LEAF: u8 = 1;
+const NONE_OFFSET: u64 = u64::MAX;
+
+impl BPTree {
+    /// 将树写成紧凑的只读格式, 之后可以用 [`BPTree::open_mmap`] 直接映射查询
+    ///
+    /// 节点按写入顺序紧密排列, 不按页对齐; 叶子节点最多 [`leaf_order`](Self::leaf_order) 个键值对, 内部节点最多 `order` 个子节点,
+    /// 同一层的节点平均分配条目, 几乎都是满的. 只能导出按字节比较 key 的树, 设置了比较器时返回 [`io::ErrorKind::InvalidInput`]
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let path = std::env::temp_dir().join(format!("btree-test-doc-mmap-{}", std::process::id()));
+    /// let mut tree = BPTree::new(5);
+    /// for (key, value) in [("apple", "1"), ("banana", "2"), ("cherry", "3")] {
+    ///     tree.put(key.to_string(), value.to_string()).unwrap();
+    /// }
+    /// tree.write_mmap(&path).unwrap();
+    ///
+    /// let table = BPTree::open_mmap(&path).unwrap();
+    /// assert_eq!(table.get("banana").unwrap(), Some("2"));
+    /// assert_eq!(table.iter().map(Result::unwrap).last(), Some(("cherry", "3")));
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn write_mmap<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        if !self.key_order.is_bytes() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "mmap format only supports byte-ordered keys"));
+        }
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&[0; HEADER_LEN])?;
+        let mut offset = HEADER_LEN as u64;
+
+        // 先按顺序写出所有叶子节点, 每个节点的 next 就是紧接在它后面的节点
+        let entries: Vec<(&str, &str)> = self.iter().collect();
+        let leaf_ranges = even_chunks(entries.len(), self.fanout.leaf);
+        let leaf_count = leaf_ranges.len();
+        // 每一层记录每个节点的第一个 key 与偏移量, 作为上一层的分隔 key
+        let mut level = Vec::with_capacity(leaf_count);
+        for (idx, range) in leaf_ranges.into_iter().enumerate() {
+            let kvs = &entries[range];
+            let mut buf = vec![TAG_LEAF];
+            buf.extend_from_slice(&NONE_OFFSET.to_le_bytes());
+            buf.extend_from_slice(&(kvs.len() as u32).to_le_bytes());
+            put_slots(&mut buf, kvs.iter().map(|&(_key, _value)| (_key, Some(_value))))?;
+            if idx + 1 < leaf_count {
+                let next = offset + buf.len() as u64;
+                buf[1..9].copy_from_slice(&next.to_le_bytes());
+            }
+            level.push((kvs.first().map_or("", |_kv| _kv.0), offset));
+            out.write_all(&buf)?;
+            offset += buf.len() as u64;
+        }
+
+        // 再自底向上逐层写出内部节点, 子节点总是在父节点之前
+        while level.len() > 1 {
+            let mut upper = vec![];
+            for range in even_chunks(level.len(), self.fanout.internal) {
+                let children = &level[range];
+                let mut buf = vec![TAG_INTERNAL];
+                buf.extend_from_slice(&(children.len() as u32 - 1).to_le_bytes());
+                for (_, child) in children {
+                    buf.extend_from_slice(&child.to_le_bytes());
+                }
+                put_slots(&mut buf, children[1..].iter().map(|&(_key, _)| (_key, None)))?;
+                upper.push((children[0].0, offset));
+                out.write_all(&buf)?;
+                offset += buf.len() as u64;
+            }
+            level = upper;
+        }
+
+        let mut file = out.into_inner().map_err(io::IntoInnerError::into_error)?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&level[0].1.to_le_bytes());
+        header.extend_from_slice(&(HEADER_LEN as u64).to_le_bytes());
+        header.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        file.sync_all()
+    }
+
+    /// 映射 [`BPTree::write_mmap`] 写出的文件, 查询时直接读取映射的内存, 不会把节点反序列化到内存中
+    ///
+    /// 打开时只检查文件头, 节点在第一次访问时才由操作系统按需读入, 适合随程序发布的大型只读查找表
+    ///
+    /// 映射期间文件不能被修改或截断, 否则读到的内容是未定义的
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> io::Result<MmapBPTree> {
+        MmapBPTree::open(path)
+    }
+}
+
+/// 把 `len` 个元素尽量平均地分成每组不超过 `size` 个, 没有元素时也返回一个空组
+fn even_chunks(len: usize, size: usize) -> Vec<Range<usize>> {
+    let count = len.div_ceil(size).max(1);
+    (0..count).map(|_i| _i * len / count..(_i + 1) * len / count).collect()
+}
+
+/// 在节点中写入每个条目的相对偏移量, 再依次写入条目本身, 查找时可以直接二分
+fn put_slots<'a, I: ExactSizeIterator<Item = (&'a str, Option<&'a str>)>>(buf: &mut Vec<u8>, items: I) -> io::Result<()> {
+    let mut slot = buf.len();
+    buf.resize(slot + items.len() * 4, 0);
+    for (key, value) in items {
+        let position = u32::try_from(buf.len()).map_err(|_| invalid_data("node is too large"))?;
+        buf[slot..slot + 4].copy_from_slice(&position.to_le_bytes());
+        slot += 4;
+        put_str(buf, key);
+        if let Some(value) = value {
+            put_str(buf, value);
+        }
+    }
+    Ok(())
+}
+
+/// 通过内存映射查询的只读 B+Tree, 由 [`BPTree::open_mmap`] 打开
+///
+/// 文件中的 key 按字节排序, 查找与遍历都直接在映射的字节上进行, 返回的字符串借用自映射的内存;
+/// 文件损坏时返回 [`io::ErrorKind::InvalidData`] 错误
+pub struct MmapBPTree {
+    map: Mmap,
+    root: usize,
+    first_leaf: usize,
+    len: usize,
+}
+
+impl MmapBPTree {
+    fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: 映射是只读的, 文件在映射期间不被修改是调用方需要保证的前提, 见 BPTree::open_mmap 的文档
+        let map = unsafe { Mmap::map(&file)? };
+        let mut reader = Reader::new(&map[..]);
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(invalid_data("not a mmap tree file"));
+        }
+        let mut offset = || {
+            reader.u64().and_then(|_offset| usize::try_from(_offset).map_err(|_| invalid_data("offset out of range")))
+        };
+        let (root, first_leaf, len) = (offset()?, offset()?, offset()?);
+        if root >= map.len() || first_leaf >= map.len() {
+            return Err(invalid_data("corrupted mmap header"));
+        }
+        Ok(Self { map, root, first_leaf, len })
+    }
+
+    /// 键值对数量
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 是否没有键值对
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 按 key 查找值
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> io::Result<Option<&str>> {
+        let key = key.as_ref();
+        let (leaf, idx) = self.seek(Bound::Included(key))?;
+        if idx < leaf.count && leaf.key(idx)? == key {
+            return leaf.kv(idx).map(|(_, _value)| Some(_value));
+        }
+        Ok(None)
+    }
+
+    /// 按 key 的顺序遍历 `start` 到 `end` 之间的键值对
+    ///
+    /// 遍历中遇到损坏的节点时返回一次错误, 之后结束
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> MmapRange<'_> {
+        let end = end.map(|_end| _end.as_ref().to_vec());
+        match self.seek(start.map(AsRef::as_ref)) {
+            Ok((leaf, idx)) => MmapRange { tree: self, leaf: Some(leaf.offset), idx, end, error: None },
+            Err(error) => MmapRange { tree: self, leaf: None, idx: 0, end, error: Some(error) },
+        }
+    }
+
+    /// 按 key 的顺序遍历所有键值对
+    pub fn iter(&self) -> MmapRange<'_> {
+        self.range::<[u8]>(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// 找到 `start` 所在的叶子节点, 以及其中第一个不在 `start` 之前的位置
+    fn seek(&self, start: Bound<&[u8]>) -> io::Result<(NodeView<'_>, usize)> {
+        let key = match start {
+            Bound::Included(key) | Bound::Excluded(key) => key,
+            Bound::Unbounded => return Ok((self.node(self.first_leaf)?, 0)),
+        };
+        let inclusive = matches!(start, Bound::Excluded(_));
+        let mut node = self.node(self.root)?;
+        while !node.is_leaf {
+            let child = node.child(node.partition(key, true)?)?;
+            // 子节点总是写在父节点之前, 偏移量严格递减保证损坏的文件也不会死循环
+            if child >= node.offset {
+                return Err(invalid_data("child offset is not before its parent"));
+            }
+            node = self.node(child)?;
+        }
+        let idx = node.partition(key, inclusive)?;
+        Ok((node, idx))
+    }
+
+    fn node(&self, offset: usize) -> io::Result<NodeView<'_>> {
+        let bytes = self.map.get(offset..).ok_or_else(|| invalid_data("node offset out of range"))?;
+        let mut reader = Reader::new(bytes);
+        let is_leaf = match reader.u8()? {
+            TAG_LEAF => true,
+            TAG_INTERNAL => false,
+            _ => return Err(invalid_data("unknown node tag")),
+        };
+        let next = if is_leaf { reader.u64()? } else { NONE_OFFSET };
+        let count = reader.u32()? as usize;
+        let children = if is_leaf { &[][..] } else { reader.take(array_len(count + 1, 8)?)? };
+        let slots = reader.take(array_len(count, 4)?)?;
+        Ok(NodeView { bytes, offset, is_leaf, next, count, children, slots })
+    }
+}
+
+fn array_len(count: usize, width: usize) -> io::Result<usize> {
+    count.checked_mul(width).ok_or_else(|| invalid_data("node is too large"))
+}
+
+/// 映射中的一个节点, 只解析了节点头, 条目在访问时才读取
+struct NodeView<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    is_leaf: bool,
+    next: u64,
+    count: usize,
+    children: &'a [u8],
+    slots: &'a [u8],
+}
+
+impl<'a> NodeView<'a> {
+    fn entry(&self, idx: usize) -> io::Result<Reader<'a>> {
+        let mut slot = Reader::new(&self.slots[idx * 4..]);
+        let position = slot.u32()? as usize;
+        self.bytes.get(position..).map(Reader::new).ok_or_else(|| invalid_data("entry offset out of range"))
+    }
+
+    /// 第 `idx` 个 key 的原始字节, 比较时不需要检查 UTF-8
+    fn key(&self, idx: usize) -> io::Result<&'a [u8]> {
+        let mut reader = self.entry(idx)?;
+        let len = reader.u32()? as usize;
+        reader.take(len)
+    }
+
+    fn kv(&self, idx: usize) -> io::Result<(&'a str, &'a str)> {
+        let mut reader = self.entry(idx)?;
+        Ok((reader.str()?, reader.str()?))
+    }
+
+    fn child(&self, idx: usize) -> io::Result<usize> {
+        let offset = Reader::new(&self.children[idx * 8..]).u64()?;
+        usize::try_from(offset).map_err(|_| invalid_data("offset out of range"))
+    }
+
+    /// 小于 `key` (`inclusive` 时为不大于) 的 key 的数量
+    fn partition(&self, key: &[u8], inclusive: bool) -> io::Result<usize> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let ordering = self.key(mid)?.cmp(key);
+            if ordering.is_lt() || (inclusive && ordering.is_eq()) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+}
+
+/// [`MmapBPTree::range`] 返回的迭代器
+pub struct MmapRange<'a> {
+    tree: &'a MmapBPTree,
+    leaf: Option<usize>,
+    idx: usize,
+    end: Bound<Vec<u8>>,
+    error: Option<io::Error>,
+}
+
+impl<'a> MmapRange<'a> {
+    fn step(&mut self, offset: usize) -> io::Result<Option<(&'a str, &'a str)>> {
+        let leaf = self.tree.node(offset)?;
+        if !leaf.is_leaf {
+            return Err(invalid_data("leaf chain points to an internal node"));
+        }
+        if self.idx >= leaf.count {
+            self.idx = 0;
+            self.leaf = match leaf.next {
+                NONE_OFFSET => None,
+                // 叶子节点按顺序写出, next 必须在当前节点之后
+                next if next > offset as u64 => Some(usize::try_from(next).map_err(|_| invalid_data("offset out of range"))?),
+                _ => return Err(invalid_data("leaf chain goes backwards")),
+            };
+            return Ok(None);
+        }
+        let in_range = match &self.end {
+            Bound::Included(end) => leaf.key(self.idx)? <= end.as_slice(),
+            Bound::Excluded(end) => leaf.key(self.idx)? < end.as_slice(),
+            Bound::Unbounded => true,
+        };
+        if !in_range {
+            self.leaf = None;
+            return Ok(None);
+        }
+        let kv = leaf.kv(self.idx)?;
+        self.idx += 1;
+        Ok(Some(kv))
+    }
+}
+
+impl<'a> Iterator for MmapRange<'a> {
+    type Item = io::Result<(&'a str, &'a str)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        while let Some(offset) = self.leaf {
+            match self.step(offset) {
+                Ok(Some(kv)) => return Some(Ok(kv)),
+                Ok(None) => {}
+                Err(error) => {
+                    self.leaf = None;
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+}
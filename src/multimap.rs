@@ -0,0 +1,158 @@
+use std::ops::Bound;
+
+use crate::bptree::BPTree;
+use crate::comparator::KeyOrder;
+use crate::error::BPTreeError;
+use crate::iter::Range;
+
+// 每个 key 后面追加的序号的长度, 序号用固定长度的十六进制表示
+pub(crate) const SEQ_LEN: usize = 16;
+pub(crate) const SEQ_MIN: &str = "0000000000000000";
+pub(crate) const SEQ_MAX: &str = "ffffffffffffffff";
+
+/// 允许重复 key 的 B+Tree, 由 [`BPTreeBuilder::build_multimap`](crate::BPTreeBuilder::build_multimap) 创建
+///
+/// [`put`](Self::put) 不会覆盖已有的值, 而是追加一个新的键值对, 相同 key 的键值对按插入的顺序排列
+///
+/// 内部的树中每个 key 后面追加了一个递增的序号, 比较时先比较 key 再比较序号, 因此树中的 key 仍然唯一,
+/// 返回的 key 已经去掉了序号
+///
+/// ```
+/// use std::ops::Bound;
+/// use btree_test::BPTree;
+///
+/// let mut index = BPTree::builder().order(4).build_multimap();
+/// index.put("blue".to_string(), "row 3".to_string()).unwrap();
+/// index.put("red".to_string(), "row 1".to_string()).unwrap();
+/// index.put("blue".to_string(), "row 7".to_string()).unwrap();
+/// assert_eq!(index.get_all("blue"), ["row 3", "row 7"]);
+///
+/// let all: Vec<_> = index.range::<str>(Bound::Unbounded, Bound::Unbounded).collect();
+/// assert_eq!(all, [("blue", "row 3"), ("blue", "row 7"), ("red", "row 1")]);
+/// ```
+#[derive(Debug)]
+pub struct BPTreeMultimap {
+    tree: BPTree,
+    // 下一个插入的键值对的序号
+    next_seq: u64,
+}
+
+impl BPTreeMultimap {
+    pub(crate) fn new(mut tree: BPTree) -> Self {
+        tree.key_order = seq_order(std::mem::take(&mut tree.key_order));
+        Self { tree, next_seq: 0 }
+    }
+
+    /// 键值对的数量, 重复的 key 分别计数
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// 没有任何键值对
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// 追加一个键值对, key 已存在时也不会覆盖原来的值
+    pub fn put(&mut self, key: String, value: String) -> Result<(), BPTreeError> {
+        let mut key = key;
+        key.push_str(&seq_suffix(self.next_seq));
+        self.tree.put(key, value)?;
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    /// key 对应的所有值, 按插入的顺序排列
+    pub fn get_all<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Vec<&str> {
+        self.range(Bound::Included(key), Bound::Included(key)).map(|(_, value)| value).collect()
+    }
+
+    /// 删除 key 对应的所有值, 按插入的顺序返回
+    pub fn remove_all<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<Vec<String>, BPTreeError> {
+        let (start, end) = (with_seq(key.as_ref(), SEQ_MIN), with_seq(key.as_ref(), SEQ_MAX));
+        let keys: Vec<String> = self
+            .tree
+            .range(Bound::Included(start.as_slice()), Bound::Included(end.as_slice()))
+            .map(|(key, _)| key.to_string())
+            .collect();
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.extend(self.tree.remove(&key)?);
+        }
+        Ok(values)
+    }
+
+    /// 按 key 的顺序遍历 `start` 到 `end` 之间的键值对, 相同 key 的键值对按插入的顺序排列
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> MultiRange<'_> {
+        let (start, end) = seq_bounds(start.map(AsRef::as_ref), end.map(AsRef::as_ref));
+        MultiRange {
+            inner: self.tree.range(start.as_ref().map(Vec::as_slice), end.as_ref().map(Vec::as_slice)),
+        }
+    }
+
+    /// 按 key 的顺序遍历所有键值对
+    pub fn iter(&self) -> MultiRange<'_> {
+        self.range::<str>(Bound::Unbounded, Bound::Unbounded)
+    }
+}
+
+/// 按 key 顺序遍历 [`BPTreeMultimap`] 中键值对的迭代器, 返回的 key 不包含序号
+pub struct MultiRange<'a> {
+    inner: Range<'a>,
+}
+
+impl<'a> Iterator for MultiRange<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, value)| (strip_seq(key), value))
+    }
+}
+
+impl DoubleEndedIterator for MultiRange<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, value)| (strip_seq(key), value))
+    }
+}
+
+/// 先按 `key_order` 比较去掉序号的 key, 相同时再比较序号
+pub(crate) fn seq_order(key_order: KeyOrder) -> KeyOrder {
+    KeyOrder::new(move |_a: &[u8], _b: &[u8]| {
+        let (a_key, a_seq) = _a.split_at(_a.len().saturating_sub(SEQ_LEN));
+        let (b_key, b_seq) = _b.split_at(_b.len().saturating_sub(SEQ_LEN));
+        key_order.cmp(a_key, b_key).then_with(|| a_seq.cmp(b_seq))
+    })
+}
+
+/// 包含起点时从起点 key 的最小序号开始, 不包含时跳过起点 key 的所有序号, 终点相反
+pub(crate) fn seq_bounds(start: Bound<&[u8]>, end: Bound<&[u8]>) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let start = match start {
+        Bound::Included(key) => Bound::Included(with_seq(key, SEQ_MIN)),
+        Bound::Excluded(key) => Bound::Excluded(with_seq(key, SEQ_MAX)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let end = match end {
+        Bound::Included(key) => Bound::Included(with_seq(key, SEQ_MAX)),
+        Bound::Excluded(key) => Bound::Excluded(with_seq(key, SEQ_MIN)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (start, end)
+}
+
+pub(crate) fn seq_suffix(seq: u64) -> String {
+    format!("{:0width$x}", seq, width = SEQ_LEN)
+}
+
+pub(crate) fn with_seq(key: &[u8], seq: &str) -> Vec<u8> {
+    [key, seq.as_bytes()].concat()
+}
+
+pub(crate) fn strip_seq(key: &str) -> &str {
+    // 序号只包含 ASCII 字符, 去掉后仍然是合法的 UTF-8
+    &key[..key.len() - SEQ_LEN]
+}
+
+/// key 后面追加的序号
+pub(crate) fn seq_of(key: &str) -> u64 {
+    u64::from_str_radix(&key[key.len() - SEQ_LEN..], 16).expect("keys end with a hex sequence number")
+}
@@ -0,0 +1,188 @@
+use std::io;
+use std::ops::Bound;
+use std::path::Path;
+
+use crate::bptree::{BPTree, BPTreeKeyValue, BPTreeNode, Fanout};
+use crate::buffer_pool::BufferPool;
+use crate::cipher::PageCipher;
+use crate::comparator::KeyOrder;
+use crate::error::BPTreeError;
+use crate::pager::{Meta, Pager};
+use crate::slab::NodeId;
+use crate::split::SplitPolicy;
+use crate::store::{NodeRead, NodeStore};
+
+/// 节点存放在文件中, 通过 [`BufferPool`] 按需加载的 B+Tree, 可以存放超出内存大小的数据
+///
+/// 与 [`BPTree::create`] 使用相同的文件格式, 但不会读写预写日志,
+/// 打开 [`BPTree`] 创建的文件前需要先调用 [`BPTree::checkpoint`]
+///
+/// 修改过的节点在被淘汰、调用 [`flush`](Self::flush) 或 drop 时写回文件
+pub struct PagedBPTree {
+    pool: BufferPool,
+    fanout: Fanout,
+    split_policy: SplitPolicy,
+    root: NodeId,
+    first_leaf: NodeId,
+    last_leaf: NodeId,
+}
+
+impl PagedBPTree {
+    /// 在 `path` 创建一棵空树, 已存在的文件会被清空
+    ///
+    /// `memory_budget` 为缓存节点可以使用的字节数
+    pub fn create<P: AsRef<Path>>(path: P, order: usize, page_size: usize, memory_budget: usize) -> io::Result<Self> {
+        Self::create_with(path.as_ref(), order, page_size, memory_budget, None)
+    }
+
+    pub(crate) fn create_with(
+        path: &Path,
+        order: usize,
+        page_size: usize,
+        memory_budget: usize,
+        cipher: Option<PageCipher>,
+    ) -> io::Result<Self> {
+        let fanout = Fanout::uniform(order);
+        let pager = Pager::create_with(path, page_size, cipher)?;
+        let mut pool = BufferPool::new(pager, memory_budget, 0);
+        let root = pool.alloc_node(BPTreeNode::Leaf {
+            prev: None,
+            next: None,
+            kvs: vec![],
+            prefix: String::new(),
+        })?;
+        let mut tree = Self { pool, fanout, split_policy: SplitPolicy::default(), root, first_leaf: root, last_leaf: root };
+        tree.flush()?;
+        Ok(tree)
+    }
+
+    /// 打开已有的文件
+    pub fn open<P: AsRef<Path>>(path: P, memory_budget: usize) -> io::Result<Self> {
+        Self::open_with(path.as_ref(), memory_budget, None)
+    }
+
+    pub(crate) fn open_with(path: &Path, memory_budget: usize, cipher: Option<PageCipher>) -> io::Result<Self> {
+        let (pager, meta) = Pager::open(path, cipher)?;
+        if [meta.root, meta.first_leaf, meta.last_leaf].into_iter().any(|_offset| _offset.index() >= meta.node_count) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupted meta page"));
+        }
+        Ok(Self {
+            pool: BufferPool::new(pager, memory_budget, meta.node_count),
+            fanout: meta.fanout,
+            split_policy: SplitPolicy::default(),
+            root: meta.root,
+            first_leaf: meta.first_leaf,
+            last_leaf: meta.last_leaf,
+        })
+    }
+
+    /// 设置叶子节点分裂时的分配方式, 与 [`BPTree::set_split_policy`] 相同
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.split_policy = policy;
+    }
+
+    /// 节点缓存
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    /// 按 key 查找值, 与 [`BPTree::get`] 一样可以用任何 `AsRef<[u8]>` 查找
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        let key = key.as_ref();
+        let leaf_offset = BPTree::search_leaf(&mut self.pool, self.root, &KeyOrder::BYTES, key)?;
+        let BPTreeNode::Leaf { kvs, .. } = self.pool.node(leaf_offset)? else {
+            return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+        };
+        Ok(kvs
+            .binary_search_by(|_kv| _kv.key.as_bytes().cmp(key))
+            .ok()
+            .map(|idx| kvs[idx].value.clone()))
+    }
+
+    /// 插入键值对, key 已存在时更新其值并返回旧值
+    pub fn put(&mut self, key: String, value: String) -> Result<Option<String>, BPTreeError> {
+        let kv = BPTreeKeyValue { key, value };
+        BPTree::insert(&mut self.pool, &mut self.root, &mut self.last_leaf, self.fanout, self.split_policy, &KeyOrder::BYTES, kv)
+    }
+
+    /// 批量插入键值对, 返回新插入的 key 的数量, 与 [`BPTree::put_batch`] 相同
+    pub fn put_batch<I: IntoIterator<Item = (String, String)>>(&mut self, entries: I) -> Result<usize, BPTreeError> {
+        let mut kvs: Vec<BPTreeKeyValue> = entries.into_iter().map(|(key, value)| BPTreeKeyValue { key, value }).collect();
+        BPTree::sort_dedup(&mut kvs, &KeyOrder::BYTES);
+        BPTree::insert_batch(&mut self.pool, &mut self.root, &mut self.last_leaf, self.fanout, self.split_policy, &KeyOrder::BYTES, kvs)
+    }
+
+    /// 删除 key, 返回被删除的值
+    pub fn remove<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        BPTree::delete(&mut self.pool, &mut self.root, &mut self.last_leaf, self.fanout, &KeyOrder::BYTES, key.as_ref())
+    }
+
+    /// 按 key 的顺序取出 `start` 到 `end` 之间的键值对
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        start: Bound<&Q>,
+        end: Bound<&Q>,
+    ) -> Result<Vec<(String, String)>, BPTreeError> {
+        let start = start.map(AsRef::as_ref);
+        let end = end.map(AsRef::as_ref);
+        // 从起点所在的叶子节点开始沿链表向后遍历, 遇到超出终点的 key 即停止
+        let (mut leaf_offset, mut idx) = match start {
+            Bound::Unbounded => (Some(self.first_leaf), 0),
+            Bound::Included(key) | Bound::Excluded(key) => {
+                let leaf_offset = BPTree::search_leaf(&mut self.pool, self.root, &KeyOrder::BYTES, key)?;
+                let BPTreeNode::Leaf { kvs, .. } = self.pool.node(leaf_offset)? else {
+                    return Err(BPTreeError::corrupted(leaf_offset, "expected a leaf"));
+                };
+                let idx = match start {
+                    Bound::Included(_) => kvs.partition_point(|_kv| _kv.key.as_bytes() < key),
+                    _ => kvs.partition_point(|_kv| _kv.key.as_bytes() <= key),
+                };
+                (Some(leaf_offset), idx)
+            }
+        };
+
+        let mut result = vec![];
+        while let Some(offset) = leaf_offset {
+            let BPTreeNode::Leaf { next, kvs, .. } = self.pool.node(offset)? else {
+                return Err(BPTreeError::corrupted(offset, "leaf chain points to an internal node"));
+            };
+            for kv in &kvs[idx.min(kvs.len())..] {
+                let in_range = match end {
+                    Bound::Included(end) => kv.key.as_bytes() <= end,
+                    Bound::Excluded(end) => kv.key.as_bytes() < end,
+                    Bound::Unbounded => true,
+                };
+                if !in_range {
+                    return Ok(result);
+                }
+                result.push((kv.key.clone(), kv.value.clone()));
+            }
+            leaf_offset = *next;
+            idx = 0;
+        }
+        Ok(result)
+    }
+
+    /// 将修改过的节点与元数据写回文件
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.pool.flush()?;
+        let node_count = self.pool.node_count();
+        let pager = self.pool.pager_mut();
+        pager.write_meta(&Meta {
+            page_size: pager.page_size(),
+            fanout: self.fanout,
+            root: self.root,
+            first_leaf: self.first_leaf,
+            last_leaf: self.last_leaf,
+            node_count,
+        })?;
+        pager.sync(node_count)
+    }
+}
+
+impl Drop for PagedBPTree {
+    fn drop(&mut self) {
+        // 与 BufWriter 一样, drop 时的写回错误只能忽略, 需要确认写回结果时应先调用 flush
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,564 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bptree::{common_prefix, BPTreeKeyValue, BPTreeNode, Fanout};
+use crate::branches::Branches;
+use crate::cipher::PageCipher;
+use crate::slab::NodeId;
+
+/// 默认页大小
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+
+/// 文件格式的版本, 文件头或节点的布局改变时加一, 打开旧版本的文件时自动升级到这个版本
+pub const FORMAT_VERSION: u32 = 3;
+
+// 页大小至少要能放下整个文件头
+const MIN_PAGE_SIZE: usize = 64;
+// 页大小的上限, 文件头损坏时不会按读出的页大小分配过大的内存
+const MAX_PAGE_SIZE: usize = 1 << 24;
+// 文件头的魔数, 没有魔数的文件是加入文件头之前的格式 (版本 0)
+const MAGIC: &[u8; 8] = b"BPTFILE\0";
+// 按小端序写入, 用其他字节序读出时不相等
+const BYTE_ORDER_MARK: u32 = 0x0102_0304;
+// 文件头之后的加密信息: 加密方式 (u8), 加密的文件再跟上以文件头为附加数据加密的空内容, 用来在打开时检查密钥
+const CIPHER_NONE: u8 = 0;
+const CIPHER_XCHACHA20_POLY1305: u8 = 1;
+// 加密的页中还要放下 nonce 与认证标签
+const MIN_ENCRYPTED_PAGE_SIZE: usize = 128;
+
+const TAG_INTERNAL: u8 = 0;
+const TAG_LEAF: u8 = 1;
+const NONE_PAGE: u64 = u64::MAX;
+
+// 值直接存放在页中, 或者存放在溢出文件中
+const TAG_INLINE: u8 = 0;
+const TAG_OVERFLOW: u8 = 1;
+// 超过页大小的 1 / OVERFLOW_DIVISOR 的值存放在溢出文件中
+const OVERFLOW_DIVISOR: usize = 4;
+
+/// 树的元数据, 存放在文件第 0 页的文件头中
+///
+/// ```text
+/// magic(8) version(u32) byte_order_mark(u32) page_size(u64) internal_order(u32) leaf_order(u32)
+/// root(u64) first_leaf(u64) last_leaf(u64) node_count(u64)
+/// cipher(u8) [nonce(24) tag(16)]
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Meta {
+    pub(crate) page_size: usize,
+    pub(crate) fanout: Fanout,
+    pub(crate) root: NodeId,
+    pub(crate) first_leaf: NodeId,
+    pub(crate) last_leaf: NodeId,
+    pub(crate) node_count: usize,
+}
+
+/// 以固定大小的页读写文件
+///
+/// 超过页大小 1/4 的值不放在叶子节点的页中, 而是追加到旁边 `.ovf` 后缀的溢出文件里,
+/// 页中只保存它在溢出文件中的位置与长度, 一个很大的值不会撑满整个叶子节点的页
+///
+/// 加密的文件中, 节点页与溢出文件中的值都是加密后再写入的, [`read_page`](Self::read_page) 读出的是密文
+#[derive(Debug)]
+pub struct Pager {
+    file: File,
+    path: PathBuf,
+    page_size: usize,
+    overflow: Overflow,
+    cipher: Option<PageCipher>,
+}
+
+impl Pager {
+    /// 创建 (或清空) 文件
+    pub fn create<P: AsRef<Path>>(path: P, page_size: usize) -> io::Result<Self> {
+        Self::create_with(path.as_ref(), page_size, None)
+    }
+
+    /// 创建 (或清空) 文件, 有 `cipher` 时之后写入的内容都会被加密
+    pub(crate) fn create_with(path: &Path, page_size: usize, cipher: Option<PageCipher>) -> io::Result<Self> {
+        let min_page_size = if cipher.is_some() { MIN_ENCRYPTED_PAGE_SIZE } else { MIN_PAGE_SIZE };
+        if !(min_page_size..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page size must be between {} and {} bytes", min_page_size, MAX_PAGE_SIZE),
+            ));
+        }
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let overflow = Overflow::open(path, true, cipher.clone())?;
+        Ok(Self { file, path: path.to_path_buf(), page_size, overflow, cipher })
+    }
+
+    /// 打开已有的文件, 页大小从文件头中读取
+    ///
+    /// 比 [`FORMAT_VERSION`] 新的文件返回 [`io::ErrorKind::Unsupported`], 旧版本的文件先升级再打开.
+    /// 文件是否加密与是否提供了 `cipher` 不一致时返回 [`io::ErrorKind::InvalidInput`], 密钥不对时返回 [`io::ErrorKind::InvalidData`].
+    /// 文件头中的页大小超出范围或文件放不下其中记录的节点数量时, 文件头已经损坏, 同样返回 [`io::ErrorKind::InvalidData`]
+    pub(crate) fn open(path: &Path, cipher: Option<PageCipher>) -> io::Result<(Self, Meta)> {
+        finish_rewrite(path)?;
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut buf = [0u8; MIN_PAGE_SIZE];
+        file.read_exact(&mut buf).map_err(|_| invalid_data("not a tree file"))?;
+        let (version, meta) = decode_meta(&buf)?;
+        // 版本 0 没有魔数, 只有文件长度与元数据一致时才认为是树文件, 避免把其他文件当作旧版本改写;
+        // 其他版本的文件至少要放得下文件头中记录的所有节点, 否则文件头已经损坏
+        let expected_len = (meta.node_count as u64).checked_add(1).and_then(|_pages| _pages.checked_mul(meta.page_size as u64));
+        let file_len = file.metadata()?.len();
+        if version == 0 && expected_len != Some(file_len) {
+            return Err(invalid_data("not a tree file"));
+        }
+        if expected_len.is_none_or(|_len| _len > file_len) {
+            return Err(invalid_data("file is shorter than the node count in its header"));
+        }
+        // 只放得下文件头的页后面没有加密信息, 旧版本的文件在文件头后面都是 0, 也就是没有加密
+        let mut encryption = [CIPHER_NONE; 1 + PageCipher::OVERHEAD];
+        if meta.page_size >= MIN_PAGE_SIZE + encryption.len() {
+            file.read_exact(&mut encryption)?;
+        }
+        match (encryption[0], &cipher) {
+            (CIPHER_NONE, None) => {}
+            (CIPHER_NONE, Some(_)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "file is not encrypted"));
+            }
+            (CIPHER_XCHACHA20_POLY1305, None) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "file is encrypted, a key is required to open it"));
+            }
+            (CIPHER_XCHACHA20_POLY1305, Some(cipher)) => {
+                cipher.open(&buf, &encryption[1..])?;
+            }
+            (tag, _) => return Err(invalid_data(&format!("unknown cipher {}", tag))),
+        }
+        let overflow = Overflow::open(path, false, cipher.clone())?;
+        let mut pager = Self { file, path: path.to_path_buf(), page_size: meta.page_size, overflow, cipher };
+        pager.migrate(version, &meta)?;
+        Ok((pager, meta))
+    }
+
+    fn migrate(&mut self, version: u32, meta: &Meta) -> io::Result<()> {
+        // 每个版本的升级步骤依次执行, 直到 FORMAT_VERSION
+        // 0 -> 1: 节点的布局没有变化, 只需要在第 0 页写入文件头
+        // 1 -> 2: 文件头之后加入了加密信息, 旧文件中这里都是 0, 即没有加密, 只需要更新文件头中的版本
+        // 2 -> 3: order 拆分为内部节点与叶子节点各自的 order, 旧文件读出时两者相同, 按新的布局重写文件头
+        if version < FORMAT_VERSION {
+            self.write_meta(meta)?;
+            self.file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// 页大小
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// 读取一页
+    pub fn read_page(&mut self, page: u64) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; self.page_size];
+        self.file.seek(SeekFrom::Start(page * self.page_size as u64))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// 写入一页, 不足一页的部分补 0
+    pub fn write_page(&mut self, page: u64, data: &[u8]) -> io::Result<()> {
+        if data.len() > self.page_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} bytes do not fit in a page of {} bytes", data.len(), self.page_size),
+            ));
+        }
+        let mut buf = data.to_vec();
+        buf.resize(self.page_size, 0);
+        self.file.seek(SeekFrom::Start(page * self.page_size as u64))?;
+        self.file.write_all(&buf)
+    }
+
+    pub(crate) fn write_meta(&mut self, meta: &Meta) -> io::Result<()> {
+        let mut page = encode_meta(meta);
+        if let Some(cipher) = &self.cipher {
+            // 每次写入都重新加密, 文件头被修改后打开时无法通过检查
+            let check = cipher.seal(&page, &[]);
+            page.push(CIPHER_XCHACHA20_POLY1305);
+            page.extend_from_slice(&check);
+        }
+        self.write_page(0, &page)
+    }
+
+    /// 读取节点, 存放在溢出文件中的值一并读出
+    pub(crate) fn read_node(&mut self, offset: NodeId) -> io::Result<BPTreeNode> {
+        let page_number = offset.index() as u64 + 1;
+        let mut page = self.read_page(page_number)?;
+        if let Some(cipher) = &self.cipher {
+            page = cipher.open(&page_number.to_le_bytes(), &page)?;
+        }
+        decode_node(&page, &mut self.overflow)
+    }
+
+    /// 写入节点, 节点编码后超出页大小时返回错误
+    pub(crate) fn write_node(&mut self, offset: NodeId, node: &BPTreeNode) -> io::Result<()> {
+        let page_number = offset.index() as u64 + 1;
+        let mut page = encode_node(node, self.page_size / OVERFLOW_DIVISOR, &mut self.overflow)?;
+        if let Some(cipher) = &self.cipher {
+            // 补齐到固定长度再加密, 密文正好占满一页
+            let capacity = self.page_size - PageCipher::OVERHEAD;
+            if page.len() > capacity {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} bytes do not fit in an encrypted page of {} bytes", page.len(), self.page_size),
+                ));
+            }
+            page.resize(capacity, 0);
+            page = cipher.seal(&page_number.to_le_bytes(), &page);
+        }
+        self.write_page(page_number, &page)
+    }
+
+    /// 把整个文件重写一遍: `write` 向旁边的影子文件写入所有节点与文件头, 写完并刷新到磁盘后替换原来的数据文件与溢出文件
+    ///
+    /// 原来的文件在替换之前不会被修改, 写到一半时崩溃只会留下不完整的影子文件, 下一次打开时删除;
+    /// 影子文件完整之后先改名为 `.ckpt` 后缀, 从这时起替换生效, 之后的改名在崩溃后由下一次打开继续完成
+    pub(crate) fn rewrite<F: FnOnce(&mut Pager) -> io::Result<()>>(&mut self, node_count: usize, write: F) -> io::Result<()> {
+        let shadow_path = sibling(&self.path, ".new");
+        let mut shadow = Self::create_with(&shadow_path, self.page_size, self.cipher.clone())?;
+        if let Err(error) = write(&mut shadow).and_then(|()| shadow.sync(node_count)) {
+            let _ = fs::remove_file(&shadow_path).and_then(|()| fs::remove_file(Overflow::path(&shadow_path)));
+            return Err(error);
+        }
+        fs::rename(&shadow_path, sibling(&self.path, ".ckpt"))?;
+        sync_dir(&self.path)?;
+        finish_rewrite(&self.path)?;
+        // 改名不影响已经打开的文件, 影子文件的句柄现在指向新的数据文件与溢出文件
+        self.file = shadow.file;
+        self.overflow = shadow.overflow;
+        Ok(())
+    }
+
+    /// 截断多余的页并刷新到磁盘
+    pub(crate) fn sync(&mut self, node_count: usize) -> io::Result<()> {
+        self.overflow.file.sync_all()?;
+        self.file.set_len((node_count as u64 + 1) * self.page_size as u64)?;
+        self.file.sync_all()
+    }
+}
+
+/// 溢出文件, 只追加不覆盖
+///
+/// 叶子节点重新写入时, 其中的大值也重新追加一份, 旧的副本成为无法再访问的空间;
+/// [`BPTree::sync`](crate::BPTree::sync) 每次把所有节点写入新的文件, 溢出文件也随之换成新的,
+/// [`PagedBPTree`](crate::PagedBPTree) 只写回修改过的节点, 溢出文件只增不减.
+/// 加密时每个值单独加密, 在文件中比原来多占用 [`PageCipher::OVERHEAD`] 字节
+#[derive(Debug)]
+struct Overflow {
+    file: File,
+    len: u64,
+    cipher: Option<PageCipher>,
+}
+
+impl Overflow {
+    fn path(path: &Path) -> PathBuf {
+        let mut overflow_path = OsString::from(path.as_os_str());
+        overflow_path.push(".ovf");
+        PathBuf::from(overflow_path)
+    }
+
+    fn open(path: &Path, truncate: bool, cipher: Option<PageCipher>) -> io::Result<Self> {
+        // 没有大值的旧文件没有溢出文件, 打开时创建一个空的
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(truncate).open(Self::path(path))?;
+        let len = file.metadata()?.len();
+        Ok(Self { file, len, cipher })
+    }
+
+    fn append(&mut self, value: &str) -> io::Result<u64> {
+        let offset = self.len;
+        let sealed = self.cipher.as_ref().map(|_cipher| _cipher.seal(&offset.to_le_bytes(), value.as_bytes()));
+        let bytes = sealed.as_deref().unwrap_or(value.as_bytes());
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(bytes)?;
+        self.len += bytes.len() as u64;
+        Ok(offset)
+    }
+
+    fn read(&mut self, offset: u64, len: usize) -> io::Result<String> {
+        let stored_len = if self.cipher.is_some() { len + PageCipher::OVERHEAD } else { len };
+        if offset.checked_add(stored_len as u64).is_none_or(|_end| _end > self.len) {
+            return Err(invalid_data("overflow value out of range"));
+        }
+        let mut buf = vec![0u8; stored_len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut buf)?;
+        if let Some(cipher) = &self.cipher {
+            buf = cipher.open(&offset.to_le_bytes(), &buf)?;
+        }
+        String::from_utf8(buf).map_err(|_| invalid_data("invalid utf-8 string"))
+    }
+
+}
+
+/// 与 `path` 同一目录, 文件名加上 `suffix` 的路径
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// 完成上一次 [`Pager::rewrite`] 中已经生效的替换, 或者删除没有写完的影子文件
+fn finish_rewrite(path: &Path) -> io::Result<()> {
+    let shadow_path = sibling(path, ".new");
+    let committed = sibling(path, ".ckpt");
+    if committed.exists() {
+        // 溢出文件先改名, 崩溃后再次执行时它可能已经改过名了
+        if Overflow::path(&shadow_path).exists() {
+            fs::rename(Overflow::path(&shadow_path), Overflow::path(path))?;
+        }
+        fs::rename(&committed, path)?;
+        return sync_dir(path);
+    }
+    for leftover in [Overflow::path(&shadow_path), shadow_path] {
+        if leftover.exists() {
+            fs::remove_file(leftover)?;
+        }
+    }
+    Ok(())
+}
+
+/// 刷新 `path` 所在的目录, 改名之后目录刷新到磁盘才算持久; 只有 Unix 可以这样打开目录
+fn sync_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = path.parent().filter(|_dir| !_dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn encode_meta(meta: &Meta) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(MIN_PAGE_SIZE);
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    buf.extend_from_slice(&BYTE_ORDER_MARK.to_le_bytes());
+    buf.extend_from_slice(&(meta.page_size as u64).to_le_bytes());
+    for order in [meta.fanout.internal, meta.fanout.leaf] {
+        buf.extend_from_slice(&(order as u32).to_le_bytes());
+    }
+    let ids = [meta.root, meta.first_leaf, meta.last_leaf].map(NodeId::index);
+    for value in [ids[0], ids[1], ids[2], meta.node_count] {
+        buf.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+    buf
+}
+
+fn decode_meta(buf: &[u8]) -> io::Result<(u32, Meta)> {
+    let mut reader = Reader::new(buf);
+    let version = if reader.take(MAGIC.len())? == MAGIC {
+        let version = reader.u32()?;
+        if version > FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("file format version {} is newer than the supported version {}", version, FORMAT_VERSION),
+            ));
+        }
+        if version == 0 {
+            return Err(invalid_data("corrupted file header"));
+        }
+        if reader.u32()? != BYTE_ORDER_MARK {
+            return Err(invalid_data("unsupported byte order"));
+        }
+        version
+    } else {
+        // 版本 0 没有文件头, 第 0 页直接从页大小开始
+        reader = Reader::new(buf);
+        0
+    };
+    let page_size = reader.usize()?;
+    // 版本 3 之前只有一个 order, 叶子节点与内部节点相同
+    let (internal_order, leaf_order) = if version < 3 {
+        let order = reader.usize()?;
+        (order, order)
+    } else {
+        (reader.u32()? as usize, reader.u32()? as usize)
+    };
+    if internal_order < 3 || leaf_order < 3 {
+        return Err(invalid_data("corrupted meta page"));
+    }
+    let meta = Meta {
+        page_size,
+        fanout: Fanout::new(leaf_order, internal_order),
+        root: reader.id()?,
+        first_leaf: reader.id()?,
+        last_leaf: reader.id()?,
+        node_count: reader.usize()?,
+    };
+    if !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&meta.page_size) {
+        return Err(invalid_data("corrupted meta page"));
+    }
+    Ok((version, meta))
+}
+
+/// 将节点编码为字节, 数字均为小端序, `None` 编码为 `u64::MAX`
+///
+/// 同一个节点中的 key 往往有很长的公共前缀 (例如 URL), 每个节点只存放一次所有 key 的公共前缀,
+/// 每个 key 只存放去掉前缀后的部分, 读取时再拼接成完整的 key
+///
+/// 长度超过 `overflow_threshold` 的值写入溢出文件, 页中只存放它的位置与长度
+///
+/// ```text
+/// Internal: tag(u8) key_count(u32) prefix_len(u32) prefix [suffix_len(u32) suffix]... [child(u64)]... [count(u64)]...
+/// Leaf:     tag(u8) prev(u64) next(u64) kv_count(u32) prefix_len(u32) prefix [suffix_len(u32) suffix value]...
+/// value:    0(u8) value_len(u32) value | 1(u8) offset(u64) value_len(u32)
+/// ```
+fn encode_node(node: &BPTreeNode, overflow_threshold: usize, overflow: &mut Overflow) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    match node {
+        BPTreeNode::Internal { branches, prefix } => {
+            let (child, keys, counts) = branches.parts();
+            buf.push(TAG_INTERNAL);
+            buf.extend_from_slice(&(keys.len() as u32).to_le_bytes());
+            // 页中的前缀是节点的前缀加上节点中所有 key 剩下部分的公共前缀
+            let common = common_prefix(keys.iter().map(String::as_str));
+            put_str(&mut buf, &format!("{}{}", prefix, common));
+            for key in keys {
+                put_str(&mut buf, &key[common.len()..]);
+            }
+            for child in child {
+                put_page(&mut buf, Some(*child));
+            }
+            for count in counts {
+                buf.extend_from_slice(&(*count as u64).to_le_bytes());
+            }
+        }
+        BPTreeNode::Leaf { prev, next, kvs, prefix } => {
+            buf.push(TAG_LEAF);
+            put_page(&mut buf, *prev);
+            put_page(&mut buf, *next);
+            buf.extend_from_slice(&(kvs.len() as u32).to_le_bytes());
+            let common = common_prefix(kvs.iter().map(|_kv| _kv.key.as_str()));
+            put_str(&mut buf, &format!("{}{}", prefix, common));
+            for kv in kvs {
+                put_str(&mut buf, &kv.key[common.len()..]);
+                if kv.value.len() > overflow_threshold {
+                    buf.push(TAG_OVERFLOW);
+                    buf.extend_from_slice(&overflow.append(&kv.value)?.to_le_bytes());
+                    buf.extend_from_slice(&(kv.value.len() as u32).to_le_bytes());
+                } else {
+                    buf.push(TAG_INLINE);
+                    put_str(&mut buf, &kv.value);
+                }
+            }
+        }
+    }
+    Ok(buf)
+}
+
+fn decode_node(buf: &[u8], overflow: &mut Overflow) -> io::Result<BPTreeNode> {
+    let mut reader = Reader::new(buf);
+    match reader.u8()? {
+        TAG_INTERNAL => {
+            let count = reader.u32()? as usize;
+            let prefix = reader.string()?;
+            let keys = (0..count).map(|_| Ok(prefix.clone() + &reader.string()?)).collect::<io::Result<Vec<_>>>()?;
+            let child = (0..=count).map(|_| reader.id()).collect::<io::Result<Vec<_>>>()?;
+            let counts = (0..=count).map(|_| reader.usize()).collect::<io::Result<Vec<_>>>()?;
+            Ok(BPTreeNode::Internal { branches: Branches::from_parts(child, keys, counts), prefix: String::new() })
+        }
+        TAG_LEAF => {
+            let prev = reader.page()?;
+            let next = reader.page()?;
+            let count = reader.u32()? as usize;
+            let prefix = reader.string()?;
+            let kvs = (0..count)
+                .map(|_| {
+                    let key = prefix.clone() + &reader.string()?;
+                    let value = match reader.u8()? {
+                        TAG_INLINE => reader.string()?,
+                        TAG_OVERFLOW => {
+                            let offset = reader.u64()?;
+                            overflow.read(offset, reader.u32()? as usize)?
+                        }
+                        tag => return Err(invalid_data(&format!("unknown value tag {}", tag))),
+                    };
+                    Ok(BPTreeKeyValue { key, value })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            Ok(BPTreeNode::Leaf { prev, next, kvs, prefix: String::new() })
+        }
+        tag => Err(invalid_data(&format!("unknown node tag {}", tag))),
+    }
+}
+
+fn put_page(buf: &mut Vec<u8>, page: Option<NodeId>) {
+    let page = page.map_or(NONE_PAGE, |_p| _p.index() as u64);
+    buf.extend_from_slice(&page.to_le_bytes());
+}
+
+pub(crate) fn put_str(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(invalid_data("unexpected end of page"));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid_data("offset out of range"))
+    }
+
+    fn id(&mut self) -> io::Result<NodeId> {
+        self.usize().map(NodeId::new)
+    }
+
+    fn page(&mut self) -> io::Result<Option<NodeId>> {
+        match self.u64()? {
+            NONE_PAGE => Ok(None),
+            page => usize::try_from(page).map(|_p| Some(NodeId::new(_p))).map_err(|_| invalid_data("offset out of range")),
+        }
+    }
+
+    /// 读取一个字符串但不复制, 返回的引用指向原始的字节
+    pub(crate) fn str(&mut self) -> io::Result<&'a str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.take(len)?).map_err(|_| invalid_data("invalid utf-8 string"))
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid_data("invalid utf-8 string"))
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+use std::io;
+use std::ops::Bound;
+use std::path::Path;
+
+use crate::bptree::{BPTree, MemoryStats};
+use crate::change::ChangeReceiver;
+use crate::entry::Entry;
+use crate::error::BPTreeError;
+use crate::invariant::InvariantError;
+use crate::iter::{Drain, Range};
+
+/// 每个节点只保存一次 key 的公共前缀的 B+Tree, 适合 URL、文件路径这类前缀很长的 key,
+/// 由 [`new`](Self::new) 或 [`BPTreeBuilder::build_prefix`](crate::BPTreeBuilder::build_prefix) 创建
+///
+/// 内部是一棵节点按前缀压缩存放的 [`BPTree`]: 节点中的 key 去掉所在节点的公共前缀后存放, 内部节点的分隔 key 也是如此.
+/// 查找时先把要查找的 key 与节点的前缀比较一次, 节点内只比较后缀, 不需要拼接; 修改节点时先展开它的 key,
+/// 修改结束后重新压缩, 插入、删除、分裂与合并都与 `BPTree` 相同. 预写日志、检查点、订阅修改事件、
+/// [`entry`](Self::entry) 与按范围删除也都与 `BPTree` 相同; 文件中的页本来就按前缀压缩存放, 格式不变,
+/// 同一个文件可以用 `BPTree` 或 `PrefixBPTree` 打开
+///
+/// 节点中没有完整的 key, [`range`](Self::range) 遍历时把前缀与后缀拼接为 `String` 返回;
+/// 只支持按字节比较 key
+///
+/// ```
+/// use std::ops::Bound;
+/// use btree_test::PrefixBPTree;
+///
+/// let mut tree = PrefixBPTree::new(16);
+/// for i in 0..1000 {
+///     tree.put(format!("https://example.com/articles/2024/{:04}", i), i.to_string()).unwrap();
+/// }
+/// assert_eq!(tree.get("https://example.com/articles/2024/0042"), Some("42"));
+/// // 每个节点中的公共前缀只保存一次
+/// assert!(tree.key_bytes() < 1000 * "https://example.com/articles/2024/0042".len() / 4);
+///
+/// let start = "https://example.com/articles/2024/0998";
+/// let tail: Vec<(String, &str)> = tree.range(Bound::Included(start), Bound::Unbounded).collect();
+/// assert_eq!(tail, [(start.to_string(), "998"), ("https://example.com/articles/2024/0999".to_string(), "999")]);
+/// ```
+pub struct PrefixBPTree {
+    tree: BPTree,
+}
+
+impl PrefixBPTree {
+    /// 创建一棵空树, `order` 为节点的最大路数, 小于 3 时按 3 处理
+    pub fn new(order: usize) -> Self {
+        Self::from_tree(BPTree::new(order))
+    }
+
+    // 所有节点按前缀压缩, 之后的修改都保持压缩
+    pub(crate) fn from_tree(mut tree: BPTree) -> Self {
+        assert!(tree.key_order.is_bytes(), "prefix compression only supports byte-ordered keys");
+        tree.nodes.set_compressed(true);
+        Self { tree }
+    }
+
+    /// 在 `path` 创建一棵以页的形式存放在文件中的空树, 见 [`BPTree::create`]
+    pub fn create<P: AsRef<Path>>(path: P, order: usize, page_size: usize) -> io::Result<Self> {
+        BPTree::create(path, order, page_size).map(Self::from_tree)
+    }
+
+    /// 打开 [`create`](Self::create) 或 [`BPTree::create`] 创建的文件, 重放预写日志, 见 [`BPTree::open`]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        BPTree::open(path).map(Self::from_tree)
+    }
+
+    /// 将预写日志刷新到磁盘, 见 [`BPTree::sync`]
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.tree.sync()
+    }
+
+    /// 将所有节点写入文件并清空预写日志, 见 [`BPTree::checkpoint`]
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.tree.checkpoint()
+    }
+
+    /// 节点的最大路数
+    pub fn order(&self) -> usize {
+        self.tree.order()
+    }
+
+    /// 键值对的数量
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// 树中没有键值对
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// 所有节点中实际保存的 key 的字节数, 包括每个节点的前缀与分隔 key, 即 [`memory_usage`](Self::memory_usage) 中的 `keys`
+    pub fn key_bytes(&self) -> usize {
+        self.tree.memory_usage().keys
+    }
+
+    /// 内部的树占用的内存, 其中 `keys` 为压缩后的大小, 见 [`BPTree::memory_usage`]
+    pub fn memory_usage(&self) -> MemoryStats {
+        self.tree.memory_usage()
+    }
+
+    /// 按 key 查找值
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&str> {
+        self.tree.get(key).map(|_kv| _kv.value())
+    }
+
+    /// 树中是否有 key
+    pub fn contains_key<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> bool {
+        self.tree.get(key).is_some()
+    }
+
+    /// 最小的键值对
+    pub fn first(&self) -> Option<(String, &str)> {
+        self.iter().next()
+    }
+
+    /// 最大的键值对
+    pub fn last(&self) -> Option<(String, &str)> {
+        self.iter().next_back()
+    }
+
+    /// 插入键值对, key 已存在时更新其值并返回旧值, 见 [`BPTree::put`]
+    pub fn put(&mut self, key: String, value: String) -> Result<Option<String>, BPTreeError> {
+        let old_value = self.tree.put(key, value);
+        self.tree.nodes.compress_dirty();
+        old_value
+    }
+
+    /// 删除 key, 返回被删除的值, 见 [`BPTree::remove`]
+    pub fn remove<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        let value = self.tree.remove(key);
+        self.tree.nodes.compress_dirty();
+        value
+    }
+
+    /// 删除并返回最小的键值对
+    pub fn pop_first(&mut self) -> Result<Option<(String, String)>, BPTreeError> {
+        let kv = self.tree.pop_first();
+        self.tree.nodes.compress_dirty();
+        kv
+    }
+
+    /// 删除并返回最大的键值对
+    pub fn pop_last(&mut self) -> Result<Option<(String, String)>, BPTreeError> {
+        let kv = self.tree.pop_last();
+        self.tree.nodes.compress_dirty();
+        kv
+    }
+
+    /// 删除 `start` 到 `end` 之间的所有键值对, 返回删除的数量, 见 [`BPTree::remove_range`]
+    pub fn remove_range<Q: AsRef<[u8]> + ?Sized>(&mut self, start: Bound<&Q>, end: Bound<&Q>) -> Result<usize, BPTreeError> {
+        let removed = self.tree.remove_range(start, end);
+        self.tree.nodes.compress_dirty();
+        removed
+    }
+
+    /// 删除 `start` 到 `end` 之间的所有键值对, 返回按 key 的顺序取出它们的迭代器, 见 [`BPTree::drain`]
+    pub fn drain<Q: AsRef<[u8]> + ?Sized>(&mut self, start: Bound<&Q>, end: Bound<&Q>) -> Result<Drain, BPTreeError> {
+        let drained = self.tree.drain(start, end);
+        self.tree.nodes.compress_dirty();
+        drained
+    }
+
+    /// 只保留 `f` 返回 `true` 的键值对, 返回删除的数量
+    pub fn retain<F: FnMut(&str, &str) -> bool>(&mut self, f: F) -> Result<usize, BPTreeError> {
+        let removed = self.tree.retain(f);
+        self.tree.nodes.compress_dirty();
+        removed
+    }
+
+    /// 取得 key 对应的位置, 用于原地插入或修改, 见 [`BPTree::entry`]
+    ///
+    /// 通过它修改的叶子节点先保持展开, 在下一次修改树时重新压缩
+    ///
+    /// ```
+    /// use btree_test::PrefixBPTree;
+    ///
+    /// let mut tree = PrefixBPTree::new(4);
+    /// for path in ["/usr/lib/a", "/usr/lib/b", "/usr/lib/a"] {
+    ///     tree.entry(path.to_string())
+    ///         .and_modify(|count| *count = (count.parse::<u32>().unwrap() + 1).to_string())
+    ///         .or_insert_with(|| "1".to_string());
+    /// }
+    /// assert_eq!(tree.get("/usr/lib/a"), Some("2"));
+    /// ```
+    pub fn entry(&mut self, key: String) -> Entry<'_> {
+        self.tree.nodes.compress_dirty();
+        self.tree.entry(key)
+    }
+
+    /// 订阅之后的修改, 见 [`BPTree::subscribe`]
+    pub fn subscribe(&mut self, capacity: usize) -> ChangeReceiver {
+        self.tree.subscribe(capacity)
+    }
+
+    /// 按 key 的顺序遍历 `start` 到 `end` 之间的键值对, key 由节点的前缀与后缀拼接而成
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> PrefixRange<'_> {
+        PrefixRange { inner: self.tree.range(start, end) }
+    }
+
+    /// 按 key 的顺序遍历所有键值对
+    pub fn iter(&self) -> PrefixRange<'_> {
+        self.range::<str>(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// 检查树的结构是否满足所有约束, 见 [`BPTree::check_invariants`]
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        self.tree.check_invariants()
+    }
+}
+
+impl fmt::Debug for PrefixBPTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// 按 key 顺序遍历 [`PrefixBPTree`] 中键值对的迭代器, 由 [`PrefixBPTree::range`] 创建
+pub struct PrefixRange<'a> {
+    inner: Range<'a>,
+}
+
+impl<'a> Iterator for PrefixRange<'a> {
+    type Item = (String, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_owned()
+    }
+}
+
+impl DoubleEndedIterator for PrefixRange<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back_owned()
+    }
+}
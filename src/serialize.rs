@@ -0,0 +1,70 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::bptree::{BPTree, BPTreeNode, Fanout};
+use crate::slab::{NodeId, NodeSlab};
+
+// 完整的树结构, 不包括关联的文件与预写日志
+#[derive(Serialize)]
+struct TreeRef<'a> {
+    order: usize,
+    // 叶子节点的 order 与 order 相同时省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leaf_order: Option<usize>,
+    root: NodeId,
+    first_leaf: NodeId,
+    last_leaf: NodeId,
+    nodes: Vec<&'a BPTreeNode>,
+}
+
+#[derive(Deserialize)]
+struct Tree {
+    order: usize,
+    #[serde(default)]
+    leaf_order: Option<usize>,
+    root: NodeId,
+    first_leaf: NodeId,
+    last_leaf: NodeId,
+    nodes: Vec<BPTreeNode>,
+}
+
+/// 按节点原样序列化整棵树, 反序列化得到的是一棵内存中的树
+///
+/// 只需要保存数据时可以用 [`compact`](crate::compact) 只序列化键值对
+impl Serialize for BPTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TreeRef {
+            order: self.fanout.internal,
+            leaf_order: Some(self.fanout.leaf).filter(|&_leaf| _leaf != self.fanout.internal),
+            root: self.root,
+            first_leaf: self.first_leaf,
+            last_leaf: self.last_leaf,
+            nodes: self.nodes.iter().map(|(_, _node)| _node).collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// 反序列化后会用 [`BPTree::check_invariants`] 检查树的结构, 不满足约束时返回错误
+impl<'de> Deserialize<'de> for BPTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tree = Tree::deserialize(deserializer)?;
+        let leaf_order = tree.leaf_order.unwrap_or(tree.order);
+        if tree.order < 3 || leaf_order < 3 {
+            return Err(D::Error::custom(format!("order must be at least 3, got {}", tree.order.min(leaf_order))));
+        }
+        let len = tree.nodes.iter().map(|_node| match _node {
+            BPTreeNode::Leaf { kvs, .. } => kvs.len(),
+            BPTreeNode::Internal { .. } => 0,
+        }).sum();
+        let mut result = BPTree::with_fanout(Fanout::new(leaf_order, tree.order));
+        result.nodes = NodeSlab::from_nodes(tree.nodes, tree.root);
+        result.root = tree.root;
+        result.first_leaf = tree.first_leaf;
+        result.last_leaf = tree.last_leaf;
+        result.len = len;
+        // 下标越界等问题也由检查发现, 之后的操作可以直接按下标访问节点
+        result.check_invariants().map_err(D::Error::custom)?;
+        Ok(result)
+    }
+}
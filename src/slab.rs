@@ -0,0 +1,462 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::bptree::BPTreeNode;
+use crate::error::BPTreeError;
+use crate::store::{NodeRead, NodeStore};
+
+/// 节点的编号, 即节点在 [`NodeSlab`] 中的下标
+///
+/// 存放在文件中时, 编号为 `n` 的节点存放在第 `n + 1` 页, 第 0 页存放树的元数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// 由下标创建编号
+    pub fn new(index: usize) -> Self {
+        NodeId(index)
+    }
+
+    /// 编号对应的下标
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// 存放所有节点的 slab, 被释放的位置记录在空闲链表中, 之后分配节点时优先复用
+///
+/// 被释放的位置仍然占用一个槽, 替换为一个不被任何节点引用的空叶子节点
+///
+/// 每个节点放在一个 [`Arc`] 中, 槽按编号分块存放在一棵每层 32 路的 trie 中, 每个块也放在 [`Arc`] 中.
+/// 复制 slab 时只复制 trie 根的指针, 两个 slab 共享所有块与节点; 之后修改某个节点时,
+/// 从根到它所在块的路径上仍被共享的块与节点本身先复制一份再修改 (copy-on-write)
+///
+/// 按前缀压缩存放时 (见 [`PrefixBPTree`](crate::PrefixBPTree)), 每个节点中 key 的公共前缀只保存一次;
+/// 通过可变引用修改节点之前先展开它的 key, 被展开的节点记录下来, 由修改树的一方在修改结束后重新压缩
+#[derive(Default)]
+pub struct NodeSlab {
+    chunks: Arc<Chunk>,
+    // trie 中块的层数减一, 为 0 时根就是存放槽的块
+    height: u32,
+    len: usize,
+    free: Arc<Vec<NodeId>>,
+    // 每个槽的节点哈希, 见 BPTree::root_hash; 只在计算哈希时填入, 节点被修改或释放时清除
+    hashes: Mutex<Vec<Option<[u8; 32]>>>,
+    compressed: bool,
+    // 按前缀压缩存放时被展开或新分配的节点, 等待 compress_dirty 重新压缩, 可能有重复
+    dirty: Vec<NodeId>,
+}
+
+const CHUNK_BITS: u32 = 5;
+const CHUNK: usize = 1 << CHUNK_BITS;
+
+// trie 中的块: 最下层存放槽, 其余各层存放下一层的块
+#[derive(Clone)]
+enum Chunk {
+    Slots(Vec<Arc<BPTreeNode>>),
+    Branch(Vec<Arc<Chunk>>),
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::Slots(Vec::with_capacity(CHUNK))
+    }
+}
+
+impl Chunk {
+    // 第 level 层 (存放槽的块为第 0 层) 的空块
+    fn empty(level: u32) -> Self {
+        if level == 0 { Chunk::default() } else { Chunk::Branch(Vec::with_capacity(CHUNK)) }
+    }
+
+    // 保证块中可以不再分配地存放 end 个槽, 每个子块覆盖 CHUNK^level 个槽
+    fn reserve(&mut self, level: u32, end: usize) {
+        match self {
+            Chunk::Slots(slots) => slots.reserve(end.saturating_sub(slots.len())),
+            Chunk::Branch(chunks) => {
+                let span = CHUNK.pow(level);
+                for idx in 0..end.div_ceil(span) {
+                    if idx == chunks.len() {
+                        chunks.push(Arc::new(Chunk::empty(level - 1)));
+                    }
+                    Arc::make_mut(&mut chunks[idx]).reserve(level - 1, (end - idx * span).min(span));
+                }
+            }
+        }
+    }
+
+    // 去掉没有被共享的块中未使用的容量与预留的空块, 块中存放 len 个槽
+    fn shrink_to_fit(&mut self, level: u32, len: usize) {
+        match self {
+            Chunk::Slots(slots) => {
+                slots.shrink_to_fit();
+                slots.iter_mut().filter_map(Arc::get_mut).for_each(BPTreeNode::shrink_to_fit);
+            }
+            Chunk::Branch(chunks) => {
+                let span = CHUNK.pow(level);
+                chunks.truncate(len.div_ceil(span));
+                chunks.shrink_to_fit();
+                for (idx, chunk) in chunks.iter_mut().enumerate() {
+                    if let Some(chunk) = Arc::get_mut(chunk) {
+                        chunk.shrink_to_fit(level - 1, (len - idx * span).min(span));
+                    }
+                }
+            }
+        }
+    }
+
+    // 所有块中存放槽的容量
+    fn capacity(&self) -> usize {
+        match self {
+            Chunk::Slots(slots) => slots.capacity(),
+            Chunk::Branch(chunks) => chunks.iter().map(|_chunk| _chunk.capacity()).sum(),
+        }
+    }
+
+    // 按编号顺序取出所有槽的可变引用, 仍被共享的块先复制
+    fn slots_mut<'a>(&'a mut self, out: &mut Vec<&'a mut Arc<BPTreeNode>>) {
+        match self {
+            Chunk::Slots(slots) => out.extend(slots.iter_mut()),
+            Chunk::Branch(chunks) => chunks.iter_mut().for_each(|_chunk| Arc::make_mut(_chunk).slots_mut(out)),
+        }
+    }
+
+    fn into_nodes(self, out: &mut Vec<BPTreeNode>) {
+        match self {
+            Chunk::Slots(slots) => out.extend(slots.into_iter().map(Arc::unwrap_or_clone)),
+            Chunk::Branch(chunks) => chunks.into_iter().for_each(|_chunk| Arc::unwrap_or_clone(_chunk).into_nodes(out)),
+        }
+    }
+}
+
+impl Clone for NodeSlab {
+    /// 只复制 trie 根的指针; 哈希缓存不复制, 复制出的 slab 第一次计算哈希时重新计算所有节点
+    fn clone(&self) -> Self {
+        Self {
+            chunks: self.chunks.clone(),
+            height: self.height,
+            len: self.len,
+            free: self.free.clone(),
+            hashes: Mutex::default(),
+            compressed: self.compressed,
+            dirty: self.dirty.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for NodeSlab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nodes: Vec<&BPTreeNode> = self.iter().map(|(_, _node)| _node).collect();
+        f.debug_struct("NodeSlab").field("nodes", &nodes).field("free", &self.free).finish()
+    }
+}
+
+impl NodeSlab {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// 预留至少 `additional` 个槽的空间, 需要的块都预先创建
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let end = self.len + additional;
+        self.grow(end);
+        Arc::make_mut(&mut self.chunks).reserve(self.height, end);
+    }
+
+    // trie 增加层数, 直到可以存放 end 个槽
+    fn grow(&mut self, end: usize) {
+        while CHUNK.pow(self.height + 1) < end {
+            let chunks = std::mem::take(&mut self.chunks);
+            let mut branch = Vec::with_capacity(CHUNK);
+            branch.push(chunks);
+            self.chunks = Arc::new(Chunk::Branch(branch));
+            self.height += 1;
+        }
+    }
+
+    /// 去掉 slab 与没有被共享的节点中未使用的容量
+    ///
+    /// 节点的内容不变, 已经计算的哈希仍然有效
+    pub(crate) fn shrink_to_fit(&mut self) {
+        Arc::make_mut(&mut self.free).shrink_to_fit();
+        if let Some(chunks) = Arc::get_mut(&mut self.chunks) {
+            chunks.shrink_to_fit(self.height, self.len);
+        }
+    }
+
+    /// 由已有的节点创建, 除根节点以外脱离了树的空叶子节点都视为空闲
+    pub(crate) fn from_nodes(nodes: Vec<BPTreeNode>, root: NodeId) -> Self {
+        let mut slab = Self::new();
+        let mut free = vec![];
+        for (idx, node) in nodes.into_iter().enumerate() {
+            if NodeId(idx) != root && is_free(&node) {
+                free.push(NodeId(idx));
+            }
+            slab.push(Arc::new(node));
+        }
+        slab.free = Arc::new(free);
+        slab
+    }
+
+    /// 槽的数量, 包括空闲的槽
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 没有任何槽
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 空闲槽的数量
+    pub fn free_len(&self) -> usize {
+        self.free.len()
+    }
+
+    /// 不再创建块时可以存放的槽的数量
+    pub(crate) fn capacity(&self) -> usize {
+        self.chunks.capacity()
+    }
+
+    fn slot(&self, id: NodeId) -> Option<&Arc<BPTreeNode>> {
+        if id.0 >= self.len {
+            return None;
+        }
+        let mut chunk = &*self.chunks;
+        for level in (1..=self.height).rev() {
+            let Chunk::Branch(chunks) = chunk else { unreachable!("chunks above level 0 are branches") };
+            chunk = &chunks[(id.0 >> (level * CHUNK_BITS)) & (CHUNK - 1)];
+        }
+        let Chunk::Slots(slots) = chunk else { unreachable!("chunks at level 0 hold slots") };
+        slots.get(id.0 & (CHUNK - 1))
+    }
+
+    // 槽的可变引用, 路径上仍被共享的块先复制
+    fn slot_mut(&mut self, id: NodeId) -> Option<&mut Arc<BPTreeNode>> {
+        if id.0 >= self.len {
+            return None;
+        }
+        let mut chunk = Arc::make_mut(&mut self.chunks);
+        for level in (1..=self.height).rev() {
+            let Chunk::Branch(chunks) = chunk else { unreachable!("chunks above level 0 are branches") };
+            chunk = Arc::make_mut(&mut chunks[(id.0 >> (level * CHUNK_BITS)) & (CHUNK - 1)]);
+        }
+        let Chunk::Slots(slots) = chunk else { unreachable!("chunks at level 0 hold slots") };
+        slots.get_mut(id.0 & (CHUNK - 1))
+    }
+
+    fn push(&mut self, node: Arc<BPTreeNode>) -> NodeId {
+        let id = NodeId(self.len);
+        self.grow(self.len + 1);
+        let mut chunk = Arc::make_mut(&mut self.chunks);
+        for level in (1..=self.height).rev() {
+            let Chunk::Branch(chunks) = chunk else { unreachable!("chunks above level 0 are branches") };
+            let idx = (id.0 >> (level * CHUNK_BITS)) & (CHUNK - 1);
+            if idx == chunks.len() {
+                chunks.push(Arc::new(Chunk::empty(level - 1)));
+            }
+            chunk = Arc::make_mut(&mut chunks[idx]);
+        }
+        let Chunk::Slots(slots) = chunk else { unreachable!("chunks at level 0 hold slots") };
+        slots.push(node);
+        self.len += 1;
+        id
+    }
+
+    /// 节点是否按前缀压缩存放
+    pub(crate) fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// 切换是否按前缀压缩存放, 所有节点随之压缩或展开
+    ///
+    /// 不需要修改的节点仍与快照共享; 只有按字节比较 key 的树才能压缩
+    pub(crate) fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed;
+        self.dirty.clear();
+        for idx in 0..self.len {
+            let id = NodeId(idx);
+            let changed = if compressed { self[id].is_compressible() } else { !self[id].prefix().is_empty() };
+            if changed {
+                self.invalidate(id);
+                let node = Arc::make_mut(self.slot_mut(id).expect("node id is in range"));
+                if compressed { node.compress() } else { node.expand() };
+            }
+        }
+    }
+
+    /// 重新压缩修改之后还展开着的节点, 没有按前缀压缩存放时什么也不做
+    pub(crate) fn compress_dirty(&mut self) {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        dirty.sort_unstable();
+        dirty.dedup();
+        for id in dirty {
+            if self.get(id).is_some_and(BPTreeNode::is_compressible) {
+                self.invalidate(id);
+                Arc::make_mut(self.slot_mut(id).expect("node id is in range")).compress();
+            }
+        }
+    }
+
+    /// 按前缀压缩存放时展开节点, 之后在重新压缩之前可以从节点中直接取得完整的 key
+    pub(crate) fn expand(&mut self, id: NodeId) {
+        if self.compressed && self.get(id).is_some_and(|_node| !_node.prefix().is_empty()) {
+            self.node_at_mut(id);
+        }
+    }
+
+    // 节点的可变引用, 按前缀压缩存放时先展开并记录下来; 节点的哈希被清除
+    fn node_at_mut(&mut self, id: NodeId) -> Option<&mut BPTreeNode> {
+        self.invalidate(id);
+        if self.compressed && id.0 < self.len {
+            self.dirty.push(id);
+        }
+        let compressed = self.compressed;
+        let node = Arc::make_mut(self.slot_mut(id)?);
+        if compressed {
+            node.expand();
+        }
+        Some(node)
+    }
+
+    /// 按编号取得节点
+    pub fn get(&self, id: NodeId) -> Option<&BPTreeNode> {
+        self.slot(id).map(Arc::as_ref)
+    }
+
+    /// 两个 slab 中的节点是否为同一份 (复制 slab 之后都没有被修改过)
+    pub(crate) fn shares(&self, id: NodeId, other: &NodeSlab, other_id: NodeId) -> bool {
+        Arc::ptr_eq(self.slot(id).expect("node id out of range"), other.slot(other_id).expect("node id out of range"))
+    }
+
+    /// 按编号顺序遍历所有槽, 包括空闲的槽
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (NodeId, &BPTreeNode)> + ExactSizeIterator {
+        (0..self.len).map(|_idx| (NodeId(_idx), &self[NodeId(_idx)]))
+    }
+
+    /// 按编号顺序取出所有槽, 修改被共享的节点前需要用 [`Arc::make_mut`] 取得自己的一份
+    ///
+    /// 仍被共享的块会被复制; 调用方可能修改任何节点, 所有节点的哈希都会被清除
+    pub(crate) fn slots_mut(&mut self) -> Vec<&mut Arc<BPTreeNode>> {
+        self.hashes.get_mut().unwrap_or_else(|_error| _error.into_inner()).clear();
+        let mut slots = Vec::with_capacity(self.len);
+        Arc::make_mut(&mut self.chunks).slots_mut(&mut slots);
+        slots
+    }
+
+    /// 每个槽的节点哈希, 下标为节点编号, 长度可能小于槽的数量
+    pub(crate) fn hash_cache(&self) -> MutexGuard<'_, Vec<Option<[u8; 32]>>> {
+        // 持有锁时不会 panic, 锁不会中毒
+        self.hashes.lock().unwrap_or_else(|_error| _error.into_inner())
+    }
+
+    fn invalidate(&mut self, id: NodeId) {
+        let hashes = self.hashes.get_mut().unwrap_or_else(|_error| _error.into_inner());
+        if let Some(hash) = hashes.get_mut(id.0) {
+            *hash = None;
+        }
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<BPTreeNode> {
+        let mut nodes = Vec::with_capacity(self.len);
+        Arc::unwrap_or_clone(self.chunks).into_nodes(&mut nodes);
+        nodes
+    }
+
+    /// 分配一个槽存放节点, 优先复用空闲的槽
+    pub(crate) fn alloc_node(&mut self, node: BPTreeNode) -> NodeId {
+        let id = match Arc::make_mut(&mut self.free).pop() {
+            Some(id) => {
+                *self.slot_mut(id).expect("free slots are in range") = Arc::new(node);
+                self.invalidate(id);
+                id
+            }
+            None => self.push(Arc::new(node)),
+        };
+        if self.compressed {
+            self.dirty.push(id);
+        }
+        id
+    }
+
+    /// 释放节点, 返回原来的节点; 按前缀压缩存放时返回展开后的节点
+    pub(crate) fn free_node(&mut self, id: NodeId) -> BPTreeNode {
+        Arc::make_mut(&mut self.free).push(id);
+        self.invalidate(id);
+        let slot = self.slot_mut(id).expect("freed node is in range");
+        let mut node = Arc::unwrap_or_clone(std::mem::replace(slot, Arc::new(empty_leaf())));
+        node.expand();
+        node
+    }
+}
+
+impl Index<NodeId> for NodeSlab {
+    type Output = BPTreeNode;
+
+    fn index(&self, id: NodeId) -> &BPTreeNode {
+        self.get(id).expect("node id out of range")
+    }
+}
+
+impl IndexMut<NodeId> for NodeSlab {
+    fn index_mut(&mut self, id: NodeId) -> &mut BPTreeNode {
+        self.node_at_mut(id).expect("node id out of range")
+    }
+}
+
+impl NodeRead for &NodeSlab {
+    fn node(&mut self, id: NodeId) -> Result<&BPTreeNode, BPTreeError> {
+        self.get(id).ok_or(BPTreeError::corrupted(id, "node id out of range"))
+    }
+}
+
+impl NodeRead for NodeSlab {
+    fn node(&mut self, id: NodeId) -> Result<&BPTreeNode, BPTreeError> {
+        self.get(id).ok_or(BPTreeError::corrupted(id, "node id out of range"))
+    }
+}
+
+impl NodeStore for NodeSlab {
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut BPTreeNode, BPTreeError> {
+        self.node_at_mut(id).ok_or(BPTreeError::corrupted(id, "node id out of range"))
+    }
+
+    fn header_mut(&mut self, id: NodeId) -> Result<&mut BPTreeNode, BPTreeError> {
+        self.invalidate(id);
+        self.slot_mut(id).map(Arc::make_mut).ok_or(BPTreeError::corrupted(id, "node id out of range"))
+    }
+
+    fn alloc_node(&mut self, node: BPTreeNode) -> Result<NodeId, BPTreeError> {
+        Ok(NodeSlab::alloc_node(self, node))
+    }
+
+    fn free_node(&mut self, id: NodeId) -> Result<BPTreeNode, BPTreeError> {
+        if id.0 >= self.len {
+            return Err(BPTreeError::corrupted(id, "node id out of range"));
+        }
+        Ok(NodeSlab::free_node(self, id))
+    }
+}
+
+/// 被释放的槽中存放的节点
+pub(crate) fn empty_leaf() -> BPTreeNode {
+    BPTreeNode::Leaf {
+        prev: None,
+        next: None,
+        kvs: vec![],
+        prefix: String::new(),
+    }
+}
+
+/// 脱离了树的空叶子节点, 根节点以外的这种节点都是被释放的
+pub(crate) fn is_free(node: &BPTreeNode) -> bool {
+    matches!(node, BPTreeNode::Leaf { prev: None, next: None, kvs, .. } if kvs.is_empty())
+}
@@ -0,0 +1,119 @@
+use std::ops::{Bound, Deref};
+use std::sync::Arc;
+
+use crate::bptree::BPTree;
+use crate::iter::Range;
+use crate::slab::NodeId;
+
+/// 树在某一时刻的只读视图, 由 [`BPTree::snapshot`] 创建
+///
+/// 快照与创建它的树共享所有节点, 创建时只复制 [`NodeSlab`](crate::NodeSlab) 中槽表的根指针, 耗时与节点数量无关;
+/// 之后树的修改只会复制被修改的节点以及槽表中从根到它所在块的路径 (节点之间通过编号引用, 不需要复制到根节点的整条路径),
+/// 快照中的节点保持不变, 因此可以在写入继续进行的同时对快照做耗时较长的遍历
+///
+/// 快照的 clone 只增加一个引用计数, 通过 [`Deref`] 可以调用 [`BPTree`] 的所有只读方法
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::new(4);
+/// for i in 0..100 {
+///     tree.put(format!("{:02}", i), i.to_string()).unwrap();
+/// }
+/// let snapshot = tree.snapshot();
+/// let scan = std::thread::spawn(move || snapshot.values().map(|_v| _v.parse::<u32>().unwrap()).sum::<u32>());
+/// for i in 0..50 {
+///     tree.remove(&format!("{:02}", i)).unwrap();
+/// }
+/// assert_eq!(scan.join().unwrap(), 4950);
+/// assert_eq!(tree.len(), 50);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BPTreeSnapshot {
+    tree: Arc<BPTree>,
+}
+
+impl BPTreeSnapshot {
+    pub(crate) fn new(tree: BPTree) -> Self {
+        Self { tree: Arc::new(tree) }
+    }
+}
+
+impl Deref for BPTreeSnapshot {
+    type Target = BPTree;
+
+    fn deref(&self) -> &BPTree {
+        &self.tree
+    }
+}
+
+/// 在快照上按 key 顺序遍历的迭代器, 由 [`BPTree::iter_snapshot`] 或 [`BPTree::range_snapshot`] 创建
+///
+/// 迭代器持有一份 [`BPTreeSnapshot`], 不借用原来的树, 创建之后树的修改不会影响遍历的结果.
+/// 返回的键值对是从快照中复制出来的
+#[derive(Debug, Clone)]
+pub struct SnapshotIter {
+    snapshot: BPTreeSnapshot,
+    front: (NodeId, usize),
+    back: (NodeId, usize),
+}
+
+impl SnapshotIter {
+    /// 迭代器遍历的快照
+    pub fn snapshot(&self) -> &BPTreeSnapshot {
+        &self.snapshot
+    }
+
+    fn step(&mut self, f: impl for<'a> FnOnce(&mut Range<'a>) -> Option<(&'a str, &'a str)>) -> Option<(String, String)> {
+        // 每一步在快照上重新构造一个借用的 Range, 移动之后记下新的位置
+        let mut range = Range::new(&self.snapshot.nodes, self.front, self.back);
+        let (key, value) = f(&mut range)?;
+        let item = (key.to_string(), value.to_string());
+        (self.front, self.back) = range.positions();
+        Some(item)
+    }
+}
+
+impl Iterator for SnapshotIter {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step(|_range| _range.next())
+    }
+}
+
+impl DoubleEndedIterator for SnapshotIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.step(|_range| _range.next_back())
+    }
+}
+
+impl BPTree {
+    /// 按 key 顺序遍历创建时的所有键值对, 遍历期间可以继续修改树, 见 [`range_snapshot`](Self::range_snapshot)
+    ///
+    /// ```
+    /// use btree_test::BPTree;
+    ///
+    /// let mut tree = BPTree::bulk_load(4, (0..10).map(|i| (i.to_string(), i.to_string())));
+    /// let mut iter = tree.iter_snapshot();
+    /// while let Some((key, value)) = iter.next() {
+    ///     tree.remove(&key).unwrap();
+    ///     tree.put(format!("{}!", key), value).unwrap();
+    /// }
+    /// assert_eq!(tree.len(), 10);
+    /// assert!(tree.keys().all(|_key| _key.ends_with('!')));
+    /// ```
+    pub fn iter_snapshot(&self) -> SnapshotIter {
+        self.range_snapshot::<str>(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// 按 key 顺序遍历创建时 `start` 到 `end` 之间的键值对, 之后的 `put`/`remove` 不影响遍历的结果
+    ///
+    /// 先创建一份 [`snapshot`](Self::snapshot), 只复制槽表的根指针;
+    /// 遍历期间树第一次修改某个节点时才复制这个节点, 快照中的节点保持不变
+    pub fn range_snapshot<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> SnapshotIter {
+        let snapshot = self.snapshot();
+        let (front, back) = snapshot.range(start, end).positions();
+        SnapshotIter { snapshot, front, back }
+    }
+}
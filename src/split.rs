@@ -0,0 +1,127 @@
+/// 叶子节点超出上限分裂时, 元素在左右两个节点之间的分配方式
+///
+/// 从中间平分时, 随机插入的每个叶子节点都会留出一半的空间, 以后的插入不需要马上再分裂;
+/// 按递增顺序插入时新的 key 总是落在最后一个叶子节点, 平分后左节点不会再被写入, 填充率只有一半左右,
+/// 此时使用 [`RightBiased`](Self::RightBiased) 可以让左节点保持满载.
+/// 默认的 [`Adaptive`](Self::Adaptive) 根据最近的插入在这几种方式之间自动切换
+///
+/// 为了让偏向一侧的分裂生效, 第一个与最后一个叶子节点不受元素数量下限的约束 (但不能为空),
+/// 其他节点分裂后仍然满足下限, 比例超出范围时按下限调整
+///
+/// ```
+/// use btree_test::{BPTree, SplitPolicy};
+///
+/// let mut tree = BPTree::new(16);
+/// tree.set_split_policy(SplitPolicy::RightBiased(1.0));
+/// for i in 0..1000 {
+///     tree.put(format!("{:04}", i), i.to_string()).unwrap();
+/// }
+/// assert!(tree.stats().fill_factor > 0.95);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SplitPolicy {
+    /// 从中间平分
+    Middle,
+    /// 左节点保留 `ratio` 比例的元素, `1.0` 时左节点保持满载, 右节点只放超出的元素, 适合递增的 key
+    RightBiased(f64),
+    /// 左节点保留尽量少的元素, 右节点保持满载, 适合递减的 key
+    LeftBiased,
+    /// 根据最近插入的 key 识别插入模式 (见 [`InsertPattern`]), 递增时按 `RightBiased(1.0)`, 递减时按 `LeftBiased`,
+    /// 随机时按 `Middle` 分裂; 识别出的模式可以从 [`BPTreeStats::insert_pattern`](crate::BPTreeStats::insert_pattern) 查看.
+    /// 这是默认的分配方式, 插入次数还不够识别模式时与 `Middle` 相同
+    ///
+    /// ```
+    /// use btree_test::{BPTree, InsertPattern, SplitPolicy};
+    ///
+    /// let mut tree = BPTree::new(16);
+    /// assert_eq!(tree.split_policy(), SplitPolicy::Adaptive);
+    /// for i in (0..1000).rev() {
+    ///     tree.put(format!("{:04}", i), i.to_string()).unwrap();
+    /// }
+    /// let stats = tree.stats();
+    /// assert_eq!(stats.insert_pattern, InsertPattern::Reverse);
+    /// assert!(stats.fill_factor > 0.9);
+    /// ```
+    #[default]
+    Adaptive,
+}
+
+impl SplitPolicy {
+    /// [`Adaptive`](Self::Adaptive) 在识别出的模式下对应的分配方式, 其他方式不变
+    pub(crate) fn resolve(self, pattern: InsertPattern) -> SplitPolicy {
+        match (self, pattern) {
+            (SplitPolicy::Adaptive, InsertPattern::Sequential) => SplitPolicy::RightBiased(1.0),
+            (SplitPolicy::Adaptive, InsertPattern::Reverse) => SplitPolicy::LeftBiased,
+            (SplitPolicy::Adaptive, InsertPattern::Random) => SplitPolicy::Middle,
+            (policy, _) => policy,
+        }
+    }
+
+    /// `len` 个元素的叶子节点分裂后左节点保留的元素数量
+    ///
+    /// `is_first`/`is_last` 表示分裂出来的左/右节点是否是第一个/最后一个叶子节点, 它们只需要非空
+    pub(crate) fn split_point(self, len: usize, order: usize, is_first: bool, is_last: bool) -> usize {
+        let min_len = order.div_ceil(2) - 1;
+        let left_min = if is_first { 1 } else { min_len };
+        let right_min = if is_last { 1 } else { min_len };
+        // 两边都不能超出上限 order - 1, 也不能少于下限
+        let low = len.saturating_sub(order - 1).max(left_min);
+        let high = (order - 1).min(len - right_min);
+        let at = match self {
+            // 没有识别插入模式的树 (例如 PagedBPTree) 按 Middle 处理
+            SplitPolicy::Middle | SplitPolicy::Adaptive => len / 2,
+            SplitPolicy::RightBiased(ratio) => (len as f64 * ratio).round() as usize,
+            SplitPolicy::LeftBiased => low,
+        };
+        at.clamp(low, high)
+    }
+}
+
+/// 最近插入的 key 的模式, 见 [`SplitPolicy::Adaptive`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InsertPattern {
+    /// 没有明显的顺序, 或者插入的次数还不够识别
+    #[default]
+    Random,
+    /// 新的 key 大多大于树中所有的 key, 落在最后一个叶子节点的末尾
+    Sequential,
+    /// 新的 key 大多小于树中所有的 key, 落在第一个叶子节点的开头
+    Reverse,
+}
+
+// 识别插入模式时参考的最近插入的次数, 每一位记录一次插入
+const WINDOW: u32 = u64::BITS;
+// 窗口中至少有这么多次插入落在同一端时认为是顺序插入
+const THRESHOLD: u32 = WINDOW * 3 / 4;
+
+/// 记录最近 64 次插入新 key 时, key 是否大于 (或小于) 树中所有的 key
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PatternDetector {
+    appends: u64,
+    prepends: u64,
+    // 已经记录的插入次数, 不超过窗口大小
+    seen: u32,
+}
+
+impl PatternDetector {
+    pub(crate) fn record(&mut self, append: bool, prepend: bool) {
+        self.appends = self.appends << 1 | u64::from(append);
+        self.prepends = self.prepends << 1 | u64::from(prepend);
+        self.seen = (self.seen + 1).min(WINDOW);
+    }
+
+    pub(crate) fn pattern(&self) -> InsertPattern {
+        // 插入次数不到窗口的四分之一时还不能判断
+        if self.seen < WINDOW / 4 {
+            return InsertPattern::Random;
+        }
+        let threshold = THRESHOLD * self.seen / WINDOW;
+        if self.appends.count_ones() >= threshold {
+            InsertPattern::Sequential
+        } else if self.prepends.count_ones() >= threshold {
+            InsertPattern::Reverse
+        } else {
+            InsertPattern::Random
+        }
+    }
+}
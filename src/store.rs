@@ -0,0 +1,29 @@
+use crate::bptree::BPTreeNode;
+use crate::error::BPTreeError;
+use crate::slab::NodeId;
+
+/// 按编号读取节点
+///
+/// 树的查找、插入与删除逻辑都通过它访问节点, 既可以是内存中的 [`NodeSlab`](crate::NodeSlab),
+/// 也可以是按需从文件中加载节点的 [`BufferPool`](crate::BufferPool)
+pub(crate) trait NodeRead {
+    fn node(&mut self, id: NodeId) -> Result<&BPTreeNode, BPTreeError>;
+}
+
+/// 在 [`NodeRead`] 的基础上修改、分配与释放节点
+pub(crate) trait NodeStore: NodeRead {
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut BPTreeNode, BPTreeError>;
+
+    /// 只修改节点中的子树计数或叶子链表, 不读写 key 时使用
+    ///
+    /// 按前缀压缩存放的节点通过 [`node_mut`](Self::node_mut) 修改前要先展开 key, 通过它修改时不需要
+    fn header_mut(&mut self, id: NodeId) -> Result<&mut BPTreeNode, BPTreeError> {
+        self.node_mut(id)
+    }
+
+    /// 分配一个节点, 返回它的编号
+    fn alloc_node(&mut self, node: BPTreeNode) -> Result<NodeId, BPTreeError>;
+
+    /// 释放节点, 返回原来的节点, 之后分配节点时可以复用它的位置
+    fn free_node(&mut self, id: NodeId) -> Result<BPTreeNode, BPTreeError>;
+}
@@ -0,0 +1,146 @@
+use std::ops::Bound;
+
+use crate::bptree::BPTree;
+use crate::error::BPTreeError;
+use crate::iter::Range;
+
+// 存放的值以这个字符开头, 删除标记为空字符串
+const VALUE: char = 'v';
+
+/// 删除时只写入删除标记的 B+Tree, 由 [`BPTreeBuilder::build_tombstoned`](crate::BPTreeBuilder::build_tombstoned) 创建
+///
+/// [`remove`](Self::remove) 把值替换为删除标记, 只修改 key 所在的叶子节点, 不会合并节点或从兄弟节点借元素;
+/// [`get`](Self::get) 与 [`range`](Self::range) 跳过删除标记. 之后再调用 [`compact`](Self::compact) 一次性删除所有标记,
+/// 合并少于下限的节点并释放节点的槽, 可以放在写入不频繁的时候执行. 大量删除集中发生时比逐个 [`BPTree::remove`] 调整结构少很多工作
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::builder().order(4).build_tombstoned();
+/// for i in 0..100 {
+///     tree.put(format!("{:03}", i), i.to_string()).unwrap();
+/// }
+/// for i in 0..90 {
+///     tree.remove(&format!("{:03}", i)).unwrap();
+/// }
+/// assert_eq!(tree.len(), 10);
+/// assert_eq!(tree.tombstone_count(), 90);
+/// assert_eq!(tree.get("042"), None);
+/// assert_eq!(tree.iter().next(), Some(("090", "90")));
+///
+/// assert_eq!(tree.compact().unwrap(), 90);
+/// assert_eq!(tree.tombstone_count(), 0);
+/// assert_eq!(tree.get("095"), Some("95"));
+/// ```
+#[derive(Debug)]
+pub struct TombstoneBPTree {
+    pub(crate) tree: BPTree,
+    // 没有被删除的键值对的数量
+    live: usize,
+}
+
+impl TombstoneBPTree {
+    pub(crate) fn new(tree: BPTree) -> Self {
+        Self { tree, live: 0 }
+    }
+
+    /// 没有被删除的键值对的数量
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    /// 没有任何未被删除的键值对
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    /// 还没有被 [`compact`](Self::compact) 清理的删除标记的数量
+    pub fn tombstone_count(&self) -> usize {
+        self.tree.len() - self.live
+    }
+
+    /// 插入键值对, key 已存在时更新其值并返回旧值; 已被删除的 key 视为不存在
+    pub fn put(&mut self, key: String, value: String) -> Result<Option<String>, BPTreeError> {
+        let old_value = self.tree.put(key, with_tag(value))?.and_then(strip_tag);
+        if old_value.is_none() {
+            self.live += 1;
+        }
+        Ok(old_value)
+    }
+
+    /// 按 key 查找值, 已被删除时返回 `None`
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&str> {
+        self.tree.get(key).and_then(|_kv| _kv.value().strip_prefix(VALUE))
+    }
+
+    /// 删除 key, 返回被删除的值
+    ///
+    /// 只把值替换为删除标记, 键值对仍留在叶子节点中, 直到下一次 [`compact`](Self::compact)
+    pub fn remove<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Result<Option<String>, BPTreeError> {
+        let Some(kv) = self.tree.get(key).filter(|_kv| !_kv.value().is_empty()) else { return Ok(None); };
+        let key = kv.key().to_string();
+        let old_value = self.tree.put(key, String::new())?.and_then(strip_tag);
+        self.live -= 1;
+        Ok(old_value)
+    }
+
+    /// 按 key 的顺序遍历 `start` 到 `end` 之间没有被删除的键值对
+    pub fn range<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>) -> TombstoneRange<'_> {
+        TombstoneRange { inner: self.tree.range(start, end) }
+    }
+
+    /// 按 key 的顺序遍历所有没有被删除的键值对
+    pub fn iter(&self) -> TombstoneRange<'_> {
+        self.range::<str>(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// 删除所有删除标记, 返回删除的数量
+    ///
+    /// 删除标记逐个从树中删除, 少于下限的节点与兄弟节点合并, 之后用 [`BPTree::compact`] 释放空出来的节点槽
+    pub fn compact(&mut self) -> Result<usize, BPTreeError> {
+        if self.tombstone_count() == 0 {
+            return Ok(0);
+        }
+        let removed = self.tree.retain(|_, _value| !_value.is_empty())?;
+        self.tree.compact();
+        Ok(removed)
+    }
+}
+
+/// 按 key 顺序遍历 [`TombstoneBPTree`] 中没有被删除的键值对的迭代器
+pub struct TombstoneRange<'a> {
+    inner: Range<'a>,
+}
+
+impl<'a> Iterator for TombstoneRange<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.find_map(|(key, value)| Some((key, value.strip_prefix(VALUE)?)))
+    }
+}
+
+impl DoubleEndedIterator for TombstoneRange<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = self.inner.next_back()?;
+            if let Some(value) = value.strip_prefix(VALUE) {
+                return Some((key, value));
+            }
+        }
+    }
+}
+
+fn with_tag(mut value: String) -> String {
+    value.insert(0, VALUE);
+    value
+}
+
+fn strip_tag(mut stored: String) -> Option<String> {
+    // 删除标记为空字符串, 其余的值去掉开头的标记
+    if stored.is_empty() {
+        return None;
+    }
+    stored.remove(0);
+    Some(stored)
+}
@@ -0,0 +1,99 @@
+use crate::bptree::BPTree;
+use crate::error::BPTreeError;
+
+/// 缓存修改的事务, 由 [`BPTree::begin`](crate::BPTree::begin) 创建
+///
+/// [`put`](Self::put) 与 [`remove`](Self::remove) 只写入事务自己的缓存, [`get`](Self::get) 先查缓存再查树,
+/// 可以读到事务自己的修改; [`commit`](Self::commit) 时把所有修改一次应用到树上,
+/// [`rollback`](Self::rollback) 或者直接 drop 事务会丢弃所有修改
+///
+/// 关联了文件的树提交时只写一条预写日志记录, 崩溃后重放日志时这组修改要么全部恢复要么全部丢弃
+///
+/// 事务持有树的可变引用, 提交之前其他代码无法修改树, 因此不会有冲突
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::new(4);
+/// tree.put("alice".to_string(), "100".to_string()).unwrap();
+/// tree.put("bob".to_string(), "50".to_string()).unwrap();
+///
+/// let mut txn = tree.begin();
+/// txn.put("alice".to_string(), "70".to_string());
+/// txn.put("bob".to_string(), "80".to_string());
+/// assert_eq!(txn.get("alice"), Some("70"));
+/// txn.commit().unwrap();
+/// assert_eq!(tree.get("bob").map(|kv| kv.value()), Some("80"));
+///
+/// let mut txn = tree.begin();
+/// txn.remove("alice");
+/// assert_eq!(txn.get("alice"), None);
+/// txn.rollback();
+/// assert_eq!(tree.get("alice").map(|kv| kv.value()), Some("70"));
+/// ```
+pub struct Transaction<'a> {
+    tree: &'a mut BPTree,
+    // 按树的 key 顺序排列的修改, 删除记为 None
+    writes: Vec<(String, Option<String>)>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(tree: &'a mut BPTree) -> Self {
+        Self { tree, writes: vec![] }
+    }
+
+    /// 按 key 查找值, 包括事务中尚未提交的修改
+    pub fn get<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> Option<&str> {
+        let key = key.as_ref();
+        match self.find(key) {
+            Ok(idx) => self.writes[idx].1.as_deref(),
+            Err(_) => self.tree.get(key).map(|_kv| _kv.value()),
+        }
+    }
+
+    /// 在事务中插入键值对, 返回事务中看到的旧值
+    pub fn put(&mut self, key: String, value: String) -> Option<String> {
+        let old_value = self.get(&key).map(str::to_string);
+        match self.find(key.as_bytes()) {
+            Ok(idx) => self.writes[idx].1 = Some(value),
+            Err(idx) => self.writes.insert(idx, (key, Some(value))),
+        }
+        old_value
+    }
+
+    /// 在事务中删除 key, 返回事务中看到的旧值
+    pub fn remove<Q: AsRef<[u8]> + ?Sized>(&mut self, key: &Q) -> Option<String> {
+        let old_value = self.get(key).map(str::to_string)?;
+        // 能找到说明与树或事务中的某个 key 相同, 一定是合法的 UTF-8
+        let key = std::str::from_utf8(key.as_ref()).ok()?;
+        match self.find(key.as_bytes()) {
+            Ok(idx) => self.writes[idx].1 = None,
+            Err(idx) => self.writes.insert(idx, (key.to_string(), None)),
+        }
+        Some(old_value)
+    }
+
+    /// 提交事务, 按 key 的顺序把所有修改应用到树上
+    pub fn commit(self) -> Result<(), BPTreeError> {
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+        if let Some(wal) = &mut self.tree.wal {
+            wal.append_batch(self.writes.iter().map(|(key, value)| (key.as_str(), value.as_deref())))?;
+        }
+        for (key, value) in self.writes {
+            match value {
+                Some(value) => self.tree.put_entry(key, value)?,
+                None => self.tree.remove_entry(key.as_bytes())?,
+            };
+        }
+        Ok(())
+    }
+
+    /// 放弃事务中的所有修改
+    pub fn rollback(self) {}
+
+    fn find(&self, key: &[u8]) -> Result<usize, usize> {
+        self.writes.binary_search_by(|(_key, _)| self.tree.key_order.cmp(_key.as_bytes(), key))
+    }
+}
@@ -0,0 +1,154 @@
+use std::iter::Peekable;
+use std::ops::Bound;
+
+use crate::bptree::BPTree;
+use crate::comparator::KeyOrder;
+use crate::error::BPTreeError;
+use crate::iter::Range;
+use crate::multimap::{seq_bounds, seq_of, seq_order, seq_suffix, strip_seq, with_seq, SEQ_MIN};
+
+// 存放的值以这个字符开头, 删除标记为空字符串
+const VALUE: char = 'v';
+
+/// 每个 key 保存多个版本的 B+Tree, 由 [`BPTreeBuilder::build_versioned`](crate::BPTreeBuilder::build_versioned) 创建
+///
+/// [`put`](Self::put) 与 [`remove`](Self::remove) 都带有一个提交时间戳, 写入一个新的版本而不覆盖旧的版本,
+/// 删除写入的是一个删除标记; [`get_at`](Self::get_at) 与 [`range_at`](Self::range_at) 读取时间戳 `ts` 时可见的版本,
+/// 即不晚于 `ts` 的最新版本, 以同一个时间戳读取总是得到相同的结果, 可以在此基础上实现可重复读
+///
+/// 旧版本不会自动删除, 确定不再需要读取早于某个时间戳的数据后调用 [`gc`](Self::gc) 清理
+///
+/// 与 [`BPTreeMultimap`](crate::BPTreeMultimap) 相同, 时间戳追加在 key 后面, 同一个 key 的所有版本在叶子节点中相邻,
+/// 按时间戳升序排列
+///
+/// ```
+/// use btree_test::BPTree;
+///
+/// let mut tree = BPTree::builder().order(4).build_versioned();
+/// tree.put("a".to_string(), "1".to_string(), 10).unwrap();
+/// tree.put("a".to_string(), "2".to_string(), 20).unwrap();
+/// tree.remove("a", 30).unwrap();
+/// assert_eq!(tree.get_at("a", 5), None);
+/// assert_eq!(tree.get_at("a", 15), Some("1"));
+/// assert_eq!(tree.get_at("a", 25), Some("2"));
+/// assert_eq!(tree.get_at("a", 30), None);
+///
+/// // 之后不再读取早于 25 的数据, 时间戳 10 的版本可以删除
+/// assert_eq!(tree.gc(25).unwrap(), 1);
+/// assert_eq!(tree.get_at("a", 25), Some("2"));
+/// ```
+#[derive(Debug)]
+pub struct VersionedBPTree {
+    tree: BPTree,
+    // 用户设置的 key 顺序, 不包括时间戳
+    key_order: KeyOrder,
+}
+
+impl VersionedBPTree {
+    pub(crate) fn new(mut tree: BPTree) -> Self {
+        let key_order = std::mem::take(&mut tree.key_order);
+        tree.key_order = seq_order(key_order.clone());
+        Self { tree, key_order }
+    }
+
+    /// 保存的版本数量, 包括删除标记
+    pub fn version_count(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// 写入 key 在时间戳 `ts` 提交的值, 同一个时间戳已有版本时替换它
+    pub fn put(&mut self, key: String, value: String, ts: u64) -> Result<(), BPTreeError> {
+        self.write(key, format!("{}{}", VALUE, value), ts)
+    }
+
+    /// 写入 key 在时间戳 `ts` 被删除的标记, 不晚于 `ts` 的读取看不到 key, 直到再次写入
+    pub fn remove(&mut self, key: &str, ts: u64) -> Result<(), BPTreeError> {
+        self.write(key.to_string(), String::new(), ts)
+    }
+
+    fn write(&mut self, mut key: String, value: String, ts: u64) -> Result<(), BPTreeError> {
+        key.push_str(&seq_suffix(ts));
+        self.tree.put(key, value)?;
+        Ok(())
+    }
+
+    /// 时间戳 `ts` 时 key 的值, 即不晚于 `ts` 的最新版本, 没有这样的版本或者它是删除标记时返回 `None`
+    pub fn get_at<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q, ts: u64) -> Option<&str> {
+        let (start, end) = (with_seq(key.as_ref(), SEQ_MIN), with_seq(key.as_ref(), &seq_suffix(ts)));
+        let (_, value) = self
+            .tree
+            .range(Bound::Included(start.as_slice()), Bound::Included(end.as_slice()))
+            .next_back()?;
+        value.strip_prefix(VALUE)
+    }
+
+    /// 按 key 的顺序遍历 `start` 到 `end` 之间时间戳 `ts` 时可见的键值对
+    pub fn range_at<Q: AsRef<[u8]> + ?Sized>(&self, start: Bound<&Q>, end: Bound<&Q>, ts: u64) -> VersionedRange<'_> {
+        let (start, end) = seq_bounds(start.map(AsRef::as_ref), end.map(AsRef::as_ref));
+        VersionedRange {
+            inner: self.tree.range(start.as_ref().map(Vec::as_slice), end.as_ref().map(Vec::as_slice)).peekable(),
+            key_order: &self.key_order,
+            ts,
+        }
+    }
+
+    /// 按 key 的顺序遍历时间戳 `ts` 时可见的所有键值对
+    pub fn iter_at(&self, ts: u64) -> VersionedRange<'_> {
+        self.range_at::<str>(Bound::Unbounded, Bound::Unbounded, ts)
+    }
+
+    /// 删除时间戳不晚于 `watermark` 的读取已经看不到的版本, 返回删除的版本数量
+    ///
+    /// 每个 key 只保留不晚于 `watermark` 的最新版本, 这个版本是删除标记时也一并删除;
+    /// 之后以早于 `watermark` 的时间戳读取可能得到不同的结果
+    pub fn gc(&mut self, watermark: u64) -> Result<usize, BPTreeError> {
+        // 先遍历找出要删除的版本, 再逐个删除
+        let mut garbage = vec![];
+        let mut iter = self.tree.iter().peekable();
+        while let Some((key, value)) = iter.next() {
+            if seq_of(key) > watermark {
+                continue;
+            }
+            let overwritten = iter.peek().is_some_and(|(_next, _)| {
+                self.key_order.cmp(strip_seq(_next).as_bytes(), strip_seq(key).as_bytes()).is_eq() && seq_of(_next) <= watermark
+            });
+            if overwritten || value.is_empty() {
+                garbage.push(key.to_string());
+            }
+        }
+        for key in &garbage {
+            self.tree.remove(key)?;
+        }
+        Ok(garbage.len())
+    }
+}
+
+/// 按 key 顺序遍历 [`VersionedBPTree`] 中某个时间戳可见的键值对的迭代器
+pub struct VersionedRange<'a> {
+    inner: Peekable<Range<'a>>,
+    key_order: &'a KeyOrder,
+    ts: u64,
+}
+
+impl<'a> Iterator for VersionedRange<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // 依次处理每个 key 的所有版本, 取其中不晚于 ts 的最新版本
+        loop {
+            let (first, value) = self.inner.next()?;
+            let mut visible = (seq_of(first) <= self.ts).then_some((first, value));
+            while let Some((key, value)) = self.inner.next_if(|(_next, _)| {
+                self.key_order.cmp(strip_seq(_next).as_bytes(), strip_seq(first).as_bytes()).is_eq()
+            }) {
+                if seq_of(key) <= self.ts {
+                    visible = Some((key, value));
+                }
+            }
+            let Some((key, value)) = visible else { continue; };
+            if let Some(value) = value.strip_prefix(VALUE) {
+                return Some((strip_seq(key), value));
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cipher::PageCipher;
+use crate::pager::{put_str, Reader};
+
+const TAG_PUT: u8 = 1;
+const TAG_REMOVE: u8 = 2;
+const TAG_BATCH: u8 = 3;
+
+// 每条记录前的头部: 内容长度 (u32) + 校验和 (u32)
+const HEADER_SIZE: usize = 8;
+
+/// 日志中的一条修改记录
+#[derive(Debug)]
+pub(crate) enum Record {
+    Put { key: String, value: String },
+    Remove { key: String },
+    // 事务提交的一组修改, 作为一条记录写入, 重放时要么全部应用要么全部丢弃
+    Batch(Vec<Record>),
+}
+
+/// 预写日志, 每次修改在应用到树之前先追加到日志文件中并刷新到磁盘
+///
+/// 日志文件与数据文件放在一起, 文件名为数据文件名加上 `.wal` 后缀.
+/// 数据文件加密时, 每条记录的内容以它在日志中的位置为附加数据加密
+#[derive(Debug)]
+pub(crate) struct Wal {
+    file: File,
+    cipher: Option<PageCipher>,
+    // 日志文件的字节数, 后台维护按它决定何时做检查点
+    len: u64,
+}
+
+impl Wal {
+    /// 日志文件的路径
+    pub(crate) fn path(path: &Path) -> PathBuf {
+        let mut wal_path = OsString::from(path.as_os_str());
+        wal_path.push(".wal");
+        PathBuf::from(wal_path)
+    }
+
+    /// 创建 (或清空) 日志文件
+    pub(crate) fn create(path: &Path, cipher: Option<PageCipher>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(Self::path(path))?;
+        Ok(Self { file, cipher, len: 0 })
+    }
+
+    /// 打开日志文件并读出其中所有完整的记录
+    ///
+    /// 崩溃时最后一条记录可能只写了一部分, 从第一条不完整或校验失败的记录开始的内容都会被丢弃
+    pub(crate) fn open(path: &Path, cipher: Option<PageCipher>) -> io::Result<(Self, Vec<Record>)> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(Self::path(path))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        let mut records = Vec::new();
+        let mut valid_len = 0;
+        while let Some((record, len)) = decode_record(&buf[valid_len..], valid_len as u64, cipher.as_ref()) {
+            records.push(record);
+            valid_len += len;
+        }
+
+        // 截掉尾部损坏的内容, 之后的记录从这里开始追加
+        file.set_len(valid_len as u64)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok((Self { file, cipher, len: valid_len as u64 }, records))
+    }
+
+    /// 追加一条插入记录
+    pub(crate) fn append_put(&mut self, key: &str, value: &str) -> io::Result<()> {
+        let mut payload = vec![TAG_PUT];
+        put_str(&mut payload, key);
+        put_str(&mut payload, value);
+        self.append(&payload)
+    }
+
+    /// 追加一条删除记录
+    pub(crate) fn append_remove(&mut self, key: &str) -> io::Result<()> {
+        let mut payload = vec![TAG_REMOVE];
+        put_str(&mut payload, key);
+        self.append(&payload)
+    }
+
+    /// 把一组修改作为一条记录追加, `None` 为删除
+    pub(crate) fn append_batch<'a, I: IntoIterator<Item = (&'a str, Option<&'a str>)>>(&mut self, writes: I) -> io::Result<()> {
+        let mut payload = vec![TAG_BATCH, 0, 0, 0, 0];
+        let mut count = 0u32;
+        for (key, value) in writes {
+            match value {
+                Some(value) => {
+                    payload.push(TAG_PUT);
+                    put_str(&mut payload, key);
+                    put_str(&mut payload, value);
+                }
+                None => {
+                    payload.push(TAG_REMOVE);
+                    put_str(&mut payload, key);
+                }
+            }
+            count += 1;
+        }
+        payload[1..5].copy_from_slice(&count.to_le_bytes());
+        self.append(&payload)
+    }
+
+    /// 追加一条记录并刷新到磁盘
+    ///
+    /// 失败时把日志截断回追加之前的长度: 写了一半的记录会让重放停在这里, 之后追加成功的记录也会被丢弃;
+    /// 完整写入但没能刷新到磁盘的记录也要去掉, 否则调用方收到错误之后这条修改仍然可能被重放
+    fn append(&mut self, payload: &[u8]) -> io::Result<()> {
+        let start = self.len;
+        let sealed = self.cipher.as_ref().map(|_cipher| _cipher.seal(&start.to_le_bytes(), payload));
+        let payload = sealed.as_deref().unwrap_or(payload);
+        let mut buf = Vec::with_capacity(HEADER_SIZE + payload.len());
+        buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&checksum(payload).to_le_bytes());
+        buf.extend_from_slice(payload);
+        if let Err(error) = self.file.write_all(&buf).and_then(|()| self.file.sync_data()) {
+            // 截断也失败时日志的状态未知, 返回原来的错误
+            let _ = self.file.set_len(start).and_then(|()| self.file.seek(SeekFrom::Start(start)));
+            return Err(error);
+        }
+        self.len += buf.len() as u64;
+        Ok(())
+    }
+
+    /// 日志文件的字节数, 即上一次检查点之后写入的记录的总长度
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// 清空日志
+    pub(crate) fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.len = 0;
+        self.file.sync_all()
+    }
+}
+
+fn decode_record(buf: &[u8], position: u64, cipher: Option<&PageCipher>) -> Option<(Record, usize)> {
+    let mut reader = Reader::new(buf);
+    let len = reader.u32().ok()? as usize;
+    let sum = reader.u32().ok()?;
+    let mut payload = reader.take(len).ok()?;
+    if checksum(payload) != sum {
+        return None;
+    }
+
+    let decrypted;
+    if let Some(cipher) = cipher {
+        decrypted = cipher.open(&position.to_le_bytes(), payload).ok()?;
+        payload = &decrypted;
+    }
+    let record = decode_payload(&mut Reader::new(payload))?;
+    Some((record, HEADER_SIZE + len))
+}
+
+fn decode_payload(reader: &mut Reader<'_>) -> Option<Record> {
+    let record = match reader.u8().ok()? {
+        TAG_PUT => Record::Put { key: reader.string().ok()?, value: reader.string().ok()? },
+        TAG_REMOVE => Record::Remove { key: reader.string().ok()? },
+        TAG_BATCH => {
+            let count = reader.u32().ok()?;
+            Record::Batch((0..count).map(|_| decode_payload(reader)).collect::<Option<_>>()?)
+        }
+        _ => return None,
+    };
+    Some(record)
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    // FNV-1a, 只用于发现写了一半的记录
+    data.iter().fold(0x811c9dc5u32, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+}
@@ -0,0 +1,65 @@
+use wasm_bindgen::prelude::*;
+
+use crate::bptree::BPTree;
+
+/// 供浏览器使用的 [`BPTree`], 在 JavaScript 中的名字为 `BPTree`
+///
+/// 只提供最基本的读写, 每次修改之后可以用 `toJson` 取得整棵树的结构重新绘制, 见 `www/index.html`
+#[wasm_bindgen(js_name = BPTree)]
+pub struct WasmBPTree {
+    tree: BPTree,
+}
+
+#[wasm_bindgen(js_class = BPTree)]
+impl WasmBPTree {
+    /// 创建一棵空树, 与 [`BPTree::new`] 相同
+    #[wasm_bindgen(constructor)]
+    pub fn new(order: usize) -> Self {
+        Self { tree: BPTree::new(order) }
+    }
+
+    /// 插入或更新, 返回旧值
+    pub fn put(&mut self, key: String, value: String) -> Result<Option<String>, JsError> {
+        self.tree.put(key, value).map_err(|_error| JsError::new(&_error.to_string()))
+    }
+
+    /// 按 key 查找值
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.tree.get(key).map(|_kv| _kv.value().to_string())
+    }
+
+    /// 删除并返回旧值
+    pub fn remove(&mut self, key: &str) -> Result<Option<String>, JsError> {
+        self.tree.remove(key).map_err(|_error| JsError::new(&_error.to_string()))
+    }
+
+    /// 键值对的数量
+    #[wasm_bindgen(getter)]
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// 树中没有键值对
+    #[wasm_bindgen(js_name = isEmpty)]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// 节点的最大路数
+    #[wasm_bindgen(getter)]
+    pub fn order(&self) -> usize {
+        self.tree.order()
+    }
+
+    /// JSON 格式的树结构, 见 [`BPTree::to_json`]
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> String {
+        self.tree.to_json()
+    }
+
+    /// 按层打印的树结构, 与 `Display` 相同
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_text(&self) -> String {
+        self.tree.to_string()
+    }
+}
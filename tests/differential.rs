@@ -0,0 +1,1987 @@
+//! 以 `BTreeMap` 为参照, 随机生成操作序列检查 `BPTree` 的行为, 每一步之后都检查树的结构
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use btree_test::{verify, BPTree, CasError, ConcurrentBPTree, DiffEntry, Format, InsertPattern, Key, MaintenanceConfig, MaintenanceScheduler, PrefixBPTree, RecvError, SplitPolicy, StableCursor};
+use proptest::prelude::*;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Put(String, String),
+    PutBatch(Vec<(String, String)>),
+    Get(String),
+    Remove(String),
+    Modify(String, String),
+    // 合并函数把操作数追加到原来的值后面
+    Merge(String, String),
+    // key 不存在时插入第二个字段, 已存在时把它追加到原来的值后面
+    Upsert(String, String),
+    GetOrInsert(String, String),
+    Range(Bound<String>, Bound<String>),
+    Prefix(String),
+    Rank(String),
+    Select(usize),
+    // seek 到 key 后按顺序移动游标, true 为 next, false 为 prev
+    Cursor(String, Vec<bool>),
+    CursorRemove(String),
+    Retain(String),
+    Drain(Bound<String>, Bound<String>),
+    RemoveRange(Bound<String>, Bound<String>),
+    PopFirst,
+    PopLast,
+    // 期望的值为 None 时使用参照中当前的值, 让一部分比较能够成功
+    CompareAndSwap(String, Option<Option<String>>, Option<String>),
+}
+
+// key 取自很小的字符集, 让插入、更新与删除频繁地命中同一批 key
+fn key() -> impl Strategy<Value = String> {
+    "[a-f]{1,3}"
+}
+
+fn bound() -> impl Strategy<Value = Bound<String>> {
+    prop_oneof![
+        key().prop_map(Bound::Included),
+        key().prop_map(Bound::Excluded),
+        Just(Bound::Unbounded),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        6 => (key(), "[0-9]{1,4}").prop_map(|(key, value)| Op::Put(key, value)),
+        1 => prop::collection::vec((key(), "[0-9]{1,4}"), 0..40).prop_map(Op::PutBatch),
+        2 => key().prop_map(Op::Get),
+        4 => key().prop_map(Op::Remove),
+        1 => (key(), "[0-9]{1,2}").prop_map(|(key, suffix)| Op::Modify(key, suffix)),
+        1 => (key(), "[0-9]{1,2}").prop_map(|(key, operand)| Op::Merge(key, operand)),
+        1 => (key(), "[0-9]{1,2}").prop_map(|(key, suffix)| Op::Upsert(key, suffix)),
+        1 => (key(), "[0-9]{1,4}").prop_map(|(key, value)| Op::GetOrInsert(key, value)),
+        2 => (bound(), bound()).prop_map(|(start, end)| Op::Range(start, end)),
+        1 => "[a-f]{0,2}".prop_map(Op::Prefix),
+        1 => key().prop_map(Op::Rank),
+        1 => (0usize..300).prop_map(Op::Select),
+        1 => (key(), prop::collection::vec(any::<bool>(), 0..20)).prop_map(|(key, moves)| Op::Cursor(key, moves)),
+        1 => key().prop_map(Op::CursorRemove),
+        1 => "[0-9]".prop_map(Op::Retain),
+        1 => (bound(), bound()).prop_map(|(start, end)| Op::Drain(start, end)),
+        1 => (bound(), bound()).prop_map(|(start, end)| Op::RemoveRange(start, end)),
+        1 => Just(Op::PopFirst),
+        1 => Just(Op::PopLast),
+        2 => (key(), prop::option::of(prop::option::of("[0-9]{1,2}")), prop::option::of("[0-9]{1,4}"))
+            .prop_map(|(key, expected, new)| Op::CompareAndSwap(key, expected, new)),
+    ]
+}
+
+fn split_policy() -> impl Strategy<Value = SplitPolicy> {
+    prop_oneof![
+        Just(SplitPolicy::Middle),
+        (0.0f64..=1.0).prop_map(SplitPolicy::RightBiased),
+        Just(SplitPolicy::LeftBiased),
+        Just(SplitPolicy::Adaptive),
+    ]
+}
+
+// BTreeMap::range 遇到起点大于终点的范围会 panic, BPTree::range 返回空
+fn is_empty_range(start: &Bound<String>, end: &Bound<String>) -> bool {
+    match (start, end) {
+        (Bound::Included(s), Bound::Included(e)) => s > e,
+        (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => s >= e,
+        _ => false,
+    }
+}
+
+fn as_str(bound: &Bound<String>) -> Bound<&str> {
+    bound.as_ref().map(|_s| _s.as_str())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn matches_btree_map(order in 3usize..12, policy in split_policy(), ops in prop::collection::vec(op(), 1..400)) {
+        let mut tree = BPTree::new(order);
+        tree.set_split_policy(policy);
+        tree.set_merge_operator(|_key: &str, existing: Option<&str>, operand: &str| format!("{}{}", existing.unwrap_or(""), operand));
+        let mut model = BTreeMap::new();
+        for op in ops {
+            match &op {
+                Op::Put(key, value) => {
+                    prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key.clone(), value.clone()));
+                }
+                Op::PutBatch(entries) => {
+                    let new_keys: BTreeSet<&String> = entries.iter().map(|(key, _)| key).filter(|_key| !model.contains_key(*_key)).collect();
+                    prop_assert_eq!(tree.put_batch(entries.clone()).unwrap(), new_keys.len());
+                    model.extend(entries.iter().cloned());
+                }
+                Op::Get(key) => {
+                    prop_assert_eq!(tree.get(key).map(|kv| kv.value()), model.get(key).map(|_v| _v.as_str()));
+                }
+                Op::Remove(key) => {
+                    prop_assert_eq!(tree.remove(key).unwrap(), model.remove(key));
+                }
+                Op::Modify(key, suffix) => {
+                    let modified = tree.modify(key, |value| value.push_str(suffix)).unwrap();
+                    prop_assert_eq!(modified, model.get_mut(key).map(|value| value.push_str(suffix)).is_some());
+                }
+                Op::Merge(key, operand) => {
+                    tree.merge(key.clone(), operand).unwrap();
+                    model.entry(key.clone()).or_default().push_str(operand);
+                }
+                Op::Upsert(key, suffix) => {
+                    let existed = tree.upsert(key.clone(), || suffix.clone(), |value| value.push_str(suffix)).unwrap();
+                    prop_assert_eq!(existed, model.contains_key(key));
+                    model.entry(key.clone()).and_modify(|value| value.push_str(suffix)).or_insert_with(|| suffix.clone());
+                }
+                Op::GetOrInsert(key, value) => {
+                    let actual = tree.get_or_insert_with(key.clone(), || value.clone()).unwrap().clone();
+                    prop_assert_eq!(&actual, model.entry(key.clone()).or_insert_with(|| value.clone()));
+                }
+                Op::Range(start, end) => {
+                    let actual: Vec<(&str, &str)> = tree.range(as_str(start), as_str(end)).collect();
+                    let expected: Vec<(&str, &str)> = if is_empty_range(start, end) {
+                        vec![]
+                    } else {
+                        model
+                            .range::<str, _>((as_str(start), as_str(end)))
+                            .map(|(key, value)| (key.as_str(), value.as_str()))
+                            .collect()
+                    };
+                    prop_assert_eq!(&actual, &expected);
+                    prop_assert_eq!(tree.range_count(as_str(start), as_str(end)), expected.len());
+                    let reversed: Vec<(&str, &str)> = tree.range(as_str(start), as_str(end)).rev().collect();
+                    prop_assert!(reversed.iter().eq(expected.iter().rev()));
+                }
+                Op::Prefix(prefix) => {
+                    let actual: Vec<(&str, &str)> = tree.prefix(prefix).collect();
+                    let expected: Vec<(&str, &str)> = model
+                        .iter()
+                        .filter(|(key, _)| key.starts_with(prefix.as_str()))
+                        .map(|(key, value)| (key.as_str(), value.as_str()))
+                        .collect();
+                    prop_assert_eq!(actual, expected);
+                }
+                Op::Rank(key) => prop_assert_eq!(tree.rank(key), model.range::<String, _>(..key).count()),
+                Op::Select(n) => {
+                    let expected = model.iter().nth(*n).map(|(key, value)| (key.as_str(), value.as_str()));
+                    prop_assert_eq!(tree.select(*n), expected);
+                }
+                Op::Cursor(key, moves) => {
+                    // 参照的位置为有序的键值对中的下标, None 为空位置
+                    let entries: Vec<(&str, &str)> = model.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+                    let mut expected = entries.iter().position(|(k, _)| *k >= key.as_str());
+                    let mut cursor = tree.cursor();
+                    prop_assert_eq!(cursor.seek(key), expected.map(|_idx| entries[_idx]));
+                    for &forward in moves {
+                        expected = match (expected, forward) {
+                            (Some(idx), true) => Some(idx + 1).filter(|_idx| *_idx < entries.len()),
+                            (None, true) => Some(0).filter(|_| !entries.is_empty()),
+                            (Some(idx), false) => idx.checked_sub(1),
+                            (None, false) => entries.len().checked_sub(1),
+                        };
+                        let actual = if forward { cursor.next() } else { cursor.prev() };
+                        prop_assert_eq!(actual, expected.map(|_idx| entries[_idx]));
+                    }
+                }
+                Op::CursorRemove(key) => {
+                    let mut cursor = tree.cursor_mut();
+                    cursor.seek(key);
+                    let removed = cursor.remove_current().unwrap();
+                    let expected = model.range::<String, _>(key..).next().map(|(key, _)| key.clone());
+                    let expected = expected.map(|_key| model.remove_entry(&_key).unwrap());
+                    prop_assert_eq!(&removed, &expected);
+                    let next = removed.and_then(|(removed, _)| model.range::<String, _>(removed..).next());
+                    prop_assert_eq!(cursor.key(), next.map(|(key, _)| key.as_str()));
+                }
+                Op::Retain(digit) => {
+                    // 删除值中包含该数字的键值对
+                    let removed = tree.retain(|_, value| !value.contains(digit.as_str())).unwrap();
+                    let before = model.len();
+                    model.retain(|_, value| !value.contains(digit.as_str()));
+                    prop_assert_eq!(removed, before - model.len());
+                }
+                Op::Drain(start, end) => {
+                    let expected: Vec<(String, String)> = if is_empty_range(start, end) {
+                        vec![]
+                    } else {
+                        model.range::<str, _>((as_str(start), as_str(end))).map(|(k, v)| (k.clone(), v.clone())).collect()
+                    };
+                    for (key, _) in &expected {
+                        model.remove(key);
+                    }
+                    // 先从后面取出一半, 再从前面取出剩下的, 拼起来仍按 key 的顺序排列
+                    let mut drained = tree.drain(as_str(start), as_str(end)).unwrap();
+                    prop_assert_eq!(drained.len(), expected.len());
+                    let mut back: Vec<(String, String)> = drained.by_ref().rev().take(expected.len() / 2).collect();
+                    back.reverse();
+                    let front: Vec<(String, String)> = drained.collect();
+                    prop_assert_eq!(front.into_iter().chain(back).collect::<Vec<_>>(), expected);
+                }
+                Op::RemoveRange(start, end) => {
+                    let before = model.len();
+                    if !is_empty_range(start, end) {
+                        let keys: Vec<String> = model.range::<str, _>((as_str(start), as_str(end))).map(|(k, _)| k.clone()).collect();
+                        for key in &keys {
+                            model.remove(key);
+                        }
+                    }
+                    prop_assert_eq!(tree.remove_range(as_str(start), as_str(end)).unwrap(), before - model.len());
+                }
+                Op::PopFirst => prop_assert_eq!(tree.pop_first().unwrap(), model.pop_first()),
+                Op::PopLast => prop_assert_eq!(tree.pop_last().unwrap(), model.pop_last()),
+                Op::CompareAndSwap(key, expected, new) => {
+                    let current = model.get(key).cloned();
+                    let expected = expected.clone().unwrap_or_else(|| current.clone());
+                    match tree.compare_and_swap(key.clone(), expected.as_deref(), new.clone()) {
+                        Ok(()) => {
+                            prop_assert_eq!(&expected, &current);
+                            match new {
+                                Some(value) => model.insert(key.clone(), value.clone()),
+                                None => model.remove(key),
+                            };
+                        }
+                        Err(CasError::Mismatch { current: actual }) => {
+                            prop_assert_ne!(&expected, &current);
+                            prop_assert_eq!(actual, current);
+                        }
+                        Err(error) => return Err(TestCaseError::fail(error.to_string())),
+                    }
+                }
+            }
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(format!("{} after {:?}", error, op)));
+            }
+            prop_assert_eq!(tree.len(), model.len());
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+
+    #[test]
+    fn sequential_inserts_match_btree_map(
+        order in 3usize..12,
+        policy in split_policy(),
+        descending: bool,
+        len in 0usize..300,
+        removes in prop::collection::vec(0usize..300, 0..300),
+    ) {
+        // 顺序插入时分裂偏向一侧, 第一个或最后一个叶子节点不满, 之后的删除仍要保持结构正确
+        let mut tree = BPTree::new(order);
+        tree.set_split_policy(policy);
+        let mut model = BTreeMap::new();
+        let mut keys: Vec<String> = (0..len).map(|i| format!("{:03}", i)).collect();
+        if descending {
+            keys.reverse();
+        }
+        for key in keys {
+            tree.put(key.clone(), key.clone()).unwrap();
+            model.insert(key.clone(), key);
+        }
+        if let Err(error) = tree.check_invariants() {
+            return Err(TestCaseError::fail(error.to_string()));
+        }
+        for i in removes {
+            let key = format!("{:03}", i);
+            prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key));
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(format!("{} after removing {}", error, key)));
+            }
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+
+    #[test]
+    fn comparator_matches_btree_map(
+        order in 3usize..12,
+        ops in prop::collection::vec((any::<bool>(), "[a-cA-C]{1,3}", "[0-9]{1,4}"), 1..300),
+    ) {
+        // 忽略大小写的树, 参照以小写的 key 为键, 保存第一次插入时的 key 与最新的值
+        let mut tree = BPTree::builder()
+            .order(order)
+            .comparator(|a: &[u8], b: &[u8]| a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase()))
+            .build();
+        let mut model: BTreeMap<String, (String, String)> = BTreeMap::new();
+        for (put, key, value) in ops {
+            let folded = key.to_ascii_lowercase();
+            if put {
+                let old_value = tree.put(key.clone(), value.clone()).unwrap();
+                let expected = match model.get_mut(&folded) {
+                    Some((_, old_value)) => Some(std::mem::replace(old_value, value)),
+                    None => {
+                        model.insert(folded.clone(), (key.clone(), value));
+                        None
+                    }
+                };
+                prop_assert_eq!(old_value, expected);
+            } else {
+                prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&folded).map(|(_, value)| value));
+            }
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(format!("{} after {}", error, key)));
+            }
+            prop_assert_eq!(tree.get(&folded.to_ascii_uppercase()).map(|kv| kv.value()), model.get(&folded).map(|(_, value)| value.as_str()));
+        }
+        prop_assert!(tree.iter().eq(model.values().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+
+    #[test]
+    fn multimap_matches_btree_map(
+        order in 3usize..12,
+        ops in prop::collection::vec((0u8..4, key(), "[0-9]{1,4}", bound(), bound()), 1..300),
+    ) {
+        // 参照中每个 key 对应按插入顺序排列的所有值
+        let mut tree = BPTree::builder().order(order).build_multimap();
+        let mut model: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (kind, key, value, start, end) in ops {
+            match kind {
+                0 | 1 => {
+                    tree.put(key.clone(), value.clone()).unwrap();
+                    model.entry(key).or_default().push(value);
+                }
+                2 => prop_assert_eq!(tree.remove_all(&key).unwrap(), model.remove(&key).unwrap_or_default()),
+                _ => {
+                    let actual: Vec<(&str, &str)> = tree.range(as_str(&start), as_str(&end)).collect();
+                    let expected: Vec<(&str, &str)> = if is_empty_range(&start, &end) {
+                        vec![]
+                    } else {
+                        model
+                            .range::<str, _>((as_str(&start), as_str(&end)))
+                            .flat_map(|(key, values)| values.iter().map(move |value| (key.as_str(), value.as_str())))
+                            .collect()
+                    };
+                    prop_assert_eq!(actual, expected);
+                }
+            }
+            prop_assert_eq!(tree.len(), model.values().map(Vec::len).sum::<usize>());
+        }
+        for (key, values) in &model {
+            prop_assert_eq!(tree.get_all(key), values.iter().map(String::as_str).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn tombstoned_matches_btree_map(
+        order in 3usize..8,
+        ops in prop::collection::vec((0u8..5, key(), "[0-9]{1,4}", bound(), bound()), 1..300),
+    ) {
+        // 删除只写入标记, 不定期整理, 整理前后读到的内容都与参照相同
+        let mut tree = BPTree::builder().order(order).build_tombstoned();
+        let mut model = BTreeMap::new();
+        for (kind, key, value, start, end) in ops {
+            match kind {
+                0 | 1 => prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key.clone(), value)),
+                2 => prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key)),
+                3 => {
+                    let actual: Vec<(&str, &str)> = tree.range(as_str(&start), as_str(&end)).rev().collect();
+                    let expected: Vec<(&str, &str)> = if is_empty_range(&start, &end) {
+                        vec![]
+                    } else {
+                        model.range::<str, _>((as_str(&start), as_str(&end))).rev().map(|(key, value)| (key.as_str(), value.as_str())).collect()
+                    };
+                    prop_assert_eq!(actual, expected);
+                }
+                _ => {
+                    let tombstones = tree.tombstone_count();
+                    prop_assert_eq!(tree.compact().unwrap(), tombstones);
+                    prop_assert_eq!(tree.tombstone_count(), 0);
+                }
+            }
+            prop_assert_eq!(tree.len(), model.len());
+            prop_assert_eq!(tree.get(&key), model.get(&key).map(String::as_str));
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+
+    #[test]
+    fn snapshot_matches_btree_map(
+        order in 3usize..12,
+        ops in prop::collection::vec((0u8..5, key(), "[0-9]{1,4}"), 1..400),
+    ) {
+        // 随时创建快照, 之后的修改不影响快照, 快照与当时的参照保持一致
+        let mut tree = BPTree::new(order);
+        let mut model = BTreeMap::new();
+        let mut snapshots = vec![];
+        for (kind, key, value) in ops {
+            match kind {
+                0 | 1 => prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key, value)),
+                2 => prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key)),
+                3 => snapshots.push((tree.snapshot(), model.clone())),
+                _ => {
+                    for (_, value) in tree.iter_mut().chain(model.iter_mut().map(|(key, value)| (key.as_str(), value))) {
+                        value.push('!');
+                    }
+                }
+            }
+        }
+        if let Err(error) = tree.check_invariants() {
+            return Err(TestCaseError::fail(error.to_string()));
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+        for (snapshot, model) in &snapshots {
+            if let Err(error) = snapshot.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+            prop_assert_eq!(snapshot.len(), model.len());
+            prop_assert!(snapshot.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+        }
+    }
+
+    #[test]
+    fn versioned_matches_btree_map(
+        order in 3usize..12,
+        ops in prop::collection::vec((0u8..6, key(), "[0-9]{0,3}", 0u64..50, bound(), bound()), 1..300),
+    ) {
+        // 参照中每个 key 对应按时间戳排列的所有版本, None 为删除标记
+        let mut tree = BPTree::builder().order(order).build_versioned();
+        let mut model: BTreeMap<String, BTreeMap<u64, Option<String>>> = BTreeMap::new();
+        let visible = |_versions: &BTreeMap<u64, Option<String>>, _ts: u64| {
+            _versions.range(..=_ts).next_back().and_then(|(_, value)| value.clone())
+        };
+        for (kind, key, value, ts, start, end) in ops {
+            match kind {
+                0 | 1 => {
+                    tree.put(key.clone(), value.clone(), ts).unwrap();
+                    model.entry(key).or_default().insert(ts, Some(value));
+                }
+                2 => {
+                    tree.remove(&key, ts).unwrap();
+                    model.entry(key).or_default().insert(ts, None);
+                }
+                3 => prop_assert_eq!(tree.get_at(&key, ts).map(str::to_string), model.get(&key).and_then(|_v| visible(_v, ts))),
+                4 => {
+                    let actual: Vec<(String, String)> =
+                        tree.range_at(as_str(&start), as_str(&end), ts).map(|(k, v)| (k.to_string(), v.to_string())).collect();
+                    let expected: Vec<(String, String)> = if is_empty_range(&start, &end) {
+                        vec![]
+                    } else {
+                        model
+                            .range::<str, _>((as_str(&start), as_str(&end)))
+                            .filter_map(|(key, versions)| Some((key.clone(), visible(versions, ts)?)))
+                            .collect()
+                    };
+                    prop_assert_eq!(actual, expected);
+                }
+                _ => {
+                    // 每个 key 只保留不晚于 ts 的最新版本, 它是删除标记时也删除
+                    let mut removed = 0;
+                    for versions in model.values_mut() {
+                        let old: Vec<u64> = versions.range(..=ts).map(|(_ts, _)| *_ts).collect();
+                        let keep = old.last().filter(|_ts| versions[*_ts].is_some()).copied();
+                        for old_ts in old.into_iter().filter(|_ts| Some(*_ts) != keep) {
+                            versions.remove(&old_ts);
+                            removed += 1;
+                        }
+                    }
+                    model.retain(|_, versions| !versions.is_empty());
+                    prop_assert_eq!(tree.gc(ts).unwrap(), removed);
+                }
+            }
+            prop_assert_eq!(tree.version_count(), model.values().map(BTreeMap::len).sum::<usize>());
+        }
+    }
+
+    #[test]
+    fn transaction_matches_btree_map(
+        order in 3usize..12,
+        txns in prop::collection::vec((any::<bool>(), prop::collection::vec((0u8..3, key(), "[0-9]{1,4}"), 0..30)), 1..20),
+    ) {
+        // 事务中的读取能看到自己的修改, 提交后与参照一致, 回滚后树不变
+        let mut tree = BPTree::new(order);
+        let mut model = BTreeMap::new();
+        for (commit, ops) in txns {
+            let mut txn = tree.begin();
+            let mut txn_model = model.clone();
+            for (kind, key, value) in ops {
+                match kind {
+                    0 => prop_assert_eq!(txn.put(key.clone(), value.clone()), txn_model.insert(key, value)),
+                    1 => prop_assert_eq!(txn.remove(&key), txn_model.remove(&key)),
+                    _ => prop_assert_eq!(txn.get(&key), txn_model.get(&key).map(String::as_str)),
+                }
+            }
+            if commit {
+                txn.commit().unwrap();
+                model = txn_model;
+            } else {
+                txn.rollback();
+            }
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+            prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+            prop_assert_eq!(tree.len(), model.len());
+        }
+    }
+
+    #[test]
+    fn concurrent_matches_btree_map(
+        order in 3usize..12,
+        ops in prop::collection::vec((0u8..4, key(), "[0-9]{1,4}", bound(), bound()), 1..400),
+    ) {
+        // 单线程下与 BTreeMap 的行为一致
+        let tree = ConcurrentBPTree::new(order);
+        let mut model = BTreeMap::new();
+        for (kind, key, value, start, end) in ops {
+            match kind {
+                0 | 1 => prop_assert_eq!(tree.put(key.clone(), value.clone()), model.insert(key, value)),
+                2 => prop_assert_eq!(tree.remove(&key), model.remove(&key)),
+                _ => {
+                    let expected: Vec<(String, String)> = if is_empty_range(&start, &end) {
+                        vec![]
+                    } else {
+                        model.range::<str, _>((as_str(&start), as_str(&end))).map(|(k, v)| (k.clone(), v.clone())).collect()
+                    };
+                    prop_assert_eq!(tree.range(as_str(&start), as_str(&end)), expected);
+                }
+            }
+            prop_assert_eq!(tree.len(), model.len());
+        }
+        for (key, value) in &model {
+            prop_assert_eq!(tree.get(key), Some(value.clone()));
+        }
+    }
+
+    #[test]
+    fn remove_range_matches_btree_map(
+        order in 3usize..8,
+        policy in split_policy(),
+        len in 0usize..2000,
+        ranges in prop::collection::vec((0usize..2100, 0usize..2100, any::<bool>()), 1..8),
+    ) {
+        // 键值对很多, 树有好几层, 删除的范围会跨过多层子树
+        let mut tree = BPTree::new(order);
+        tree.set_split_policy(policy);
+        let mut model = BTreeMap::new();
+        for i in 0..len {
+            let key = format!("{:04}", i);
+            tree.put(key.clone(), i.to_string()).unwrap();
+            model.insert(key, i.to_string());
+        }
+        for (start, end, inclusive) in ranges {
+            let (start, end) = (format!("{:04}", start.min(end)), format!("{:04}", start.max(end)));
+            let end_bound = if inclusive { Bound::Included(end.as_str()) } else { Bound::Excluded(end.as_str()) };
+            let before = model.len();
+            let keys: Vec<String> = model.range::<str, _>((Bound::Included(start.as_str()), end_bound)).map(|(k, _)| k.clone()).collect();
+            for key in &keys {
+                model.remove(key);
+            }
+            prop_assert_eq!(tree.remove_range(Bound::Included(start.as_str()), end_bound).unwrap(), before - model.len());
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+            prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+            prop_assert!(tree.iter().rev().eq(model.iter().rev().map(|(key, value)| (key.as_str(), value.as_str()))));
+        }
+    }
+
+    #[test]
+    fn split_off_matches_btree_map(
+        order in 3usize..8,
+        policy in split_policy(),
+        entries in prop::collection::vec((key(), "[0-9]{1,4}"), 0..300),
+        at in key(),
+    ) {
+        let mut tree = BPTree::new(order);
+        tree.set_split_policy(policy);
+        let mut model = BTreeMap::new();
+        for (key, value) in entries {
+            tree.put(key.clone(), value.clone()).unwrap();
+            model.insert(key, value);
+        }
+        let mut upper = tree.split_off(&at).unwrap();
+        let mut upper_model = model.split_off(&at);
+        for (tree, model) in [(&mut tree, &mut model), (&mut upper, &mut upper_model)] {
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+            prop_assert_eq!(tree.len(), model.len());
+            prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+            prop_assert!(tree.iter().rev().eq(model.iter().rev().map(|(key, value)| (key.as_str(), value.as_str()))));
+            // 分离后的两棵树都可以继续修改
+            tree.put(at.clone(), "new".to_string()).unwrap();
+            tree.pop_first().unwrap();
+            model.insert(at.clone(), "new".to_string());
+            model.pop_first();
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+            prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+        }
+    }
+
+    #[test]
+    fn append_matches_btree_map(
+        order in 3usize..8,
+        policy in split_policy(),
+        entries in prop::collection::vec((key(), "[0-9]{1,4}", any::<bool>()), 0..300),
+        at in key(),
+        disjoint in any::<bool>(),
+        reverse in any::<bool>(),
+    ) {
+        // 不重叠时一棵树的 key 都小于 at, 另一棵都不小于 at, reverse 时把前面的树追加到后面的树
+        let mut trees = [BPTree::new(order), BPTree::new(order)];
+        let mut models = [BTreeMap::new(), BTreeMap::new()];
+        for tree in &mut trees {
+            tree.set_split_policy(policy);
+        }
+        for (key, value, side) in entries {
+            let idx = if disjoint { usize::from(key >= at) } else { usize::from(side) };
+            trees[idx].put(key.clone(), value.clone()).unwrap();
+            models[idx].insert(key, value);
+        }
+        if reverse {
+            trees.reverse();
+            models.reverse();
+        }
+        let [mut tree, mut other] = trees;
+        let [mut model, mut other_model] = models;
+        tree.append(&mut other).unwrap();
+        model.append(&mut other_model);
+        prop_assert!(other.is_empty());
+        for tree in [&tree, &other] {
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+        }
+        prop_assert_eq!(tree.len(), model.len());
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+        prop_assert!(tree.iter().rev().eq(model.iter().rev().map(|(key, value)| (key.as_str(), value.as_str()))));
+        prop_assert_eq!(tree.rank("c"), model.range::<str, _>((Bound::Unbounded, Bound::Excluded("c"))).count());
+        // 追加后的树可以继续修改
+        tree.remove_range::<str>(Bound::Unbounded, Bound::Excluded(&at)).unwrap();
+        if let Err(error) = tree.check_invariants() {
+            return Err(TestCaseError::fail(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn bulk_load_matches_btree_map(
+        order in 3usize..12,
+        entries in prop::collection::vec((key(), "[0-9]{1,4}"), 0..300),
+    ) {
+        let model: BTreeMap<String, String> = entries.iter().cloned().collect();
+        let tree = BPTree::bulk_load(order, entries);
+        if let Err(error) = tree.check_invariants() {
+            return Err(TestCaseError::fail(error.to_string()));
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+
+    #[test]
+    fn node_search_handles_shared_prefixes(
+        order in 3usize..40,
+        entries in prop::collection::vec(("[ab\u{0}]{0,10}", "[0-9]{1,4}"), 0..200),
+        probes in prop::collection::vec("[ab\u{0}]{0,10}", 0..50),
+    ) {
+        // key 只有很少的几种字节且长度接近 8, 经常出现前 8 个字节相同或以 0 结尾的 key
+        let mut tree = BPTree::new(order);
+        let mut model = BTreeMap::new();
+        for (key, value) in entries {
+            prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key, value));
+        }
+        for key in &probes {
+            prop_assert_eq!(tree.get(key).map(|kv| kv.value()), model.get(key).map(String::as_str));
+            prop_assert_eq!(tree.rank(key), model.range::<String, _>(..key.clone()).count());
+        }
+        for key in probes {
+            prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key));
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+
+    #[test]
+    fn prefix_tree_matches_btree_map(
+        order in 3usize..10,
+        ops in prop::collection::vec((0u8..9, "(url/|url/é|u)[abé]{0,4}", "[0-9]{1,3}"), 1..400),
+        ranges in prop::collection::vec((bound(), bound()), 0..10),
+    ) {
+        // key 有几种很长或很短的公共前缀, 其中有多字节字符, 前缀在节点中不断缩短与加长
+        let mut tree = PrefixBPTree::new(order);
+        let mut model = BTreeMap::new();
+        for (kind, key, value) in ops {
+            match kind {
+                0 | 1 => prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key, value)),
+                2 => prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key)),
+                3 => prop_assert_eq!(tree.get(&key), model.get(&key).map(String::as_str)),
+                // entry 中是完整的 key, 已存在时把值追加到原来的值后面
+                4 => {
+                    let entry = tree.entry(key.clone());
+                    prop_assert_eq!(entry.key(), key.as_str());
+                    entry.and_modify(|_v| _v.push_str(&value)).or_insert(value.clone());
+                    model.entry(key).and_modify(|_v| _v.push_str(&value)).or_insert(value);
+                }
+                // 整段删除以 key 开头、下一个字符小于 'b' 的 key
+                5 | 6 => {
+                    let end = format!("{}b", key);
+                    let expected: Vec<(String, String)> = model.range(key.clone()..end.clone()).map(|(_k, _v)| (_k.clone(), _v.clone())).collect();
+                    for (removed, _) in &expected {
+                        model.remove(removed);
+                    }
+                    if kind == 5 {
+                        prop_assert_eq!(tree.drain(Bound::Included(&key), Bound::Excluded(&end)).unwrap().collect::<Vec<_>>(), expected);
+                    } else {
+                        prop_assert_eq!(tree.remove_range(Bound::Included(&key), Bound::Excluded(&end)).unwrap(), expected.len());
+                    }
+                }
+                7 => prop_assert_eq!(tree.pop_first().unwrap(), model.pop_first()),
+                _ => prop_assert_eq!(tree.pop_last().unwrap(), model.pop_last()),
+            }
+            prop_assert_eq!(tree.len(), model.len());
+            prop_assert_eq!(tree.check_invariants(), Ok(()));
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.clone(), value.as_str()))));
+        prop_assert!(tree.iter().rev().eq(model.iter().rev().map(|(key, value)| (key.clone(), value.as_str()))));
+        prop_assert_eq!(tree.first(), model.first_key_value().map(|(key, value)| (key.clone(), value.as_str())));
+        for (start, end) in ranges {
+            // bound() 中的 key 与树中的 key 前缀不同, 加上前缀之后才会落在树中间
+            let with_prefix = |_bound: &Bound<String>| _bound.as_ref().map(|_key| format!("url/{}", _key));
+            let (start, end) = (with_prefix(&start), with_prefix(&end));
+            if is_empty_range(&start, &end) {
+                continue;
+            }
+            let expected = model.range::<String, _>((start.clone(), end.clone())).map(|(key, value)| (key.clone(), value.as_str()));
+            prop_assert!(tree.range(as_str(&start), as_str(&end)).eq(expected));
+        }
+    }
+
+    #[test]
+    fn key_encodings_preserve_order(
+        (a, b) in (any::<u64>(), any::<u64>()),
+        (c, d) in (any::<i64>(), any::<i64>()),
+        (e, f) in (any::<f64>(), any::<f64>()),
+        tuples in prop::collection::vec((0u64..3, -2i64..2, prop::collection::vec(prop::sample::select(vec!['\0', '\x01', 'a']), 0..4)), 2),
+    ) {
+        // 编码后按字节比较的顺序与值的顺序相同, 并且可以解码回原来的值
+        prop_assert_eq!(a.to_key().cmp(&b.to_key()), a.cmp(&b));
+        prop_assert_eq!(c.to_key().cmp(&d.to_key()), c.cmp(&d));
+        prop_assert_eq!(e.to_key().cmp(&f.to_key()), e.total_cmp(&f));
+        prop_assert_eq!(u64::from_key(&a.to_key()), Some(a));
+        prop_assert_eq!(i64::from_key(&c.to_key()), Some(c));
+        prop_assert_eq!(f64::from_key(&e.to_key()).map(f64::to_bits), Some(e.to_bits()));
+
+        // 复合 key 中的字符串可以包含 '\0', 较短的字符串排在前面
+        let tuples: Vec<(u64, String, i64)> = tuples.into_iter().map(|(id, ts, chars)| (id, chars.into_iter().collect(), ts)).collect();
+        prop_assert_eq!(tuples[0].to_key().cmp(&tuples[1].to_key()), tuples[0].cmp(&tuples[1]));
+        prop_assert_eq!(<(u64, String, i64)>::from_key(&tuples[0].to_key()), Some(tuples[0].clone()));
+        prop_assert_eq!(u64::from_key(&format!("{}x", a.to_key())), None);
+    }
+
+    #[test]
+    fn key_histogram_matches_btree_map(
+        order in 3usize..8,
+        ops in prop::collection::vec(op(), 1..300),
+        buckets in 0usize..12,
+    ) {
+        // 桶首尾相接覆盖所有 key, 每个桶的数量与参照中对应范围的数量相同
+        let mut tree = BPTree::new(order);
+        let mut model = BTreeMap::new();
+        for op in ops {
+            match op {
+                Op::Put(key, value) => prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key, value)),
+                Op::Remove(key) => prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key)),
+                _ => {}
+            }
+        }
+        let histogram = tree.key_histogram(buckets);
+        if buckets == 0 || model.is_empty() {
+            prop_assert!(histogram.is_empty());
+            return Ok(());
+        }
+        prop_assert!(histogram.len() <= buckets);
+        prop_assert_eq!(histogram[0].start.as_ref(), None);
+        prop_assert_eq!(histogram[histogram.len() - 1].end.as_ref(), None);
+        prop_assert!(histogram.windows(2).all(|_w| _w[0].end.is_some() && _w[0].end == _w[1].start));
+        for bucket in &histogram {
+            let start = bucket.start.as_ref().map_or(Bound::Unbounded, Bound::Included);
+            let end = bucket.end.as_ref().map_or(Bound::Unbounded, Bound::Excluded);
+            prop_assert!(bucket.count > 0);
+            prop_assert_eq!(bucket.count, model.range::<String, _>((start, end)).count());
+        }
+    }
+
+    #[test]
+    fn stable_cursor_matches_btree_map(
+        order in 3usize..8,
+        steps in prop::collection::vec((op(), 0u8..4, key()), 1..300),
+    ) {
+        // 每次移动游标之前修改一次树, 移动的结果只由当前 key 与参照的内容决定
+        let mut tree = BPTree::new(order);
+        let mut model: BTreeMap<String, String> = BTreeMap::new();
+        let mut cursor = StableCursor::new();
+        for (op, movement, target) in steps {
+            match op {
+                Op::Put(key, value) => prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key, value)),
+                Op::PutBatch(entries) => {
+                    tree.put_batch(entries.clone()).unwrap();
+                    model.extend(entries);
+                }
+                Op::Remove(key) => prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key)),
+                Op::RemoveRange(start, end) if !is_empty_range(&start, &end) => {
+                    tree.remove_range(as_str(&start), as_str(&end)).unwrap();
+                    model.retain(|_key, _| !std::ops::RangeBounds::contains(&(start.as_ref(), end.as_ref()), _key));
+                }
+                Op::PopFirst => prop_assert_eq!(tree.pop_first().unwrap(), model.pop_first()),
+                Op::Retain(_) => tree.compact(),
+                _ => {}
+            }
+            let current = cursor.key().map(str::to_string);
+            let (actual, expected) = match movement {
+                0 | 1 => (cursor.next(&tree), match &current {
+                    Some(key) => model.range::<String, _>((Bound::Excluded(key), Bound::Unbounded)).next(),
+                    None => model.iter().next(),
+                }),
+                2 => (cursor.prev(&tree), match &current {
+                    Some(key) => model.range::<String, _>(..key).next_back(),
+                    None => model.iter().next_back(),
+                }),
+                _ => (cursor.seek(&tree, &target), model.range::<String, _>(&target..).next()),
+            };
+            prop_assert_eq!(actual, expected.map(|(key, value)| (key.as_str(), value.as_str())));
+            prop_assert_eq!(cursor.key(), actual.map(|(key, _)| key));
+        }
+    }
+
+    #[test]
+    fn diff_matches_btree_map(
+        order in 3usize..8,
+        entries in prop::collection::vec((key(), "[0-9]{1,2}"), 0..200),
+        edits in prop::collection::vec((any::<bool>(), key(), prop::option::of("[0-9]{1,2}")), 0..60),
+    ) {
+        // 副本从同一棵树复制出来, 两边各自修改之后, 差异与两个参照逐个比较的结果相同, 共享的叶子节点被跳过也不影响结果
+        let mut left = BPTree::new(order);
+        let mut left_model = BTreeMap::new();
+        for (key, value) in entries {
+            left.put(key.clone(), value.clone()).unwrap();
+            left_model.insert(key, value);
+        }
+        let mut right = left.clone();
+        let mut right_model = left_model.clone();
+        for (on_left, key, value) in edits {
+            let (tree, model) = if on_left { (&mut left, &mut left_model) } else { (&mut right, &mut right_model) };
+            match value {
+                Some(value) => prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), model.insert(key, value)),
+                None => prop_assert_eq!(tree.remove(&key).unwrap(), model.remove(&key)),
+            }
+        }
+        let keys: BTreeSet<&String> = left_model.keys().chain(right_model.keys()).collect();
+        let expected: Vec<DiffEntry> = keys
+            .into_iter()
+            .filter_map(|key| match (left_model.get(key), right_model.get(key)) {
+                (Some(old), Some(new)) if old != new => Some(DiffEntry::Changed { key, old_value: old, new_value: new }),
+                (Some(value), None) => Some(DiffEntry::Removed { key, value }),
+                (None, Some(value)) => Some(DiffEntry::Added { key, value }),
+                _ => None,
+            })
+            .collect();
+        prop_assert_eq!(left.diff(&right).collect::<Vec<_>>(), expected);
+        // 与结构不同、不共享节点的树比较, 结果相同
+        let rebuilt = BPTree::bulk_load(order + 1, right_model.clone());
+        prop_assert_eq!(left.diff(&rebuilt).collect::<Vec<_>>(), left.diff(&right).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn get_many_matches_btree_map(
+        order in 3usize..8,
+        entries in prop::collection::vec((key(), "[0-9]{1,4}"), 0..200),
+        probes in prop::collection::vec(key(), 0..50),
+    ) {
+        let model: BTreeMap<String, String> = entries.iter().cloned().collect();
+        let mut tree = BPTree::new(order);
+        tree.put_batch(entries).unwrap();
+        let expected: Vec<Option<&str>> = probes.iter().map(|key| model.get(key).map(String::as_str)).collect();
+        prop_assert_eq!(tree.get_many(&probes), expected);
+    }
+
+    #[test]
+    fn std_traits_match_btree_map(
+        order in 3usize..8,
+        entries in prop::collection::vec((key(), "[0-9]{1,4}"), 0..200),
+        more in prop::collection::vec((key(), "[0-9]{1,4}"), 0..50),
+        removed in prop::collection::vec(key(), 0..20),
+    ) {
+        let mut tree = BPTree::new(order);
+        for (key, value) in &entries {
+            tree.put(key.clone(), value.clone()).unwrap();
+        }
+        let mut model: BTreeMap<String, String> = entries.into_iter().collect();
+        // 形状不同但内容相同的树相等
+        let collected: BPTree = model.clone().into_iter().collect();
+        prop_assert!(collected == tree);
+
+        let mut copy = tree.clone();
+        let copy_model = model.clone();
+        tree.extend(more.clone());
+        model.extend(more);
+        for key in &removed {
+            tree.remove(key).unwrap();
+            model.remove(key);
+        }
+        for tree in [&tree, &copy] {
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+        prop_assert!(copy.iter().eq(copy_model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+        prop_assert_eq!(copy == tree, copy_model == model);
+        copy.put("new".to_string(), "1".to_string()).unwrap();
+        prop_assert!(copy != collected);
+    }
+
+    #[test]
+    fn export_import_round_trips(
+        order in 3usize..8,
+        entries in prop::collection::vec(("[a-c,\"\r\n \\\\é😀]{0,6}", "[a-c,\"\r\n\t \u{1}é😀]{0,6}"), 0..100),
+        existing in prop::collection::vec((key(), "[0-9]{1,4}"), 0..20),
+    ) {
+        let source: BTreeMap<String, String> = entries.into_iter().collect();
+        let tree = BPTree::bulk_load(order, source.clone());
+        for format in [Format::Csv, Format::JsonLines] {
+            let mut buf = vec![];
+            tree.export(&mut buf, format).unwrap();
+            // 导入到空树与已有数据的树中
+            for existing in [&[][..], &existing[..]] {
+                let mut copy = BPTree::bulk_load(order, existing.to_vec());
+                let mut model: BTreeMap<String, String> = existing.iter().cloned().collect();
+                let inserted = copy.import(buf.as_slice(), format).unwrap();
+                prop_assert_eq!(inserted, source.keys().filter(|_key| !model.contains_key(*_key)).count());
+                model.extend(source.clone());
+                if let Err(error) = copy.check_invariants() {
+                    return Err(TestCaseError::fail(error.to_string()));
+                }
+                prop_assert!(copy.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+            }
+        }
+    }
+
+    #[test]
+    fn mmap_matches_btree_map(
+        order in 3usize..12,
+        entries in prop::collection::vec((key(), "[0-9]{1,4}"), 0..300),
+        probes in prop::collection::vec(key(), 0..20),
+        start in bound(),
+        end in bound(),
+    ) {
+        static CASE: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "btree-test-mmap-{}-{}",
+            std::process::id(),
+            CASE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let model: BTreeMap<String, String> = entries.iter().cloned().collect();
+        BPTree::bulk_load(order, entries).write_mmap(&path).unwrap();
+        let table = BPTree::open_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        prop_assert_eq!(table.len(), model.len());
+        for key in model.keys().chain(&probes) {
+            prop_assert_eq!(table.get(key).unwrap(), model.get(key).map(String::as_str));
+        }
+        let expected: Vec<_> = if is_empty_range(&start, &end) {
+            vec![]
+        } else {
+            model.range::<str, _>((as_str(&start), as_str(&end))).map(|(key, value)| (key.as_str(), value.as_str())).collect()
+        };
+        let actual: Vec<_> = table.range(as_str(&start), as_str(&end)).map(Result::unwrap).collect();
+        prop_assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn merkle_proofs_verify_after_mutations(
+        order in 3usize..8,
+        entries in prop::collection::vec((key(), "[0-9]{1,4}"), 0..200),
+        rounds in prop::collection::vec(prop::collection::vec(op(), 0..30), 1..5),
+    ) {
+        // 每一轮修改之前都计算一次根哈希, 之后的证明只有在被修改的节点都重新计算过时才能通过检查
+        let mut tree = BPTree::bulk_load(order, entries);
+        tree.set_merge_operator(|_key: &str, existing: Option<&str>, operand: &str| format!("{}{}", existing.unwrap_or(""), operand));
+        for ops in rounds {
+            let old_hash = tree.root_hash();
+            let before: Vec<(String, String)> = tree.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+            for op in ops {
+                match op {
+                    Op::Put(key, value) => drop(tree.put(key, value).unwrap()),
+                    Op::PutBatch(entries) => drop(tree.put_batch(entries).unwrap()),
+                    Op::Remove(key) => drop(tree.remove(&key).unwrap()),
+                    // 通过可变引用修改值时不经过父节点
+                    Op::Modify(key, suffix) => drop(tree.get_mut(&key).map(|mut value| value.push_str(&suffix))),
+                    Op::Merge(key, operand) => tree.merge(key, &operand).unwrap(),
+                    Op::Upsert(key, suffix) => drop(tree.upsert(key, || suffix.clone(), |value| value.push_str(&suffix)).unwrap()),
+                    Op::GetOrInsert(key, value) => drop(tree.get_or_insert_with(key, || value).unwrap()),
+                    Op::Retain(digit) => {
+                        for (_, value) in tree.iter_mut() {
+                            value.push_str(&digit);
+                        }
+                    }
+                    Op::RemoveRange(start, end) => drop(tree.remove_range(as_str(&start), as_str(&end)).unwrap()),
+                    Op::PopFirst => drop(tree.pop_first().unwrap()),
+                    _ => {}
+                }
+            }
+            let root_hash = tree.root_hash();
+            let changed = !tree.iter().eq(before.iter().map(|(key, value)| (key.as_str(), value.as_str())));
+            // 内容不变时形状仍可能改变, 只检查内容改变时根哈希一定改变
+            if changed {
+                prop_assert_ne!(root_hash, old_hash);
+            }
+            for (key, value) in tree.iter() {
+                let mut proof = tree.prove(key).unwrap();
+                prop_assert_eq!((proof.key.as_str(), proof.value.as_str()), (key, value));
+                prop_assert!(verify(&root_hash, &proof));
+                proof.value.push('!');
+                prop_assert!(!verify(&root_hash, &proof));
+            }
+            prop_assert!(tree.prove("z").is_none());
+        }
+    }
+
+    #[test]
+    fn change_feed_mirrors_tree(
+        order in 3usize..8,
+        ops in prop::collection::vec(op(), 1..200),
+        split_key in key(),
+        other in prop::collection::vec((key(), "[0-9]{1,4}"), 0..50),
+    ) {
+        // 只根据事件维护一份副本, 每个事件的旧值都必须与副本一致, 最后副本与树的内容相同
+        let mut tree = BPTree::new(order);
+        tree.set_merge_operator(|_key: &str, existing: Option<&str>, operand: &str| format!("{}{}", existing.unwrap_or(""), operand));
+        let changes = tree.subscribe(1 << 16);
+        for op in ops {
+            match op {
+                Op::Put(key, value) => drop(tree.put(key, value).unwrap()),
+                Op::PutBatch(entries) => drop(tree.put_batch(entries).unwrap()),
+                Op::Remove(key) => drop(tree.remove(&key).unwrap()),
+                Op::Modify(key, suffix) => drop(tree.modify(&key, |value| value.push_str(&suffix)).unwrap()),
+                Op::Merge(key, operand) => tree.merge(key, &operand).unwrap(),
+                Op::Upsert(key, suffix) => drop(tree.upsert(key, || suffix.clone(), |value| value.push_str(&suffix)).unwrap()),
+                Op::GetOrInsert(key, value) => drop(tree.get_or_insert_with(key, || value).unwrap()),
+                Op::CursorRemove(key) => {
+                    let mut cursor = tree.cursor_mut();
+                    cursor.seek(&key);
+                    cursor.remove_current().unwrap();
+                }
+                Op::Retain(digit) => drop(tree.retain(|_, value| !value.contains(digit.as_str())).unwrap()),
+                Op::Drain(start, end) => drop(tree.drain(as_str(&start), as_str(&end)).unwrap()),
+                Op::RemoveRange(start, end) => drop(tree.remove_range(as_str(&start), as_str(&end)).unwrap()),
+                Op::PopFirst => drop(tree.pop_first().unwrap()),
+                Op::PopLast => drop(tree.pop_last().unwrap()),
+                _ => {}
+            }
+        }
+        tree.entry("entry".to_string()).and_modify(|value| value.push('!')).or_insert("1".to_string());
+        let mut value = tree.entry("entry".to_string()).or_insert_with(String::new);
+        value.push('?');
+        value.commit().unwrap();
+        if let Some(mut value) = tree.get_mut("entry") {
+            value.push('?');
+            value.commit().unwrap();
+        }
+        // 没有 commit 的修改被丢弃, 也不发出事件
+        tree.get_mut("entry").unwrap().push('?');
+        let mut moved = tree.split_off(&split_key).unwrap();
+        let moved_changes = moved.subscribe(1 << 16);
+        let mut other = BPTree::bulk_load(order, other);
+        let other_changes = other.subscribe(1 << 16);
+        let moved_lens = [moved.len(), other.len()];
+        tree.append(&mut other).unwrap();
+        tree.append(&mut moved).unwrap();
+
+        let mut mirror = BTreeMap::new();
+        while let Some(event) = changes.try_recv().unwrap() {
+            let old_value = match event.new_value {
+                Some(value) => mirror.insert(event.key, value),
+                None => mirror.remove(&event.key),
+            };
+            prop_assert_eq!(old_value, event.old_value);
+        }
+        prop_assert!(tree.iter().eq(mirror.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+        // 被合并进来的两棵树收到了所有键值对的删除事件
+        for (changes, len) in [moved_changes, other_changes].into_iter().zip(moved_lens) {
+            let mut removed = 0;
+            while let Some(event) = changes.try_recv().unwrap() {
+                prop_assert!(event.old_value.is_some() && event.new_value.is_none());
+                removed += 1;
+            }
+            prop_assert_eq!(removed, len);
+        }
+        drop(tree);
+        prop_assert_eq!(changes.try_recv(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn snapshot_iter_ignores_later_writes(
+        order in 3usize..8,
+        entries in prop::collection::vec((key(), "[0-9]{1,4}"), 0..200),
+        (start, end) in (bound(), bound()),
+        steps in prop::collection::vec((any::<bool>(), key(), prop::option::of("[0-9]{1,4}")), 0..200),
+    ) {
+        // 每从迭代器两端取出一个键值对, 就插入或删除一个 key, 迭代器的结果始终与创建时的内容一致
+        let mut tree = BPTree::new(order);
+        let mut model = BTreeMap::new();
+        for (key, value) in entries {
+            tree.put(key.clone(), value.clone()).unwrap();
+            model.insert(key, value);
+        }
+        let expected: Vec<(String, String)> = if is_empty_range(&start, &end) {
+            vec![]
+        } else {
+            model.range::<str, _>((as_str(&start), as_str(&end))).map(|(key, value)| (key.clone(), value.clone())).collect()
+        };
+        let mut iter = tree.range_snapshot(as_str(&start), as_str(&end));
+        let (mut front, mut back) = (vec![], vec![]);
+        for (forward, key, value) in steps {
+            match if forward { iter.next() } else { iter.next_back() } {
+                Some(item) if forward => front.push(item),
+                Some(item) => back.push(item),
+                None => {}
+            }
+            match value {
+                Some(value) => drop(tree.put(key, value).unwrap()),
+                None => drop(tree.remove(&key).unwrap()),
+            }
+        }
+        front.extend(iter);
+        front.extend(back.into_iter().rev());
+        prop_assert_eq!(front, expected);
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn leaf_and_internal_orders_match_btree_map(
+        leaf_order in 3usize..10,
+        internal_order in 3usize..10,
+        policy in split_policy(),
+        ops in prop::collection::vec((0usize..600, 0usize..600, 0u8..4), 1..150),
+        at in 0usize..600,
+    ) {
+        // 叶子节点与内部节点的上下限不同, 插入、删除、范围删除以及分离与合并后都要各自满足
+        let mut tree = BPTree::builder().leaf_order(leaf_order).internal_order(internal_order).split_policy(policy).build();
+        let mut model = BTreeMap::new();
+        for (a, b, kind) in ops {
+            let (start, end) = (format!("{:03}", a.min(b)), format!("{:03}", a.max(b)));
+            match kind {
+                0 => {
+                    let entries: Vec<(String, String)> = (a.min(b)..a.max(b)).map(|_i| (format!("{:03}", _i), _i.to_string())).collect();
+                    tree.put_batch(entries.clone()).unwrap();
+                    model.extend(entries);
+                }
+                1 => prop_assert_eq!(tree.put(start.clone(), b.to_string()).unwrap(), model.insert(start, b.to_string())),
+                2 => prop_assert_eq!(tree.remove(&start).unwrap(), model.remove(&start)),
+                _ => {
+                    let keys: Vec<String> = model.range(start.clone()..end.clone()).map(|(key, _)| key.clone()).collect();
+                    for key in &keys {
+                        model.remove(key);
+                    }
+                    prop_assert_eq!(tree.remove_range(Bound::Included(start.as_str()), Bound::Excluded(end.as_str())).unwrap(), keys.len());
+                }
+            }
+            if let Err(error) = tree.check_invariants() {
+                return Err(TestCaseError::fail(error.to_string()));
+            }
+        }
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+
+        let at = format!("{:03}", at);
+        let mut upper = tree.split_off(&at).unwrap();
+        tree.check_invariants().unwrap();
+        upper.check_invariants().unwrap();
+        prop_assert_eq!((upper.leaf_order(), upper.order()), (leaf_order, internal_order));
+        tree.append(&mut upper).unwrap();
+        tree.check_invariants().unwrap();
+        prop_assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+}
+
+#[cfg(feature = "lz4")]
+proptest! {
+    #[test]
+    fn compressed_matches_btree_map(
+        threshold in 0usize..64,
+        ops in prop::collection::vec((key(), prop::option::of(("[a-c]{1,3}", 0usize..200))), 1..200),
+    ) {
+        // 值由一小段重复很多次组成, 长的值一般会被压缩, 短的值原样存放
+        let mut tree = BPTree::builder().order(4).build_compressed(threshold);
+        let mut reference = BTreeMap::new();
+        for (key, value) in ops {
+            match value {
+                Some((unit, times)) => {
+                    let value = unit.repeat(times);
+                    prop_assert_eq!(tree.put(key.clone(), value.clone()).unwrap(), reference.insert(key, value));
+                }
+                None => prop_assert_eq!(tree.remove(&key).unwrap(), reference.remove(&key)),
+            }
+            prop_assert_eq!(tree.len(), reference.len());
+        }
+        for (key, value) in &reference {
+            let found = tree.get(key);
+            prop_assert_eq!(found.as_deref(), Some(value.as_str()));
+        }
+        prop_assert!(tree.iter().map(|(key, value)| (key.to_string(), value.into_owned())).eq(reference.clone()));
+        prop_assert!(tree.iter().rev().map(|(key, _)| key).eq(reference.keys().rev().map(String::as_str)));
+    }
+}
+
+#[test]
+fn concurrent_writers_match_btree_map() {
+    // 多个线程交错写入与删除各自的 key, 同时有线程不断遍历, 遍历结果必须始终有序
+    let tree = ConcurrentBPTree::new(4);
+    std::thread::scope(|_s| {
+        for t in 0..8 {
+            let tree = &tree;
+            _s.spawn(move || {
+                for i in 0..2000 {
+                    tree.put(format!("{:04}-{}", i, t), i.to_string());
+                    if i % 3 == 0 {
+                        assert_eq!(tree.remove(&format!("{:04}-{}", i / 2, t)), Some((i / 2).to_string()));
+                    }
+                }
+            });
+        }
+        for _ in 0..2 {
+            let tree = &tree;
+            _s.spawn(move || {
+                for _ in 0..50 {
+                    let all = tree.range::<str>(Bound::Unbounded, Bound::Unbounded);
+                    assert!(all.windows(2).all(|_w| _w[0].0 < _w[1].0));
+                }
+            });
+        }
+    });
+
+    let mut model = BTreeMap::new();
+    for t in 0..8 {
+        for i in 0..2000 {
+            model.insert(format!("{:04}-{}", i, t), i.to_string());
+        }
+        for i in (0..2000).filter(|_i| _i % 3 == 0) {
+            model.remove(&format!("{:04}-{}", i / 2, t));
+        }
+    }
+    assert_eq!(tree.len(), model.len());
+    assert_eq!(tree.range::<str>(Bound::Unbounded, Bound::Unbounded), model.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn concurrent_removes_reclaim_empty_leaves() {
+    // 多个线程同时删空大部分叶子节点, 同时有线程遍历; 摘下的叶子节点在之后的插入中被复用
+    let tree = ConcurrentBPTree::new(4);
+    for i in 0..8000 {
+        tree.put(format!("{:05}", i), i.to_string());
+    }
+    let full = tree.node_count();
+    std::thread::scope(|_s| {
+        for t in 0..8 {
+            let tree = &tree;
+            _s.spawn(move || {
+                for i in (t..8000).step_by(8).filter(|_i| _i % 100 != 0) {
+                    assert_eq!(tree.remove(&format!("{:05}", i)), Some(i.to_string()));
+                }
+            });
+        }
+        for _ in 0..2 {
+            let tree = &tree;
+            _s.spawn(move || {
+                for _ in 0..50 {
+                    let all = tree.range::<str>(Bound::Unbounded, Bound::Unbounded);
+                    assert!(all.windows(2).all(|_w| _w[0].0 < _w[1].0));
+                    assert!(all.len() >= 80);
+                }
+            });
+        }
+    });
+    let remaining: Vec<String> = (0..8000).step_by(100).map(|_i| format!("{:05}", _i)).collect();
+    assert_eq!(tree.range::<str>(Bound::Unbounded, Bound::Unbounded).into_iter().map(|(key, _)| key).collect::<Vec<_>>(), remaining);
+    let reclaimed = tree.node_count();
+    // 内部节点不回收, 每个内部节点下至少留一个叶子节点
+    assert!(reclaimed < full * 2 / 3, "{} of {} nodes left", reclaimed, full);
+
+    for i in 0..8000 {
+        tree.put(format!("{:05}", i), i.to_string());
+    }
+    assert_eq!(tree.len(), 8000);
+    assert_eq!(tree.get("04321"), Some("4321".to_string()));
+    assert!(tree.node_count() <= full + reclaimed, "{} nodes after reinserting", tree.node_count());
+}
+
+#[test]
+fn concurrent_compare_and_swap_inserts_once() {
+    // 多个线程同时用 compare_and_swap 插入同一批 key, 每个 key 只有一个线程成功, 插入时叶子节点会不断分裂
+    let tree = ConcurrentBPTree::new(4);
+    let successes = AtomicUsize::new(0);
+    std::thread::scope(|_s| {
+        for t in 0..8 {
+            let (tree, successes) = (&tree, &successes);
+            _s.spawn(move || {
+                for i in 0..1000 {
+                    match tree.compare_and_swap(format!("{:04}", i), None, Some(t.to_string())) {
+                        Ok(()) => drop(successes.fetch_add(1, Ordering::Relaxed)),
+                        Err(CasError::Mismatch { current }) => assert!(current.is_some()),
+                        Err(error) => panic!("{}", error),
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(successes.load(Ordering::Relaxed), 1000);
+    assert_eq!(tree.len(), 1000);
+    let all = tree.range::<str>(Bound::Unbounded, Bound::Unbounded);
+    assert!(all.iter().map(|(key, _)| key.clone()).eq((0..1000).map(|_i| format!("{:04}", _i))));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn async_tasks_share_paged_tree() {
+    // 多个任务交错地插入与删除各自的 key, 第 i 次插入后删除第 i / 3 个 key, 最后从文件重新打开检查内容
+    let path = std::env::temp_dir().join(format!("btree-test-async-{}", std::process::id()));
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let tree = btree_test::AsyncBPTree::create(&path, 4, 256, 4096).await.unwrap();
+        let tasks: Vec<_> = (0..4)
+            .map(|t| {
+                let tree = tree.clone();
+                tokio::spawn(async move {
+                    for i in 0..200 {
+                        tree.put(format!("{}-{:03}", t, i), "x".repeat(i % 100)).await.unwrap();
+                        if i % 3 == 0 {
+                            let removed = tree.remove(&format!("{}-{:03}", t, i / 3)).await.unwrap();
+                            assert_eq!(removed.map(|_value| _value.len()), Some(i / 3 % 100));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        tree.flush().await.unwrap();
+    });
+    drop(runtime);
+
+    let expected: BTreeSet<String> = (0..4)
+        .flat_map(|_t| (200 / 3 + 1..200).map(move |_i| format!("{}-{:03}", _t, _i)))
+        .collect();
+    let mut tree = btree_test::PagedBPTree::open(&path, 4096).unwrap();
+    let all = tree.range::<str>(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert!(all.iter().map(|(key, _)| key.clone()).eq(expected));
+    drop(tree);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(path.with_extension("ovf")).unwrap();
+}
+
+#[test]
+fn file_header_versions() {
+    let path = std::env::temp_dir().join(format!("btree-test-header-{}", std::process::id()));
+    let mut tree = BPTree::create(&path, 4, 256).unwrap();
+    tree.put_batch((0..50).map(|_i| (format!("{:02}", _i), _i.to_string()))).unwrap();
+    tree.checkpoint().unwrap();
+    drop(tree);
+    let header = std::fs::read(&path).unwrap()[..64].to_vec();
+    assert_eq!(&header[..8], b"BPTFILE\0");
+    assert_eq!(header[8..12], btree_test::FORMAT_VERSION.to_le_bytes());
+
+    // 比当前版本新的文件拒绝打开
+    let mut newer = std::fs::read(&path).unwrap();
+    newer[8..12].copy_from_slice(&(btree_test::FORMAT_VERSION + 1).to_le_bytes());
+    std::fs::write(&path, &newer).unwrap();
+    assert_eq!(BPTree::open(&path).unwrap_err().kind(), std::io::ErrorKind::Unsupported);
+
+    // 版本 0 没有文件头, 第 0 页直接从页大小开始, order 为一个 u64, 打开时升级
+    let mut legacy = newer;
+    legacy[..64].fill(0);
+    legacy[..8].copy_from_slice(&header[16..24]);
+    legacy[8..16].copy_from_slice(&4u64.to_le_bytes());
+    legacy[16..48].copy_from_slice(&header[32..]);
+    std::fs::write(&path, &legacy).unwrap();
+    let tree = BPTree::open(&path).unwrap();
+    assert_eq!(tree.len(), 50);
+    assert_eq!(tree.get("42").map(|_kv| _kv.value()), Some("42"));
+    drop(tree);
+    assert_eq!(std::fs::read(&path).unwrap()[..64], header[..]);
+
+    // 长度与元数据不一致的文件不会被当作版本 0 改写
+    let mut truncated = legacy;
+    truncated.pop();
+    std::fs::write(&path, &truncated).unwrap();
+    assert_eq!(BPTree::open(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(std::fs::read(&path).unwrap(), truncated);
+
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        std::fs::remove_file(path.with_extension(extension)).unwrap();
+    }
+}
+
+#[test]
+fn corrupted_file_header_is_rejected() {
+    // 文件头中的页大小或节点数量损坏时返回 InvalidData, 而不是按损坏的值分配内存
+    let path = std::env::temp_dir().join(format!("btree-test-corrupted-header-{}", std::process::id()));
+    assert_eq!(BPTree::create(&path, 4, 1 << 30).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    let mut tree = BPTree::create(&path, 4, 256).unwrap();
+    tree.put_batch((0..50).map(|_i| (format!("{:02}", _i), _i.to_string()))).unwrap();
+    tree.checkpoint().unwrap();
+    drop(tree);
+    let file = std::fs::read(&path).unwrap();
+
+    // 页大小在第 16..24 字节, 节点数量在第 56..64 字节
+    for (range, value) in [(16..24, u64::MAX), (16..24, 1 << 40), (16..24, 16), (56..64, u64::MAX), (56..64, 1 << 20)] {
+        let mut corrupted = file.clone();
+        corrupted[range].copy_from_slice(&value.to_le_bytes());
+        std::fs::write(&path, &corrupted).unwrap();
+        assert_eq!(BPTree::open(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+    std::fs::write(&path, &file).unwrap();
+    assert_eq!(BPTree::open(&path).unwrap().len(), 50);
+
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        std::fs::remove_file(path.with_extension(extension)).unwrap();
+    }
+}
+
+#[test]
+fn interrupted_checkpoint_keeps_file_intact() {
+    // 检查点先把节点写入影子文件, 刷新后改名替换, 模拟在替换前后崩溃再重新打开
+    let path = std::env::temp_dir().join(format!("btree-test-checkpoint-{}", std::process::id()));
+    let sibling = |_suffix: &str| {
+        let mut name = path.clone().into_os_string();
+        name.push(_suffix);
+        std::path::PathBuf::from(name)
+    };
+    let mut tree = BPTree::create(&path, 4, 256).unwrap();
+    // 较长的值放在溢出文件中, 它也要和数据文件一起替换
+    tree.put_batch((0..100).map(|_i| (format!("{:03}", _i), _i.to_string().repeat(40)))).unwrap();
+    tree.checkpoint().unwrap();
+    tree.put_batch((100..150).map(|_i| (format!("{:03}", _i), _i.to_string().repeat(40)))).unwrap();
+    tree.remove("042").unwrap();
+    let expected: Vec<(String, String)> = tree.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+    let old = (std::fs::read(&path).unwrap(), std::fs::read(path.with_extension("ovf")).unwrap());
+    // sync 不清空日志, 日志中仍然是上一次检查点之后的修改
+    tree.sync().unwrap();
+    drop(tree);
+    let new = (std::fs::read(&path).unwrap(), std::fs::read(path.with_extension("ovf")).unwrap());
+    let reopen = || {
+        let tree = BPTree::open(&path).unwrap();
+        tree.check_invariants().unwrap();
+        let entries: Vec<(String, String)> = tree.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        assert_eq!(entries, expected);
+    };
+
+    // 影子文件没有写完: 删除它, 原来的文件没有被修改过, 重放日志后得到同样的结果
+    std::fs::write(&path, &old.0).unwrap();
+    std::fs::write(path.with_extension("ovf"), &old.1).unwrap();
+    std::fs::write(sibling(".new"), &new.0[..new.0.len() / 2]).unwrap();
+    std::fs::write(sibling(".new.ovf"), b"torn").unwrap();
+    reopen();
+    assert!(!sibling(".new").exists() && !sibling(".new.ovf").exists());
+    assert_eq!(std::fs::read(&path).unwrap(), old.0);
+
+    // 影子文件完整并改名为 .ckpt 之后替换就已经生效, 打开时完成剩下的改名, 日志在新的文件上重放
+    for overflow_renamed in [false, true] {
+        std::fs::write(&path, &old.0).unwrap();
+        std::fs::write(sibling(".ckpt"), &new.0).unwrap();
+        let overflow = if overflow_renamed { path.with_extension("ovf") } else { sibling(".new.ovf") };
+        std::fs::write(overflow, &new.1).unwrap();
+        reopen();
+        assert!(!sibling(".ckpt").exists() && !sibling(".new.ovf").exists());
+        assert_eq!((std::fs::read(&path).unwrap(), std::fs::read(path.with_extension("ovf")).unwrap()), new);
+    }
+
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        std::fs::remove_file(path.with_extension(extension)).unwrap();
+    }
+}
+
+#[test]
+fn prefix_tree_logs_and_publishes_changes() {
+    // 节点按前缀压缩存放的树与 BPTree 一样写预写日志与检查点, 同一个文件两者都可以打开
+    let path = std::env::temp_dir().join(format!("btree-test-prefix-{}", std::process::id()));
+    let url = |_i: usize| format!("https://example.com/articles/{:04}", _i);
+    let mut tree = PrefixBPTree::create(&path, 8, 512).unwrap();
+    for i in 0..200 {
+        tree.put(url(i), i.to_string()).unwrap();
+    }
+    tree.checkpoint().unwrap();
+    let changes = tree.subscribe(256);
+    assert_eq!(tree.remove_range(Bound::Included(&url(50)), Bound::Excluded(&url(150))).unwrap(), 100);
+    tree.remove(&url(7)).unwrap();
+    let mut value = tree.entry(url(8)).or_insert_with(String::new);
+    value.push('!');
+    value.commit().unwrap();
+    tree.sync().unwrap();
+    drop(tree);
+
+    // 订阅收到的事件中是完整的 key
+    let events: Vec<_> = std::iter::from_fn(|| changes.try_recv().ok().flatten()).collect();
+    assert_eq!(events.len(), 102);
+    assert_eq!((events[0].key.as_str(), events[0].old_value.as_deref()), (url(50).as_str(), Some("50")));
+    assert_eq!((events[101].key.as_str(), events[101].new_value.as_deref()), (url(8).as_str(), Some("8!")));
+
+    let mut tree = PrefixBPTree::open(&path).unwrap();
+    tree.check_invariants().unwrap();
+    assert_eq!(tree.len(), 99);
+    assert_eq!(tree.get(&url(8)), Some("8!"));
+    assert_eq!(tree.get(&url(7)), None);
+    assert_eq!(tree.range(Bound::Included(&url(49)), Bound::Included(&url(150))).map(|(key, _)| key).collect::<Vec<_>>(), [url(49), url(150)]);
+    tree.checkpoint().unwrap();
+    drop(tree);
+    let plain = BPTree::open(&path).unwrap();
+    plain.check_invariants().unwrap();
+    assert_eq!(plain.get(&url(199)).map(|_kv| _kv.value()), Some("199"));
+
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        let _ = std::fs::remove_file(path.with_extension(extension));
+    }
+}
+
+#[test]
+fn leaf_order_is_saved_in_file() {
+    // 值很大时叶子节点的 order 小, 内部节点的 order 大, 重新打开后两者都不变
+    let path = std::env::temp_dir().join(format!("btree-test-leaf-order-{}", std::process::id()));
+    let mut tree = BPTree::builder().leaf_order(4).internal_order(32).create(&path, 1024).unwrap();
+    tree.put_batch((0..300).map(|_i| (format!("{:03}", _i), "v".repeat(200)))).unwrap();
+    tree.checkpoint().unwrap();
+    drop(tree);
+    let header = std::fs::read(&path).unwrap()[..64].to_vec();
+    assert_eq!(header[24..32], [32, 0, 0, 0, 4, 0, 0, 0]);
+
+    let mut tree = BPTree::open(&path).unwrap();
+    assert_eq!((tree.leaf_order(), tree.order()), (4, 32));
+    assert_eq!(tree.len(), 300);
+    tree.remove_range(Bound::Included("100"), Bound::Excluded("250")).unwrap();
+    tree.check_invariants().unwrap();
+    tree.checkpoint().unwrap();
+    drop(tree);
+
+    let mut paged = btree_test::PagedBPTree::open(&path, 1 << 16).unwrap();
+    paged.put_batch((100..250).map(|_i| (format!("{:03}", _i), _i.to_string()))).unwrap();
+    paged.flush().unwrap();
+    drop(paged);
+    let tree = BPTree::open(&path).unwrap();
+    tree.check_invariants().unwrap();
+    assert_eq!(tree.len(), 300);
+    assert_eq!(tree.get("150").map(|_kv| _kv.value()), Some("150"));
+
+    // 比较器不会保存在文件中
+    let reversed = BPTree::builder().comparator(|_a: &[u8], _b: &[u8]| _b.cmp(_a)).create(&path, 1024);
+    assert_eq!(reversed.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        std::fs::remove_file(path.with_extension(extension)).unwrap();
+    }
+}
+
+#[test]
+fn append_between_different_orders() {
+    // order 不同的两棵树不能直接接上叶子节点, 合并后结构仍然正确, 之后可以继续插入
+    let entries = |_range: std::ops::Range<usize>| _range.map(|_i| (format!("{:04}", _i), _i.to_string())).collect::<Vec<_>>();
+    let configs = [
+        (BPTree::new(3), BPTree::bulk_load(64, entries(0..200))),
+        (BPTree::bulk_load(3, entries(0..200)), BPTree::bulk_load(64, entries(200..400))),
+        (BPTree::bulk_load(64, entries(200..400)), BPTree::bulk_load(3, entries(0..200))),
+        (BPTree::builder().leaf_order(4).internal_order(16).build(), BPTree::bulk_load(4, entries(0..200))),
+        (BPTree::bulk_load(4, entries(200..400)), BPTree::builder().leaf_order(16).internal_order(4).build()),
+    ];
+    for (mut tree, mut other) in configs {
+        let mut model: BTreeMap<String, String> = tree.iter().chain(other.iter()).map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        tree.append(&mut other).unwrap();
+        assert!(other.is_empty());
+        tree.check_invariants().unwrap();
+        for i in (0..500).step_by(3) {
+            tree.put(format!("{:04}", i), "new".to_string()).unwrap();
+            model.insert(format!("{:04}", i), "new".to_string());
+        }
+        tree.check_invariants().unwrap();
+        assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+}
+
+#[test]
+fn adaptive_split_follows_insert_pattern() {
+    // 顺序插入时按识别出的方向分裂, 叶子节点几乎都是满的; 随机插入时与 Middle 相同
+    let keys: Vec<String> = (0..2000).map(|_i| format!("{:04}", _i)).collect();
+    // 7919 与 2000 互质, 乘上它再取模得到 0..2000 的一个打乱的排列
+    let shuffled = (0..2000).map(|_i| keys[_i * 7919 % 2000].clone()).collect();
+    let cases = [
+        (keys.clone(), InsertPattern::Sequential),
+        (keys.iter().rev().cloned().collect(), InsertPattern::Reverse),
+        (shuffled, InsertPattern::Random),
+    ];
+    for (keys, pattern) in cases {
+        // Adaptive 是默认的分配方式
+        let mut tree = BPTree::new(16);
+        assert_eq!(tree.split_policy(), SplitPolicy::Adaptive);
+        for key in &keys {
+            tree.put(key.clone(), key.clone()).unwrap();
+        }
+        tree.check_invariants().unwrap();
+        let stats = tree.stats();
+        assert_eq!(stats.insert_pattern, pattern);
+        if pattern == InsertPattern::Random {
+            assert!(stats.fill_factor < 0.9, "{:?}", stats);
+        } else {
+            assert!(stats.fill_factor > 0.9, "{:?}", stats);
+        }
+    }
+
+    // 插入模式改变之后, 最近的插入决定新的模式
+    let mut tree = BPTree::new(16);
+    tree.set_split_policy(SplitPolicy::Adaptive);
+    assert_eq!(tree.stats().insert_pattern, InsertPattern::Random);
+    for i in 1000..1100 {
+        tree.put(format!("{:04}", i), String::new()).unwrap();
+    }
+    assert_eq!(tree.stats().insert_pattern, InsertPattern::Sequential);
+    for i in (0..100).rev() {
+        tree.put(format!("{:04}", i), String::new()).unwrap();
+    }
+    assert_eq!(tree.stats().insert_pattern, InsertPattern::Reverse);
+    tree.check_invariants().unwrap();
+}
+
+#[test]
+fn in_place_writes_are_written_to_wal() {
+    // 插入与原地修改都写入预写日志, 不 checkpoint 直接重新打开后从日志恢复
+    let path = std::env::temp_dir().join(format!("btree-test-upsert-{}", std::process::id()));
+    let mut tree = BPTree::create(&path, 4, 512).unwrap();
+    for word in ["a", "b", "a", "c", "a"] {
+        tree.upsert(word.to_string(), || "1".to_string(), |count| *count = (count.parse::<u32>().unwrap() + 1).to_string()).unwrap();
+    }
+    assert_eq!(tree.get_or_insert_with("d".to_string(), || "new".to_string()).unwrap().as_str(), "new");
+    assert_eq!(tree.get_or_insert_with("a".to_string(), || unreachable!()).unwrap().as_str(), "3");
+    // 通过返回的可变引用修改的值在 commit 时写入日志, 没有 commit 的修改被丢弃
+    let mut value = tree.get_or_insert_with("e".to_string(), || "e".to_string()).unwrap();
+    value.push('!');
+    value.commit().unwrap();
+    let mut value = tree.get_mut("b").unwrap();
+    value.push('0');
+    assert_eq!(value.as_str(), "10");
+    value.commit().unwrap();
+    tree.get_mut("b").unwrap().push('0');
+    let mut value = tree.entry("c".to_string()).or_insert_with(String::new);
+    value.push_str("00");
+    value.commit().unwrap();
+    let mut cursor = tree.cursor_mut();
+    cursor.seek("d");
+    let mut value = cursor.value_mut().unwrap();
+    value.insert(0, '+');
+    value.commit().unwrap();
+    let iter_mut = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tree.iter_mut().count()));
+    assert!(iter_mut.is_err());
+    drop(tree);
+
+    let tree = BPTree::open(&path).unwrap();
+    let entries: Vec<(&str, &str)> = tree.iter().collect();
+    assert_eq!(entries, [("a", "3"), ("b", "10"), ("c", "100"), ("d", "+new"), ("e", "e!")]);
+    drop(tree);
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        std::fs::remove_file(path.with_extension(extension)).unwrap();
+    }
+}
+
+#[test]
+fn maintenance_recycles_wal_and_vacuums_tombstones() {
+    use std::time::{Duration, Instant};
+
+    let wait_for = |_done: &dyn Fn() -> bool| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !_done() {
+            assert!(Instant::now() < deadline, "maintenance did not run");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    };
+
+    // 预写日志超过上限时做检查点, 日志文件被清空, 重新打开时内容来自数据文件
+    let path = std::env::temp_dir().join(format!("btree-test-maintenance-{}", std::process::id()));
+    let config = MaintenanceConfig { poll_interval: Duration::from_millis(1), wal_limit: Some(4096), ..MaintenanceConfig::default() };
+    let scheduler = MaintenanceScheduler::new(BPTree::create(&path, 8, 512).unwrap(), config);
+    for i in 0..500 {
+        scheduler.lock().put(format!("{:04}", i), i.to_string()).unwrap();
+    }
+    wait_for(&|| std::fs::metadata(path.with_extension("wal")).unwrap().len() < 4096);
+    scheduler.pause();
+    let stats = scheduler.stats();
+    assert!(stats.checkpoints > 0 && stats.bytes_written > 0, "{:?}", stats);
+    assert_eq!(stats.errors, 0);
+    // 暂停之后不再做检查点, 日志只增不减
+    let wal_len = || std::fs::metadata(path.with_extension("wal")).unwrap().len();
+    for i in 500..1000 {
+        scheduler.lock().put(format!("{:04}", i), i.to_string()).unwrap();
+    }
+    let paused_len = wal_len();
+    assert!(paused_len > 4096);
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(scheduler.stats().checkpoints, stats.checkpoints);
+    assert_eq!(wal_len(), paused_len);
+    scheduler.resume();
+    wait_for(&|| wal_len() < 4096);
+    drop(scheduler.into_inner());
+    let tree = BPTree::open(&path).unwrap();
+    assert_eq!(tree.len(), 1000);
+    tree.check_invariants().unwrap();
+    drop(tree);
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        std::fs::remove_file(path.with_extension(extension)).unwrap();
+    }
+
+    // 删除标记超过一半时清理
+    let config = MaintenanceConfig { poll_interval: Duration::from_millis(1), vacuum_ratio: Some(0.5), ..MaintenanceConfig::default() };
+    let scheduler = MaintenanceScheduler::new(BPTree::builder().order(4).build_tombstoned(), config);
+    {
+        let mut tree = scheduler.lock();
+        for i in 0..100 {
+            tree.put(format!("{:03}", i), i.to_string()).unwrap();
+        }
+        for i in 0..40 {
+            tree.remove(&format!("{:03}", i)).unwrap();
+        }
+    }
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(scheduler.lock().tombstone_count(), 40);
+    {
+        let mut tree = scheduler.lock();
+        for i in 40..60 {
+            tree.remove(&format!("{:03}", i)).unwrap();
+        }
+    }
+    wait_for(&|| scheduler.lock().tombstone_count() == 0);
+    assert_eq!(scheduler.stats().vacuums, 1);
+    let tree = scheduler.into_inner();
+    assert!(tree.iter().map(|(key, _)| key.to_string()).eq((60..100).map(|_i| format!("{:03}", _i))));
+
+    // 限流: 每次检查点估计写入的字节数按速度折算成等待时间, 之后的检查点被推迟
+    let config = MaintenanceConfig {
+        poll_interval: Duration::from_millis(1),
+        checkpoint_interval: Some(Duration::ZERO),
+        io_bytes_per_sec: Some(1),
+        ..MaintenanceConfig::default()
+    };
+    let scheduler = MaintenanceScheduler::new(BPTree::create(&path, 8, 512).unwrap(), config);
+    scheduler.lock().put("a".to_string(), "1".to_string()).unwrap();
+    wait_for(&|| scheduler.stats().checkpoints > 0);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(scheduler.stats().checkpoints, 1);
+    drop(scheduler);
+    std::fs::remove_file(&path).unwrap();
+    for extension in ["wal", "ovf"] {
+        std::fs::remove_file(path.with_extension(extension)).unwrap();
+    }
+}
+
+#[test]
+fn btkv_composes_file_repl_backup_and_server() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command, Stdio};
+
+    let btkv = |_args: &[&str], _input: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_btkv")).args(_args).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(_input.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "btkv {:?} failed", _args);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let dir = std::env::temp_dir().join(format!("btree-test-btkv-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (db, backup, restored) = (dir.join("kv"), dir.join("kv.bak"), dir.join("restored"));
+    let [db, backup, restored] = [&db, &backup, &restored].map(|_path| _path.to_str().unwrap().to_string());
+
+    // 数据库文件不存在时创建, 退出后重新打开时内容仍在
+    btkv(&["open", &db, "--order", "4"], "put a 1\nput b hello world\nput c 3\ndel c\nquit\n");
+    assert!(btkv(&["open", &db], "get b\n").contains("hello world"));
+    assert!(btkv(&["fsck", &db], "").contains("2 条"));
+    btkv(&["backup", &db, &backup], "");
+    btkv(&["restore", &backup, &restored, "--page-size", "1024"], "");
+    assert!(btkv(&["stats", &restored], "").contains("键值对: 2"));
+    assert!(btkv(&["open", &restored], "scan\n").contains("a: 1\nb: hello world\n(2 条)"));
+
+    // serve 与命令行使用相同的命令, 两个连接看到同一棵树
+    let mut server = Command::new(env!("CARGO_BIN_EXE_btkv")).args(["serve", &db, "127.0.0.1:0"]).stdout(Stdio::piped()).spawn().unwrap();
+    let mut banner = String::new();
+    BufReader::new(server.stdout.as_mut().unwrap()).read_line(&mut banner).unwrap();
+    let addr = banner.split_whitespace().nth(1).unwrap().to_string();
+    let request = |_line: &str| {
+        let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+        writeln!(stream, "{}\nquit", _line).unwrap();
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response).unwrap();
+        response
+    };
+    assert_eq!(request("put c 30"), "插入 c\n");
+    assert_eq!(request("get c"), "30\n");
+    assert_eq!(request("count .."), "3\n");
+    server.kill().unwrap();
+    server.wait().unwrap();
+    // 结束服务进程之后从预写日志恢复
+    assert!(btkv(&["open", &db], "get c\n").contains("30"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn backup_restores_snapshot_while_writing() {
+    // 在另一个线程中备份快照, 同时继续修改树, 恢复出来的是创建快照时的内容
+    let path = std::env::temp_dir().join(format!("btree-test-backup-{}", std::process::id()));
+    let mut tree = BPTree::builder().leaf_order(6).internal_order(9).build();
+    tree.put_batch((0..5000).map(|_i| (format!("{:05}", _i), _i.to_string()))).unwrap();
+    let snapshot = tree.snapshot();
+    let expected: Vec<(String, String)> = snapshot.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+    let backup = std::thread::spawn({
+        let path = path.clone();
+        move || snapshot.backup(&path)
+    });
+    for i in 0..5000 {
+        if i % 2 == 0 {
+            tree.remove(&format!("{:05}", i)).unwrap();
+        } else {
+            tree.put(format!("{:05}", i), "changed".to_string()).unwrap();
+        }
+    }
+    backup.join().unwrap().unwrap();
+    assert!(!std::path::Path::new(&format!("{}.tmp", path.display())).exists());
+
+    let restored = BPTree::restore(&path).unwrap();
+    restored.check_invariants().unwrap();
+    assert_eq!((restored.leaf_order(), restored.order()), (6, 9));
+    assert!(restored.iter().eq(expected.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+
+    // 内容被修改或文件被截断时拒绝恢复
+    let bytes = std::fs::read(&path).unwrap();
+    let mut corrupted = bytes.clone();
+    corrupted[100] ^= 1;
+    std::fs::write(&path, &corrupted).unwrap();
+    assert_eq!(BPTree::restore(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    assert_eq!(BPTree::restore(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn snapshots_of_large_tree_share_slots() {
+    // 节点数量超过槽表一层 trie 的容量, 写入期间多次创建快照, 树整理之后各快照仍与创建时一致
+    let mut tree = BPTree::new(3);
+    let mut model = BTreeMap::new();
+    let mut snapshots = vec![];
+    for i in 0..40_000u32 {
+        let key = format!("{:05}", i.wrapping_mul(7919) % 40_000);
+        if i % 3 == 0 {
+            assert_eq!(tree.remove(&key).unwrap(), model.remove(&key));
+        } else {
+            assert_eq!(tree.put(key.clone(), i.to_string()).unwrap(), model.insert(key, i.to_string()));
+        }
+        if i % 5000 == 0 {
+            snapshots.push((tree.snapshot(), model.clone()));
+        }
+    }
+    assert!(tree.nodes().len() > 32 * 32);
+    tree.compact();
+    tree.shrink_to_fit();
+    tree.check_invariants().unwrap();
+    assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    for (snapshot, model) in &snapshots {
+        snapshot.check_invariants().unwrap();
+        assert!(snapshot.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    }
+}
+
+#[test]
+fn shrink_to_fit_after_bulk_writes() {
+    // 预留容量后大量插入再删除, 缩小之后内容不变, 快照中共享的节点不受影响, 之后仍可以继续写入
+    let mut tree = BPTree::with_capacity(6, 5000);
+    let mut model = BTreeMap::new();
+    for i in 0..5000 {
+        tree.put(format!("{:05}", i), i.to_string()).unwrap();
+        model.insert(format!("{:05}", i), i.to_string());
+    }
+    for i in (0..5000).filter(|_i| _i % 5 != 0) {
+        tree.remove(&format!("{:05}", i)).unwrap();
+        model.remove(&format!("{:05}", i));
+    }
+    let snapshot = tree.snapshot();
+    tree.put("00001".to_string(), "again".to_string()).unwrap();
+    let before = tree.memory_usage();
+    tree.shrink_to_fit();
+    let after = tree.memory_usage();
+    assert!(after.slack < before.slack);
+    assert_eq!((after.keys, after.values), (before.keys, before.values));
+    tree.check_invariants().unwrap();
+    assert!(snapshot.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+
+    model.insert("00001".to_string(), "again".to_string());
+    for i in 5000..6000 {
+        tree.put(format!("{:05}", i), i.to_string()).unwrap();
+        model.insert(format!("{:05}", i), i.to_string());
+    }
+    tree.check_invariants().unwrap();
+    assert!(tree.iter().eq(model.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted_files_round_trip() {
+    // 大值写入溢出文件, 最后一批修改只在预写日志中, 重新打开后都要能读出, 文件中不能出现明文
+    let path = std::env::temp_dir().join(format!("btree-test-encrypted-{}", std::process::id()));
+    let key = [42u8; 32];
+    let value = |_i: usize| format!("plaintext-{}", "x".repeat(_i * 37 % 2000));
+    let mut tree = BPTree::create_encrypted(&path, 4, 4096, &key).unwrap();
+    tree.put_batch((0..200).map(|_i| (format!("key-{:03}", _i), value(_i)))).unwrap();
+    tree.checkpoint().unwrap();
+    for i in 0..20 {
+        tree.remove(&format!("key-{:03}", i * 3)).unwrap();
+    }
+    drop(tree);
+    for extension in ["", ".wal", ".ovf"] {
+        let bytes = std::fs::read(format!("{}{}", path.display(), extension)).unwrap();
+        assert!(!bytes.windows(9).any(|_w| _w == b"plaintext"), "plaintext in {:?}", extension);
+    }
+
+    assert_eq!(BPTree::open(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(BPTree::open_encrypted(&path, &[0u8; 32]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    let mut tree = BPTree::open_encrypted(&path, &key).unwrap();
+    let expected: Vec<_> = (0..200).filter(|_i| _i % 3 != 0 || *_i >= 60).map(|_i| (format!("key-{:03}", _i), value(_i))).collect();
+    assert!(tree.iter().eq(expected.iter().map(|(key, value)| (key.as_str(), value.as_str()))));
+    tree.checkpoint().unwrap();
+    drop(tree);
+
+    // PagedBPTree 读写同一个文件
+    let mut paged = btree_test::PagedBPTree::open_encrypted(&path, 4096, &key).unwrap();
+    assert_eq!(paged.get("key-100").unwrap(), Some(value(100)));
+    paged.put("key-999".to_string(), value(999)).unwrap();
+    drop(paged);
+    let tree = BPTree::open_encrypted(&path, &key).unwrap();
+    assert_eq!(tree.len(), expected.len() + 1);
+    drop(tree);
+
+    for extension in ["", ".wal", ".ovf"] {
+        std::fs::remove_file(format!("{}{}", path.display(), extension)).unwrap();
+    }
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn sample_is_distinct_and_uniform() {
+    use rand::SeedableRng;
+
+    // 删除一部分后子树计数不均匀, 每个键值对被抽到的次数仍然接近期望值
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let mut tree = BPTree::bulk_load(4, (0..100).map(|_i| (format!("{:03}", _i), _i.to_string())));
+    for i in (0..100).filter(|_i| _i % 3 == 0 || (40..60).contains(_i)) {
+        tree.remove(&format!("{:03}", i)).unwrap();
+    }
+    let mut hits = BTreeMap::new();
+    for _ in 0..4000 {
+        let sample = tree.sample(5, &mut rng);
+        assert_eq!(sample.len(), 5);
+        assert!(sample.windows(2).all(|_w| _w[0].0 < _w[1].0));
+        for (key, value) in sample {
+            assert_eq!(tree.get(key).map(|_kv| _kv.value()), Some(value));
+            *hits.entry(key.to_string()).or_insert(0usize) += 1;
+        }
+    }
+    // 每个键值对期望被抽到 4000 * 5 / len 次
+    let expected = 4000 * 5 / tree.len();
+    assert_eq!(hits.len(), tree.len());
+    assert!(hits.values().all(|_count| _count.abs_diff(expected) < expected / 4), "{:?}", hits);
+    assert_eq!(tree.sample(1000, &mut rng).len(), tree.len());
+}